@@ -0,0 +1,33 @@
+use anyhow::Result;
+use std::future::Future;
+use std::time::Instant;
+
+/// Histogram of `DatabaseItem` operation latencies, labelled by model and operation
+pub const DB_DURATION_METRIC: &str = "portico_db_operation_duration_seconds";
+
+/// Count of `DatabaseItem` operations that returned an error
+pub const DB_ERRORS_METRIC: &str = "portico_db_operation_errors_total";
+
+/// Upper bounds (in seconds) for exporters to bucket `DB_DURATION_METRIC` by
+pub const DB_DURATION_BUCKETS: [f64; 10] =
+    [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 10.0];
+
+/// Times `fut` and records it under `model`/`operation` in the `metrics` registry,
+/// counting it as an error if it fails. Nothing is kept unless the process has
+/// installed a recorder (the engine's Prometheus exporter).
+pub(crate) async fn instrument<T>(
+    model: &'static str,
+    operation: &'static str,
+    fut: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let started = Instant::now();
+    let result = fut.await;
+
+    metrics::histogram!(DB_DURATION_METRIC, "model" => model, "operation" => operation)
+        .record(started.elapsed().as_secs_f64());
+    if result.is_err() {
+        metrics::counter!(DB_ERRORS_METRIC, "model" => model, "operation" => operation)
+            .increment(1);
+    }
+    result
+}
@@ -10,21 +10,51 @@ pub use models::{Agent, RuntimeSession, Signal, Step};
 
 /// Module for web scraping functionality
 pub mod webscrape;
-pub use webscrape::scrape_webpage;
+pub use webscrape::{
+    check_url, fetch_sitemap, scrape_webpage, scrape_webpage_with_config, ScrapeError,
+    ScrapeOutput, ScraperConfig, UrlCheck, UserAgentRotation, WebScrapeTarget,
+};
+
+/// Module for LLM provider configuration
+pub mod llm_providers;
+pub use llm_providers::{LlmProvider, LlmProviderRegistry, LlmRequestFormat};
+
+/// Module for resolving secret references in step content
+pub mod secrets;
+pub use secrets::SecretStore;
+
+/// Module for evaluating control-flow predicates
+pub mod predicate;
+pub use predicate::eval_predicate;
+
+/// Module for rendering prompt templates
+pub mod template;
+pub use template::render_prompt;
+
+/// Module for persistence backends
+pub mod store;
+pub use store::{MemoryStore, PgStore, Store};
+
+/// Module for database operation metrics
+pub mod db_metrics;
+pub use db_metrics::{DB_DURATION_BUCKETS, DB_DURATION_METRIC, DB_ERRORS_METRIC};
 
 // ============ Custom Enums / Traits ============
 // === Imports ===
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use models::runtime_sessions::RunContext;
+use models::steps::{StepError, StepErrorKind, StepOutput};
 use pyo3::prelude::*;
+use pyo3::types::PyDict;
 use reqwest::Client;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value;
 use sqlx::postgres::PgPool;
 use sqlx::{Postgres, Row};
 use std::collections::HashMap;
-use std::env;
 use std::ffi::CString;
+use std::time::Duration;
 use uuid::Uuid;
 
 // === Shared Enum definitions ===
@@ -36,7 +66,45 @@ pub enum RunningStatus {
     Waiting,
     Running,
     Completed,
+    /// Stopped on request (e.g. the client went away) before finishing
     Cancelled,
+    /// Stopped because a step (or the session setup) errored
+    Failed,
+    /// Stopped because the run used up its `RunBudget` (see `budget_exceeded`)
+    #[sqlx(rename = "budget_exceeded")]
+    #[cfg_attr(feature = "strum", strum(serialize = "budget_exceeded"))]
+    BudgetExceeded,
+}
+
+impl RunningStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RunningStatus::Waiting => "waiting",
+            RunningStatus::Running => "running",
+            RunningStatus::Completed => "completed",
+            RunningStatus::Cancelled => "cancelled",
+            RunningStatus::Failed => "failed",
+            RunningStatus::BudgetExceeded => "budget_exceeded",
+        }
+    }
+}
+
+// With the `strum` feature, `EnumString` provides this instead
+#[cfg(not(feature = "strum"))]
+impl std::str::FromStr for RunningStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "waiting" => Ok(RunningStatus::Waiting),
+            "running" => Ok(RunningStatus::Running),
+            "completed" => Ok(RunningStatus::Completed),
+            "cancelled" => Ok(RunningStatus::Cancelled),
+            "failed" => Ok(RunningStatus::Failed),
+            "budget_exceeded" => Ok(RunningStatus::BudgetExceeded),
+            _ => Err(anyhow!("Invalid running status: {}", s)),
+        }
+    }
 }
 
 // ============ Struct definitions =============
@@ -132,8 +200,20 @@ pub struct PythonRuntime {
     module: Py<PyModule>,
     /// Maps step UUIDs to their Python function names
     step_functions: HashMap<String, String>,
+    /// `default` hook for `json.dumps` of step results, see `JSON_DEFAULT_PY`
+    json_default: Py<PyAny>,
 }
 
+/// Converts values `json.dumps` can't serialize but steps commonly return: numpy
+/// scalars and arrays (`numpy.bool_`, `numpy.int64`, `ndarray`, ...) become the
+/// Python bools, ints, floats and lists they hold, so they keep their JSON type
+const JSON_DEFAULT_PY: &str = r#"
+def json_default(obj):
+    if type(obj).__module__ == "numpy" and hasattr(obj, "tolist"):
+        return obj.tolist()
+    raise TypeError(f"Object of type {type(obj).__name__} is not JSON serializable")
+"#;
+
 impl PythonRuntime {
     /// Create a new Python runtime with a unique module name
     pub fn new(name: &str) -> Result<Self> {
@@ -144,22 +224,65 @@ impl PythonRuntime {
             // Import common modules - just make it available in Python context
             let _ = py.import("json")?;
 
+            // Always defined so steps can call `AGENT_ENV.get(...)`
+            module.setattr("AGENT_ENV", PyDict::new(py))?;
+            module.setattr("context", PyDict::new(py))?;
+            module.setattr("outputs", PyDict::new(py))?;
+
+            // Defined outside the module so steps can't see or shadow it
+            let helpers = PyDict::new(py);
+            py.run(&CString::new(JSON_DEFAULT_PY)?, Some(&helpers), None)?;
+            let json_default = helpers
+                .get_item("json_default")?
+                .ok_or_else(|| anyhow!("json_default helper wasn't defined"))?;
+
             Ok(Self {
                 module: module.into(),
                 step_functions: HashMap::new(),
+                json_default: json_default.unbind(),
             })
         })
     }
 
+    /// Exposes the agent's env vars to its Python steps as the `AGENT_ENV` dict.
+    /// Scoped to this runtime's module, so `os.environ` and other agents are untouched.
+    pub fn set_env(&self, env: &HashMap<String, String>) -> Result<()> {
+        Python::with_gil(|py| {
+            let env_dict = PyDict::new(py);
+            for (key, value) in env {
+                env_dict.set_item(key, value)?;
+            }
+            self.module.bind(py).setattr("AGENT_ENV", env_dict)?;
+            Ok(())
+        })
+    }
+
+    /// Exposes the current run's `RunContext` to Python steps as the `context` dict.
+    /// Set by the session before its steps run, so it reflects the latest run.
+    pub fn set_context(&self, context: &RunContext) -> Result<()> {
+        Python::with_gil(|py| {
+            let json_str = serde_json::to_string(context)?;
+            let py_context = py.import("json")?.getattr("loads")?.call1((json_str,))?;
+            self.module.bind(py).setattr("context", py_context)?;
+            Ok(())
+        })
+    }
+
     /// Add a step to the runtime
     pub fn add_step(&mut self, step: &Step) -> Result<()> {
+        // Control-flow steps carry their own inner steps, which may be Python
+        for nested in step.nested_steps() {
+            self.add_step(&nested)?;
+        }
+
         if !step.is_python_step() {
             return Ok(()); // Skip non-Python steps
         }
 
         Python::with_gil(|py| {
-            // Get the Python function code for this step
+            // Get the Python function code for this step, with secret references resolved
             let func_code = step.to_python_function();
+            let func_code = SecretStore::from_env().resolve(&func_code)?;
             let func_name = step.python_function_name();
 
             // Get a reference to the module
@@ -168,9 +291,10 @@ impl PythonRuntime {
             // Add the function to the module
             let locals = module_ref.dict();
 
-            // Convert to CString for py.run
+            // Convert to CString for py.run. The module dict is the function's
+            // globals, so module attributes like `AGENT_ENV` are visible to it
             let code_cstring = CString::new(func_code.as_bytes())?;
-            py.run(code_cstring.as_c_str(), None, Some(&locals))?;
+            py.run(code_cstring.as_c_str(), Some(&locals), None)?;
 
             // Store the function name mapped to the step UUID
             self.step_functions
@@ -182,10 +306,29 @@ impl PythonRuntime {
 
     /// Execute a step with the given input data
     pub fn execute_step(&self, step_uuid: &str, input: Value) -> Result<Value> {
+        self.execute_step_with_outputs(step_uuid, input, None)
+            .map(|output| output.primary)
+    }
+
+    /// Like `execute_step`, but also collects the named outputs the step set on
+    /// the `outputs` dict (e.g. `outputs["links"] = links`). With a `seed`, Python's
+    /// `random` (and `numpy.random`, if numpy is imported) are seeded with it just
+    /// before the call, so a step using randomness gives the same output every time.
+    pub fn execute_step_with_outputs(
+        &self,
+        step_uuid: &str,
+        input: Value,
+        seed: Option<u64>,
+    ) -> Result<StepOutput> {
         let func_name = self
             .step_functions
             .get(step_uuid)
-            .ok_or_else(|| anyhow!("Step function not found: {}", step_uuid))?;
+            .ok_or_else(|| {
+                StepError::new(
+                    StepErrorKind::Config,
+                    format!("Step function not found: {}", step_uuid),
+                )
+            })?;
 
         Python::with_gil(|py| {
             // Get a reference to the module
@@ -193,21 +336,46 @@ impl PythonRuntime {
 
             // Get the json module
             let py_json = py.import("json")?;
+            let dumps_kwargs = PyDict::new(py);
+            dumps_kwargs.set_item("default", self.json_default.bind(py))?;
+            let to_value = |obj: Bound<'_, PyAny>| -> Result<Value> {
+                let py_json_str = py_json
+                    .getattr("dumps")?
+                    .call((obj,), Some(&dumps_kwargs))?;
+                let rust_json_str: String = py_json_str.extract()?;
+                Ok(serde_json::from_str(&rust_json_str)?)
+            };
 
-            // Convert input to Python object
+            // Convert input to Python object (a dict, see `standardize_input`)
+            let (input, input_wrapped) = standardize_input(input);
             let json_str = serde_json::to_string(&input)?;
             let py_input = py_json.getattr("loads")?.call1((json_str,))?;
 
+            // Each call starts with no named outputs
+            module_ref.setattr("outputs", PyDict::new(py))?;
+
+            if let Some(seed) = seed {
+                py.import("random")?.getattr("seed")?.call1((seed,))?;
+                // Only if a step imported it; numpy seeds must fit in 32 bits
+                let modules = py.import("sys")?.getattr("modules")?;
+                if let Ok(numpy) = modules.get_item("numpy") {
+                    numpy
+                        .getattr("random")?
+                        .getattr("seed")?
+                        .call1((seed & u64::from(u32::MAX),))?;
+                }
+            }
+
             // Call the function
             if let Ok(func) = module_ref.getattr(func_name) {
                 let result = func.call1((py_input,))?;
 
-                // Convert the result back to Rust
-                let py_json_str = py_json.getattr("dumps")?.call1((result,))?;
-                let rust_json_str: String = py_json_str.extract()?;
-                let rust_value: Value = serde_json::from_str(&rust_json_str)?;
+                // Convert the result and named outputs back to Rust
+                let primary = standardize_output(to_value(result)?, input_wrapped);
+                let named = serde_json::from_value(to_value(module_ref.getattr("outputs")?)?)
+                    .map_err(|e| anyhow!("`outputs` must be a dict: {}", e))?;
 
-                Ok(rust_value)
+                Ok(StepOutput { primary, named })
             } else {
                 Err(anyhow!("Function not found in module: {}", func_name))
             }
@@ -217,9 +385,11 @@ impl PythonRuntime {
 
 // ============ Trait definitions =============
 
-/// Item that is in the `public` schema (Portico-custom, not Supabase-predefined)
+/// Item that is in the `public` schema (Portico-custom, not Supabase-predefined).
+/// Implementors provide the `db_*` methods; callers use the `try_db_*` wrappers,
+/// which record latency and errors per model (see `db_metrics`).
 #[async_trait]
-pub trait DatabaseItem {
+pub trait DatabaseItem: Send + Sync {
     /// The integer type used for the local_id (defaults to i32)
     type IdType: sqlx::Type<Postgres>
         + for<'r> sqlx::Decode<'r, Postgres>
@@ -229,19 +399,71 @@ pub trait DatabaseItem {
         + std::fmt::Debug
         + 'static;
 
+    /// Model name that operations are recorded under in `db_metrics`
+    const MODEL: &'static str;
+
     fn id(&self) -> &IdFields<Self::IdType>;
-    async fn try_db_create(&self, pool: &PgPool) -> Result<()>;
-    async fn try_db_update(&self, pool: &PgPool) -> Result<()>;
-    async fn try_db_delete(&self, pool: &PgPool) -> Result<()>;
-    async fn try_db_select_all(pool: &PgPool) -> Result<Vec<Self>>
+    async fn db_create(&self, pool: &PgPool) -> Result<()>;
+    async fn db_update(&self, pool: &PgPool) -> Result<()>;
+    async fn db_delete(&self, pool: &PgPool) -> Result<()>;
+    async fn db_select_all(pool: &PgPool) -> Result<Vec<Self>>
+    where
+        Self: Sized;
+    async fn db_select_by_id(pool: &PgPool, id: &IdFields<Self::IdType>) -> Result<Option<Self>>
+    where
+        Self: Sized;
+    async fn db_select_by_ids(pool: &PgPool, ids: &[Self::IdType]) -> Result<Vec<Self>>
     where
         Self: Sized;
+    async fn db_select_by_uuids(pool: &PgPool, uuids: &[String]) -> Result<Vec<Self>>
+    where
+        Self: Sized;
+
+    async fn try_db_create(&self, pool: &PgPool) -> Result<()> {
+        db_metrics::instrument(Self::MODEL, "create", self.db_create(pool)).await
+    }
+    async fn try_db_update(&self, pool: &PgPool) -> Result<()> {
+        db_metrics::instrument(Self::MODEL, "update", self.db_update(pool)).await
+    }
+    async fn try_db_delete(&self, pool: &PgPool) -> Result<()> {
+        db_metrics::instrument(Self::MODEL, "delete", self.db_delete(pool)).await
+    }
+    async fn try_db_select_all(pool: &PgPool) -> Result<Vec<Self>>
+    where
+        Self: Sized,
+    {
+        db_metrics::instrument(Self::MODEL, "select_all", Self::db_select_all(pool)).await
+    }
     async fn try_db_select_by_id(
         pool: &PgPool,
         id: &IdFields<Self::IdType>,
     ) -> Result<Option<Self>>
     where
-        Self: Sized;
+        Self: Sized,
+    {
+        db_metrics::instrument(Self::MODEL, "select_by_id", Self::db_select_by_id(pool, id)).await
+    }
+    /// The items with these local ids in one query, ordered by id. Ids that have
+    /// no row are left out rather than being an error.
+    async fn try_db_select_by_ids(pool: &PgPool, ids: &[Self::IdType]) -> Result<Vec<Self>>
+    where
+        Self: Sized,
+    {
+        db_metrics::instrument(Self::MODEL, "select_by_ids", Self::db_select_by_ids(pool, ids))
+            .await
+    }
+    /// Like `try_db_select_by_ids`, by global UUID
+    async fn try_db_select_by_uuids(pool: &PgPool, uuids: &[String]) -> Result<Vec<Self>>
+    where
+        Self: Sized,
+    {
+        db_metrics::instrument(
+            Self::MODEL,
+            "select_by_uuids",
+            Self::db_select_by_uuids(pool, uuids),
+        )
+        .await
+    }
 }
 
 pub trait JsonLike {
@@ -254,44 +476,210 @@ pub trait JsonLike {
 
 // ============ Shared functions ============
 
+/// Parses UUID strings for binding as a `uuid[]`, failing on the first malformed one
+pub(crate) fn parse_uuids(uuids: &[String]) -> Result<Vec<Uuid>> {
+    uuids
+        .iter()
+        .map(|uuid| Uuid::parse_str(uuid).map_err(|e| anyhow!("Invalid UUID '{}': {}", uuid, e)))
+        .collect()
+}
+
 /// Checks if a record with the given UUID already exists in the specified table
-pub async fn check_exists_by_uuid(pool: &PgPool, table: &str, uuid: &str) -> Result<bool> {
+pub async fn check_exists_by_uuid<'e>(
+    executor: impl sqlx::PgExecutor<'e>,
+    table: &str,
+    uuid: &str,
+) -> Result<bool> {
     let uuid_parsed = Uuid::parse_str(uuid)?;
     let query = format!(
         "SELECT EXISTS(SELECT 1 FROM {} WHERE global_uuid = $1)",
-        table
+        sql_identifier(table)?
     );
     sqlx::query_scalar::<_, bool>(&query)
         .bind(uuid_parsed)
-        .fetch_one(pool)
+        .fetch_one(executor)
         .await
         .map_err(|e| anyhow!("Failed to check if record exists: {}", e))
 }
 
-/// Returns a SQL fragment for Step JSON aggregation that's used in several queries
-pub fn steps_json_agg_sql(parent_table: &str, parent_id_column: &str) -> String {
-    format!(
-        r#"COALESCE(
-            (
-                SELECT json_agg(json_build_object(
+/// Resolves a simple JSONPath (e.g. `$.article.title`, `$.items[0]`, `$['key']`) against `data`.
+/// The leading `$` is optional. Returns `None` if any segment is missing.
+pub fn resolve_json_path<'a>(data: &'a Value, path: &str) -> Option<&'a Value> {
+    let path = path.trim();
+    let mut rest = path.strip_prefix('$').unwrap_or(path);
+    let mut current = data;
+
+    while !rest.is_empty() {
+        if let Some(after_dot) = rest.strip_prefix('.') {
+            // `.key` segment, ending at the next `.` or `[`
+            let end = after_dot.find(['.', '[']).unwrap_or(after_dot.len());
+            let key = &after_dot[..end];
+            if key.is_empty() {
+                return None;
+            }
+            current = current.get(key)?;
+            rest = &after_dot[end..];
+        } else if let Some(after_bracket) = rest.strip_prefix('[') {
+            // `[0]` or `['key']` segment
+            let end = after_bracket.find(']')?;
+            let inner = after_bracket[..end].trim();
+            current = if let Ok(idx) = inner.parse::<usize>() {
+                current.get(idx)?
+            } else {
+                current.get(inner.trim_matches(|c| c == '\'' || c == '"'))?
+            };
+            rest = &after_bracket[end + 1..];
+        } else {
+            // Bare leading key without a dot (e.g. `article.title`)
+            let end = rest.find(['.', '[']).unwrap_or(rest.len());
+            current = current.get(&rest[..end])?;
+            rest = &rest[end..];
+        }
+    }
+
+    Some(current)
+}
+
+/// JSON truthiness: `null`, `false`, `0`, and empty strings/arrays/objects are false
+pub fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().map(|f| f != 0.0).unwrap_or(false),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(a) => !a.is_empty(),
+        Value::Object(o) => !o.is_empty(),
+    }
+}
+
+/// A duration as JSON: fractional seconds as a number, the way the execution
+/// times are stored
+pub fn duration_to_json(duration: Duration) -> Value {
+    serde_json::json!(duration.as_secs_f64())
+}
+
+/// Reads a duration written by `duration_to_json`
+pub fn duration_from_json(value: &Value) -> Result<Duration> {
+    let secs = value
+        .as_f64()
+        .ok_or_else(|| anyhow!("Expected a duration in seconds, got {}", value))?;
+    Duration::try_from_secs_f64(secs).map_err(|e| anyhow!("Invalid duration {}: {}", value, e))
+}
+
+/// Key that arrays and scalars are wrapped under before a Python step sees them
+pub const PYTHON_DATA_KEY: &str = "data";
+
+/// Shapes a Python step's input so `source` is always a dict: arrays and scalars
+/// are passed as `{"data": value}` (`null` stays `None`). Returns whether the
+/// input was wrapped, for `standardize_output`.
+pub fn standardize_input(input: Value) -> (Value, bool) {
+    match input {
+        Value::Object(_) | Value::Null => (input, false),
+        other => (serde_json::json!({ PYTHON_DATA_KEY: other }), true),
+    }
+}
+
+/// Undoes `standardize_input`'s wrapping: if the input was wrapped and the step
+/// returned a lone `{"data": value}` (e.g. the default `result = source`), `value`
+/// is the output, so arrays and scalars keep their shape through the step.
+pub fn standardize_output(output: Value, input_wrapped: bool) -> Value {
+    match output {
+        Value::Object(mut map)
+            if input_wrapped && map.len() == 1 && map.contains_key(PYTHON_DATA_KEY) =>
+        {
+            map.remove(PYTHON_DATA_KEY).unwrap_or_default()
+        }
+        other => other,
+    }
+}
+
+/// Table names, aliases and columns that the SQL builders below may interpolate.
+/// Identifiers can't be bind parameters, so anything else is refused rather than
+/// risk a caller passing one through from user input.
+const SQL_IDENTIFIERS: &[&str] = &[
+    // Tables
+    "agents",
+    "steps",
+    "signals",
+    "runtime_sessions",
+    "dead_letter_signals",
+    // Aliases used by the queries that embed the builders
+    "a",
+    "rs",
+    "subq",
+    // Columns
+    "agent_id",
+];
+
+/// `name` if it may be interpolated into SQL as an identifier (see `SQL_IDENTIFIERS`)
+pub(crate) fn sql_identifier(name: &str) -> Result<&str> {
+    if SQL_IDENTIFIERS.contains(&name) {
+        Ok(name)
+    } else {
+        Err(anyhow!("Invalid SQL identifier: {:?}", name))
+    }
+}
+
+/// SQL building one step's JSON (from the `steps` row aliased `s`)
+const STEP_JSON_OBJECT_SQL: &str = r#"json_build_object(
                     'id', s.id,
                     'global_uuid', s.global_uuid,
                     'created_at', s.created_at,
                     'updated_at', s.updated_at,
+                    'agent_id', s.agent_id,
                     'description', s.description,
                     'step_type', s.step_type,
-                    'step_content', s.step_content
-                ))
+                    'step_content', s.step_content,
+                    'llm_model', s.llm_model,
+                    'llm_provider', s.llm_provider,
+                    'system_prompt', s.system_prompt,
+                    'input_mapping', s.input_mapping,
+                    'cacheable', s.cacheable,
+                    'expected_duration', s.expected_duration,
+                    'wrap_output', s.wrap_output,
+                    'on_error', s.on_error,
+                    'run_count', s.run_count,
+                    'success_count', s.success_count
+                )"#;
+
+/// Returns a SQL fragment aggregating the steps owned by `parent_table` (through
+/// their `parent_id_column`) as JSON
+pub fn steps_json_agg_sql(parent_table: &str, parent_id_column: &str) -> Result<String> {
+    Ok(format!(
+        r#"COALESCE(
+            (
+                SELECT json_agg({})
                 FROM steps s
                 WHERE s.{} = {}.id
             ),
             '[]'::json
         ) as steps"#,
-        parent_id_column, parent_table
-    )
+        STEP_JSON_OBJECT_SQL,
+        sql_identifier(parent_id_column)?,
+        sql_identifier(parent_table)?
+    ))
+}
+
+/// Returns a SQL fragment aggregating a runtime session's steps (the ones in its
+/// `step_ids`) as JSON, in the order they ran. A step listed twice is loaded twice;
+/// one deleted since the run is left out.
+pub fn session_steps_json_agg_sql(session_table: &str) -> Result<String> {
+    Ok(format!(
+        r#"COALESCE(
+            (
+                SELECT json_agg({} ORDER BY ran.idx)
+                FROM unnest({}.step_ids) WITH ORDINALITY AS ran(step_id, idx)
+                JOIN steps s ON s.id = ran.step_id
+            ),
+            '[]'::json
+        ) as steps"#,
+        STEP_JSON_OBJECT_SQL,
+        sql_identifier(session_table)?
+    ))
 }
 
-/// Returns a SQL fragment for the common Signal-Agent JOIN query
+/// Returns a SQL fragment for the common Signal-Agent JOIN query. `where_clause`
+/// is pasted in as is, so it must be fixed SQL with values passed as bind parameters.
 pub fn signal_with_agent_sql(where_clause: &str) -> String {
     format!(
         r#"
@@ -329,42 +717,283 @@ impl std::fmt::Display for JsonModeLLMs {
     }
 }
 
+impl std::str::FromStr for JsonModeLLMs {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "meta-llama/Llama-3.3-70B-Instruct-Turbo" => Ok(JsonModeLLMs::MetaLlama33_70b),
+            "deepseek-ai/DeepSeek-V3" => Ok(JsonModeLLMs::DeepseekV3_671b),
+            "Qwen/Qwen2.5-VL-72B-Instruct" => Ok(JsonModeLLMs::Qwen25_72b),
+            _ => Err(anyhow!("Unsupported LLM model: {}", s)),
+        }
+    }
+}
+
 // Call the LLM with a specific model or use the default
 pub async fn call_llm(prompt: &str, context: Value, model: Option<String>) -> Result<String> {
+    call_llm_with_provider(prompt, context, model, None, None).await
+}
+
+// Call the LLM through a named provider from `LlmProviderRegistry::from_env` (or the default one).
+// A `system_prompt` is sent as a system message ahead of the prompt.
+pub async fn call_llm_with_provider(
+    prompt: &str,
+    context: Value,
+    model: Option<String>,
+    provider_name: Option<&str>,
+    system_prompt: Option<&str>,
+) -> Result<String> {
+    complete_with_provider(
+        &prompt_with_context(prompt, &context),
+        model,
+        provider_name,
+        system_prompt,
+    )
+    .await
+}
+
+// Like `call_llm_with_provider`, but sends `prompt` as-is without appending a context block
+pub async fn complete_with_provider(
+    prompt: &str,
+    model: Option<String>,
+    provider_name: Option<&str>,
+    system_prompt: Option<&str>,
+) -> Result<String> {
+    request_completion(prompt, model, provider_name, system_prompt, None).await
+}
+
+// Call the LLM in JSON mode and decode its response as `T`. The response must match
+// the JSON `schema`; if it doesn't, the model is asked once more with the validation
+// errors appended to the prompt before giving up with a `Validation` error.
+// JSON wrapped in code fences or prose is accepted, see `extract_json_from_text`.
+pub async fn call_llm_typed<T: DeserializeOwned>(
+    prompt: &str,
+    context: Value,
+    model: Option<String>,
+    schema: &Value,
+) -> Result<T> {
+    request_typed(prompt, context, model, schema, true).await
+}
+
+// Like `call_llm_typed`, but the response must be bare JSON
+pub async fn call_llm_typed_strict<T: DeserializeOwned>(
+    prompt: &str,
+    context: Value,
+    model: Option<String>,
+    schema: &Value,
+) -> Result<T> {
+    request_typed(prompt, context, model, schema, false).await
+}
+
+async fn request_typed<T: DeserializeOwned>(
+    prompt: &str,
+    context: Value,
+    model: Option<String>,
+    schema: &Value,
+    lenient: bool,
+) -> Result<T> {
+    let validator = jsonschema::validator_for(schema).map_err(|e| {
+        StepError::new(StepErrorKind::Config, format!("Invalid JSON schema: {}", e))
+    })?;
+    let response_format = serde_json::json!({"type": "json_object", "schema": schema});
+
+    let prompt = prompt_with_context(prompt, &context);
+    let mut request_prompt = prompt.clone();
+    let mut errors = Vec::new();
+
+    // The first attempt plus one reprompt
+    for _ in 0..2 {
+        let response = request_completion(
+            &request_prompt,
+            model.clone(),
+            None,
+            None,
+            Some(response_format.clone()),
+        )
+        .await?;
+
+        errors = match decode_typed::<T>(&response, &validator, lenient) {
+            Ok(decoded) => return Ok(decoded),
+            Err(errors) => errors,
+        };
+        request_prompt = format!(
+            "{}\n\nYour previous response did not match the required JSON schema:\n- {}\nRespond with only JSON that matches the schema.",
+            prompt,
+            errors.join("\n- ")
+        );
+    }
+
+    Err(StepError::new(
+        StepErrorKind::Validation,
+        format!(
+            "LLM response did not match the JSON schema after a retry: {}",
+            errors.join("; ")
+        ),
+    )
+    .into())
+}
+
+/// Parses an LLM response as JSON (if `lenient`, also JSON found inside prose),
+/// validates it and decodes it, or lists what's wrong with it
+fn decode_typed<T: DeserializeOwned>(
+    response: &str,
+    validator: &jsonschema::Validator,
+    lenient: bool,
+) -> std::result::Result<T, Vec<String>> {
+    let value: Value = match serde_json::from_str(response.trim()) {
+        Ok(value) => value,
+        Err(e) => lenient
+            .then(|| extract_json_from_text(response))
+            .flatten()
+            .ok_or_else(|| vec![format!("response is not valid JSON: {}", e)])?,
+    };
+
+    let errors: Vec<String> = validator
+        .iter_errors(&value)
+        .map(|e| match e.instance_path().as_str() {
+            "" => e.to_string(),
+            path => format!("{}: {}", path, e),
+        })
+        .collect();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    serde_json::from_value(value).map_err(|e| vec![e.to_string()])
+}
+
+/// Finds the JSON in an LLM response that wraps it in prose or a code fence:
+/// the whole text if it parses, else the first fenced block that does, else the
+/// first balanced `{...}` / `[...]` that does. `None` if there's no JSON in it.
+pub fn extract_json_from_text(text: &str) -> Option<Value> {
+    let text = text.trim();
+    if let Ok(value) = serde_json::from_str(text) {
+        return Some(value);
+    }
+
+    // ```json ... ``` (or an unlabelled fence)
+    let mut fences = text.split("```").skip(1).step_by(2);
+    if let Some(value) = fences.find_map(|block| {
+        let block = block.strip_prefix("json").unwrap_or(block);
+        serde_json::from_str(block.trim()).ok()
+    }) {
+        return Some(value);
+    }
+
+    text.char_indices()
+        .filter(|(_, c)| matches!(c, '{' | '['))
+        .find_map(|(start, _)| {
+            let end = balanced_json_end(&text[start..])?;
+            serde_json::from_str(&text[start..start + end]).ok()
+        })
+}
+
+/// Length of the bracketed JSON value at the start of `text`, skipping brackets
+/// inside strings. `None` if the brackets don't balance.
+fn balanced_json_end(text: &str) -> Option<usize> {
+    let mut closers = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for (idx, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => closers.push('}'),
+            '[' => closers.push(']'),
+            '}' | ']' => {
+                if closers.pop() != Some(c) {
+                    return None;
+                }
+                if closers.is_empty() {
+                    return Some(idx + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn prompt_with_context(prompt: &str, context: &Value) -> String {
+    format!("{} | Context: ```json\n{}\n```", prompt, context)
+}
+
+async fn request_completion(
+    prompt: &str,
+    model: Option<String>,
+    provider_name: Option<&str>,
+    system_prompt: Option<&str>,
+    response_format: Option<Value>,
+) -> Result<String> {
     const MAX_RETRIES: usize = 3;
     const INITIAL_RETRY_DELAY_MS: u64 = 500;
 
-    let api_key = env::var("LLM_API_KEY").map_err(|_| anyhow!("Missing LLM_API_KEY environment variable"))?;
-    let api_endpoint = env::var("LLM_API_ENDPOINT").map_err(|_| anyhow!("Missing LLM_API_ENDPOINT environment variable"))?;
+    let registry = LlmProviderRegistry::from_env()?;
+    let provider = registry.get(provider_name)?;
+    // Retrying can't fix a malformed endpoint
+    provider.validate()?;
 
     // Determine which model to use
-    let model_name = if let Some(model_str) = model {
+    let model_name = if let (Some(model_str), Some(_)) = (&model, provider_name) {
+        // Named providers serve their own models, so pass the name through untouched
+        model_str.clone()
+    } else if let Some(model_str) = model {
         // Try to match the provided model string with a known model
-        match model_str.as_str() {
-            "meta-llama/Llama-3.3-70B-Instruct-Turbo" => JsonModeLLMs::MetaLlama33_70b.to_string(),
-            "deepseek-ai/DeepSeek-V3" => JsonModeLLMs::DeepseekV3_671b.to_string(),
-            "Qwen/Qwen2.5-VL-72B-Instruct" => JsonModeLLMs::Qwen25_72b.to_string(),
-            _ => JsonModeLLMs::MetaLlama33_70b.to_string(), // Default if not recognized
-        }
+        model_str
+            .parse::<JsonModeLLMs>()
+            .unwrap_or(JsonModeLLMs::MetaLlama33_70b) // Default if not recognized
+            .to_string()
     } else {
         // Use default model if none specified
         JsonModeLLMs::MetaLlama33_70b.to_string()
     };
 
-    let request = serde_json::json!({
+    let mut request = serde_json::json!({
         "model": model_name,
-        "prompt": format!("{} | Context: ```json\n{}\n```", prompt, context),
         "max_tokens": 1000,
         "temperature": 0.7
     });
+    match provider.request_format {
+        LlmRequestFormat::Chat => {
+            let mut messages = Vec::new();
+            if let Some(system_prompt) = system_prompt {
+                messages.push(serde_json::json!({"role": "system", "content": system_prompt}));
+            }
+            messages.push(serde_json::json!({"role": "user", "content": prompt}));
+            request["messages"] = Value::Array(messages);
+        }
+        // Legacy completions take one string, so the system prompt goes first in it
+        LlmRequestFormat::Completions => {
+            request["prompt"] = Value::String(match system_prompt {
+                Some(system_prompt) => format!("{}\n\n{}", system_prompt, prompt),
+                None => prompt.to_string(),
+            });
+        }
+    }
+    if let Some(response_format) = response_format {
+        request["response_format"] = response_format;
+    }
 
     let mut last_error = None;
 
     // Implement retry logic with exponential backoff
     for attempt in 0..MAX_RETRIES {
-        match attempt_llm_call(&api_endpoint, &api_key, &request).await {
+        match attempt_llm_call(provider, &request).await {
             Ok(result) => return Ok(result),
             Err(err) => {
+                // Retrying can't fix a rejected key or a malformed request
+                if !StepErrorKind::of(&err).is_some_and(|kind| kind.is_retryable()) {
+                    return Err(err);
+                }
                 last_error = Some(err);
 
                 // Don't sleep on the last attempt
@@ -384,46 +1013,69 @@ pub async fn call_llm(prompt: &str, context: Value, model: Option<String>) -> Re
 }
 
 // Helper function to perform a single LLM API call attempt
-async fn attempt_llm_call(api_endpoint: &str, api_key: &str, request: &Value) -> Result<String> {
-    let response: Value = Client::new()
-        .post(api_endpoint)
-        .header("Authorization", format!("Bearer {}", api_key))
+async fn attempt_llm_call(provider: &LlmProvider, request: &Value) -> Result<String> {
+    let http_response = provider
+        .auth_header_style
+        .apply(Client::new().post(&provider.endpoint), &provider.api_key)
         .json(request)
         .send()
         .await
-        .map_err(|e| anyhow!("LLM API request failed: {}", e))?
-        .json()
+        .map_err(|e| StepError::new(StepErrorKind::from_reqwest(&e), format!("LLM API request failed: {}", e)))?;
+
+    let status = http_response.status();
+    if !status.is_success() {
+        return Err(StepError::new(
+            StepErrorKind::from_status(status),
+            format!("LLM API returned HTTP status {}", status),
+        )
+        .into());
+    }
+
+    let body = http_response
+        .bytes()
         .await
-        .map_err(|e| anyhow!("Failed to parse LLM API response: {}", e))?;
+        .map_err(|e| StepError::new(StepErrorKind::from_reqwest(&e), format!("Failed to read LLM API response: {}", e)))?;
+    crate::models::runtime_sessions::charge_bytes(body.len())?;
+
+    let response: Value = serde_json::from_slice(&body)
+        .map_err(|e| StepError::new(StepErrorKind::Validation, format!("Failed to parse LLM API response: {}", e)))?;
 
     // Check if there's an error in the response
     if let Some(error) = response.get("error") {
-        return Err(anyhow!("LLM API returned an error: {}", error));
+        return Err(StepError::new(StepErrorKind::Network, format!("LLM API returned an error: {}", error)).into());
     }
 
     // Extract completion text with better error handling
-    response.get("choices")
-        .and_then(|choices| choices.get(0))
+    // Chat completions answer in `message.content`, legacy completions in `text`
+    let choice = response.get("choices").and_then(|choices| choices.get(0));
+    choice
         .and_then(|choice| choice.get("message"))
         .and_then(|message| message.get("content"))
+        .or_else(|| choice.and_then(|choice| choice.get("text")))
         .and_then(|content| content.as_str())
         .map(String::from)
         .ok_or_else(|| {
             // Debug log the response structure for troubleshooting
             eprintln!("Unexpected LLM API response structure: {:?}", response);
-            anyhow!("No completion found in LLM response. Check API endpoint and model configuration.")
+            StepError::new(
+                StepErrorKind::Validation,
+                "No completion found in LLM response. Check API endpoint and model configuration.",
+            )
+            .into()
         })
 }
 
 /// Loads steps for an agent by ID
 pub async fn load_agent_steps(pool: &PgPool, agent_id: i32) -> Result<Option<Value>> {
     let steps_query = format!(
-        "SELECT {} FROM (SELECT {}) subq",
-        steps_json_agg_sql("subq", "agent_id"),
-        agent_id
+        "SELECT {} FROM (SELECT $1::int4 AS id) subq",
+        steps_json_agg_sql("subq", "agent_id")?
     );
 
-    let steps_row = sqlx::query(&steps_query).fetch_one(pool).await?;
+    let steps_row = sqlx::query(&steps_query)
+        .bind(agent_id)
+        .fetch_one(pool)
+        .await?;
 
     let steps_json: Option<Value> = steps_row.get("steps");
     Ok(steps_json)
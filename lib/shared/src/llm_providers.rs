@@ -0,0 +1,179 @@
+use crate::models::steps::{StepError, StepErrorKind};
+use anyhow::{anyhow, Result};
+use reqwest::RequestBuilder;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use url::Url;
+
+/// Name of the provider built from `LLM_API_ENDPOINT` / `LLM_API_KEY`,
+/// used by Prompt steps that don't name a provider
+pub const DEFAULT_LLM_PROVIDER: &str = "default";
+
+/// How the API key is sent to the provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthHeaderStyle {
+    /// `Authorization: Bearer <key>` (OpenAI-compatible APIs)
+    #[default]
+    Bearer,
+    /// `x-api-key: <key>`
+    XApiKey,
+    /// `api-key: <key>` (Azure OpenAI)
+    ApiKey,
+}
+
+impl AuthHeaderStyle {
+    pub fn apply(&self, request: RequestBuilder, api_key: &str) -> RequestBuilder {
+        match self {
+            AuthHeaderStyle::Bearer => {
+                request.header("Authorization", format!("Bearer {}", api_key))
+            }
+            AuthHeaderStyle::XApiKey => request.header("x-api-key", api_key),
+            AuthHeaderStyle::ApiKey => request.header("api-key", api_key),
+        }
+    }
+}
+
+/// The request body shape the provider's endpoint expects
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LlmRequestFormat {
+    /// `messages: [{"role": "user", "content": ...}]` (OpenAI-compatible chat completions)
+    #[default]
+    Chat,
+    /// A single `prompt` string (legacy completions endpoints); a system prompt
+    /// is sent ahead of it in the same string
+    Completions,
+}
+
+impl FromStr for LlmRequestFormat {
+    type Err = StepError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chat" => Ok(LlmRequestFormat::Chat),
+            "completions" => Ok(LlmRequestFormat::Completions),
+            _ => Err(StepError::new(
+                StepErrorKind::Config,
+                format!("Invalid LLM_API_REQUEST_FORMAT: {}", s),
+            )),
+        }
+    }
+}
+
+/// Connection details for one LLM API
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LlmProvider {
+    pub endpoint: String,
+    pub api_key: String,
+    #[serde(default)]
+    pub auth_header_style: AuthHeaderStyle,
+    #[serde(default)]
+    pub request_format: LlmRequestFormat,
+}
+
+impl LlmProvider {
+    /// Checks that the endpoint is an absolute http(s) URL, so a misconfigured
+    /// provider fails with a `Config` error instead of network errors that get retried
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |reason: String| {
+            anyhow!(StepError::new(
+                StepErrorKind::Config,
+                format!("Invalid LLM endpoint '{}': {}", self.endpoint, reason),
+            ))
+        };
+        let url = Url::parse(self.endpoint.trim()).map_err(|e| invalid(e.to_string()))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(invalid(format!(
+                "unsupported scheme '{}', use http:// or https://",
+                url.scheme()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Named LLM providers that Prompt steps can route to
+#[derive(Debug, Clone, Default)]
+pub struct LlmProviderRegistry {
+    providers: HashMap<String, LlmProvider>,
+}
+
+impl LlmProviderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds the registry from the environment:
+    ///   - `LLM_API_ENDPOINT` + `LLM_API_KEY` become the `default` provider, with
+    ///     `LLM_API_REQUEST_FORMAT=completions` for a legacy completions endpoint
+    ///   - `LLM_PROVIDERS` optionally holds a JSON object of named providers, e.g.
+    ///     `{"premium": {"endpoint": "...", "api_key": "...", "auth_header_style": "x_api_key",
+    ///     "request_format": "completions"}}`
+    pub fn from_env() -> Result<Self> {
+        let mut registry = Self::new();
+
+        if let (Ok(endpoint), Ok(api_key)) = (env::var("LLM_API_ENDPOINT"), env::var("LLM_API_KEY"))
+        {
+            let request_format = match env::var("LLM_API_REQUEST_FORMAT") {
+                Ok(raw) => raw.parse()?,
+                Err(_) => LlmRequestFormat::default(),
+            };
+            registry.register(
+                DEFAULT_LLM_PROVIDER,
+                LlmProvider {
+                    endpoint,
+                    api_key,
+                    auth_header_style: AuthHeaderStyle::Bearer,
+                    request_format,
+                },
+            );
+        }
+
+        if let Ok(raw) = env::var("LLM_PROVIDERS") {
+            let named: HashMap<String, LlmProvider> = serde_json::from_str(&raw).map_err(|e| {
+                StepError::new(
+                    StepErrorKind::Config,
+                    format!("Invalid LLM_PROVIDERS: {}", e),
+                )
+            })?;
+            for (name, provider) in named {
+                registry.register(&name, provider);
+            }
+        }
+
+        Ok(registry)
+    }
+
+    /// Validates every provider's endpoint (see `LlmProvider::validate`), e.g. at
+    /// startup so a typo in the config surfaces before the first Prompt step runs
+    pub fn validate(&self) -> Result<()> {
+        let mut names: Vec<_> = self.providers.keys().collect();
+        names.sort();
+        for name in names {
+            self.providers[name]
+                .validate()
+                .map_err(|e| e.context(format!("LLM provider '{}' is misconfigured", name)))?;
+        }
+        Ok(())
+    }
+
+    pub fn register(&mut self, name: &str, provider: LlmProvider) {
+        self.providers.insert(name.to_string(), provider);
+    }
+
+    /// Looks up a provider by name, using the default provider when `name` is `None`
+    pub fn get(&self, name: Option<&str>) -> Result<&LlmProvider> {
+        let name = name.unwrap_or(DEFAULT_LLM_PROVIDER);
+        self.providers.get(name).ok_or_else(|| {
+            let message = if name == DEFAULT_LLM_PROVIDER {
+                "No default LLM provider: set the LLM_API_ENDPOINT and LLM_API_KEY environment variables".to_string()
+            } else {
+                format!("Unknown LLM provider: {}", name)
+            };
+            anyhow!(StepError::new(StepErrorKind::Config, message))
+        })
+    }
+}
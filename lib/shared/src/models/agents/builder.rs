@@ -0,0 +1,88 @@
+use super::types::{Agent, AgentConfig, AgentState};
+use crate::models::steps::{Step, StepType};
+use crate::{IdFields, TimestampFields};
+
+/// Puts an `Agent` together step by step, e.g.
+/// ```ignore
+/// let agent = Agent::builder()
+///     .description("Summarize a page")
+///     .webscrape_step("https://example.com")
+///     .prompt_step("Summarize {{content}}")
+///     .state(AgentState::Stable)
+///     .build();
+/// ```
+/// The agent and every step get fresh UUIDs and timestamps (and no database ids).
+/// Steps run in the order they're added; the agent starts `Inactive` unless `state` says otherwise.
+#[derive(Debug, Default)]
+pub struct AgentBuilder {
+    description: String,
+    steps: Vec<Step>,
+    state: AgentState,
+    config: AgentConfig,
+}
+
+impl AgentBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Adds a Python step running `code`, which sets `result` from `source`
+    pub fn python_step(self, code: impl Into<String>) -> Self {
+        self.step(Step::new(
+            IdFields::new(),
+            StepType::Python,
+            code.into(),
+            None,
+        ))
+    }
+
+    /// Adds a Prompt step on the agent's default model
+    pub fn prompt_step(self, prompt: impl Into<String>) -> Self {
+        self.step(Step::new_prompt(IdFields::new(), prompt.into(), None, None))
+    }
+
+    /// Adds a WebScrape step fetching `url`
+    pub fn webscrape_step(self, url: impl Into<String>) -> Self {
+        self.step(Step::new_webscrape(IdFields::new(), url.into(), None))
+    }
+
+    /// Adds a step built elsewhere, e.g. a Prompt step with its own model or provider
+    pub fn step(mut self, step: Step) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    pub fn state(mut self, state: AgentState) -> Self {
+        self.state = state;
+        self
+    }
+
+    pub fn config(mut self, config: AgentConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn build(self) -> Agent {
+        let mut agent = Agent::new(
+            IdFields::new(),
+            TimestampFields::new(),
+            self.description,
+            self.steps,
+        );
+        agent.config = self.config;
+        agent.set_state(self.state);
+        agent
+    }
+}
+
+impl Agent {
+    /// Starts an `AgentBuilder`
+    pub fn builder() -> AgentBuilder {
+        AgentBuilder::new()
+    }
+}
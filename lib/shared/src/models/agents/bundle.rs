@@ -0,0 +1,160 @@
+use super::types::{Agent, AgentConfig};
+use crate::models::steps::{LoopConfig, Step, StepType};
+use crate::{duration_to_json, IdFields, JsonLike, JsonModeLLMs, TimestampFields};
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+
+/// Version written by `export_bundle`; `import_bundle` rejects newer bundles
+pub const AGENT_BUNDLE_VERSION: u64 = 1;
+
+impl Agent {
+    /// Exports the agent definition (description, config, steps) as a portable bundle.
+    /// Database ids, UUIDs, timestamps and state are left out so the bundle
+    /// can be imported into any environment.
+    pub fn export_bundle(&self) -> Value {
+        json!({
+            "bundle_version": AGENT_BUNDLE_VERSION,
+            "agent": {
+                "description": self.description,
+                "config": self.config,
+                "steps": self
+                    .steps
+                    .iter()
+                    .map(|step| step_bundle(step, &self.steps))
+                    .collect::<Vec<Value>>(),
+            },
+        })
+    }
+
+    /// Rebuilds an agent from `export_bundle` output. The agent and its steps
+    /// get fresh UUIDs and start Inactive, ready to be saved with `try_db_create`.
+    pub fn import_bundle(obj: Value) -> Result<Agent> {
+        let version = obj
+            .get("bundle_version")
+            .and_then(|v| v.as_u64())
+            .ok_or_else(|| anyhow!("Agent bundle is missing `bundle_version`"))?;
+        if version == 0 || version > AGENT_BUNDLE_VERSION {
+            return Err(anyhow!(
+                "Unsupported agent bundle version: {} (supported: 1 to {})",
+                version,
+                AGENT_BUNDLE_VERSION
+            ));
+        }
+
+        let agent = obj
+            .get("agent")
+            .and_then(|v| v.as_object())
+            .ok_or_else(|| anyhow!("Agent bundle is missing the `agent` object"))?;
+
+        let description = agent
+            .get("description")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+
+        let config = match agent.get("config") {
+            None | Some(Value::Null) => AgentConfig::default(),
+            Some(raw) => serde_json::from_value(raw.clone())
+                .map_err(|e| anyhow!("Invalid agent config in bundle: {}", e))?,
+        };
+
+        let raw_steps = agent
+            .get("steps")
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| anyhow!("Agent bundle is missing the `steps` array"))?;
+        let mut steps = raw_steps
+            .iter()
+            .enumerate()
+            .map(|(idx, raw)| {
+                import_step(raw).map_err(|e| anyhow!("Invalid step {} in bundle: {}", idx, e))
+            })
+            .collect::<Result<Vec<Step>>>()?;
+
+        // Error handlers are exported by position, as the steps' UUIDs are new
+        for (idx, raw) in raw_steps.iter().enumerate() {
+            let Some(handler) = raw.get("on_error").and_then(|v| v.as_u64()) else {
+                continue;
+            };
+            let handler_uuid = steps
+                .get(handler as usize)
+                .map(|handler| handler.identifiers.global_uuid.clone())
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Invalid step {} in bundle: no step {} for on_error",
+                        idx,
+                        handler
+                    )
+                })?;
+            steps[idx].on_error = Some(handler_uuid);
+        }
+
+        let mut imported = Agent::new(IdFields::new(), TimestampFields::new(), description, steps);
+        imported.config = config;
+        Ok(imported)
+    }
+}
+
+fn step_bundle(step: &Step, steps: &[Step]) -> Value {
+    let mut bundle = json!({
+        "description": step.description,
+        "step_type": step.step_type.as_str(),
+        "step_content": step.step_content,
+    });
+
+    if let Some(mapping) = &step.input_mapping {
+        bundle["input_mapping"] = json!(mapping);
+    }
+
+    if step.cacheable {
+        bundle["cacheable"] = json!(true);
+    }
+
+    if let Some(handler) = &step.on_error {
+        let position = steps
+            .iter()
+            .position(|other| &other.identifiers.global_uuid == handler);
+        bundle["on_error"] = json!(position);
+    }
+
+    if step.wrap_output {
+        bundle["wrap_output"] = json!(true);
+    }
+
+    if let Some(expected) = step.expected_duration {
+        bundle["expected_duration"] = duration_to_json(expected);
+    }
+
+    if let StepType::Prompt(model) = &step.step_type {
+        bundle["llm_model"] = json!(model);
+        bundle["llm_provider"] = json!(step.llm_provider);
+        bundle["system_prompt"] = json!(step.system_prompt);
+    }
+
+    bundle
+}
+
+fn import_step(raw: &Value) -> Result<Step> {
+    if !raw.is_object() {
+        return Err(anyhow!("Step must be a JSON object"));
+    }
+
+    // Drop any ids so the step gets a fresh UUID
+    let mut raw = raw.clone();
+    for key in ["id", "global_uuid", "created_at", "updated_at"] {
+        raw[key] = Value::Null;
+    }
+    let step = Step::from_json(raw)?;
+
+    match &step.step_type {
+        // Named providers serve their own models, so only default-provider models are checked
+        StepType::Prompt(Some(model)) if step.llm_provider.is_none() => {
+            model.parse::<JsonModeLLMs>()?;
+        }
+        StepType::Loop => {
+            LoopConfig::from_step(&step)?;
+        }
+        _ => {}
+    }
+
+    Ok(step)
+}
@@ -0,0 +1,133 @@
+use super::types::Agent;
+use crate::{db_metrics, DatabaseItem};
+use anyhow::Result;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+/// What `Agent::try_db_delete_cascade` does with the signals sent to the agent and
+/// the runtime sessions it requested
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CascadeMode {
+    /// Delete them along with the agent
+    #[default]
+    Delete,
+    /// Keep them for the record, with their reference to the agent set to NULL
+    Detach,
+}
+
+/// Rows deleted or detached by `Agent::try_db_delete_cascade` (or that would be, in
+/// a dry run)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CascadeCounts {
+    pub signals: u64,
+    pub runtime_sessions: u64,
+    pub steps: u64,
+    /// 0 if the agent had no row
+    pub agents: u64,
+}
+
+impl Agent {
+    /// Deletes the agent and its steps, and deletes or detaches (see `CascadeMode`)
+    /// its signals and runtime sessions, all in one transaction so no row is left
+    /// pointing at a missing agent. With `dry_run` the same statements run and are
+    /// rolled back, so the counts say what a real run would affect.
+    pub async fn try_db_delete_cascade(
+        &self,
+        pool: &PgPool,
+        mode: CascadeMode,
+        dry_run: bool,
+    ) -> Result<CascadeCounts> {
+        db_metrics::instrument(Self::MODEL, "delete_cascade", async {
+            let mut tx = pool.begin().await?;
+            let counts = self.delete_cascade_in(&mut tx, mode).await?;
+            if dry_run {
+                tx.rollback().await?;
+            } else {
+                tx.commit().await?;
+            }
+            Ok(counts)
+        })
+        .await
+    }
+
+    async fn delete_cascade_in(
+        &self,
+        conn: &mut PgConnection,
+        mode: CascadeMode,
+    ) -> Result<CascadeCounts> {
+        let uuid_parsed = Uuid::parse_str(&self.identifiers.global_uuid)?;
+        let agent_id: Option<i32> =
+            sqlx::query_scalar("SELECT id FROM agents WHERE global_uuid = $1")
+                .bind(uuid_parsed)
+                .fetch_optional(&mut *conn)
+                .await?;
+        let Some(agent_id) = agent_id else {
+            return Ok(CascadeCounts::default());
+        };
+
+        let (signals, runtime_sessions) = match mode {
+            CascadeMode::Delete => {
+                let signals = sqlx::query("DELETE FROM signals WHERE agent_id = $1")
+                    .bind(agent_id)
+                    .execute(&mut *conn)
+                    .await?
+                    .rows_affected();
+                // Other agents' signals may be linked to the sessions; they only lose the link
+                sqlx::query(
+                    r#"
+                    UPDATE signals SET rts_id = NULL
+                    WHERE rts_id IN (
+                        SELECT id FROM runtime_sessions WHERE requested_by_agent_id = $1
+                    )
+                    "#,
+                )
+                .bind(agent_id)
+                .execute(&mut *conn)
+                .await?;
+                let runtime_sessions =
+                    sqlx::query("DELETE FROM runtime_sessions WHERE requested_by_agent_id = $1")
+                        .bind(agent_id)
+                        .execute(&mut *conn)
+                        .await?
+                        .rows_affected();
+                (signals, runtime_sessions)
+            }
+            CascadeMode::Detach => {
+                let signals = sqlx::query("UPDATE signals SET agent_id = NULL WHERE agent_id = $1")
+                    .bind(agent_id)
+                    .execute(&mut *conn)
+                    .await?
+                    .rows_affected();
+                let runtime_sessions = sqlx::query(
+                    r#"
+                    UPDATE runtime_sessions SET requested_by_agent_id = NULL
+                    WHERE requested_by_agent_id = $1
+                    "#,
+                )
+                .bind(agent_id)
+                .execute(&mut *conn)
+                .await?
+                .rows_affected();
+                (signals, runtime_sessions)
+            }
+        };
+
+        let steps = sqlx::query("DELETE FROM steps WHERE agent_id = $1")
+            .bind(agent_id)
+            .execute(&mut *conn)
+            .await?
+            .rows_affected();
+        let agents = sqlx::query("DELETE FROM agents WHERE id = $1")
+            .bind(agent_id)
+            .execute(&mut *conn)
+            .await?
+            .rows_affected();
+
+        Ok(CascadeCounts {
+            signals,
+            runtime_sessions,
+            steps,
+            agents,
+        })
+    }
+}
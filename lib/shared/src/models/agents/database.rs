@@ -1,11 +1,11 @@
-use super::types::{Agent, AgentState};
-use crate::models::steps::Step;
+use super::types::{Agent, AgentConfig, AgentState};
+use crate::models::steps::{aggregated_steps_json, Step};
 use crate::{DatabaseItem, IdFields, JsonLike, TimestampFields};
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use serde_json::Value;
-use sqlx::types::JsonValue;
-use sqlx::{PgPool, Row};
+use sqlx::{FromRow, PgConnection, PgPool, Row};
+use std::collections::HashMap;
 use std::str::FromStr;
 use uuid::Uuid;
 
@@ -21,8 +21,13 @@ impl sqlx::FromRow<'_, sqlx::postgres::PgRow> for Agent {
         let agent_state: AgentState = row.try_get("agent_state")?;
 
         // Parse steps - each raw JSON will look like a `json_build_object` result
-        let steps_json: Value = row.try_get("steps")?;
-        let steps = Step::from_json_array(&steps_json);
+        let steps_json = aggregated_steps_json(row)?;
+        let steps = strict_steps(&steps_json, &global_uuid.to_string())
+            .map_err(|e| sqlx::Error::Decode(e.into()))?;
+
+        // Config is optional so older rows (or queries without it) still load
+        let config_json: Option<Value> = row.try_get("config").unwrap_or_default();
+        let env_json: Option<Value> = row.try_get("env").unwrap_or_default();
 
         Ok(Self {
             identifiers: IdFields {
@@ -36,6 +41,9 @@ impl sqlx::FromRow<'_, sqlx::postgres::PgRow> for Agent {
             description,
             agent_state: std::sync::Mutex::new(agent_state),
             steps,
+            config: AgentConfig::from_value(config_json.as_ref()),
+            env: env_from_value(env_json.as_ref()),
+            runtime_cache: Default::default(),
         })
     }
 }
@@ -50,6 +58,7 @@ impl JsonLike for Agent {
             "description": self.description,
             "agent_state": self.state(),
             "steps": self.steps.iter().map(|step| step.to_json()).collect::<Vec<Value>>(),
+            "config": self.config,
         })
     }
 
@@ -66,7 +75,7 @@ impl JsonLike for Agent {
                 },
                 timestamps: TimestampFields {
                     created: chrono::DateTime::parse_from_str(
-                        &obj.get("created_at")
+                        obj.get("created_at")
                             .and_then(|v| v.as_str())
                             .unwrap_or_default(),
                         "%Y-%m-%d %H:%M:%S %z",
@@ -74,7 +83,7 @@ impl JsonLike for Agent {
                     .unwrap_or_default()
                     .with_timezone(&chrono::Utc),
                     updated: chrono::DateTime::parse_from_str(
-                        &obj.get("updated_at")
+                        obj.get("updated_at")
                             .and_then(|v| v.as_str())
                             .unwrap_or_default(),
                         "%Y-%m-%d %H:%M:%S %z",
@@ -102,6 +111,9 @@ impl JsonLike for Agent {
                             .collect()
                     })
                     .unwrap_or_default(),
+                config: AgentConfig::from_value(obj.get("config")),
+                env: env_from_value(obj.get("env")),
+                runtime_cache: Default::default(),
             })
         } else {
             Err(anyhow!("Expected JSON object"))
@@ -109,53 +121,124 @@ impl JsonLike for Agent {
     }
 }
 
-#[async_trait]
-impl DatabaseItem for Agent {
-    type IdType = i32;
-
-    fn id(&self) -> &IdFields<Self::IdType> {
-        &self.identifiers
+impl Agent {
+    /// Inserts the agent, or updates its row if one with the same `global_uuid`
+    /// exists. Steps are only inserted along with a new agent. Returns the agent's `id`.
+    pub async fn try_db_upsert(&self, pool: &PgPool) -> Result<i32> {
+        let mut tx = pool.begin().await?;
+        let agent_id = self.upsert_in(&mut tx).await?;
+        tx.commit().await?;
+        Ok(agent_id)
     }
 
-    async fn try_db_create(&self, pool: &PgPool) -> Result<()> {
-        // Check if an agent with the same UUID already exists
-        if crate::check_exists_by_uuid(pool, "agents", &self.identifiers.global_uuid).await? {
-            return Ok(()); // Agent already exists, no need to create it again
-        }
-
+    /// `try_db_upsert` on an open connection, so it can share a transaction
+    pub(crate) async fn upsert_in(&self, conn: &mut PgConnection) -> Result<i32> {
         let uuid_parsed = Uuid::parse_str(&self.identifiers.global_uuid)?;
-        let agent_state = self.state(); // Get the current state
+        let config = serde_json::to_value(&self.config)?;
+        let env = serde_json::to_value(&self.env)?;
 
-        // Use query_scalar! for inserting the agent and returning the ID
-        let agent_id = sqlx::query_scalar!(
+        // `xmax` is only 0 for a row this statement inserted
+        let (agent_id, inserted) = sqlx::query_as::<_, (i32, bool)>(
             r#"
             INSERT INTO agents (
-                global_uuid, description, agent_state, created_at, updated_at
+                global_uuid, description, agent_state, config, env, created_at, updated_at
             )
-            VALUES ($1, $2, $3::agent_state, $4, $5)
-            RETURNING id
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (global_uuid) DO UPDATE SET
+                description = EXCLUDED.description,
+                agent_state = EXCLUDED.agent_state,
+                config = EXCLUDED.config,
+                env = EXCLUDED.env,
+                updated_at = EXCLUDED.updated_at
+            RETURNING id, (xmax = 0)
             "#,
-            uuid_parsed,
-            &self.description,
-            agent_state as AgentState,
-            &self.timestamps.created,
-            &self.timestamps.updated
         )
-        .fetch_one(pool)
+        .bind(uuid_parsed)
+        .bind(&self.description)
+        .bind(self.state())
+        .bind(config)
+        .bind(env)
+        .bind(self.timestamps.created)
+        .bind(self.timestamps.updated)
+        .fetch_one(&mut *conn)
         .await?;
 
-        // Then create step records if any exist
+        if inserted {
+            self.upsert_steps(conn, agent_id).await?;
+        }
+
+        Ok(agent_id)
+    }
+
+    /// Deletes the agent with this id (its `local_id` if set, otherwise its UUID) and
+    /// its steps, without loading it, so an agent whose steps no longer parse can
+    /// still be removed. Returns whether there was an agent to delete.
+    pub async fn try_db_delete_by_id(pool: &PgPool, id: &IdFields) -> Result<bool> {
+        crate::db_metrics::instrument(Self::MODEL, "delete", async {
+            let mut tx = pool.begin().await?;
+            let agent_id = match id.local_id {
+                Some(local_id) => Some(local_id),
+                None => {
+                    sqlx::query_scalar::<_, i32>("SELECT id FROM agents WHERE global_uuid = $1")
+                        .bind(Uuid::parse_str(&id.global_uuid)?)
+                        .fetch_optional(&mut *tx)
+                        .await?
+                }
+            };
+            let Some(agent_id) = agent_id else {
+                return Ok(false);
+            };
+
+            sqlx::query("DELETE FROM steps WHERE agent_id = $1")
+                .bind(agent_id)
+                .execute(&mut *tx)
+                .await?;
+            let deleted = sqlx::query("DELETE FROM agents WHERE id = $1")
+                .bind(agent_id)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+            tx.commit().await?;
+
+            Ok(deleted > 0)
+        })
+        .await
+    }
+
+    /// Inserts the agent's steps under `agent_id`, updating any that already exist
+    async fn upsert_steps(&self, conn: &mut PgConnection, agent_id: i32) -> Result<()> {
         for step in self.steps.iter() {
             let step_uuid = Uuid::parse_str(&step.identifiers.global_uuid)?;
             let step_type_str = step.step_type.as_str();
 
+            // The model (and provider / system prompt) only apply to Prompt steps
+            let llm_model = step.step_type.get_llm_model();
+            let is_prompt = step.is_prompt_step();
+            let llm_provider = step.llm_provider.as_ref().filter(|_| is_prompt);
+            let system_prompt = step.system_prompt.as_ref().filter(|_| is_prompt);
+
             sqlx::query!(
                 r#"
                 INSERT INTO steps (
                     global_uuid, agent_id, description,
-                    step_type, step_content, created_at, updated_at
+                    step_type, step_content, created_at, updated_at, input_mapping,
+                    llm_model, llm_provider, system_prompt, cacheable,
+                    expected_duration, wrap_output, on_error
                 )
-                VALUES ($1, $2, $3, ($4::text)::step_type, $5, $6, $7)
+                VALUES ($1, $2, $3, ($4::text)::step_type, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+                ON CONFLICT (global_uuid) DO UPDATE SET
+                    description = EXCLUDED.description,
+                    step_type = EXCLUDED.step_type,
+                    step_content = EXCLUDED.step_content,
+                    updated_at = EXCLUDED.updated_at,
+                    input_mapping = EXCLUDED.input_mapping,
+                    llm_model = EXCLUDED.llm_model,
+                    llm_provider = EXCLUDED.llm_provider,
+                    system_prompt = EXCLUDED.system_prompt,
+                    cacheable = EXCLUDED.cacheable,
+                    expected_duration = EXCLUDED.expected_duration,
+                    wrap_output = EXCLUDED.wrap_output,
+                    on_error = EXCLUDED.on_error
                 "#,
                 step_uuid,
                 agent_id,
@@ -163,29 +246,98 @@ impl DatabaseItem for Agent {
                 step_type_str,
                 &step.step_content,
                 &step.timestamps.created,
-                &step.timestamps.updated
+                &step.timestamps.updated,
+                step.input_mapping.as_ref().map(|mapping| serde_json::json!(mapping)),
+                llm_model,
+                llm_provider,
+                system_prompt,
+                step.cacheable,
+                step.expected_duration.map(|expected| expected.as_secs_f64()),
+                step.wrap_output,
+                step.on_error
             )
-            .execute(pool)
+            .execute(&mut *conn)
             .await?;
         }
 
         Ok(())
     }
+}
+
+#[async_trait]
+impl DatabaseItem for Agent {
+    type IdType = i32;
+    const MODEL: &'static str = "agent";
+
+    fn id(&self) -> &IdFields<Self::IdType> {
+        &self.identifiers
+    }
 
-    async fn try_db_update(&self, pool: &PgPool) -> Result<()> {
+    async fn db_create(&self, pool: &PgPool) -> Result<()> {
+        let uuid_parsed = Uuid::parse_str(&self.identifiers.global_uuid)?;
+
+        // An agent that already exists is left as-is, but its steps are still saved
+        let existing =
+            sqlx::query_scalar::<_, i32>("SELECT id FROM agents WHERE global_uuid = $1")
+                .bind(uuid_parsed)
+                .fetch_optional(pool)
+                .await?;
+        if let Some(agent_id) = existing {
+            let mut conn = pool.acquire().await?;
+            return self.upsert_steps(&mut conn, agent_id).await;
+        }
+
+        let agent_state = self.state(); // Get the current state
+        let config = serde_json::to_value(&self.config)?;
+        let env = serde_json::to_value(&self.env)?;
+
+        // Use query_scalar! for inserting the agent and returning the ID
+        let agent_id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO agents (
+                global_uuid, description, agent_state, config, env, created_at, updated_at
+            )
+            VALUES ($1, $2, $3::agent_state, $4, $5, $6, $7)
+            RETURNING id
+            "#,
+            uuid_parsed,
+            &self.description,
+            agent_state as AgentState,
+            config,
+            env,
+            &self.timestamps.created,
+            &self.timestamps.updated
+        )
+        .fetch_one(pool)
+        .await?;
+
+        // Then create step records if any exist
+        let mut conn = pool.acquire().await?;
+        self.upsert_steps(&mut conn, agent_id).await?;
+
+        Ok(())
+    }
+
+    async fn db_update(&self, pool: &PgPool) -> Result<()> {
         let uuid_parsed = Uuid::parse_str(&self.identifiers.global_uuid)?;
         let agent_state = self.state();
+        let config = serde_json::to_value(&self.config)?;
+        let env = serde_json::to_value(&self.env)?;
 
         sqlx::query!(
             r#"
             UPDATE agents
             SET description = $1,
                 agent_state = $2::agent_state,
-                updated_at = $3
-            WHERE global_uuid = $4
+                config = $3,
+                env = $4,
+                updated_at = $5
+            WHERE global_uuid = $6
             "#,
             &self.description,
             agent_state as AgentState,
+            config,
+            env,
             &self.timestamps.updated,
             uuid_parsed
         )
@@ -195,7 +347,7 @@ impl DatabaseItem for Agent {
         Ok(())
     }
 
-    async fn try_db_delete(&self, pool: &PgPool) -> Result<()> {
+    async fn db_delete(&self, pool: &PgPool) -> Result<()> {
         if let Some(id) = self.identifiers.local_id {
             sqlx::query!("DELETE FROM steps WHERE agent_id = $1", id)
                 .execute(pool)
@@ -210,64 +362,17 @@ impl DatabaseItem for Agent {
         Ok(())
     }
 
-    async fn try_db_select_all(pool: &PgPool) -> Result<Vec<Self>> {
-        struct AgentRow {
-            id: i32,
-            global_uuid: uuid::Uuid,
-            description: Option<String>,
-            agent_state: AgentState,
-            created_at: chrono::DateTime<chrono::Utc>,
-            updated_at: chrono::DateTime<chrono::Utc>,
-            steps: serde_json::Value,
-        }
-
-        let rows = sqlx::query_as!(
-            AgentRow,
-            r#"
-            SELECT
-                a.id, a.global_uuid, a.description,
-                a.agent_state as "agent_state: _",
-                a.created_at, a.updated_at,
-                COALESCE(
-                    (
-                        SELECT json_agg(json_build_object(
-                            'id', s.id,
-                            'global_uuid', s.global_uuid,
-                            'created_at', s.created_at,
-                            'updated_at', s.updated_at,
-                            'agent_id', s.agent_id,
-                            'description', s.description,
-                            'step_type', s.step_type::text,
-                            'step_content', s.step_content
-                        ))
-                        FROM steps s
-                        WHERE s.agent_id = a.id
-                    ),
-                    '[]'::json
-                ) as "steps: JsonValue"
-            FROM agents a
-            "#
-        )
-        .fetch_all(pool)
-        .await?;
+    async fn db_select_all(pool: &PgPool) -> Result<Vec<Self>> {
+        let rows = sqlx::query(&select_agents_sql("")?).fetch_all(pool).await?;
 
+        // One agent with malformed steps shouldn't keep all the others from loading
         let agents = rows
-            .into_iter()
-            .map(|row| {
-                let steps = Step::from_json_array(&row.steps);
-
-                Agent {
-                    identifiers: IdFields {
-                        local_id: Some(row.id),
-                        global_uuid: row.global_uuid.to_string(),
-                    },
-                    timestamps: TimestampFields {
-                        created: row.created_at,
-                        updated: row.updated_at,
-                    },
-                    description: row.description.unwrap_or_default(),
-                    agent_state: std::sync::Mutex::new(row.agent_state),
-                    steps,
+            .iter()
+            .filter_map(|row| match Agent::from_row(row) {
+                Ok(agent) => Some(agent),
+                Err(e) => {
+                    eprintln!("Skipping agent that failed to load: {}", e);
+                    None
                 }
             })
             .collect();
@@ -275,103 +380,80 @@ impl DatabaseItem for Agent {
         Ok(agents)
     }
 
-    async fn try_db_select_by_id(
+    async fn db_select_by_id(
         pool: &PgPool,
         id: &IdFields<Self::IdType>,
     ) -> Result<Option<Self>> {
-        struct AgentRow {
-            id: i32,
-            global_uuid: uuid::Uuid,
-            description: Option<String>,
-            agent_state: AgentState,
-            created_at: chrono::DateTime<chrono::Utc>,
-            updated_at: chrono::DateTime<chrono::Utc>,
-            steps: serde_json::Value,
-        }
-
-        let row_opt = if let Some(local_id) = id.local_id {
-            sqlx::query_as!(
-                AgentRow,
-                r#"
-                SELECT
-                    a.id, a.global_uuid, a.description,
-                    a.agent_state as "agent_state: _",
-                    a.created_at, a.updated_at,
-                    COALESCE(
-                        (
-                            SELECT json_agg(json_build_object(
-                                'id', s.id,
-                                'global_uuid', s.global_uuid,
-                                'created_at', s.created_at,
-                                'updated_at', s.updated_at,
-                                'agent_id', s.agent_id,
-                                'description', s.description,
-                                'step_type', s.step_type::text,
-                                'step_content', s.step_content
-                            ))
-                            FROM steps s
-                            WHERE s.agent_id = a.id
-                        ),
-                        '[]'::json
-                    ) as "steps: JsonValue"
-                FROM agents a
-                WHERE a.id = $1
-                "#,
-                local_id
-            )
-            .fetch_optional(pool)
-            .await?
+        let agent = if let Some(local_id) = id.local_id {
+            sqlx::query_as::<_, Agent>(&select_agents_sql("WHERE a.id = $1")?)
+                .bind(local_id)
+                .fetch_optional(pool)
+                .await?
         } else {
             let uuid_parsed = Uuid::parse_str(&id.global_uuid)?;
-            sqlx::query_as!(
-                AgentRow,
-                r#"
-                SELECT
-                    a.id, a.global_uuid, a.description,
-                    a.agent_state as "agent_state: _",
-                    a.created_at, a.updated_at,
-                    COALESCE(
-                        (
-                            SELECT json_agg(json_build_object(
-                                'id', s.id,
-                                'global_uuid', s.global_uuid,
-                                'created_at', s.created_at,
-                                'updated_at', s.updated_at,
-                                'agent_id', s.agent_id,
-                                'description', s.description,
-                                'step_type', s.step_type::text,
-                                'step_content', s.step_content
-                            ))
-                            FROM steps s
-                            WHERE s.agent_id = a.id
-                        ),
-                        '[]'::json
-                    ) as "steps: JsonValue"
-                FROM agents a
-                WHERE a.global_uuid = $1
-                "#,
-                uuid_parsed
-            )
-            .fetch_optional(pool)
-            .await?
+            sqlx::query_as::<_, Agent>(&select_agents_sql("WHERE a.global_uuid = $1")?)
+                .bind(uuid_parsed)
+                .fetch_optional(pool)
+                .await?
         };
 
-        Ok(row_opt.map(|row| {
-            let steps = Step::from_json_array(&row.steps);
+        Ok(agent)
+    }
 
-            Agent {
-                identifiers: IdFields {
-                    local_id: Some(row.id),
-                    global_uuid: row.global_uuid.to_string(),
-                },
-                timestamps: TimestampFields {
-                    created: row.created_at,
-                    updated: row.updated_at,
-                },
-                description: row.description.unwrap_or_default(),
-                agent_state: std::sync::Mutex::new(row.agent_state),
-                steps,
-            }
-        }))
+    async fn db_select_by_ids(pool: &PgPool, ids: &[Self::IdType]) -> Result<Vec<Self>> {
+        let agents =
+            sqlx::query_as::<_, Agent>(&select_agents_sql("WHERE a.id = ANY($1) ORDER BY a.id")?)
+                .bind(ids)
+                .fetch_all(pool)
+                .await?;
+
+        Ok(agents)
     }
+
+    async fn db_select_by_uuids(pool: &PgPool, uuids: &[String]) -> Result<Vec<Self>> {
+        let agents = sqlx::query_as::<_, Agent>(&select_agents_sql(
+            "WHERE a.global_uuid = ANY($1) ORDER BY a.id",
+        )?)
+        .bind(crate::parse_uuids(uuids)?)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(agents)
+    }
+}
+
+/// Query selecting the columns `Agent::from_row` reads, with each agent's steps
+fn select_agents_sql(where_clause: &str) -> Result<String> {
+    Ok(format!(
+        r#"
+        SELECT
+            a.id, a.global_uuid, a.description, a.agent_state,
+            a.config, a.env, a.created_at, a.updated_at,
+            {}
+        FROM agents a
+        {}
+        "#,
+        crate::steps_json_agg_sql("a", "agent_id")?,
+        where_clause
+    ))
+}
+
+/// Parse an agent's aggregated steps, failing if any step is malformed: silently
+/// dropping one would make the agent run a different pipeline than configured
+fn strict_steps(steps_json: &Value, agent_uuid: &str) -> Result<Vec<Step>> {
+    Step::from_json_array_strict(steps_json).map_err(|errors| {
+        anyhow!(
+            "Agent {} has malformed steps: {}",
+            agent_uuid,
+            Step::describe_json_array_errors(&errors)
+        )
+    })
+}
+
+/// Parse stored agent env vars, ignoring a missing or malformed value
+fn env_from_value(value: Option<&Value>) -> HashMap<String, String> {
+    value
+        .filter(|v| !v.is_null())
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+        .unwrap_or_default()
 }
@@ -1,6 +1,15 @@
+mod builder;
+mod bundle;
+mod cascade;
 mod database;
+mod rate_limit;
 mod runtime;
 mod state;
 mod types;
 
-pub use types::{Agent, AgentState};
+pub use builder::AgentBuilder;
+pub use bundle::AGENT_BUNDLE_VERSION;
+pub use cascade::{CascadeCounts, CascadeMode};
+pub use rate_limit::RateLimiter;
+pub use runtime::PreparedSteps;
+pub use types::{Agent, AgentConfig, AgentState, RateLimitConfig, RetryConfig};
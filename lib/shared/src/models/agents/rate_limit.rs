@@ -0,0 +1,56 @@
+use super::types::RateLimitConfig;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Token-bucket rate limiter used by agent workers to pace runs.
+/// The bucket starts full, so the first `burst` acquisitions don't wait.
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        let capacity = config.burst.max(1) as f64;
+        Self {
+            config,
+            tokens: capacity,
+            last_refill: Instant::now(),
+        }
+    }
+
+    pub fn config(&self) -> &RateLimitConfig {
+        &self.config
+    }
+
+    /// Wait until a token is available, then consume it.
+    /// A non-positive `requests_per_second` disables limiting.
+    pub async fn acquire(&mut self) {
+        let rate = self.config.requests_per_second;
+        if rate <= 0.0 {
+            return;
+        }
+
+        loop {
+            self.refill(rate);
+            if self.tokens >= 1.0 {
+                self.tokens -= 1.0;
+                return;
+            }
+
+            // Sleep just long enough for the next token to accrue
+            let missing = 1.0 - self.tokens;
+            tokio::time::sleep(Duration::from_secs_f64(missing / rate)).await;
+        }
+    }
+
+    fn refill(&mut self, rate: f64) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        let capacity = self.config.burst.max(1) as f64;
+        self.tokens = (self.tokens + elapsed * rate).min(capacity);
+        self.last_refill = now;
+    }
+}
@@ -1,18 +1,73 @@
 use super::types::Agent;
 use crate::models::agents::AgentState;
-use crate::models::runtime_sessions::RuntimeSession;
-use crate::PythonRuntime;
+use crate::models::runtime_sessions::{RunContext, RuntimeSession};
+use crate::models::steps::{Step, StepType};
+use crate::{PythonRuntime, RunningStatus};
 use anyhow::{anyhow, Result};
 use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// The agent's prepared `PythonRuntime`, kept between runs so step functions
+/// aren't recompiled for every signal. It's rebuilt once the steps or env change.
+#[derive(Default)]
+pub(crate) struct RuntimeCache {
+    /// Fingerprint of the steps / env the runtime was built from, and the runtime.
+    /// A run takes it out and puts it back afterwards, so concurrent runs never
+    /// share a runtime (a second run meanwhile builds its own).
+    prepared: Mutex<Option<(u64, PythonRuntime)>>,
+    /// Number of runtimes built for the agent
+    builds: AtomicUsize,
+}
+
+impl RuntimeCache {
+    pub(crate) fn builds(&self) -> usize {
+        self.builds.load(Ordering::SeqCst)
+    }
+}
+
+impl std::fmt::Debug for RuntimeCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RuntimeCache")
+            .field("builds", &self.builds())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Replacement steps checked by `Agent::prepare_steps`, along with the Python
+/// runtime built for them
+pub struct PreparedSteps {
+    steps: Vec<Step>,
+    fingerprint: u64,
+    runtime: PythonRuntime,
+}
+
+impl std::fmt::Debug for PreparedSteps {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PreparedSteps")
+            .field("steps", &self.steps)
+            .finish_non_exhaustive()
+    }
+}
 
 impl Agent {
     /// Create a Python runtime for this agent
     pub fn create_python_runtime(&self) -> Result<PythonRuntime> {
+        self.python_runtime_for(&self.steps)
+    }
+
+    fn python_runtime_for(&self, steps: &[Step]) -> Result<PythonRuntime> {
         let mut runtime = PythonRuntime::new(&self.identifiers.global_uuid)?;
+        runtime.set_env(&self.env)?;
 
         // Add all Python steps
-        for step in &self.steps {
-            if step.is_python_step() {
+        for step in steps {
+            if step.requires_runtime() {
                 runtime.add_step(step)?;
             }
         }
@@ -20,29 +75,224 @@ impl Agent {
         Ok(runtime)
     }
 
+    /// Dry run for replacing the agent's steps with `steps`: builds their Python
+    /// runtime (so Python steps that don't compile are an error) without touching
+    /// the agent. Install them with `set_steps`.
+    pub fn prepare_steps(&self, steps: Vec<Step>) -> Result<PreparedSteps> {
+        let mut uuids = HashSet::new();
+        if let Some(step) = steps
+            .iter()
+            .find(|step| !uuids.insert(&step.identifiers.global_uuid))
+        {
+            return Err(anyhow!(
+                "Step {} appears more than once",
+                step.identifiers.global_uuid
+            ));
+        }
+        let runtime = self.python_runtime_for(&steps)?;
+        self.runtime_cache.builds.fetch_add(1, Ordering::SeqCst);
+        Ok(PreparedSteps {
+            fingerprint: self.fingerprint_for(&steps),
+            steps,
+            runtime,
+        })
+    }
+
+    /// Swaps in steps from `prepare_steps`. Their runtime replaces the prepared
+    /// one, so the next run uses the new steps without building anything.
+    pub fn set_steps(&mut self, prepared: PreparedSteps) {
+        self.steps = prepared.steps;
+        self.timestamps.update();
+        self.checkin_runtime(prepared.fingerprint, prepared.runtime);
+    }
+
+    /// `prepare_steps` and `set_steps` in one go, for callers that don't need to
+    /// build the runtime before taking the agent mutably
+    pub fn replace_steps(&mut self, steps: Vec<Step>) -> Result<()> {
+        let prepared = self.prepare_steps(steps)?;
+        self.set_steps(prepared);
+        Ok(())
+    }
+
+    /// Appends a step, as `replace_steps` would
+    pub fn add_step(&mut self, step: Step) -> Result<()> {
+        let mut steps = self.steps.clone();
+        steps.push(step);
+        self.replace_steps(steps)
+    }
+
+    /// Takes out the step with `step_uuid`, as `replace_steps` would
+    pub fn remove_step(&mut self, step_uuid: &str) -> Result<Step> {
+        let mut steps = self.steps.clone();
+        let idx = steps
+            .iter()
+            .position(|step| step.identifiers.global_uuid == step_uuid)
+            .ok_or_else(|| anyhow!("Step {} not found", step_uuid))?;
+        let removed = steps.remove(idx);
+        self.replace_steps(steps)?;
+        Ok(removed)
+    }
+
+    /// The steps for editing in place (e.g. reordering). Marks the agent updated;
+    /// its runtime is rebuilt on the next run if the steps' code changed.
+    pub fn steps_mut(&mut self) -> &mut Vec<Step> {
+        self.timestamps.update();
+        &mut self.steps
+    }
+
+    /// Builds the agent's Python runtime ahead of its first run, e.g. when it's registered
+    pub fn warmup(&self) -> Result<()> {
+        let (fingerprint, runtime) = self.checkout_runtime()?;
+        self.checkin_runtime(fingerprint, runtime);
+        Ok(())
+    }
+
+    /// Takes the prepared runtime if it still matches the agent, building a new one otherwise
+    fn checkout_runtime(&self) -> Result<(u64, PythonRuntime)> {
+        let fingerprint = self.runtime_fingerprint();
+        let cached = self.runtime_cache.prepared.lock().unwrap().take();
+        if let Some((cached_fingerprint, runtime)) = cached {
+            if cached_fingerprint == fingerprint {
+                return Ok((fingerprint, runtime));
+            }
+        }
+
+        let runtime = self.create_python_runtime()?;
+        self.runtime_cache.builds.fetch_add(1, Ordering::SeqCst);
+        Ok((fingerprint, runtime))
+    }
+
+    fn checkin_runtime(&self, fingerprint: u64, runtime: PythonRuntime) {
+        *self.runtime_cache.prepared.lock().unwrap() = Some((fingerprint, runtime));
+    }
+
+    /// Identifies what `create_python_runtime` builds from: the steps and `env`
+    fn runtime_fingerprint(&self) -> u64 {
+        self.fingerprint_for(&self.steps)
+    }
+
+    fn fingerprint_for(&self, steps: &[Step]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for step in steps {
+            step.identifiers.global_uuid.hash(&mut hasher);
+            step.step_type.as_str().hash(&mut hasher);
+            step.step_content.hash(&mut hasher);
+        }
+        let mut env: Vec<_> = self.env.iter().collect();
+        env.sort();
+        env.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// The steps as they run: Prompt steps without a model get the agent's
+    /// `default_model` (if it has one)
+    pub fn effective_steps(&self) -> Vec<Step> {
+        let mut steps = self.steps.clone();
+        if let Some(default_model) = &self.config.default_model {
+            for step in &mut steps {
+                if let StepType::Prompt(model @ None) = &mut step.step_type {
+                    *model = Some(default_model.clone());
+                }
+            }
+        }
+        steps
+    }
+
     /// Process data with this agent using an immutable reference
     pub async fn run(&self, source: Value) -> Result<RuntimeSession> {
+        self.run_with_context(source, RunContext::default()).await
+    }
+
+    /// Like `run`, but records why and by whom the run was started. The context
+    /// is kept on the session and visible to Python steps as `context`.
+    pub async fn run_with_context(
+        &self,
+        source: Value,
+        context: RunContext,
+    ) -> Result<RuntimeSession> {
+        self.run_with_context_cancellable(source, context, &CancellationToken::new())
+            .await
+    }
+
+    /// Like `run`, but stops once `cancel` fires. A cancelled run isn't an error:
+    /// the session is returned with status `Cancelled`. Likewise a run stopped by
+    /// the agent's `RunBudget` is returned with status `BudgetExceeded`.
+    pub async fn run_cancellable(
+        &self,
+        source: Value,
+        cancel: &CancellationToken,
+    ) -> Result<RuntimeSession> {
+        self.run_with_context_cancellable(source, RunContext::default(), cancel)
+            .await
+    }
+
+    /// `run_with_context` that stops once `cancel` fires, see `run_cancellable`
+    pub async fn run_with_context_cancellable(
+        &self,
+        source: Value,
+        context: RunContext,
+        cancel: &CancellationToken,
+    ) -> Result<RuntimeSession> {
         // Check if state is Inactive. If so, return error
         if self.state() == AgentState::Inactive {
             return Err(anyhow!("Cannot run agent in Inactive state"));
         }
 
-        // Create a Python runtime for this agent
-        let runtime = self.create_python_runtime()?;
-
-        // Create a new RuntimeSession with the agent's steps and local_id
-        let mut session =
-            RuntimeSession::new(source, self.steps.clone(), self.identifiers.local_id);
+        // Reuse the agent's prepared Python runtime (or build one)
+        let (fingerprint, runtime) = self.checkout_runtime()?;
 
-        // Start the RuntimeSession with the Python runtime
-        let result = session.start_with_runtime(&runtime).await;
+        let mut session = self.new_session(source, context);
 
-        // If there was an error, propagate it
-        if let Err(e) = result {
-            return Err(e);
+        // Start the RuntimeSession with the Python runtime, propagating failures
+        // (a cancelled or over-budget session is returned as-is)
+        let result = session
+            .unified_start_cancellable(Some(&runtime), cancel)
+            .await;
+        self.checkin_runtime(fingerprint, runtime);
+        if let Err(err) = result {
+            if !matches!(
+                session.status,
+                RunningStatus::Cancelled | RunningStatus::BudgetExceeded
+            ) {
+                return Err(err);
+            }
         }
 
         // Return final session
         Ok(session)
     }
+
+    /// Runs the agent on `source` and returns the final result, without keeping the
+    /// session: nothing is saved, so an agent can be previewed on sample data before
+    /// it's created. Unlike `run`, the agent's state isn't checked, and a run that
+    /// doesn't complete (e.g. stopped by its budget) is an error.
+    pub async fn run_ephemeral(&self, source: Value) -> Result<Value> {
+        let (fingerprint, runtime) = self.checkout_runtime()?;
+        let mut session = self.new_session(source, RunContext::default());
+        let result = session.unified_start(Some(&runtime)).await;
+        self.checkin_runtime(fingerprint, runtime);
+
+        let result = result?;
+        if session.status != RunningStatus::Completed {
+            return Err(anyhow!("Preview run ended {}", session.status.as_str()));
+        }
+        Ok(result)
+    }
+
+    /// A session running the agent's steps with its config, attributed to the
+    /// agent when it has been saved
+    fn new_session(&self, source: Value, context: RunContext) -> RuntimeSession {
+        let steps = self.effective_steps();
+        let mut session = match self.identifiers.local_id {
+            Some(agent_id) => RuntimeSession::new_requested_by(source, steps, agent_id),
+            None => RuntimeSession::new(source, steps, None),
+        };
+        session.budget = self.config.budget.clone();
+        session.failure_policy = self.config.failure_policy;
+        session.legacy_prompt_format = self.config.legacy_prompt_format;
+        session.llm_io_log = self.config.log_llm_io.clone();
+        session.min_inter_step_delay = Duration::from_millis(self.config.min_inter_step_delay_ms);
+        session.context = context;
+        session
+    }
 }
@@ -1,6 +1,10 @@
-use crate::models::steps::Step;
+use super::runtime::RuntimeCache;
+use crate::models::runtime_sessions::{FailurePolicy, RunBudget};
+use crate::models::steps::{LlmIoLogConfig, Step, StepMetrics};
 use crate::{IdFields, TimestampFields};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 use std::sync::Mutex;
 
 /// An Agent represents a component that listens for and reacts to Signals in the system.
@@ -13,6 +17,103 @@ pub struct Agent {
     pub description: String,
     pub agent_state: Mutex<AgentState>,
     pub steps: Vec<Step>,
+    pub config: AgentConfig,
+    /// Variables exposed to the agent's Python steps as `AGENT_ENV`.
+    /// May hold secrets, so it's never serialized (`to_json`, bundles, ...).
+    #[serde(default, skip_serializing)]
+    pub env: HashMap<String, String>,
+    /// Python runtime prepared by `warmup` / the last run (not serialized)
+    #[serde(skip)]
+    pub(crate) runtime_cache: RuntimeCache,
+}
+
+/// Per-agent execution settings, configured in the UI alongside the Agent
+/// and stored in the `agents.config` column.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct AgentConfig {
+    /// Limits how often the agent worker may start a run (default: unlimited)
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitConfig>,
+    /// Time / download / LLM call ceilings for each run (default: unlimited)
+    #[serde(default)]
+    pub budget: Option<RunBudget>,
+    /// Send Prompt steps their raw content followed by the input as a JSON context
+    /// block, as before prompt templates (default: false, the content is rendered
+    /// with `render_prompt`)
+    #[serde(default)]
+    pub legacy_prompt_format: bool,
+    /// Whether a failing step stops the run (default: `FailFast`)
+    #[serde(default)]
+    pub failure_policy: FailurePolicy,
+    /// How often the agent worker retries a run that failed transiently
+    /// (default: never)
+    #[serde(default)]
+    pub run_retry: Option<RetryConfig>,
+    /// Model for the agent's Prompt steps that don't name one (default: the
+    /// global default model)
+    #[serde(default)]
+    pub default_model: Option<String>,
+    /// Log each Prompt step's prompt and response, redacted (default: off)
+    #[serde(default)]
+    pub log_llm_io: Option<LlmIoLogConfig>,
+    /// Pause between one step finishing and the next starting, e.g. to stay under
+    /// an external API's rate limit (default: 0, steps run back-to-back)
+    #[serde(default)]
+    pub min_inter_step_delay_ms: u64,
+}
+
+impl AgentConfig {
+    /// Parse a stored config, falling back to defaults if it's missing or malformed
+    pub fn from_value(value: Option<&Value>) -> Self {
+        value
+            .filter(|v| !v.is_null())
+            .and_then(|v| serde_json::from_value(v.clone()).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Token-bucket settings: `burst` runs may start back-to-back, after which
+/// runs are spaced out to `requests_per_second`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    pub requests_per_second: f64,
+    #[serde(default = "default_burst")]
+    pub burst: u32,
+}
+
+fn default_burst() -> u32 {
+    1
+}
+
+/// Retries of a whole run whose failure may be transient (see
+/// `StepErrorKind::is_retryable`). The first retry waits `initial_backoff_ms`,
+/// and each later one twice as long, up to `max_backoff_ms`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetryConfig {
+    pub max_retries: u32,
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    1000
+}
+
+fn default_max_backoff_ms() -> u64 {
+    30_000
+}
+
+impl RetryConfig {
+    /// How long to wait before retry number `retry` (counting from 0)
+    pub fn backoff(&self, retry: u32) -> std::time::Duration {
+        let delay_ms = self
+            .initial_backoff_ms
+            .saturating_mul(1u64 << retry.min(32))
+            .min(self.max_backoff_ms);
+        std::time::Duration::from_millis(delay_ms)
+    }
 }
 
 /// Different states for Agent to be in. State diagram:
@@ -48,6 +149,66 @@ impl Agent {
             description,
             agent_state: Mutex::new(AgentState::Inactive),
             steps,
+            config: AgentConfig::default(),
+            env: HashMap::new(),
+            runtime_cache: RuntimeCache::default(),
+        }
+    }
+}
+
+// Manual impl since `Mutex` isn't `Clone`; the copy starts in the current state,
+// without a prepared runtime
+impl Clone for Agent {
+    fn clone(&self) -> Self {
+        Self {
+            identifiers: self.identifiers.clone(),
+            timestamps: self.timestamps.clone(),
+            description: self.description.clone(),
+            agent_state: Mutex::new(self.state()),
+            steps: self.steps.clone(),
+            config: self.config.clone(),
+            env: self.env.clone(),
+            runtime_cache: RuntimeCache::default(),
         }
     }
 }
+
+impl Agent {
+    /// Copies the agent as a starting point for a new one: the agent and its
+    /// steps get fresh UUIDs (and no database ids), new timestamps and zeroed
+    /// step metrics, and the copy starts Inactive. Error handlers point at the
+    /// copied steps. Use `clone` to keep the ids.
+    pub fn clone_with_new_identity(&self) -> Agent {
+        let mut steps = self
+            .steps
+            .iter()
+            .map(|step| Step {
+                identifiers: IdFields::new(),
+                timestamps: TimestampFields::new(),
+                metrics: StepMetrics::default(),
+                ..step.clone()
+            })
+            .collect::<Vec<_>>();
+        // Error handlers are named by UUID, so each follows its step (by position)
+        // to the step's new UUID
+        for (idx, original) in self.steps.iter().enumerate() {
+            let handler = original.on_error.as_ref().and_then(|uuid| {
+                self.steps
+                    .iter()
+                    .position(|step| &step.identifiers.global_uuid == uuid)
+            });
+            if let Some(handler) = handler {
+                steps[idx].on_error = Some(steps[handler].identifiers.global_uuid.clone());
+            }
+        }
+        let mut copy = Agent::new(
+            IdFields::new(),
+            TimestampFields::new(),
+            self.description.clone(),
+            steps,
+        );
+        copy.config = self.config.clone();
+        copy.env = self.env.clone();
+        copy
+    }
+}
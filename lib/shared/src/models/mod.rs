@@ -1,5 +1,5 @@
 pub mod signals;
-pub use signals::{RunDataPayload, RunPayload, Signal, SignalType, SyncPayload};
+pub use signals::{RunDataPayload, RunPayload, Signal, SignalSource, SignalType, SyncPayload};
 
 pub mod agents;
 pub use agents::Agent;
@@ -0,0 +1,118 @@
+use super::scope::RunScope;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Ceilings for a single agent run, configured in `AgentConfig::budget`.
+/// Each limit is optional; the session stops with `RunningStatus::BudgetExceeded`
+/// as soon as one is hit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct RunBudget {
+    /// Wall-clock time for the whole run, in milliseconds
+    #[serde(default)]
+    pub max_total_duration_ms: Option<u64>,
+    /// Bytes downloaded by WebScrape steps and LLM responses
+    #[serde(default)]
+    pub max_total_bytes: Option<u64>,
+    /// LLM calls made by Prompt steps, including those inside loops
+    #[serde(default)]
+    pub max_llm_calls: Option<u64>,
+}
+
+impl RunBudget {
+    pub fn max_total_duration(&self) -> Option<Duration> {
+        self.max_total_duration_ms.map(Duration::from_millis)
+    }
+}
+
+/// The `RunBudget` limit that stopped a session
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "run_budget_limit", rename_all = "snake_case")]
+pub enum BudgetLimit {
+    Duration,
+    Bytes,
+    LlmCalls,
+}
+
+impl BudgetLimit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BudgetLimit::Duration => "duration",
+            BudgetLimit::Bytes => "bytes",
+            BudgetLimit::LlmCalls => "llm_calls",
+        }
+    }
+}
+
+impl std::fmt::Display for BudgetLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// What a running session has used of its `RunBudget`. Steps charge it through
+/// `charge_llm_call` / `charge_bytes` while running in the session's `RunScope`.
+#[derive(Debug, Default)]
+pub(crate) struct RunUsage {
+    budget: RunBudget,
+    bytes: AtomicU64,
+    llm_calls: AtomicU64,
+    exceeded: Mutex<Option<BudgetLimit>>,
+}
+
+impl RunUsage {
+    pub(crate) fn new(budget: RunBudget) -> Arc<Self> {
+        Arc::new(Self {
+            budget,
+            ..Self::default()
+        })
+    }
+
+    /// The first limit that was hit, if any
+    pub(crate) fn exceeded(&self) -> Option<BudgetLimit> {
+        *self.exceeded.lock().unwrap()
+    }
+
+    fn charge(&self, limit: BudgetLimit, counter: &AtomicU64, amount: u64) -> Result<()> {
+        let max = match limit {
+            BudgetLimit::Duration => None,
+            BudgetLimit::Bytes => self.budget.max_total_bytes,
+            BudgetLimit::LlmCalls => self.budget.max_llm_calls,
+        };
+        let used = counter.fetch_add(amount, Ordering::SeqCst) + amount;
+
+        match max {
+            Some(max) if used > max => {
+                self.exceeded.lock().unwrap().get_or_insert(limit);
+                Err(anyhow!(
+                    "Run budget exceeded: {} used {} of {}",
+                    limit,
+                    used,
+                    max
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Counts an LLM call against the current run's budget, failing (before the call is
+/// made) once `max_llm_calls` is used up. Does nothing outside a budgeted run.
+pub(crate) fn charge_llm_call() -> Result<()> {
+    charge_current(|usage| usage.charge(BudgetLimit::LlmCalls, &usage.llm_calls, 1))
+}
+
+/// Counts downloaded bytes against the current run's budget, failing once
+/// `max_total_bytes` is passed. Does nothing outside a budgeted run.
+pub(crate) fn charge_bytes(len: usize) -> Result<()> {
+    charge_current(|usage| usage.charge(BudgetLimit::Bytes, &usage.bytes, len as u64))
+}
+
+fn charge_current(charge: impl FnOnce(&RunUsage) -> Result<()>) -> Result<()> {
+    RunScope::with_current(|scope| scope.usage.as_deref().map(charge))
+        .flatten()
+        .unwrap_or(Ok(()))
+}
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+/// Why and by whom a run was started. Python steps see it as the `context` dict,
+/// e.g. `context["trigger"]`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunContext {
+    /// What started the run, e.g. `"manual"`, `"scheduled"` or `"signal"`
+    pub trigger: String,
+    /// Who asked for the run (a user or service name), if known
+    pub requested_by: Option<String>,
+    /// The signal that started the run, if any
+    pub signal_uuid: Option<String>,
+}
+
+impl Default for RunContext {
+    fn default() -> Self {
+        Self {
+            trigger: "manual".to_string(),
+            requested_by: None,
+            signal_uuid: None,
+        }
+    }
+}
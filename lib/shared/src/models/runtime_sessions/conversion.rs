@@ -0,0 +1,38 @@
+use super::types::RuntimeSession;
+use crate::{duration_to_json, JsonLike};
+use serde_json::{json, Value};
+
+impl RuntimeSession {
+    /// The session as the UI shows it, with a `step_summaries` timeline so the
+    /// detail view needs no further queries. Times are in seconds, as stored.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.identifiers.local_id,
+            "global_uuid": self.identifiers.global_uuid,
+            "created_at": self.timestamps.created.to_rfc3339(),
+            "updated_at": self.timestamps.updated.to_rfc3339(),
+            "requested_by_agent_id": self.requested_by_agent_id,
+            "status": self.status.as_str(),
+            "initial_data": self.source_data,
+            "latest_step_idx": self.last_step_idx,
+            "latest_result": self.last_successful_result,
+            "steps": self.steps.iter().map(|step| step.to_json()).collect::<Vec<_>>(),
+            "step_execution_times": self
+                .step_execution_times
+                .iter()
+                .copied()
+                .map(duration_to_json)
+                .collect::<Vec<_>>(),
+            "total_execution_time": duration_to_json(self.total_execution_time),
+            "step_results": self.step_results,
+            "error_kind": self.error_kind,
+            "budget_exceeded": self.budget_exceeded,
+            "metadata": self.metadata,
+            "step_summaries": self
+                .step_summaries()
+                .iter()
+                .map(|summary| summary.to_json())
+                .collect::<Vec<_>>(),
+        })
+    }
+}
@@ -1,12 +1,17 @@
+use super::budget::BudgetLimit;
+use super::context::RunContext;
+use super::policy::FailurePolicy;
 use super::types::RuntimeSession;
-use crate::{DatabaseItem, IdFields, RunningStatus, Step, TimestampFields};
-use anyhow::Result;
+use crate::models::steps::{aggregated_steps_json, StepErrorKind, DEFAULT_MAX_NESTING_DEPTH};
+use crate::{DatabaseItem, IdFields, PythonRuntime, RunningStatus, Step, TimestampFields};
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use serde_json::Value;
 use sqlx::types::BigDecimal;
-use sqlx::{PgPool, Row};
-use std::str::FromStr;
+use sqlx::{PgConnection, PgPool, Row};
+use std::collections::HashMap;
 use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
 #[derive(Debug, sqlx::FromRow)]
@@ -19,52 +24,47 @@ struct RuntimeSessionRow {
     latest_result: Option<Value>,
     created_at: chrono::DateTime<chrono::Utc>,
     updated_at: chrono::DateTime<chrono::Utc>,
-    step_execution_times: Option<Vec<f64>>,
-    total_execution_time: Option<f64>,
+    step_execution_times: Option<Vec<BigDecimal>>,
+    total_execution_time: Option<BigDecimal>,
     steps: Value, // JSON aggregation result
     requested_by_agent_id: Option<i32>,
     step_results: Option<Vec<Value>>, // Array of step results
+    error_kind: Option<StepErrorKind>,
+    budget_exceeded: Option<BudgetLimit>,
+    metadata: Option<Value>,
 }
 
 impl sqlx::FromRow<'_, sqlx::postgres::PgRow> for RuntimeSession {
     fn from_row(row: &sqlx::postgres::PgRow) -> sqlx::Result<Self> {
         // Get the steps JSON array from the row
-        let steps_json: Value = row.try_get("steps")?;
+        let steps_json = aggregated_steps_json(row)?;
 
         // Convert the JSON array into Vec<Step> using the shared function
-        let steps = Step::from_json_array(&steps_json);
-
-        // Get execution times as array of numeric values
-        let step_execution_times = match row.try_get::<Option<Vec<f64>>, _>("step_execution_times")
-        {
-            Ok(Some(times)) => {
-                times
-                    .into_iter()
-                    .map(|seconds| {
-                        // Convert seconds to Duration
-                        let secs = seconds.trunc() as u64;
-                        let nanos = ((seconds.fract() * 1_000_000_000.0) as u32).min(999_999_999);
-                        Duration::new(secs, nanos)
-                    })
-                    .collect()
-            }
-            _ => Vec::new(),
-        };
+        let steps = session_steps(&steps_json);
+
+        // Get execution times as array of numeric values (seconds)
+        let step_execution_times =
+            match row.try_get::<Option<Vec<BigDecimal>>, _>("step_execution_times") {
+                Ok(Some(times)) => times
+                    .iter()
+                    .map(decimal_to_duration)
+                    .collect::<Result<_>>()
+                    .map_err(|e| sqlx::Error::Decode(e.into()))?,
+                _ => Vec::new(),
+            };
 
         // Get total execution time if available (stored as seconds in numeric type)
-        let total_execution_time = match row.try_get::<Option<f64>, _>("total_execution_time") {
-            Ok(Some(seconds)) => {
-                // Convert seconds to Duration
-                let secs = seconds.trunc() as u64;
-                let nanos = ((seconds.fract() * 1_000_000_000.0) as u32).min(999_999_999);
-                Duration::new(secs, nanos)
-            }
-            _ => Duration::ZERO,
-        };
+        let total_execution_time =
+            match row.try_get::<Option<BigDecimal>, _>("total_execution_time") {
+                Ok(Some(seconds)) => {
+                    decimal_to_duration(&seconds).map_err(|e| sqlx::Error::Decode(e.into()))?
+                }
+                _ => Duration::ZERO,
+            };
 
         // Get step results as array of JSON values, converting to Option<Value>
         let step_results = match row.try_get::<Option<Vec<Value>>, _>("step_results") {
-            Ok(Some(results)) => results.into_iter().map(Some).collect(),
+            Ok(Some(results)) => loaded_step_results(results),
             _ => Vec::new(),
         };
 
@@ -86,31 +86,47 @@ impl sqlx::FromRow<'_, sqlx::postgres::PgRow> for RuntimeSession {
             total_execution_time,
             requested_by_agent_id: row.try_get("requested_by_agent_id")?,
             step_results,
+            error_kind: row.try_get("error_kind").unwrap_or_default(),
+            budget: None,
+            budget_exceeded: row.try_get("budget_exceeded").unwrap_or_default(),
+            metadata: row.try_get("metadata").unwrap_or_default(),
+            failure_policy: FailurePolicy::default(),
+            legacy_prompt_format: false,
+            llm_io_log: None,
+            seed: None,
+            min_inter_step_delay: Duration::ZERO,
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+            context: RunContext::default(),
+            named_outputs: HashMap::new(),
+            checkpoint_pool: None,
+            events: Vec::new(),
         })
     }
 }
 
-#[async_trait]
-impl DatabaseItem for RuntimeSession {
-    type IdType = i64;
-
-    fn id(&self) -> &IdFields<Self::IdType> {
-        &self.identifiers
-    }
+impl RuntimeSession {
+    /// Creates the session row on an open connection, so it can share a transaction.
+    /// Returns the row's `id`; a session that already exists is left as-is.
+    pub(crate) async fn insert_in(&self, conn: &mut PgConnection) -> Result<i64> {
+        // Parse UUID once for all operations
+        let parsed_uuid = Uuid::parse_str(&self.identifiers.global_uuid)?;
 
-    async fn try_db_create(&self, pool: &PgPool) -> Result<()> {
         // Check if a session with the same UUID already exists
-        if crate::check_exists_by_uuid(pool, "runtime_sessions", &self.identifiers.global_uuid)
-            .await?
-        {
-            return Ok(()); // Session already exists, no need to create it again
+        let existing = sqlx::query_scalar::<_, i64>(
+            "SELECT id FROM runtime_sessions WHERE global_uuid = $1",
+        )
+        .bind(parsed_uuid)
+        .fetch_optional(&mut *conn)
+        .await?;
+        if let Some(id) = existing {
+            return Ok(id); // Session already exists, no need to create it again
         }
 
         // Convert execution times to BigDecimal array
         let step_times_secs: Vec<BigDecimal> = self
             .step_execution_times
             .iter()
-            .map(|duration| BigDecimal::from_str(&duration.as_secs_f64().to_string()).unwrap())
+            .map(|duration| duration_to_decimal(*duration))
             .collect();
 
         // Collect step IDs from the steps vector
@@ -121,29 +137,21 @@ impl DatabaseItem for RuntimeSession {
             .collect();
 
         // Convert Duration to BigDecimal seconds
-        let total_time_secs =
-            BigDecimal::from_str(&self.total_execution_time.as_secs_f64().to_string()).unwrap();
+        let total_time_secs = duration_to_decimal(self.total_execution_time);
 
-        // Parse UUID once for all operations
-        let parsed_uuid = Uuid::parse_str(&self.identifiers.global_uuid)?;
-
-        // Prepare step_results for database by filtering out None values
-        let filtered_step_results: Vec<Value> = self
-            .step_results
-            .iter()
-            .filter_map(|v| v.clone())
-            .collect();
+        let step_results = stored_step_results(&self.step_results);
 
-        // Create the session record using query! macro
-        sqlx::query!(
+        // Create the session record using query_scalar! macro
+        let id = sqlx::query_scalar!(
             r#"
             INSERT INTO runtime_sessions (
                 global_uuid, rts_status, initial_data,
                 latest_step_idx, latest_result, created_at, updated_at,
                 step_execution_times, step_ids, total_execution_time, requested_by_agent_id,
-                step_results
+                step_results, error_kind, budget_exceeded, metadata
             )
-            VALUES ($1, $2::running_status, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            VALUES ($1, $2::running_status, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+            RETURNING id
             "#,
             parsed_uuid,
             &self.status as &RunningStatus,
@@ -156,20 +164,97 @@ impl DatabaseItem for RuntimeSession {
             &step_ids,
             total_time_secs,
             self.requested_by_agent_id,
-            &filtered_step_results as &[Value]
+            &step_results as &[Value],
+            self.error_kind as Option<StepErrorKind>,
+            self.budget_exceeded as Option<BudgetLimit>,
+            self.metadata
         )
-        .execute(pool)
+        .fetch_one(&mut *conn)
         .await?;
 
+        Ok(id)
+    }
+
+    /// Saves the session's progress to its row (creating it on the first call)
+    /// if `checkpoint_pool` is set. A failed save is logged and the run carries on.
+    pub(super) async fn checkpoint(&self) {
+        let Some(pool) = &self.checkpoint_pool else {
+            return;
+        };
+        let saved = async {
+            self.try_db_create(pool).await?;
+            self.try_db_update(pool).await
+        }
+        .await;
+        if let Err(e) = saved {
+            eprintln!(
+                "Failed to checkpoint runtime session {}: {}",
+                self.identifiers.global_uuid, e
+            );
+        }
+    }
+
+    /// Loads a session that didn't finish (still `Waiting` or `Running`, e.g. because
+    /// the process running it died) and continues it from its last checkpoint: the
+    /// steps after `last_step_idx` run on `last_successful_result`, and checkpoints
+    /// carry on. Named outputs aren't persisted, so `@name` input mappings can't see
+    /// outputs from before the checkpoint. Like `Agent::run`, a failed step is an
+    /// error, while a session stopped by its budget is returned as-is.
+    pub async fn try_resume_from_db(
+        pool: &PgPool,
+        global_uuid: &str,
+        runtime: Option<&PythonRuntime>,
+    ) -> Result<Self> {
+        let mut session =
+            Self::try_db_select_by_id(pool, &IdFields::with_values(None, global_uuid.to_string()))
+                .await?
+                .ok_or_else(|| anyhow!("Runtime session {} not found", global_uuid))?;
+        if !matches!(
+            session.status,
+            RunningStatus::Waiting | RunningStatus::Running
+        ) {
+            return Err(anyhow!(
+                "Runtime session {} already finished with status {}",
+                global_uuid,
+                session.status.as_str()
+            ));
+        }
+
+        session.checkpoint_pool = Some(pool.clone());
+        let result = session
+            .resume_cancellable(runtime, &CancellationToken::new())
+            .await;
+        if let Err(err) = result {
+            if session.status != RunningStatus::BudgetExceeded {
+                return Err(err);
+            }
+        }
+
+        Ok(session)
+    }
+}
+
+#[async_trait]
+impl DatabaseItem for RuntimeSession {
+    type IdType = i64;
+    const MODEL: &'static str = "runtime_session";
+
+    fn id(&self) -> &IdFields<Self::IdType> {
+        &self.identifiers
+    }
+
+    async fn db_create(&self, pool: &PgPool) -> Result<()> {
+        let mut conn = pool.acquire().await?;
+        self.insert_in(&mut conn).await?;
         Ok(())
     }
 
-    async fn try_db_update(&self, pool: &PgPool) -> Result<()> {
+    async fn db_update(&self, pool: &PgPool) -> Result<()> {
         // Convert execution times to BigDecimal array
         let step_times_secs: Vec<BigDecimal> = self
             .step_execution_times
             .iter()
-            .map(|duration| BigDecimal::from_str(&duration.as_secs_f64().to_string()).unwrap())
+            .map(|duration| duration_to_decimal(*duration))
             .collect();
 
         // Collect step IDs from the steps vector
@@ -180,18 +265,12 @@ impl DatabaseItem for RuntimeSession {
             .collect();
 
         // Convert Duration to BigDecimal seconds
-        let total_time_secs =
-            BigDecimal::from_str(&self.total_execution_time.as_secs_f64().to_string()).unwrap();
+        let total_time_secs = duration_to_decimal(self.total_execution_time);
 
         // Parse UUID once
         let parsed_uuid = Uuid::parse_str(&self.identifiers.global_uuid)?;
 
-        // Prepare step_results for database by filtering out None values
-        let filtered_step_results: Vec<Value> = self
-            .step_results
-            .iter()
-            .filter_map(|v| v.clone())
-            .collect();
+        let step_results = stored_step_results(&self.step_results);
 
         sqlx::query!(
             r#"
@@ -205,7 +284,10 @@ impl DatabaseItem for RuntimeSession {
                 step_ids = $7,
                 total_execution_time = $8,
                 requested_by_agent_id = $9,
-                step_results = $11
+                step_results = $11,
+                error_kind = $12,
+                budget_exceeded = $13,
+                metadata = $14
             WHERE global_uuid = $10
             "#,
             &self.status as &RunningStatus,
@@ -218,7 +300,10 @@ impl DatabaseItem for RuntimeSession {
             total_time_secs,
             self.requested_by_agent_id,
             parsed_uuid,
-            &filtered_step_results as &[Value]
+            &step_results as &[Value],
+            self.error_kind as Option<StepErrorKind>,
+            self.budget_exceeded as Option<BudgetLimit>,
+            self.metadata
         )
         .execute(pool)
         .await?;
@@ -226,7 +311,7 @@ impl DatabaseItem for RuntimeSession {
         Ok(())
     }
 
-    async fn try_db_delete(&self, pool: &PgPool) -> Result<()> {
+    async fn db_delete(&self, pool: &PgPool) -> Result<()> {
         // Delete the session record
         let parsed_uuid = Uuid::parse_str(&self.identifiers.global_uuid)?;
 
@@ -240,30 +325,8 @@ impl DatabaseItem for RuntimeSession {
         Ok(())
     }
 
-    async fn try_db_select_all(pool: &PgPool) -> Result<Vec<Self>> {
-        let steps_json_agg = crate::steps_json_agg_sql("rs", "agent_id");
-
-        // Use query_as! with our RuntimeSessionRow struct
-        let query = format!(
-            r#"
-            SELECT
-                rs.id as "id!",
-                rs.global_uuid as "global_uuid!",
-                rs.rts_status as "rts_status!: RunningStatus",
-                rs.initial_data as "initial_data!",
-                rs.latest_step_idx,
-                rs.latest_result,
-                rs.created_at as "created_at!",
-                rs.updated_at as "updated_at!",
-                rs.step_execution_times,
-                rs.step_ids,
-                rs.total_execution_time,
-                rs.step_results,
-                {} as "steps!: Value"
-            FROM runtime_sessions rs
-            "#,
-            steps_json_agg
-        );
+    async fn db_select_all(pool: &PgPool) -> Result<Vec<Self>> {
+        let query = select_sessions_sql("")?;
 
         let rows = sqlx::query_as::<_, RuntimeSessionRow>(&query)
             .fetch_all(pool)
@@ -272,116 +335,65 @@ impl DatabaseItem for RuntimeSession {
         // Convert RuntimeSessionRow to RuntimeSession
         let sessions = rows
             .into_iter()
-            .map(|row| RuntimeSession {
-                identifiers: IdFields {
-                    local_id: Some(row.id),
-                    global_uuid: row.global_uuid.to_string(),
-                },
-                timestamps: TimestampFields {
-                    created: row.created_at,
-                    updated: row.updated_at,
-                },
-                steps: Step::from_json_array(&row.steps),
-                status: row.rts_status,
-                source_data: row.initial_data,
-                last_step_idx: row.latest_step_idx,
-                last_successful_result: row.latest_result,
-                step_execution_times: row
-                    .step_execution_times
-                    .unwrap_or_default()
-                    .into_iter()
-                    .map(|secs| {
-                        let secs_int = secs.trunc() as u64;
-                        let nanos = ((secs.fract() * 1_000_000_000.0) as u32).min(999_999_999);
-                        Duration::new(secs_int, nanos)
-                    })
-                    .collect(),
-                total_execution_time: row
-                    .total_execution_time
-                    .map(|secs| {
-                        let secs_int = secs.trunc() as u64;
-                        let nanos = ((secs.fract() * 1_000_000_000.0) as u32).min(999_999_999);
-                        Duration::new(secs_int, nanos)
-                    })
-                    .unwrap_or_default(),
-                requested_by_agent_id: row.requested_by_agent_id,
-                step_results: row
-                    .step_results
-                    .unwrap_or_default()
-                    .into_iter()
-                    .map(Some)
-                    .collect(),
-            })
-            .collect();
+            .map(RuntimeSession::try_from)
+            .collect::<Result<_>>()?;
 
         Ok(sessions)
     }
 
-    async fn try_db_select_by_id(
+    async fn db_select_by_id(
         pool: &PgPool,
         id: &IdFields<Self::IdType>,
     ) -> Result<Option<Self>> {
-        let steps_json_agg = crate::steps_json_agg_sql("rs", "agent_id");
-
         let row = if let Some(local_id) = id.local_id {
-            let query = format!(
-                r#"
-                SELECT
-                    rs.id as "id!",
-                    rs.global_uuid as "global_uuid!",
-                    rs.rts_status as "rts_status!: RunningStatus",
-                    rs.initial_data as "initial_data!",
-                    rs.latest_step_idx,
-                    rs.latest_result,
-                    rs.created_at as "created_at!",
-                    rs.updated_at as "updated_at!",
-                    rs.step_execution_times,
-                    rs.step_ids,
-                    rs.total_execution_time,
-                    rs.step_results,
-                    {} as "steps!: Value"
-                FROM runtime_sessions rs
-                WHERE rs.id = $1
-                "#,
-                steps_json_agg
-            );
-
-            sqlx::query_as::<_, RuntimeSessionRow>(&query)
+            sqlx::query_as::<_, RuntimeSessionRow>(&select_sessions_sql("WHERE rs.id = $1")?)
                 .bind(local_id)
                 .fetch_optional(pool)
                 .await?
         } else {
             let parsed_uuid = Uuid::parse_str(&id.global_uuid)?;
 
-            let query = format!(
-                r#"
-                SELECT
-                    rs.id as "id!",
-                    rs.global_uuid as "global_uuid!",
-                    rs.rts_status as "rts_status!: RunningStatus",
-                    rs.initial_data as "initial_data!",
-                    rs.latest_step_idx,
-                    rs.latest_result,
-                    rs.created_at as "created_at!",
-                    rs.updated_at as "updated_at!",
-                    rs.step_execution_times,
-                    rs.step_ids,
-                    rs.total_execution_time,
-                    rs.step_results,
-                    {} as "steps!: Value"
-                FROM runtime_sessions rs
-                WHERE rs.global_uuid = $1
-                "#,
-                steps_json_agg
-            );
-
-            sqlx::query_as::<_, RuntimeSessionRow>(&query)
-                .bind(parsed_uuid)
-                .fetch_optional(pool)
-                .await?
+            sqlx::query_as::<_, RuntimeSessionRow>(&select_sessions_sql(
+                "WHERE rs.global_uuid = $1",
+            )?)
+            .bind(parsed_uuid)
+            .fetch_optional(pool)
+            .await?
         };
 
-        Ok(row.map(|row| RuntimeSession {
+        row.map(RuntimeSession::try_from).transpose()
+    }
+
+    async fn db_select_by_ids(pool: &PgPool, ids: &[Self::IdType]) -> Result<Vec<Self>> {
+        sqlx::query_as::<_, RuntimeSessionRow>(&select_sessions_sql(
+            "WHERE rs.id = ANY($1) ORDER BY rs.id",
+        )?)
+        .bind(ids)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(RuntimeSession::try_from)
+        .collect()
+    }
+
+    async fn db_select_by_uuids(pool: &PgPool, uuids: &[String]) -> Result<Vec<Self>> {
+        sqlx::query_as::<_, RuntimeSessionRow>(&select_sessions_sql(
+            "WHERE rs.global_uuid = ANY($1) ORDER BY rs.id",
+        )?)
+        .bind(crate::parse_uuids(uuids)?)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(RuntimeSession::try_from)
+        .collect()
+    }
+}
+
+impl TryFrom<RuntimeSessionRow> for RuntimeSession {
+    type Error = anyhow::Error;
+
+    fn try_from(row: RuntimeSessionRow) -> Result<Self> {
+        Ok(RuntimeSession {
             identifiers: IdFields {
                 local_id: Some(row.id),
                 global_uuid: row.global_uuid.to_string(),
@@ -390,7 +402,7 @@ impl DatabaseItem for RuntimeSession {
                 created: row.created_at,
                 updated: row.updated_at,
             },
-            steps: Step::from_json_array(&row.steps),
+            steps: session_steps(&row.steps),
             status: row.rts_status,
             source_data: row.initial_data,
             last_step_idx: row.latest_step_idx,
@@ -398,28 +410,105 @@ impl DatabaseItem for RuntimeSession {
             step_execution_times: row
                 .step_execution_times
                 .unwrap_or_default()
-                .into_iter()
-                .map(|secs| {
-                    let secs_int = secs.trunc() as u64;
-                    let nanos = ((secs.fract() * 1_000_000_000.0) as u32).min(999_999_999);
-                    Duration::new(secs_int, nanos)
-                })
-                .collect(),
+                .iter()
+                .map(decimal_to_duration)
+                .collect::<Result<_>>()?,
             total_execution_time: row
                 .total_execution_time
-                .map(|secs| {
-                    let secs_int = secs.trunc() as u64;
-                    let nanos = ((secs.fract() * 1_000_000_000.0) as u32).min(999_999_999);
-                    Duration::new(secs_int, nanos)
-                })
+                .as_ref()
+                .map(decimal_to_duration)
+                .transpose()?
                 .unwrap_or_default(),
             requested_by_agent_id: row.requested_by_agent_id,
-            step_results: row
-                .step_results
-                .unwrap_or_default()
-                .into_iter()
-                .map(Some)
-                .collect(),
-        }))
+            step_results: loaded_step_results(row.step_results.unwrap_or_default()),
+            error_kind: row.error_kind,
+            budget: None,
+            budget_exceeded: row.budget_exceeded,
+            metadata: row.metadata,
+            failure_policy: FailurePolicy::default(),
+            legacy_prompt_format: false,
+            llm_io_log: None,
+            seed: None,
+            min_inter_step_delay: Duration::ZERO,
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+            context: RunContext::default(),
+            named_outputs: HashMap::new(),
+            checkpoint_pool: None,
+            events: Vec::new(),
+        })
     }
 }
+
+/// Query selecting the columns of `RuntimeSessionRow`, with the steps each session ran
+fn select_sessions_sql(where_clause: &str) -> Result<String> {
+    Ok(format!(
+        r#"
+        SELECT
+            rs.id,
+            rs.global_uuid,
+            rs.rts_status,
+            rs.initial_data,
+            rs.latest_step_idx,
+            rs.latest_result,
+            rs.created_at,
+            rs.updated_at,
+            rs.step_execution_times,
+            rs.total_execution_time,
+            rs.requested_by_agent_id,
+            rs.step_results,
+            rs.error_kind,
+            rs.budget_exceeded,
+            rs.metadata,
+            {}
+        FROM runtime_sessions rs
+        {}
+        "#,
+        crate::session_steps_json_agg_sql("rs")?,
+        where_clause
+    ))
+}
+
+/// Parse a session's steps, keeping the valid ones. Sessions are a record of past
+/// runs, so a malformed step is logged rather than making the session unreadable.
+fn session_steps(steps_json: &Value) -> Vec<Step> {
+    Step::from_json_array_strict(steps_json).unwrap_or_else(|errors| {
+        eprintln!(
+            "Skipping malformed runtime session steps: {}",
+            Step::describe_json_array_errors(&errors)
+        );
+        Step::from_json_array(steps_json)
+    })
+}
+
+/// `step_results` as stored: a step that didn't run (e.g. an error handler that
+/// wasn't needed) is `null`, so the results after it keep their indexes
+fn stored_step_results(results: &[Option<Value>]) -> Vec<Value> {
+    results
+        .iter()
+        .map(|result| result.clone().unwrap_or(Value::Null))
+        .collect()
+}
+
+/// Reverse of `stored_step_results`
+fn loaded_step_results(results: Vec<Value>) -> Vec<Option<Value>> {
+    results
+        .into_iter()
+        .map(|result| Some(result).filter(|result| !result.is_null()))
+        .collect()
+}
+
+/// Seconds as an exact decimal, for the `numeric` execution time columns
+fn duration_to_decimal(duration: Duration) -> BigDecimal {
+    BigDecimal::new(duration.as_nanos().into(), 9)
+}
+
+/// Reads seconds from a `numeric` execution time column without going through
+/// `f64` (digits past nanoseconds are dropped)
+pub(super) fn decimal_to_duration(seconds: &BigDecimal) -> Result<Duration> {
+    let (nanos, _) = seconds.with_scale(9).into_bigint_and_exponent();
+    let nanos = u128::try_from(nanos)
+        .map_err(|_| anyhow!("Invalid execution time: {} seconds", seconds))?;
+    let secs = u64::try_from(nanos / 1_000_000_000)
+        .map_err(|_| anyhow!("Execution time out of range: {} seconds", seconds))?;
+    Ok(Duration::new(secs, (nanos % 1_000_000_000) as u32))
+}
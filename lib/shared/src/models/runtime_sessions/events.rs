@@ -0,0 +1,33 @@
+use crate::Step;
+use std::time::Duration;
+
+/// Something noteworthy that happened while a session ran, without failing it
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeEvent {
+    /// Step `step_idx` took `actual`, longer than its `expected_duration`
+    SlowStep {
+        step_idx: usize,
+        actual: Duration,
+        expected: Duration,
+    },
+}
+
+/// The `SlowStep` event for step `idx` of a session, if `actual` is over the
+/// step's `expected_duration`. Logs a warning when it is.
+pub(super) fn slow_step(idx: usize, step: &Step, actual: Duration) -> Option<RuntimeEvent> {
+    let expected = step
+        .expected_duration
+        .filter(|expected| actual > *expected)?;
+    eprintln!(
+        "[WARN] Step {} (UUID: {}) took {:?}, longer than the expected {:?}",
+        idx + 1,
+        step.identifiers.global_uuid,
+        actual,
+        expected
+    );
+    Some(RuntimeEvent::SlowStep {
+        step_idx: idx,
+        actual,
+        expected,
+    })
+}
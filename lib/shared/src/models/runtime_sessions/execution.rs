@@ -1,24 +1,37 @@
+use super::budget::{BudgetLimit, RunUsage};
+use super::events::slow_step;
+use super::policy::FailurePolicy;
+use super::scope::RunScope;
 use super::types::RuntimeSession;
-use crate::{PythonRuntime, RunningStatus};
+use super::SessionWorkspace;
+use crate::models::steps::{
+    cache_step_output, cached_step_output, StepError, StepErrorKind, StepOutput,
+};
+use crate::{PythonRuntime, RunningStatus, Step};
 use anyhow::{anyhow, Result};
 use serde_json::Value;
-use std::time::Instant;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
 
 impl RuntimeSession {
     /// Start executing the session with an optional Python runtime
     /// This is a unified method that works with or without a runtime.
     /// If no runtime is provided, only Prompt steps can be executed.
     pub async fn unified_start(&mut self, runtime: Option<&PythonRuntime>) -> Result<Value> {
-        // Set status to Running
-        self.status = RunningStatus::Running;
+        self.unified_start_cancellable(runtime, &CancellationToken::new())
+            .await
+    }
 
-        // Check if a runtime is required but not provided
-        if runtime.is_none() && self.steps.iter().any(|step| step.is_python_step()) {
-            self.status = RunningStatus::Cancelled;
-            return Err(anyhow!(
-                "Python steps require a runtime but none was provided"
-            ));
-        }
+    /// Like `unified_start`, but stops early once `cancel` is triggered: the remaining
+    /// steps are skipped (an in-flight async step is abandoned) and the session ends `Cancelled`.
+    /// A session with a `budget` stops the same way, ending `BudgetExceeded`, once a limit is hit.
+    pub async fn unified_start_cancellable(
+        &mut self,
+        runtime: Option<&PythonRuntime>,
+        cancel: &CancellationToken,
+    ) -> Result<Value> {
+        self.prepare_to_run(runtime)?;
 
         // Initialize timing fields
         self.step_execution_times = Vec::with_capacity(self.steps.len());
@@ -26,28 +39,241 @@ impl RuntimeSession {
 
         // Initialize step_results with None values for each step
         self.step_results = vec![None; self.steps.len()];
+        self.named_outputs.clear();
 
-        let start_time = Instant::now();
+        let source_data = self.source_data.clone();
+        self.run_checkpointed(0, source_data, runtime, cancel).await
+    }
+
+    /// Continues a session cut short after `last_step_idx`: the later steps run on
+    /// `last_successful_result`, keeping the earlier steps' results and times
+    pub(crate) async fn resume_cancellable(
+        &mut self,
+        runtime: Option<&PythonRuntime>,
+        cancel: &CancellationToken,
+    ) -> Result<Value> {
+        self.prepare_to_run(runtime)?;
+
+        let first_idx = self.last_step_idx.map_or(0, |idx| idx as usize + 1);
+        let input = match &self.last_successful_result {
+            Some(result) if first_idx > 0 => result.clone(),
+            _ => self.source_data.clone(),
+        };
+        self.step_results.resize(self.steps.len(), None);
+        self.step_execution_times.truncate(first_idx);
+        self.named_outputs.clear();
+
+        self.run_checkpointed(first_idx, input, runtime, cancel).await
+    }
+
+    /// Marks the session running, checking it has the runtime its steps need
+    fn prepare_to_run(&mut self, runtime: Option<&PythonRuntime>) -> Result<()> {
+        // Set status to Running
+        self.status = RunningStatus::Running;
+
+        // Check if a runtime is required but not provided
+        if runtime.is_none() && self.steps.iter().any(|step| step.requires_runtime()) {
+            self.status = RunningStatus::Failed;
+            self.error_kind = Some(StepErrorKind::Config);
+            return Err(StepError::new(
+                StepErrorKind::Config,
+                "Python steps require a runtime but none was provided",
+            )
+            .into());
+        }
+
+        if let Some(runtime) = runtime {
+            if let Err(err) = runtime.set_context(&self.context) {
+                self.status = RunningStatus::Failed;
+                self.error_kind = Some(StepErrorKind::Config);
+                return Err(err);
+            }
+        }
+
+        // Each handler must be another step of the session
+        for step in &self.steps {
+            let Some(handler) = &step.on_error else {
+                continue;
+            };
+            let found = self.steps.iter().any(|other| {
+                &other.identifiers.global_uuid == handler
+                    && other.identifiers.global_uuid != step.identifiers.global_uuid
+            });
+            if !found {
+                self.status = RunningStatus::Failed;
+                self.error_kind = Some(StepErrorKind::Config);
+                return Err(StepError::new(
+                    StepErrorKind::Config,
+                    format!(
+                        "Step {} has on_error {}, which is not another step of the session",
+                        step.identifiers.global_uuid, handler
+                    ),
+                )
+                .into());
+            }
+        }
+
+        self.error_kind = None;
+        self.budget_exceeded = None;
+        self.events.clear();
+        Ok(())
+    }
+
+    /// Runs the steps from `first_idx` on, starting with `input`. With checkpoints
+    /// on, the row is saved after each step and once more with the final state.
+    async fn run_checkpointed(
+        &mut self,
+        first_idx: usize,
+        input: Value,
+        runtime: Option<&PythonRuntime>,
+        cancel: &CancellationToken,
+    ) -> Result<Value> {
+        let result = self.run_steps(first_idx, input, runtime, cancel).await;
+        self.checkpoint().await;
+        result
+    }
+
+    async fn run_steps(
+        &mut self,
+        first_idx: usize,
+        input: Value,
+        runtime: Option<&PythonRuntime>,
+        cancel: &CancellationToken,
+    ) -> Result<Value> {
+        // A resumed session's earlier steps count toward its total time and budget
+        let start_time = Instant::now()
+            .checked_sub(self.total_execution_time)
+            .unwrap_or_else(Instant::now);
+
+        // Usage is charged by the steps; the duration limit is enforced here
+        let budget = self.budget.clone().unwrap_or_default();
+        let deadline = budget
+            .max_total_duration()
+            .map(|limit| tokio::time::Instant::from_std(start_time) + limit);
+        let usage = RunUsage::new(budget);
+        // What each step sees of the session. Its workspace is FileOp steps'
+        // scratch directory, removed when this returns.
+        let scope = RunScope {
+            usage: Some(usage.clone()),
+            workspace: Some(SessionWorkspace::new()),
+            legacy_prompt_format: self.legacy_prompt_format,
+            llm_io_log: self.llm_io_log.clone(),
+            seed: self.seed,
+            max_nesting_depth: self.max_nesting_depth,
+            nesting_depth: 0,
+        };
+        let min_inter_step_delay = self.min_inter_step_delay;
 
         // Execute each step in order, passing the result of each step to the next
-        let mut current_value = self.source_data.clone();
+        let mut current_value = input;
+
+        // Set if `cancel` fires; holds the index of the step that didn't complete
+        let mut cancelled_at = None;
+
+        // Set if the budget runs out; holds the step that didn't complete and the limit hit
+        let mut exceeded_at = None;
+
+        // Steps that handle other steps' failures only run for those (see `Step::on_error`)
+        let handlers: HashSet<&str> = self
+            .steps
+            .iter()
+            .filter_map(|step| step.on_error.as_deref())
+            .collect();
 
         // Track step execution
-        for (idx, step) in self.steps.iter().enumerate() {
+        for (idx, step) in self.steps.iter().enumerate().skip(first_idx) {
+            if handlers.contains(step.identifiers.global_uuid.as_str()) {
+                self.step_execution_times.push(Duration::ZERO);
+                continue;
+            }
+
+            // Throttle between steps; the pause isn't part of either step's time
+            if idx > first_idx && !min_inter_step_delay.is_zero() {
+                tokio::select! {
+                    biased;
+                    _ = cancel.cancelled() => {
+                        cancelled_at = Some(idx);
+                        break;
+                    }
+                    _ = until_deadline(deadline) => {
+                        exceeded_at = Some((idx, BudgetLimit::Duration));
+                        break;
+                    }
+                    _ = tokio::time::sleep(min_inter_step_delay) => {}
+                }
+            }
+
+            // Python steps block the thread, so give other tasks (e.g. the one
+            // noticing a client disconnect) a chance to run between steps
+            tokio::task::yield_now().await;
+            if cancel.is_cancelled() {
+                cancelled_at = Some(idx);
+                break;
+            }
+            // Python steps can't be interrupted, so an overrun is caught before the next step
+            if deadline.is_some_and(|deadline| tokio::time::Instant::now() >= deadline) {
+                exceeded_at = Some((idx, BudgetLimit::Duration));
+                break;
+            }
+
             // Update latest step index before execution
             self.last_step_idx = Some(idx as i32);
 
             // Track this step's execution time
             let step_start = Instant::now();
 
-            // Use step.run which will handle the runtime appropriately for each step type
-            let result = step.run(current_value.clone(), idx, runtime).await;
+            let input = StepOutput {
+                primary: current_value.clone(),
+                named: self.named_outputs.clone(),
+            };
+
+            // A cacheable step that has seen this input before isn't run again
+            let cache_key = step.cacheable.then(|| step.cache_key(&input, &scope));
+            let cached = cache_key.as_ref().and_then(cached_step_output);
+
+            let result = match cached {
+                Some(output) => Ok((output, None)),
+                None => {
+                    // Use step.run which will handle the runtime appropriately for each step type
+                    let result = tokio::select! {
+                        biased;
+                        _ = cancel.cancelled() => {
+                            self.step_execution_times.push(step_start.elapsed());
+                            cancelled_at = Some(idx);
+                            break;
+                        }
+                        _ = until_deadline(deadline) => {
+                            self.step_execution_times.push(step_start.elapsed());
+                            exceeded_at = Some((idx, BudgetLimit::Duration));
+                            break;
+                        }
+                        result = scope.clone().scope(run_step(
+                            &self.steps,
+                            idx,
+                            input,
+                            runtime,
+                            &usage,
+                        )) => result,
+                    };
+                    // A handler's output isn't the step's own, so it isn't cached
+                    if let (Some(key), Ok((output, None))) = (cache_key, &result) {
+                        cache_step_output(key, output.clone());
+                    }
+                    result
+                }
+            };
 
             match result {
-                Ok(value) => {
+                Ok((output, recovery)) => {
+                    let StepOutput { primary: value, named } = output;
+
+                    // Later steps can address these with `@name` input mappings
+                    self.named_outputs.extend(named);
+
                     // Record execution time for this step
                     let step_duration = step_start.elapsed();
                     self.step_execution_times.push(step_duration);
+                    self.events.extend(slow_step(idx, step, step_duration));
 
                     // Update current value for next step
                     current_value = value.clone();
@@ -55,27 +281,81 @@ impl RuntimeSession {
                     // Store the intermediate result
                     self.last_successful_result = Some(value.clone());
 
-                    // Store the step result
-                    self.step_results[idx] = Some(value);
+                    // Store the step result; a step its handler recovered keeps its
+                    // error output, and the handler's output is recorded as its own
+                    match recovery {
+                        Some(Recovery { handler_idx, error_output }) => {
+                            self.step_results[idx] = Some(error_output);
+                            self.step_results[handler_idx] = Some(value);
+                        }
+                        None => self.step_results[idx] = Some(value),
+                    }
+                    self.checkpoint().await;
                 }
                 Err(e) => {
                     // Still record execution time for the failed step
                     let step_duration = step_start.elapsed();
                     self.step_execution_times.push(step_duration);
+                    self.events.extend(slow_step(idx, step, step_duration));
+
+                    // The step failed because it ran out of budget
+                    if let Some(limit) = usage.exceeded() {
+                        exceeded_at = Some((idx, limit));
+                        break;
+                    }
+
+                    // Best-effort sessions record the failure and carry on
+                    match self.failure_policy {
+                        FailurePolicy::FailFast => {}
+                        FailurePolicy::SkipAndContinue => {
+                            self.step_results[idx] = Some(step.error_output(&e));
+                            self.checkpoint().await;
+                            continue;
+                        }
+                        FailurePolicy::ContinueWithNull => {
+                            self.step_results[idx] = Some(step.error_output(&e));
+                            current_value = Value::Null;
+                            self.checkpoint().await;
+                            continue;
+                        }
+                    }
 
                     // Calculate total time before returning
                     self.total_execution_time = start_time.elapsed();
 
-                    // Update status to cancelled
-                    self.status = RunningStatus::Cancelled;
+                    // A failing step fails the session (`Cancelled` is only for explicit cancellation)
+                    self.status = RunningStatus::Failed;
+
+                    // Keep the failure category for filtering, defaulting to user code
+                    let kind = StepErrorKind::of(&e).unwrap_or(StepErrorKind::UserCode);
+                    self.error_kind = Some(kind);
+
+                    // The step's result records the error (and its kind) as well
+                    self.step_results[idx] = Some(step.error_output(&e));
 
                     // Include step index and UUID in the error message for better debugging
                     let step_uuid = &step.identifiers.global_uuid;
-                    return Err(anyhow!("Step execution failed: Step {} (UUID: {}) failed: {}", idx + 1, step_uuid, e));
+                    return Err(StepError::new(
+                        kind,
+                        format!(
+                            "Step execution failed: Step {} (UUID: {}) failed: {}",
+                            idx + 1,
+                            step_uuid,
+                            e
+                        ),
+                    )
+                    .into());
                 }
             }
         }
 
+        if let Some(idx) = cancelled_at {
+            return Err(self.cancel_at(idx, start_time));
+        }
+        if let Some((idx, limit)) = exceeded_at {
+            return Err(self.exceed_budget_at(idx, limit, start_time));
+        }
+
         // All steps completed successfully
         self.status = RunningStatus::Completed;
 
@@ -87,6 +367,32 @@ impl RuntimeSession {
         Ok(current_value)
     }
 
+    /// Marks the session as cancelled before step `idx` finished
+    fn cancel_at(&mut self, idx: usize, start_time: Instant) -> anyhow::Error {
+        self.total_execution_time = start_time.elapsed();
+        self.status = RunningStatus::Cancelled;
+        self.error_kind = None;
+        anyhow!("Session cancelled before step {} completed", idx + 1)
+    }
+
+    /// Marks the session as stopped by its budget before step `idx` finished
+    fn exceed_budget_at(
+        &mut self,
+        idx: usize,
+        limit: BudgetLimit,
+        start_time: Instant,
+    ) -> anyhow::Error {
+        self.total_execution_time = start_time.elapsed();
+        self.status = RunningStatus::BudgetExceeded;
+        self.budget_exceeded = Some(limit);
+        self.error_kind = None;
+        anyhow!(
+            "Session stopped before step {} completed: {} budget exceeded",
+            idx + 1,
+            limit
+        )
+    }
+
     /// Start executing the session with a Python runtime
     pub async fn start_with_runtime(&mut self, runtime: &PythonRuntime) -> Result<Value> {
         self.unified_start(Some(runtime)).await
@@ -97,10 +403,66 @@ impl RuntimeSession {
     /// Use start_with_runtime for sessions with Python steps.
     pub async fn start(&mut self) -> Result<Value> {
         // Check if this session has any Python steps
-        if self.steps.iter().any(|step| step.is_python_step()) {
+        if self.steps.iter().any(|step| step.requires_runtime()) {
             return Err(anyhow!("This session contains Python steps which require a runtime. Use start_with_runtime() instead."));
         }
 
         self.unified_start(None).await
     }
 }
+
+/// How a step's failure was recovered by its `on_error` handler
+struct Recovery {
+    handler_idx: usize,
+    /// The failed step's `error_output`, which the handler got as input
+    error_output: Value,
+}
+
+/// Runs step `idx` of `steps`. If it fails and has an `on_error` handler, the
+/// handler runs on its error output, and its output stands in for the step's.
+/// Failures from running out of budget aren't handed to the handler.
+async fn run_step(
+    steps: &[Step],
+    idx: usize,
+    input: StepOutput,
+    runtime: Option<&PythonRuntime>,
+    usage: &RunUsage,
+) -> Result<(StepOutput, Option<Recovery>)> {
+    let step = &steps[idx];
+    let named = input.named.clone();
+    let err = match step.run(input, idx, runtime).await {
+        Ok(output) => return Ok((output, None)),
+        Err(err) => err,
+    };
+
+    let handler_idx = step.on_error.as_ref().and_then(|handler| {
+        steps
+            .iter()
+            .position(|other| &other.identifiers.global_uuid == handler)
+    });
+    let Some(handler_idx) = handler_idx.filter(|_| usage.exceeded().is_none()) else {
+        return Err(err);
+    };
+
+    let error_output = step.error_output(&err);
+    let handler_input = StepOutput {
+        primary: error_output.clone(),
+        named,
+    };
+    let output = Box::pin(steps[handler_idx].run(handler_input, handler_idx, runtime)).await?;
+    Ok((
+        output,
+        Some(Recovery {
+            handler_idx,
+            error_output,
+        }),
+    ))
+}
+
+/// Resolves once `deadline` passes, or never without one
+async fn until_deadline(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
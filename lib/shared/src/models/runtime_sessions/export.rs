@@ -0,0 +1,117 @@
+use super::budget::BudgetLimit;
+use super::database::decimal_to_duration;
+use super::types::RuntimeSession;
+use crate::models::steps::StepErrorKind;
+use crate::{duration_to_json, RunningStatus};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use sqlx::types::BigDecimal;
+use sqlx::{PgPool, Row};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use uuid::Uuid;
+
+/// Sessions fetched per query by `export_jsonl`
+const EXPORT_PAGE_SIZE: i64 = 500;
+
+impl RuntimeSession {
+    /// Writes the agent's sessions created at or after `since` to `writer` as JSON
+    /// lines, oldest first, for offline analysis. Sessions are read a page at a time
+    /// (keyed on `id`), so memory use doesn't grow with the number of sessions.
+    /// Returns the number of sessions written.
+    pub async fn export_jsonl(
+        pool: &PgPool,
+        agent_id: i32,
+        since: DateTime<Utc>,
+        writer: impl AsyncWrite + Unpin,
+    ) -> Result<usize> {
+        Self::export_jsonl_in_pages(pool, agent_id, since, writer, EXPORT_PAGE_SIZE).await
+    }
+
+    pub(crate) async fn export_jsonl_in_pages(
+        pool: &PgPool,
+        agent_id: i32,
+        since: DateTime<Utc>,
+        mut writer: impl AsyncWrite + Unpin,
+        page_size: i64,
+    ) -> Result<usize> {
+        let mut last_id = 0_i64;
+        let mut written = 0;
+
+        loop {
+            let rows = sqlx::query(
+                r#"
+                SELECT
+                    id, global_uuid, rts_status, initial_data,
+                    latest_step_idx, latest_result, created_at, updated_at,
+                    step_execution_times, total_execution_time,
+                    step_ids, step_results, error_kind, budget_exceeded, metadata
+                FROM runtime_sessions
+                WHERE requested_by_agent_id = $1 AND created_at >= $2 AND id > $3
+                ORDER BY id
+                LIMIT $4
+                "#,
+            )
+            .bind(agent_id)
+            .bind(since)
+            .bind(last_id)
+            .bind(page_size)
+            .fetch_all(pool)
+            .await?;
+
+            for row in &rows {
+                last_id = row.try_get("id")?;
+                let mut line = serde_json::to_vec(&export_line(row, agent_id)?)?;
+                line.push(b'\n');
+                writer.write_all(&line).await?;
+                written += 1;
+            }
+
+            if (rows.len() as i64) < page_size {
+                break;
+            }
+        }
+
+        writer.flush().await?;
+        Ok(written)
+    }
+}
+
+/// One exported session. Times are in seconds, as stored.
+fn export_line(row: &sqlx::postgres::PgRow, agent_id: i32) -> Result<Value> {
+    let status: RunningStatus = row.try_get("rts_status")?;
+    let created_at: DateTime<Utc> = row.try_get("created_at")?;
+    let updated_at: DateTime<Utc> = row.try_get("updated_at")?;
+    let step_execution_times = row
+        .try_get::<Option<Vec<BigDecimal>>, _>("step_execution_times")?
+        .map(|times| {
+            times
+                .iter()
+                .map(|secs| decimal_to_duration(secs).map(duration_to_json))
+                .collect::<Result<Vec<_>>>()
+        })
+        .transpose()?;
+    let total_execution_time = row
+        .try_get::<Option<BigDecimal>, _>("total_execution_time")?
+        .map(|secs| decimal_to_duration(&secs).map(duration_to_json))
+        .transpose()?;
+
+    Ok(json!({
+        "id": row.try_get::<i64, _>("id")?,
+        "global_uuid": row.try_get::<Uuid, _>("global_uuid")?.to_string(),
+        "agent_id": agent_id,
+        "status": status.as_str(),
+        "created_at": created_at.to_rfc3339(),
+        "updated_at": updated_at.to_rfc3339(),
+        "initial_data": row.try_get::<Value, _>("initial_data")?,
+        "latest_step_idx": row.try_get::<Option<i32>, _>("latest_step_idx")?,
+        "latest_result": row.try_get::<Option<Value>, _>("latest_result")?,
+        "step_ids": row.try_get::<Option<Vec<i32>>, _>("step_ids")?,
+        "step_execution_times": step_execution_times,
+        "total_execution_time": total_execution_time,
+        "step_results": row.try_get::<Option<Vec<Value>>, _>("step_results")?,
+        "error_kind": row.try_get::<Option<StepErrorKind>, _>("error_kind")?,
+        "budget_exceeded": row.try_get::<Option<BudgetLimit>, _>("budget_exceeded")?,
+        "metadata": row.try_get::<Option<Value>, _>("metadata")?,
+    }))
+}
@@ -1,5 +1,27 @@
+mod budget;
+mod context;
+mod conversion;
 mod database;
+mod events;
 mod execution;
+mod export;
+mod policy;
+mod reliability;
+mod replay;
+mod scope;
+mod timeline;
 mod types;
+mod workspace;
 
+pub(crate) use budget::{charge_bytes, charge_llm_call};
+pub use budget::{BudgetLimit, RunBudget};
+pub use context::RunContext;
+pub use events::RuntimeEvent;
+pub use policy::FailurePolicy;
+pub use reliability::Reliability;
+pub use replay::{diff_against, ResultDiff};
+pub(crate) use scope::RunScope;
+pub use timeline::StepSummary;
 pub use types::RuntimeSession;
+pub(crate) use workspace::workspace_path;
+use workspace::SessionWorkspace;
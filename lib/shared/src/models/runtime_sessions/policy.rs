@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+/// What a session does when a step fails, configured in `AgentConfig::failure_policy`.
+/// With either "continue" policy the failed step's entry in `step_results` holds
+/// its error output (see `Step::error_output`) and the session still completes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FailurePolicy {
+    /// Stop the session with status `Failed`
+    #[default]
+    FailFast,
+    /// Skip the step and pass the last good value on to the next one
+    SkipAndContinue,
+    /// Pass `null` on to the next step
+    ContinueWithNull,
+}
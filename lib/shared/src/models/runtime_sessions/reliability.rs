@@ -0,0 +1,92 @@
+use crate::models::{Agent, Step};
+use anyhow::Result;
+use sqlx::PgPool;
+
+/// How often an agent or step ran, failed and how long it took, aggregated in
+/// the database over finished runtime sessions (still `waiting` / `running`
+/// ones aren't counted)
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct Reliability {
+    /// The agent's or step's `local_id`
+    pub id: i32,
+    pub runs: i64,
+    pub failures: i64,
+    /// `failures / runs` (0 when there were no runs)
+    pub failure_ratio: f64,
+    /// Mean run duration in seconds (`None` when there were no runs)
+    pub avg_duration_secs: Option<f64>,
+}
+
+impl Agent {
+    /// Reliability of every agent, by `local_id`. A run is one session the agent
+    /// requested; it failed if the session ended `failed`.
+    pub async fn reliability_report(pool: &PgPool) -> Result<Vec<Reliability>> {
+        let report = sqlx::query_as::<_, Reliability>(
+            r#"
+            SELECT
+                a.id,
+                COUNT(rs.id) AS runs,
+                COUNT(*) FILTER (WHERE rs.rts_status = 'failed') AS failures,
+                COALESCE(
+                    COUNT(*) FILTER (WHERE rs.rts_status = 'failed')::float8
+                        / NULLIF(COUNT(rs.id), 0),
+                    0
+                ) AS failure_ratio,
+                AVG(rs.total_execution_time)::float8 AS avg_duration_secs
+            FROM agents a
+            LEFT JOIN runtime_sessions rs
+                ON rs.requested_by_agent_id = a.id
+                AND rs.rts_status NOT IN ('waiting', 'running')
+            GROUP BY a.id
+            ORDER BY a.id
+            "#,
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(report)
+    }
+}
+
+impl Step {
+    /// Reliability of every step, by `local_id`. A run is one session that got to
+    /// the step; it failed if the session failed on it or, when the session carried
+    /// on past failures, its recorded result is an error.
+    pub async fn reliability_report(pool: &PgPool) -> Result<Vec<Reliability>> {
+        // A session's `step_execution_times` has an entry for each step it got to,
+        // in `step_ids` order, and `step_results` has their results (`null` for a
+        // step that didn't run)
+        let report = sqlx::query_as::<_, Reliability>(
+            r#"
+            SELECT
+                s.id,
+                COUNT(r.secs) AS runs,
+                COUNT(*) FILTER (WHERE r.failed) AS failures,
+                COALESCE(
+                    COUNT(*) FILTER (WHERE r.failed)::float8 / NULLIF(COUNT(r.secs), 0),
+                    0
+                ) AS failure_ratio,
+                AVG(r.secs)::float8 AS avg_duration_secs
+            FROM steps s
+            LEFT JOIN (
+                SELECT
+                    ran.step_id,
+                    rs.step_execution_times[ran.idx] AS secs,
+                    (rs.rts_status = 'failed' AND rs.latest_step_idx = ran.idx - 1)
+                        OR COALESCE(rs.step_results[ran.idx]->>'status' = 'error', false)
+                        AS failed
+                FROM runtime_sessions rs
+                CROSS JOIN LATERAL unnest(rs.step_ids) WITH ORDINALITY AS ran(step_id, idx)
+                WHERE rs.rts_status NOT IN ('waiting', 'running')
+                    AND ran.idx <= COALESCE(cardinality(rs.step_execution_times), 0)
+            ) r ON r.step_id = s.id
+            GROUP BY s.id
+            ORDER BY s.id
+            "#,
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(report)
+    }
+}
@@ -0,0 +1,106 @@
+use super::types::RuntimeSession;
+use crate::PythonRuntime;
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+
+impl RuntimeSession {
+    /// Re-runs the session from its saved `source_data` and `steps`, for debugging.
+    /// The stored session isn't touched; compare the returned result to
+    /// `last_successful_result` with `diff_against`.
+    ///
+    /// Python steps are loaded into `runtime` from the saved step definitions first,
+    /// replacing any code registered under the same step UUIDs, so the replay isn't
+    /// affected by later edits to the agent. Use a scratch runtime rather than an
+    /// agent's live one.
+    pub async fn replay(&self, runtime: Option<&mut PythonRuntime>) -> Result<Value> {
+        let runtime = match runtime {
+            Some(runtime) => {
+                for step in &self.steps {
+                    runtime.add_step(step)?;
+                }
+                Some(&*runtime)
+            }
+            None => None,
+        };
+
+        let mut session = RuntimeSession::new(
+            self.source_data.clone(),
+            self.steps.clone(),
+            self.requested_by_agent_id,
+        );
+        session.budget = self.budget.clone();
+        session.failure_policy = self.failure_policy;
+        session.legacy_prompt_format = self.legacy_prompt_format;
+        session.llm_io_log = self.llm_io_log.clone();
+        session.seed = self.seed;
+        session.min_inter_step_delay = self.min_inter_step_delay;
+        session.context = self.context.clone();
+        session.metadata = self.metadata.clone();
+        session.unified_start(runtime).await
+    }
+}
+
+/// One place where a replayed result differs from the stored one
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResultDiff {
+    /// JSON Pointer to the differing value (`""` for the whole result)
+    pub path: String,
+    /// `None` if the value is missing from the stored result
+    pub stored: Option<Value>,
+    /// `None` if the value is missing from the replayed result
+    pub replayed: Option<Value>,
+}
+
+/// Compares a replayed `result` with the `stored_result`, descending into objects
+/// and arrays so each difference is reported at the deepest path it occurs.
+/// An empty list means the results are identical.
+pub fn diff_against(result: &Value, stored_result: &Value) -> Vec<ResultDiff> {
+    let mut diffs = Vec::new();
+    diff_values(String::new(), Some(stored_result), Some(result), &mut diffs);
+    diffs
+}
+
+fn diff_values(
+    path: String,
+    stored: Option<&Value>,
+    replayed: Option<&Value>,
+    diffs: &mut Vec<ResultDiff>,
+) {
+    match (stored, replayed) {
+        (Some(Value::Object(stored)), Some(Value::Object(replayed))) => {
+            let mut keys: Vec<&String> = stored.keys().chain(replayed.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                diff_values(
+                    format!("{}/{}", path, escape_pointer(key)),
+                    stored.get(key),
+                    replayed.get(key),
+                    diffs,
+                );
+            }
+        }
+        (Some(Value::Array(stored)), Some(Value::Array(replayed))) => {
+            for idx in 0..stored.len().max(replayed.len()) {
+                diff_values(
+                    format!("{}/{}", path, idx),
+                    stored.get(idx),
+                    replayed.get(idx),
+                    diffs,
+                );
+            }
+        }
+        (stored, replayed) if stored != replayed => diffs.push(ResultDiff {
+            path,
+            stored: stored.cloned(),
+            replayed: replayed.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+/// Escapes a key for use as a JSON Pointer segment (RFC 6901)
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
@@ -0,0 +1,59 @@
+use super::budget::RunUsage;
+use super::workspace::SessionWorkspace;
+use crate::models::steps::{LlmIoLogConfig, DEFAULT_MAX_NESTING_DEPTH};
+use std::future::Future;
+use std::sync::Arc;
+
+tokio::task_local! {
+    static RUN_SCOPE: RunScope;
+}
+
+/// What a running session's steps can see of it: the budget they charge, the
+/// workspace FileOp steps use, and the session's settings for Prompt, Python and
+/// Loop steps. A session runs each step inside `scope`; a step run on its own
+/// sees `RunScope::default()`.
+#[derive(Debug, Clone)]
+pub(crate) struct RunScope {
+    pub(crate) usage: Option<Arc<RunUsage>>,
+    pub(crate) workspace: Option<Arc<SessionWorkspace>>,
+    /// Prompt steps send their raw content followed by the input as a JSON context
+    /// block, instead of rendering it as a template
+    pub(crate) legacy_prompt_format: bool,
+    pub(crate) llm_io_log: Option<LlmIoLogConfig>,
+    /// Python steps' randomness is seeded with this before each call
+    pub(crate) seed: Option<u64>,
+    pub(crate) max_nesting_depth: usize,
+    /// How many Loop steps the running step is nested inside
+    pub(crate) nesting_depth: usize,
+}
+
+impl Default for RunScope {
+    fn default() -> Self {
+        Self {
+            usage: None,
+            workspace: None,
+            legacy_prompt_format: false,
+            llm_io_log: None,
+            seed: None,
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+            nesting_depth: 0,
+        }
+    }
+}
+
+impl RunScope {
+    /// Runs `fut` with this as the scope steps see
+    pub(crate) async fn scope<F: Future>(self, fut: F) -> F::Output {
+        RUN_SCOPE.scope(self, fut).await
+    }
+
+    /// `f` applied to the current scope, or `None` outside one
+    pub(crate) fn with_current<R>(f: impl FnOnce(&RunScope) -> R) -> Option<R> {
+        RUN_SCOPE.try_with(f).ok()
+    }
+
+    /// A copy of the current scope, or the default one outside a session
+    pub(crate) fn current() -> RunScope {
+        Self::with_current(RunScope::clone).unwrap_or_default()
+    }
+}
@@ -0,0 +1,72 @@
+use super::types::RuntimeSession;
+use crate::models::steps::{STEP_OUTPUT_SOURCE_KEY, STEP_OUTPUT_STATUS_KEY};
+use crate::{duration_to_json, RunningStatus};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// One step of a session as the run timeline shows it
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepSummary {
+    pub step_uuid: String,
+    pub step_type: &'static str,
+    pub description: Option<String>,
+    /// `Completed` or `Failed` once the step ran; the session's status for the step
+    /// it stopped at; `Waiting` for steps it never reached
+    pub status: RunningStatus,
+    /// `None` for steps that never started
+    pub duration: Option<Duration>,
+    /// The step's output, or its error output when it failed but the session
+    /// carried on (see `FailurePolicy`)
+    pub output: Option<Value>,
+}
+
+impl StepSummary {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "step_uuid": self.step_uuid,
+            "step_type": self.step_type,
+            "description": self.description,
+            "status": self.status.as_str(),
+            "duration": self.duration.map(duration_to_json),
+            "output": self.output,
+        })
+    }
+}
+
+impl RuntimeSession {
+    /// The session's steps in run order, each with its status, time and output,
+    /// from what is persisted with the session (`step_results`, the execution
+    /// times and `latest_step_idx`)
+    pub fn step_summaries(&self) -> Vec<StepSummary> {
+        self.steps
+            .iter()
+            .enumerate()
+            .map(|(idx, step)| {
+                let output = self.step_results.get(idx).cloned().flatten();
+                let status = match &output {
+                    Some(output) if is_error_output(output, &step.identifiers.global_uuid) => {
+                        RunningStatus::Failed
+                    }
+                    Some(_) => RunningStatus::Completed,
+                    // The step the session stopped at (or is running)
+                    None if self.last_step_idx == Some(idx as i32) => self.status.clone(),
+                    None => RunningStatus::Waiting,
+                };
+
+                StepSummary {
+                    step_uuid: step.identifiers.global_uuid.clone(),
+                    step_type: step.step_type.as_str(),
+                    description: step.description.clone(),
+                    status,
+                    duration: self.step_execution_times.get(idx).copied(),
+                    output,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Whether `output` is the error output `Step::error_output` recorded for the step
+fn is_error_output(output: &Value, step_uuid: &str) -> bool {
+    output[STEP_OUTPUT_STATUS_KEY] == "error" && output[STEP_OUTPUT_SOURCE_KEY] == step_uuid
+}
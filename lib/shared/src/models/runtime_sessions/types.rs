@@ -1,8 +1,15 @@
+use super::budget::{BudgetLimit, RunBudget};
+use super::context::RunContext;
+use super::events::RuntimeEvent;
+use super::policy::FailurePolicy;
+use crate::models::steps::{LlmIoLogConfig, StepErrorKind, DEFAULT_MAX_NESTING_DEPTH};
 use crate::{IdFields, RunningStatus, Step, TimestampFields};
 use serde_json::Value;
+use sqlx::PgPool;
+use std::collections::HashMap;
 use std::time::Duration;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RuntimeSession {
     pub identifiers: IdFields<i64>,
     pub timestamps: TimestampFields,
@@ -14,7 +21,21 @@ pub struct RuntimeSession {
     pub step_execution_times: Vec<Duration>, // Stores duration for each step
     pub total_execution_time: Duration,      // Stores total runtime
     pub requested_by_agent_id: Option<i32>, // The local ID of the agent that requested this session
-    pub step_results: Vec<Option<Value>>,   // Stores result for each step (None if failed)
+    pub step_results: Vec<Option<Value>>,   // Stores result for each step (its error output if failed, None if not run)
+    pub error_kind: Option<StepErrorKind>,  // Category of the failure that cancelled the session
+    pub budget: Option<RunBudget>,          // Limits enforced while running (not persisted)
+    pub budget_exceeded: Option<BudgetLimit>, // The limit that stopped the session, if any
+    pub metadata: Option<Value>, // Caller-supplied tags for tracing, e.g. request, tenant or experiment ids
+    pub failure_policy: FailurePolicy, // What to do when a step fails (not persisted)
+    pub legacy_prompt_format: bool, // Prompt steps get a JSON context block instead of a rendered template (not persisted)
+    pub llm_io_log: Option<LlmIoLogConfig>, // Whether Prompt steps log their prompts and responses (not persisted)
+    pub seed: Option<u64>, // Seeds Python steps' randomness before each call, for reproducible runs (not persisted)
+    pub min_inter_step_delay: Duration, // Pause between consecutive steps, counted in the total time only (not persisted)
+    pub max_nesting_depth: usize, // How deep Loop steps may nest inside each other (not persisted)
+    pub context: RunContext, // Why the run was started, exposed to Python steps (not persisted)
+    pub named_outputs: HashMap<String, Value>, // Named outputs of the steps run so far, later ones win (not persisted)
+    pub checkpoint_pool: Option<PgPool>, // Where progress is saved after each step, if anywhere (not persisted)
+    pub events: Vec<RuntimeEvent>, // Noteworthy things seen during the last run, e.g. slow steps (not persisted)
 }
 
 impl RuntimeSession {
@@ -31,6 +52,74 @@ impl RuntimeSession {
             total_execution_time: Duration::ZERO,
             requested_by_agent_id,
             step_results: Vec::new(),
+            error_kind: None,
+            budget: None,
+            budget_exceeded: None,
+            metadata: None,
+            failure_policy: FailurePolicy::default(),
+            legacy_prompt_format: false,
+            llm_io_log: None,
+            seed: None,
+            min_inter_step_delay: Duration::ZERO,
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+            context: RunContext::default(),
+            named_outputs: HashMap::new(),
+            checkpoint_pool: None,
+            events: Vec::new(),
         }
     }
+
+    /// A session started on behalf of the agent with local ID `agent_id`,
+    /// so the run can be attributed to it once saved
+    pub fn new_requested_by(source_data: Value, steps: Vec<Step>, agent_id: i32) -> Self {
+        Self::new(source_data, steps, Some(agent_id))
+    }
+
+    /// Stamps the session with `metadata` (e.g. `{"request_id": ..., "tenant_id": ...}`),
+    /// saved with it for correlating runs
+    pub fn with_metadata(mut self, metadata: Value) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// Seeds Python's `random` (and `numpy.random`) with `seed` before each Python
+    /// step, so steps using randomness give the same outputs on every run
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Waits `delay` between one step finishing and the next starting, e.g. to
+    /// stay under an external API's rate limit. The pauses count toward the
+    /// session's total time but not its steps' times.
+    pub fn with_min_inter_step_delay(mut self, delay: Duration) -> Self {
+        self.min_inter_step_delay = delay;
+        self
+    }
+
+    /// Lets Loop steps nest at most `max_depth` levels deep (default:
+    /// `DEFAULT_MAX_NESTING_DEPTH`); a deeper one fails the step when it's reached
+    pub fn with_max_nesting_depth(mut self, max_depth: usize) -> Self {
+        self.max_nesting_depth = max_depth;
+        self
+    }
+
+    /// Enforces `budget` when the session runs
+    pub fn with_budget(mut self, budget: RunBudget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Handles failing steps according to `policy` (default: `FailFast`)
+    pub fn with_failure_policy(mut self, policy: FailurePolicy) -> Self {
+        self.failure_policy = policy;
+        self
+    }
+
+    /// Saves the session's row after every step while it runs, so a run cut short
+    /// (e.g. by the process dying) can be picked up with `try_resume_from_db`
+    pub fn with_checkpoints(mut self, pool: PgPool) -> Self {
+        self.checkpoint_pool = Some(pool);
+        self
+    }
 }
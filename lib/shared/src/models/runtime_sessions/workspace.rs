@@ -0,0 +1,68 @@
+use super::scope::RunScope;
+use crate::models::steps::{StepError, StepErrorKind};
+use anyhow::Result;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+/// Scratch directory a running session's FileOp steps are confined to. It's
+/// created on first use and removed along with everything in it when the
+/// session's last reference to it is dropped.
+#[derive(Debug, Default)]
+pub(crate) struct SessionWorkspace {
+    dir: Mutex<Option<TempDir>>,
+}
+
+impl SessionWorkspace {
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    fn root(&self) -> Result<PathBuf> {
+        let mut dir = self.dir.lock().unwrap();
+        if dir.is_none() {
+            *dir = Some(
+                tempfile::Builder::new()
+                    .prefix("portico-session-")
+                    .tempdir()?,
+            );
+        }
+        Ok(dir
+            .as_ref()
+            .map(|dir| dir.path().to_path_buf())
+            .unwrap_or_default())
+    }
+}
+
+/// Resolves `path` inside the current session's workspace. Absolute paths are
+/// taken relative to the workspace root; `..` is rejected rather than resolved,
+/// so no path can leave the workspace. Fails outside a running session.
+pub(crate) fn workspace_path(path: &str) -> Result<PathBuf> {
+    let root = RunScope::with_current(|scope| scope.workspace.as_ref().map(|ws| ws.root()))
+        .flatten()
+        .ok_or_else(|| {
+            StepError::new(
+                StepErrorKind::Config,
+                "FileOp steps need a session workspace and can only run inside a session",
+            )
+        })??;
+    Ok(root.join(sandboxed_relative_path(path)?))
+}
+
+fn sandboxed_relative_path(path: &str) -> Result<PathBuf> {
+    let mut relative = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir | Component::Prefix(_) => {
+                return Err(StepError::new(
+                    StepErrorKind::Validation,
+                    format!("Path '{}' escapes the session workspace", path),
+                )
+                .into())
+            }
+        }
+    }
+    Ok(relative)
+}
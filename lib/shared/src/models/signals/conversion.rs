@@ -1,4 +1,4 @@
-use super::types::{Signal, SignalType};
+use super::types::{Signal, SignalSource, SignalType};
 use crate::models::agents::Agent;
 use crate::{IdFields, JsonLike, TimestampFields};
 use anyhow::{anyhow, Result};
@@ -16,6 +16,7 @@ impl JsonLike for Signal {
             "agent": self.agent.as_ref().map(|a| a.to_json()),
             "linked_rts_id": self.linked_rts.as_ref().and_then(|rts| rts.identifiers.local_id),
             "signal_type": self.signal_type.as_str(),
+            "source": self.source.to_string(),
             "initial_data": self.initial_data,
             "result_data": self.result_data,
             "error_message": self.error_message
@@ -45,7 +46,12 @@ impl JsonLike for Signal {
             .map_err(|e| anyhow!("Invalid signal type: {}", e))?;
 
         // Optional fields
-        let local_id = obj.get("id").and_then(|v| v.as_i64()).map(|id| id as i64);
+        let source = match obj.get("source").and_then(|v| v.as_str()) {
+            Some(source) => SignalSource::from_str(source).map_err(|e| anyhow!(e))?,
+            None => SignalSource::default(),
+        };
+
+        let local_id = obj.get("id").and_then(|v| v.as_i64());
 
         let agent = if let Some(agent_obj) = obj.get("agent") {
             if agent_obj.is_null() {
@@ -75,6 +81,7 @@ impl JsonLike for Signal {
             agent,
             linked_rts: None, // This would need to be loaded separately
             signal_type,
+            source,
             initial_data,
             result_data,
             error_message,
@@ -1,28 +1,32 @@
 use super::types::Signal;
 use crate::models::agents::Agent;
-use crate::models::agents::AgentState;
-use crate::models::SignalType;
-use crate::{DatabaseItem, IdFields, TimestampFields};
+use crate::models::agents::AgentConfig;
+use crate::models::{SignalSource, SignalType};
+use crate::{DatabaseItem, IdFields, RunningStatus, TimestampFields};
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use serde_json::Value;
+use chrono::{DateTime, Utc};
 use sqlx::{PgPool, Row};
 use std::sync::Mutex;
 use uuid::Uuid;
 
 impl sqlx::FromRow<'_, sqlx::postgres::PgRow> for Signal {
     fn from_row(row: &sqlx::postgres::PgRow) -> sqlx::Result<Self> {
-        // Get the signal type
-        let signal_type = if let Ok(signal_type_str) = row.try_get::<&str, _>("signal_type") {
-            signal_type_str
-                .parse()
-                .unwrap_or(crate::models::signals::SignalType::Fyi)
-        } else {
-            crate::models::signals::SignalType::Fyi
-        };
+        // Get the signal type (a `signal_type` enum column, so it can't be read as text)
+        let signal_type = row
+            .try_get::<SignalType, _>("signal_type")
+            .unwrap_or(SignalType::Fyi);
 
-        // Get the agent if one exists
-        let agent = if row.try_get::<Option<i64>, _>("agent_id")?.is_some() {
+        // Rows written before signals recorded their source were all user requests
+        let source = row
+            .try_get::<Option<String>, _>("source")
+            .ok()
+            .flatten()
+            .and_then(|source| source.parse::<SignalSource>().ok())
+            .unwrap_or_default();
+
+        // Get the agent if one exists (`agents.id` is an INT4)
+        let agent = if row.try_get::<Option<i32>, _>("agent_id")?.is_some() {
             Some(Agent {
                 identifiers: IdFields {
                     local_id: row.try_get("agent_id")?,
@@ -32,9 +36,14 @@ impl sqlx::FromRow<'_, sqlx::postgres::PgRow> for Signal {
                     created: row.try_get("agent_created_at")?,
                     updated: row.try_get("agent_updated_at")?,
                 },
-                description: row.try_get("agent_description")?,
+                description: row
+                    .try_get::<Option<String>, _>("agent_description")?
+                    .unwrap_or_default(),
                 agent_state: Mutex::new(row.try_get("agent_state")?),
                 steps: Vec::new(), // Steps are loaded separately
+                config: AgentConfig::default(),
+                env: Default::default(),
+                runtime_cache: Default::default(),
             })
         } else {
             None
@@ -51,6 +60,7 @@ impl sqlx::FromRow<'_, sqlx::postgres::PgRow> for Signal {
             },
             user_requested_uuid: row.try_get::<Uuid, _>("user_requested_uuid")?.to_string(),
             signal_type,
+            source,
             linked_rts: None, // This will be populated after if needed
             agent,
             initial_data: row.try_get("initial_data")?,
@@ -60,58 +70,195 @@ impl sqlx::FromRow<'_, sqlx::postgres::PgRow> for Signal {
     }
 }
 
-#[async_trait]
-impl DatabaseItem for Signal {
-    type IdType = i64;
-
-    fn id(&self) -> &IdFields<Self::IdType> {
-        &self.identifiers
+impl Signal {
+    /// Like `try_db_create`, but first upserts the signal's agent (see
+    /// `Agent::try_db_upsert`), so a signal whose agent isn't persisted yet can
+    /// still be saved. Either everything is written or nothing is.
+    pub async fn try_db_create_with_agent(&self, pool: &PgPool) -> Result<()> {
+        self.create_in_transaction(pool, true).await
     }
 
-    async fn try_db_create(&self, pool: &PgPool) -> Result<()> {
+    /// Writes parent rows first (agent, then runtime session, then signal), the
+    /// same order as every other create, so concurrent creates can't deadlock
+    async fn create_in_transaction(&self, pool: &PgPool, upsert_agent: bool) -> Result<()> {
+        let mut tx = pool.begin().await?;
+
         // First, check if a record with this UUID already exists
-        if crate::check_exists_by_uuid(pool, "signals", &self.identifiers.global_uuid).await? {
+        if crate::check_exists_by_uuid(&mut *tx, "signals", &self.identifiers.global_uuid).await? {
             return Err(anyhow!(
                 "Signal with UUID {} already exists",
                 self.identifiers.global_uuid
             ));
         }
 
-        // First ensure the linked RuntimeSession is saved if it exists
-        if let Some(rts) = &self.linked_rts {
-            rts.try_db_create(pool).await?;
-        }
+        let agent_id = match &self.agent {
+            Some(agent) if upsert_agent => Some(agent.upsert_in(&mut tx).await?),
+            agent => agent.as_ref().and_then(|a| a.identifiers.local_id),
+        };
+
+        // Then ensure the linked RuntimeSession is saved if it exists
+        let rts_id = match &self.linked_rts {
+            Some(rts) => Some(rts.insert_in(&mut tx).await?),
+            None => None,
+        };
 
         let uuid_parsed = Uuid::parse_str(&self.identifiers.global_uuid)?;
         let user_requested_uuid = Uuid::parse_str(&self.user_requested_uuid)?;
         let signal_type_str = self.signal_type.as_str();
+        let source = self.source.to_string();
 
         sqlx::query!(
             r#"
             INSERT INTO signals (
                 global_uuid, user_requested_uuid, agent_id, rts_id,
-                signal_type, initial_data, response_data, error_message
-            ) VALUES ($1, $2, $3, $4, ($5::text)::signal_type, $6, $7, $8)
+                signal_type, initial_data, response_data, error_message, source
+            ) VALUES ($1, $2, $3, $4, ($5::text)::signal_type, $6, $7, $8, $9)
             "#,
             uuid_parsed,
             user_requested_uuid,
-            self.agent.as_ref().and_then(|a| a.identifiers.local_id),
-            self.linked_rts
-                .as_ref()
-                .and_then(|rts| rts.identifiers.local_id),
+            agent_id,
+            rts_id,
             signal_type_str,
             &self.initial_data as _,
             &self.result_data as _,
-            &self.error_message.as_deref().unwrap_or_default()
+            &self.error_message.as_deref().unwrap_or_default(),
+            source
         )
-        .execute(pool)
+        .execute(&mut *tx)
         .await
         .map_err(|e| anyhow!("Failed to create signal: {}", e))?;
 
+        tx.commit().await?;
         Ok(())
     }
 
-    async fn try_db_update(&self, pool: &PgPool) -> Result<()> {
+    /// Saves what `process` produced in one transaction: the linked runtime
+    /// session (if the run got that far), then the signal's result, error and
+    /// session link. Fails if the signal has no row to update.
+    pub(super) async fn save_outcome(&self, pool: &PgPool) -> Result<()> {
+        let mut tx = pool.begin().await?;
+
+        let rts_id = match &self.linked_rts {
+            Some(rts) => Some(rts.insert_in(&mut tx).await?),
+            None => None,
+        };
+
+        let uuid_parsed = Uuid::parse_str(&self.identifiers.global_uuid)?;
+        let updated = sqlx::query!(
+            r#"
+            UPDATE signals SET
+                rts_id = COALESCE($1, rts_id),
+                response_data = $2,
+                error_message = $3,
+                updated_at = CURRENT_TIMESTAMP
+            WHERE global_uuid = $4
+            "#,
+            rts_id,
+            &self.result_data as _,
+            &self.error_message.as_deref().unwrap_or_default(),
+            uuid_parsed
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| anyhow!("Failed to save signal outcome: {}", e))?;
+        if updated.rows_affected() == 0 {
+            return Err(anyhow!(
+                "Signal with UUID {} isn't in the database, so its outcome can't be saved",
+                self.identifiers.global_uuid
+            ));
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Links the signal with local ID `signal_id` to the saved runtime session
+    /// `session_uuid`, for runs made outside `process` (e.g. by the engine's agent
+    /// workers). Returns whether there was a signal to link.
+    pub async fn try_db_link_session(
+        pool: &PgPool,
+        signal_id: i64,
+        session_uuid: &str,
+    ) -> Result<bool> {
+        let session_uuid = Uuid::parse_str(session_uuid)?;
+        let updated = sqlx::query!(
+            r#"
+            UPDATE signals SET
+                rts_id = (SELECT id FROM runtime_sessions WHERE global_uuid = $1),
+                updated_at = CURRENT_TIMESTAMP
+            WHERE id = $2
+            "#,
+            session_uuid,
+            signal_id
+        )
+        .execute(pool)
+        .await
+        .map_err(|e| anyhow!("Failed to link signal to its session: {}", e))?;
+
+        Ok(updated.rows_affected() > 0)
+    }
+
+    /// All signals requested by `user_uuid`, newest first.
+    /// Backed by the `signals_user_requested_uuid_idx` (user_requested_uuid, created_at) index.
+    pub async fn try_db_select_by_user_uuid(pool: &PgPool, user_uuid: &str) -> Result<Vec<Self>> {
+        let user_uuid = Uuid::parse_str(user_uuid)?;
+        let signals = sqlx::query_as::<_, Signal>(&crate::signal_with_agent_sql(
+            "WHERE s.user_requested_uuid = $1 ORDER BY s.created_at DESC, s.id DESC",
+        ))
+        .bind(user_uuid)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(signals)
+    }
+
+    /// Run signals for the agent with local ID `agent_id`, oldest first, e.g. to
+    /// reprocess the ones that failed. `status` matches the outcome of each signal's
+    /// run: its linked session's status, or `Failed` for a signal that errored
+    /// without one (signals not processed yet have no status; they're saved with an
+    /// empty error message). `since` keeps the signals created from then on.
+    pub async fn try_db_select_by_agent(
+        pool: &PgPool,
+        agent_id: i32,
+        status: Option<RunningStatus>,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Self>> {
+        let signals = sqlx::query_as::<_, Signal>(&crate::signal_with_agent_sql(
+            r#"
+            WHERE s.agent_id = $1
+                AND s.signal_type = 'run'
+                AND ($2::running_status IS NULL OR $2 = COALESCE(
+                    (SELECT rs.rts_status FROM runtime_sessions rs WHERE rs.id = s.rts_id),
+                    CASE WHEN NULLIF(s.error_message, '') IS NOT NULL THEN 'failed'::running_status END
+                ))
+                AND ($3::timestamptz IS NULL OR s.created_at >= $3)
+            ORDER BY s.created_at, s.id
+            "#,
+        ))
+        .bind(agent_id)
+        .bind(status)
+        .bind(since)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(signals)
+    }
+}
+
+#[async_trait]
+impl DatabaseItem for Signal {
+    type IdType = i64;
+    const MODEL: &'static str = "signal";
+
+    fn id(&self) -> &IdFields<Self::IdType> {
+        &self.identifiers
+    }
+
+    async fn db_create(&self, pool: &PgPool) -> Result<()> {
+        self.create_in_transaction(pool, false).await
+    }
+
+    async fn db_update(&self, pool: &PgPool) -> Result<()> {
         let id = self
             .identifiers
             .local_id
@@ -123,6 +270,7 @@ impl DatabaseItem for Signal {
         }
 
         let signal_type_str = self.signal_type.as_str();
+        let source = self.source.to_string();
         let user_requested_uuid = Uuid::parse_str(&self.user_requested_uuid)?;
 
         sqlx::query!(
@@ -135,8 +283,9 @@ impl DatabaseItem for Signal {
                 initial_data = $5,
                 response_data = $6,
                 error_message = $7,
+                source = $8,
                 updated_at = CURRENT_TIMESTAMP
-            WHERE id = $8
+            WHERE id = $9
             "#,
             user_requested_uuid,
             self.agent.as_ref().and_then(|a| a.identifiers.local_id),
@@ -147,6 +296,7 @@ impl DatabaseItem for Signal {
             &self.initial_data as _,
             &self.result_data as _,
             &self.error_message.as_deref().unwrap_or_default(),
+            source,
             id
         )
         .execute(pool)
@@ -156,7 +306,7 @@ impl DatabaseItem for Signal {
         Ok(())
     }
 
-    async fn try_db_delete(&self, pool: &PgPool) -> Result<()> {
+    async fn db_delete(&self, pool: &PgPool) -> Result<()> {
         let id = self
             .identifiers
             .local_id
@@ -170,160 +320,48 @@ impl DatabaseItem for Signal {
         Ok(())
     }
 
-    async fn try_db_select_all(pool: &PgPool) -> Result<Vec<Self>> {
-        // Define struct compatible with query_as! output
-        struct SignalRow {
-            id: i64,
-            global_uuid: uuid::Uuid,
-            user_requested_uuid: String,
-            created_at: chrono::DateTime<chrono::Utc>,
-            updated_at: chrono::DateTime<chrono::Utc>,
-            agent_id: Option<i32>,
-            agent_global_uuid: Option<uuid::Uuid>,
-            agent_created_at: Option<chrono::DateTime<chrono::Utc>>,
-            agent_updated_at: Option<chrono::DateTime<chrono::Utc>>,
-            agent_description: Option<String>,
-            agent_state: Option<AgentState>,
-            #[allow(dead_code)]
-            /// This field is required to match the SQL query structure but is handled
-            /// separately through RuntimeSession loading after row mapping
-            rts_id: Option<i64>,
-            signal_type: SignalType,
-            initial_data: Option<serde_json::Value>,
-            response_data: Option<serde_json::Value>,
-            error_message: Option<String>,
-        }
-
-        let rows = sqlx::query_as!(
-            SignalRow,
-            r#"
-            SELECT
-                s.id, s.global_uuid, s.user_requested_uuid,
-                s.created_at, s.updated_at,
-                s.signal_type as "signal_type: _",
-                s.initial_data as "initial_data: serde_json::Value",
-                s.response_data as "response_data: serde_json::Value",
-                s.error_message,
-                s.rts_id,
-                a.id as agent_id,
-                a.global_uuid as agent_global_uuid,
-                a.created_at as agent_created_at,
-                a.updated_at as agent_updated_at,
-                a.description as agent_description,
-                a.agent_state as "agent_state: AgentState"
-            FROM signals s
-            LEFT JOIN agents a ON s.agent_id = a.id
-            "#
-        )
-        .fetch_all(pool)
-        .await?;
-
-        let mut signals = Vec::with_capacity(rows.len());
-        for row in rows {
-            let signal = Signal {
-                identifiers: IdFields {
-                    local_id: Some(row.id),
-                    global_uuid: row.global_uuid.to_string(),
-                },
-                timestamps: TimestampFields {
-                    created: row.created_at,
-                    updated: row.updated_at,
-                },
-                user_requested_uuid: row.user_requested_uuid,
-                agent: if row.agent_id.is_some() {
-                    Some(Agent {
-                        identifiers: IdFields {
-                            local_id: row.agent_id,
-                            global_uuid: row
-                                .agent_global_uuid
-                                .map(|uuid| uuid.to_string())
-                                .unwrap_or_default(),
-                        },
-                        timestamps: TimestampFields {
-                            created: row.agent_created_at.unwrap_or_default(),
-                            updated: row.agent_updated_at.unwrap_or_default(),
-                        },
-                        description: row.agent_description.unwrap_or_default(),
-                        agent_state: Mutex::new(row.agent_state.unwrap_or_default()),
-                        steps: Vec::new(), // Steps are loaded separately
-                    })
-                } else {
-                    None
-                },
-                linked_rts: None, // Will be populated after if needed
-                signal_type: row.signal_type,
-                initial_data: row.initial_data,
-                result_data: row.response_data,
-                error_message: row.error_message,
-            };
-
-            signals.push(signal);
-        }
+    async fn db_select_all(pool: &PgPool) -> Result<Vec<Self>> {
+        let signals = sqlx::query_as::<_, Signal>(&crate::signal_with_agent_sql(""))
+            .fetch_all(pool)
+            .await?;
 
         Ok(signals)
     }
 
-    async fn try_db_select_by_id(
+    async fn db_select_by_id(
         pool: &PgPool,
         id: &IdFields<Self::IdType>,
     ) -> Result<Option<Self>> {
         let uuid_parsed = Uuid::parse_str(&id.global_uuid)?;
-        let row = sqlx::query!(
-            r#"
-            SELECT
-                s.id, s.global_uuid, s.user_requested_uuid,
-                s.created_at, s.updated_at,
-                s.signal_type as "signal_type!: crate::models::signals::SignalType",
-                s.initial_data as "initial_data: Value",
-                s.response_data as "response_data: Value",
-                s.error_message,
-                a.id as "agent_id?",
-                a.global_uuid as "agent_global_uuid?",
-                a.created_at as "agent_created_at?",
-                a.updated_at as "agent_updated_at?",
-                a.description as "agent_description?",
-                a.agent_state as "agent_state?: crate::models::agents::AgentState"
-            FROM signals s
-            LEFT JOIN agents a ON s.agent_id = a.id
-            WHERE s.global_uuid = $1
-            "#,
-            uuid_parsed
-        )
+        let signal = sqlx::query_as::<_, Signal>(&crate::signal_with_agent_sql(
+            "WHERE s.global_uuid = $1",
+        ))
+        .bind(uuid_parsed)
         .fetch_optional(pool)
         .await?;
 
-        Ok(row.map(|row| Signal {
-            identifiers: IdFields {
-                local_id: Some(row.id),
-                global_uuid: row.global_uuid.to_string(),
-            },
-            timestamps: TimestampFields {
-                created: row.created_at,
-                updated: row.updated_at,
-            },
-            user_requested_uuid: row.user_requested_uuid.to_string(),
-            agent: if row.agent_id.is_some() {
-                Some(Agent {
-                    identifiers: IdFields {
-                        local_id: row.agent_id,
-                        global_uuid: row.agent_global_uuid.unwrap().to_string(),
-                    },
-                    timestamps: TimestampFields {
-                        created: row.agent_created_at.unwrap(),
-                        updated: row.agent_updated_at.unwrap(),
-                    },
-                    description: row.agent_description.unwrap_or_default(),
-                    agent_state: Mutex::new(row.agent_state.unwrap_or_default()),
-                    steps: Vec::new(), // Steps are loaded separately
-                })
-            } else {
-                None
-            },
-            linked_rts: None, // Will be populated after if needed
-            signal_type: row.signal_type,
-            initial_data: row.initial_data,
-            result_data: row.response_data,
-            error_message: row.error_message,
-        }))
+        Ok(signal)
+    }
+
+    async fn db_select_by_ids(pool: &PgPool, ids: &[Self::IdType]) -> Result<Vec<Self>> {
+        let signals = sqlx::query_as::<_, Signal>(&crate::signal_with_agent_sql(
+            "WHERE s.id = ANY($1) ORDER BY s.id",
+        ))
+        .bind(ids)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(signals)
+    }
+
+    async fn db_select_by_uuids(pool: &PgPool, uuids: &[String]) -> Result<Vec<Self>> {
+        let signals = sqlx::query_as::<_, Signal>(&crate::signal_with_agent_sql(
+            "WHERE s.global_uuid = ANY($1) ORDER BY s.id",
+        ))
+        .bind(crate::parse_uuids(uuids)?)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(signals)
     }
 }
@@ -1,8 +1,13 @@
-use super::types::{RunPayload, Signal, SignalType, SyncPayload};
+use super::types::{RunPayload, Signal, SignalSource, SignalType, SyncPayload};
+use crate::models::runtime_sessions::RunContext;
+use crate::RunningStatus;
 use anyhow::{anyhow, Result};
 use serde_json::Value;
+use sqlx::PgPool;
 
 impl Signal {
+    /// Creates a signal requested by a user; see `new_from_agent` and `with_source`
+    /// for other origins
     pub fn new(
         identifiers: crate::IdFields<i64>,
         user_requested_uuid: String,
@@ -17,6 +22,7 @@ impl Signal {
             agent,
             linked_rts: None,
             signal_type,
+            source: SignalSource::User,
             initial_data,
             result_data: None,
             error_message: None,
@@ -28,6 +34,7 @@ impl Signal {
         user_requested_uuid: String,
         agent: Option<crate::models::agents::Agent>,
         run_payload: RunPayload,
+        source: SignalSource,
     ) -> Self {
         Self::new(
             identifiers,
@@ -36,6 +43,7 @@ impl Signal {
             SignalType::Run,
             Some(serde_json::to_value(run_payload).unwrap_or(Value::Null)),
         )
+        .with_source(source)
     }
 
     pub fn new_sync(
@@ -43,6 +51,7 @@ impl Signal {
         user_requested_uuid: String,
         agent: Option<crate::models::agents::Agent>,
         sync_payload: SyncPayload,
+        source: SignalSource,
     ) -> Self {
         Self::new(
             identifiers,
@@ -51,6 +60,7 @@ impl Signal {
             SignalType::Sync,
             Some(serde_json::to_value(sync_payload).unwrap_or(Value::Null)),
         )
+        .with_source(source)
     }
 
     pub fn new_fyi(
@@ -58,6 +68,7 @@ impl Signal {
         user_requested_uuid: String,
         agent: Option<crate::models::agents::Agent>,
         data: Value,
+        source: SignalSource,
     ) -> Self {
         Self::new(
             identifiers,
@@ -66,6 +77,32 @@ impl Signal {
             SignalType::Fyi,
             Some(data),
         )
+        .with_source(source)
+    }
+
+    /// Creates a signal sent by `sender`, stamped with its global UUID
+    pub fn new_from_agent(
+        sender: &crate::models::agents::Agent,
+        identifiers: crate::IdFields<i64>,
+        user_requested_uuid: String,
+        agent: Option<crate::models::agents::Agent>,
+        signal_type: SignalType,
+        initial_data: Option<Value>,
+    ) -> Self {
+        Self::new(
+            identifiers,
+            user_requested_uuid,
+            agent,
+            signal_type,
+            initial_data,
+        )
+        .with_source(SignalSource::Agent(sender.identifiers.global_uuid.clone()))
+    }
+
+    /// Marks where the signal came from, e.g. a schedule or another agent
+    pub fn with_source(mut self, source: SignalSource) -> Self {
+        self.source = source;
+        self
     }
 
     pub fn parse_run_payload(&self) -> Result<RunPayload> {
@@ -95,24 +132,54 @@ impl Signal {
         }
     }
 
-    pub async fn process(&mut self) -> Result<()> {
-        match self.execute_signal().await {
+    /// Runs the signal's agent and saves the outcome: `result_data` is the
+    /// session's final result, `error_message` is set if the run failed or
+    /// stopped early, and the session is saved and linked. All of it is written
+    /// in one transaction, which updates the signal's existing row.
+    ///
+    /// An FYI signal without an agent is only recorded: it completes with its
+    /// data as the result and no session.
+    pub async fn process(&mut self, pool: &PgPool) -> Result<()> {
+        if self.agent.is_none() && self.signal_type == SignalType::Fyi {
+            self.result_data = self.initial_data.clone();
+            self.error_message = None;
+            return self.save_outcome(pool).await;
+        }
+
+        let run_error = match self.execute_signal().await {
             Ok(runtime_session) => {
+                self.result_data = runtime_session.last_successful_result.clone();
+                self.error_message = match &runtime_session.status {
+                    RunningStatus::Completed => None,
+                    status => Some(format!("Run ended with status {}", status.as_str())),
+                };
                 self.linked_rts = Some(runtime_session);
-                Ok(())
+                None
             }
             Err(e) => {
+                self.result_data = None;
                 self.error_message = Some(e.to_string());
-                Err(e)
+                Some(e)
             }
+        };
+
+        self.save_outcome(pool).await?;
+        match run_error {
+            Some(e) => Err(e),
+            None => Ok(()),
         }
     }
 
     async fn execute_signal(&self) -> Result<crate::models::runtime_sessions::RuntimeSession> {
         match &self.agent {
             Some(agent) => {
+                let context = RunContext {
+                    trigger: "signal".to_string(),
+                    requested_by: Some(self.user_requested_uuid.clone()),
+                    signal_uuid: Some(self.identifiers.global_uuid.clone()),
+                };
                 let result = agent
-                    .run(self.initial_data.clone().unwrap_or(Value::Null))
+                    .run_with_context(self.initial_data.clone().unwrap_or(Value::Null), context)
                     .await?;
                 Ok(result)
             }
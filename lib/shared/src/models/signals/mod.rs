@@ -3,4 +3,4 @@ mod database;
 mod execution;
 mod types;
 
-pub use types::{RunDataPayload, RunPayload, Signal, SignalType, SyncPayload};
+pub use types::{RunDataPayload, RunPayload, Signal, SignalSource, SignalType, SyncPayload};
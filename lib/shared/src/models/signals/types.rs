@@ -3,7 +3,9 @@ use crate::models::runtime_sessions::RuntimeSession;
 use crate::{IdFields, TimestampFields};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::fmt;
 use std::str::FromStr;
+use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum SignalType {
@@ -67,6 +69,47 @@ impl<'q> sqlx::Encode<'q, sqlx::Postgres> for SignalType {
     }
 }
 
+/// Where a signal came from, stored in the `source` column as `user`,
+/// `scheduled`, `webhook` or `agent:<uuid>`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum SignalSource {
+    #[default]
+    User,
+    Scheduled,
+    Webhook,
+    /// Sent by another agent, identified by its global UUID
+    Agent(String),
+}
+
+impl fmt::Display for SignalSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignalSource::User => write!(f, "user"),
+            SignalSource::Scheduled => write!(f, "scheduled"),
+            SignalSource::Webhook => write!(f, "webhook"),
+            SignalSource::Agent(uuid) => write!(f, "agent:{}", uuid),
+        }
+    }
+}
+
+impl FromStr for SignalSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(SignalSource::User),
+            "scheduled" => Ok(SignalSource::Scheduled),
+            "webhook" => Ok(SignalSource::Webhook),
+            _ => match s.strip_prefix("agent:") {
+                Some(uuid) if Uuid::parse_str(uuid).is_ok() => {
+                    Ok(SignalSource::Agent(uuid.to_string()))
+                }
+                _ => Err(format!("Invalid signal source: {}", s)),
+            },
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RunPayload {
     pub operation: String,
@@ -86,7 +129,7 @@ pub struct SyncPayload {
     pub targets: Option<Vec<String>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Signal {
     pub identifiers: IdFields<i64>,
     pub timestamps: TimestampFields,
@@ -94,6 +137,7 @@ pub struct Signal {
     pub agent: Option<Agent>,
     pub linked_rts: Option<RuntimeSession>,
     pub signal_type: SignalType,
+    pub source: SignalSource,
     pub initial_data: Option<Value>,
     pub result_data: Option<Value>,
     pub error_message: Option<String>,
@@ -0,0 +1,95 @@
+use super::output::StepOutput;
+use super::types::Step;
+use crate::models::runtime_sessions::RunScope;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{LazyLock, Mutex};
+
+/// Most outputs `STEP_CACHE` keeps; the least recently used are dropped first
+const STEP_CACHE_CAPACITY: usize = 1024;
+
+/// (step UUID, hash of the step's definition and input), see `Step::cache_key`
+pub(crate) type StepCacheKey = (String, String);
+
+/// Outputs of `cacheable` steps, shared by all sessions in the process
+static STEP_CACHE: LazyLock<Mutex<StepCache>> =
+    LazyLock::new(|| Mutex::new(StepCache::new(STEP_CACHE_CAPACITY)));
+
+/// In-memory LRU map of step outputs by `StepCacheKey`
+#[derive(Debug)]
+pub(crate) struct StepCache {
+    capacity: usize,
+    /// Output and when it was last used (a `tick`)
+    entries: HashMap<StepCacheKey, (u64, StepOutput)>,
+    tick: u64,
+}
+
+impl StepCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            tick: 0,
+        }
+    }
+
+    pub(crate) fn get(&mut self, key: &StepCacheKey) -> Option<StepOutput> {
+        self.tick += 1;
+        let (last_used, output) = self.entries.get_mut(key)?;
+        *last_used = self.tick;
+        Some(output.clone())
+    }
+
+    pub(crate) fn insert(&mut self, key: StepCacheKey, output: StepOutput) {
+        self.tick += 1;
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (last_used, _))| *last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(key, (self.tick, output));
+    }
+}
+
+/// The cached output of a step for a key, if there is one
+pub(crate) fn cached_step_output(key: &StepCacheKey) -> Option<StepOutput> {
+    STEP_CACHE.lock().unwrap().get(key)
+}
+
+pub(crate) fn cache_step_output(key: StepCacheKey, output: StepOutput) {
+    STEP_CACHE.lock().unwrap().insert(key, output);
+}
+
+impl Step {
+    /// Key of the step's output for `input` in the step cache. The hash covers the
+    /// step's definition too, so a step edited in place doesn't reuse old outputs,
+    /// and the prompt format and seed of the session's `scope`, which change what
+    /// it outputs. Anything else a step may read (`context`, `AGENT_ENV`, the web,
+    /// ...) isn't part of it, which is why caching is opt-in.
+    pub(crate) fn cache_key(&self, input: &StepOutput, scope: &RunScope) -> StepCacheKey {
+        let named: BTreeMap<_, _> = input.named.iter().collect();
+        let keyed = json!({
+            "step_type": self.step_type.as_str(),
+            "llm_model": self.get_llm_model(),
+            "llm_provider": self.llm_provider,
+            "system_prompt": self.system_prompt,
+            "step_content": self.step_content,
+            "input_mapping": self.input_mapping,
+            "wrap_output": self.wrap_output,
+            "legacy_prompt_format": scope.legacy_prompt_format,
+            "seed": scope.seed,
+            "primary": input.primary,
+            "named": named,
+        });
+        (
+            self.identifiers.global_uuid.clone(),
+            format!("{:x}", Sha256::digest(keyed.to_string())),
+        )
+    }
+}
@@ -0,0 +1,206 @@
+use super::execution::STEP_OUTPUT_DATA_KEY;
+use super::output::StepOutput;
+use super::types::{Step, StepError, StepErrorKind};
+use crate::models::runtime_sessions::RunScope;
+use crate::{JsonLike, PythonRuntime};
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// Output key holding the number of iterations a Loop step ran
+pub const LOOP_ITERATIONS_KEY: &str = "iterations";
+
+const DEFAULT_MAX_ITERATIONS: usize = 10;
+
+/// How many Loop steps may be nested inside each other unless a session says otherwise
+pub const DEFAULT_MAX_NESTING_DEPTH: usize = 5;
+
+/// Parsed form of a Loop step's `step_content`, e.g.
+/// ```json
+/// {
+///     "steps": [{"step_type": "python", "step_content": "..."}],
+///     "until": "$.done",
+///     "max_iterations": 5,
+///     "fail_on_max": true
+/// }
+/// ```
+/// A single inner step may be given as `"step": {...}` instead of `"steps"`.
+/// `until` is either a boolean or a predicate for `eval_predicate` (e.g. `"$.score >= 0.8"`);
+/// a lone JSONPath like `"$.done"` is checked for truthiness.
+#[derive(Clone, Debug)]
+pub struct LoopConfig {
+    pub steps: Vec<Step>,
+    pub until: Value,
+    pub max_iterations: usize,
+    /// Whether hitting `max_iterations` without satisfying `until` is an error (default: true)
+    pub fail_on_max: bool,
+}
+
+impl LoopConfig {
+    pub fn from_step(step: &Step) -> Result<Self> {
+        let obj: Value = serde_json::from_str(&step.step_content)
+            .map_err(|e| anyhow!("Loop step content must be a JSON object: {}", e))?;
+
+        let raw_steps = match (obj.get("steps"), obj.get("step")) {
+            (Some(Value::Array(steps)), _) => steps.clone(),
+            (None, Some(single)) => vec![single.clone()],
+            _ => {
+                return Err(anyhow!(
+                    "Loop step requires a `steps` array or a `step` object"
+                ))
+            }
+        };
+        if raw_steps.is_empty() {
+            return Err(anyhow!("Loop step requires at least one inner step"));
+        }
+
+        let steps = raw_steps
+            .into_iter()
+            .enumerate()
+            .map(|(idx, mut raw)| {
+                // Inner steps aren't persisted on their own, so give them a stable
+                // identity derived from the parent (used to look up Python functions)
+                if raw.get("global_uuid").and_then(|v| v.as_str()).is_none() {
+                    raw["global_uuid"] =
+                        json!(format!("{}_loop_{}", step.identifiers.global_uuid, idx));
+                }
+                Step::from_json(raw).map_err(|e| anyhow!("Invalid inner step {}: {}", idx, e))
+            })
+            .collect::<Result<Vec<Step>>>()?;
+
+        let until = obj
+            .get("until")
+            .cloned()
+            .ok_or_else(|| anyhow!("Loop step requires an `until` predicate"))?;
+
+        let max_iterations = match obj.get("max_iterations") {
+            Some(v) => v
+                .as_u64()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| anyhow!("`max_iterations` must be a positive integer"))?
+                as usize,
+            None => DEFAULT_MAX_ITERATIONS,
+        };
+
+        let fail_on_max = obj
+            .get("fail_on_max")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(true);
+
+        Ok(Self {
+            steps,
+            until,
+            max_iterations,
+            fail_on_max,
+        })
+    }
+
+    /// Checks the termination predicate against the latest output
+    pub fn is_satisfied(&self, output: &Value) -> Result<bool> {
+        match &self.until {
+            Value::Bool(b) => Ok(*b),
+            Value::String(expr) => crate::eval_predicate(expr, output).map_err(|e| {
+                StepError::new(
+                    StepErrorKind::Config,
+                    format!("Invalid `until` predicate: {}", e),
+                )
+                .into()
+            }),
+            other => Err(anyhow!("Unsupported `until` predicate: {}", other)),
+        }
+    }
+}
+
+impl Step {
+    /// Steps nested inside a control-flow step (empty for other step types)
+    pub fn nested_steps(&self) -> Vec<Step> {
+        if self.is_loop_step() {
+            LoopConfig::from_step(self)
+                .map(|config| config.steps)
+                .unwrap_or_default()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Whether this step (or any step nested in it) needs a PythonRuntime
+    pub fn requires_runtime(&self) -> bool {
+        self.is_python_step() || self.nested_steps().iter().any(Step::requires_runtime)
+    }
+
+    /// Runs the inner steps repeatedly, feeding each output back in as input,
+    /// until the `until` predicate holds or `max_iterations` is reached.
+    /// Named outputs of the inner steps are visible to the inner steps after
+    /// them and returned with the loop's output. Fails without running anything
+    /// if the loop is nested deeper than the session allows.
+    pub(super) async fn run_loop(
+        &self,
+        source: StepOutput,
+        runtime: Option<&PythonRuntime>,
+    ) -> Result<StepOutput> {
+        let mut scope = RunScope::current();
+        let depth = scope.nesting_depth + 1;
+        let max_depth = scope.max_nesting_depth;
+        if depth > max_depth {
+            return Err(StepError::new(
+                StepErrorKind::Config,
+                format!(
+                    "Nesting depth exceeded: Loop step {} is nested {} levels deep (max {})",
+                    self.identifiers.global_uuid, depth, max_depth
+                ),
+            )
+            .into());
+        }
+
+        scope.nesting_depth = depth;
+        scope.scope(self.run_loop_iterations(source, runtime)).await
+    }
+
+    async fn run_loop_iterations(
+        &self,
+        source: StepOutput,
+        runtime: Option<&PythonRuntime>,
+    ) -> Result<StepOutput> {
+        let config = LoopConfig::from_step(self)?;
+        let mut current = source;
+        let mut produced = HashMap::new();
+
+        for iteration in 1..=config.max_iterations {
+            for (inner_idx, inner) in config.steps.iter().enumerate() {
+                let output = Box::pin(inner.run(current.clone(), inner_idx, runtime)).await?;
+                current.primary = output.primary;
+                current.named.extend(output.named.clone());
+                produced.extend(output.named);
+            }
+
+            if config.is_satisfied(&current.primary)? {
+                return Ok(StepOutput {
+                    primary: json!({
+                        STEP_OUTPUT_DATA_KEY: current.primary,
+                        LOOP_ITERATIONS_KEY: iteration,
+                    }),
+                    named: produced,
+                });
+            }
+        }
+
+        if config.fail_on_max {
+            Err(StepError::new(
+                StepErrorKind::Validation,
+                format!(
+                    "Loop predicate not satisfied after {} iterations",
+                    config.max_iterations
+                ),
+            )
+            .into())
+        } else {
+            Ok(StepOutput {
+                primary: json!({
+                    STEP_OUTPUT_DATA_KEY: current.primary,
+                    LOOP_ITERATIONS_KEY: config.max_iterations,
+                }),
+                named: produced,
+            })
+        }
+    }
+}
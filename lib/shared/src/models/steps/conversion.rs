@@ -1,10 +1,12 @@
+use super::metrics::StepMetrics;
 use super::types::{Step, StepType};
-use crate::{IdFields, JsonLike, TimestampFields};
+use crate::{duration_from_json, duration_to_json, IdFields, JsonLike, TimestampFields};
 use anyhow::{anyhow, Result};
 use serde_json::{json, Value};
 use uuid::Uuid;
 
 impl Step {
+    /// Lenient: skips any step that fails to parse (see `from_json_array_strict`)
     pub fn from_json_array(steps_json: &Value) -> Vec<Self> {
         if let Some(steps_array) = steps_json.as_array() {
             steps_array
@@ -15,6 +17,47 @@ impl Step {
             Vec::new()
         }
     }
+
+    /// Parses every step, or returns the index and error of each one that failed.
+    /// `null` is treated as no steps.
+    pub fn from_json_array_strict(
+        steps_json: &Value,
+    ) -> std::result::Result<Vec<Self>, Vec<(usize, anyhow::Error)>> {
+        let steps_array = match steps_json {
+            Value::Null => return Ok(Vec::new()),
+            Value::Array(steps_array) => steps_array,
+            other => {
+                return Err(vec![(
+                    0,
+                    anyhow!("Expected an array of steps, got {}", other),
+                )])
+            }
+        };
+
+        let mut steps = Vec::with_capacity(steps_array.len());
+        let mut errors = Vec::new();
+        for (idx, step_json) in steps_array.iter().enumerate() {
+            match Step::from_json(step_json.clone()) {
+                Ok(step) => steps.push(step),
+                Err(e) => errors.push((idx, e)),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(steps)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// One-line summary of `from_json_array_strict` errors, e.g. for logs
+    pub fn describe_json_array_errors(errors: &[(usize, anyhow::Error)]) -> String {
+        errors
+            .iter()
+            .map(|(idx, e)| format!("step {}: {}", idx, e))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
 }
 
 impl JsonLike for Step {
@@ -25,13 +68,22 @@ impl JsonLike for Step {
             "description": self.description,
             "step_type": self.step_type.as_str(),
             "step_content": self.step_content,
+            "input_mapping": self.input_mapping,
+            "cacheable": self.cacheable,
+            "expected_duration": self.expected_duration.map(duration_to_json),
+            "wrap_output": self.wrap_output,
+            "on_error": self.on_error,
+            "run_count": self.get_run_count(),
+            "success_count": self.get_success_count(),
             "created_at": self.timestamps.created.format("%Y-%m-%d %H:%M:%S").to_string(),
             "updated_at": self.timestamps.updated.format("%Y-%m-%d %H:%M:%S").to_string(),
         });
 
-        // Add llm_model field only for Prompt steps
+        // Add llm_model / llm_provider / system_prompt fields only for Prompt steps
         if let StepType::Prompt(model) = &self.step_type {
             json["llm_model"] = json!(model);
+            json["llm_provider"] = json!(self.llm_provider);
+            json["system_prompt"] = json!(self.system_prompt);
         }
 
         json
@@ -49,16 +101,41 @@ impl JsonLike for Step {
         // Handle optional fields
         let description = obj["description"].as_str().map(|s| s.to_string());
         let llm_model = obj["llm_model"].as_str().map(|s| s.to_string());
-
-        // Create the appropriate StepType based on the type string and llm_model
-        let step_type = match step_type_str {
-            "python" => StepType::Python,
-            "prompt" => StepType::Prompt(
-                llm_model.unwrap_or_else(|| crate::JsonModeLLMs::MetaLlama33_70b.to_string()),
+        let llm_provider = obj["llm_provider"].as_str().map(|s| s.to_string());
+        let system_prompt = obj["system_prompt"].as_str().map(|s| s.to_string());
+        let input_mapping = match &obj["input_mapping"] {
+            Value::Null => None,
+            mapping => Some(
+                serde_json::from_value(mapping.clone())
+                    .map_err(|e| anyhow!("input_mapping must map param names to JSONPaths: {}", e))?,
             ),
-            _ => return Err(anyhow!("Invalid step type: {}", step_type_str)),
         };
 
+        let cacheable = obj["cacheable"].as_bool().unwrap_or(false);
+        let wrap_output = obj["wrap_output"].as_bool().unwrap_or(false);
+        let on_error = obj["on_error"].as_str().map(|s| s.to_string());
+        let expected_duration = match &obj["expected_duration"] {
+            Value::Null => None,
+            secs => Some(duration_from_json(secs)?),
+        };
+
+        let metrics = StepMetrics::new(
+            obj["run_count"].as_i64().unwrap_or(0),
+            obj["success_count"].as_i64().unwrap_or(0),
+        );
+
+        // Create the appropriate StepType based on the type string and llm_model
+        let step_type =
+            StepType::from_parts(step_type_str, llm_model).map_err(|e| anyhow!("{}", e))?;
+
+        // Reject malformed patches on load rather than when the step runs
+        if let StepType::JsonPatch = step_type {
+            super::parse_json_patch(step_content)?;
+        }
+        if let StepType::FileOp = step_type {
+            super::parse_file_op(step_content)?;
+        }
+
         // Handle ID fields
         let local_id = obj["id"].as_i64().map(|id| id as i32);
         let global_uuid = if let Some(uuid_str) = obj["global_uuid"].as_str() {
@@ -69,17 +146,13 @@ impl JsonLike for Step {
 
         // Handle timestamp fields
         let created = if let Some(ts) = obj["created_at"].as_str() {
-            chrono::DateTime::parse_from_rfc3339(ts)
-                .map_err(|e| anyhow!("Invalid created_at timestamp: {}", e))?
-                .with_timezone(&chrono::Utc)
+            parse_timestamp(ts).map_err(|e| anyhow!("Invalid created_at timestamp: {}", e))?
         } else {
             chrono::Utc::now()
         };
 
         let updated = if let Some(ts) = obj["updated_at"].as_str() {
-            chrono::DateTime::parse_from_rfc3339(ts)
-                .map_err(|e| anyhow!("Invalid updated_at timestamp: {}", e))?
-                .with_timezone(&chrono::Utc)
+            parse_timestamp(ts).map_err(|e| anyhow!("Invalid updated_at timestamp: {}", e))?
         } else {
             chrono::Utc::now()
         };
@@ -93,6 +166,24 @@ impl JsonLike for Step {
             description,
             step_type,
             step_content: step_content.to_string(),
+            llm_provider,
+            system_prompt,
+            input_mapping,
+            cacheable,
+            expected_duration,
+            wrap_output,
+            on_error,
+            metrics,
         })
     }
 }
+
+/// RFC 3339, as the database's JSON has it, or the UTC `%Y-%m-%d %H:%M:%S` that
+/// `to_json` writes, so a step sent back as it was received still parses
+fn parse_timestamp(ts: &str) -> chrono::ParseResult<chrono::DateTime<chrono::Utc>> {
+    chrono::DateTime::parse_from_rfc3339(ts)
+        .map(|ts| ts.with_timezone(&chrono::Utc))
+        .or_else(|_| {
+            chrono::NaiveDateTime::parse_from_str(ts, "%Y-%m-%d %H:%M:%S").map(|ts| ts.and_utc())
+        })
+}
@@ -1,28 +1,24 @@
+use super::metrics::StepMetrics;
 use super::types::{Step, StepType};
 use crate::{DatabaseItem, IdFields, TimestampFields};
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use sqlx::{PgPool, Row};
+use serde_json::Value;
+use sqlx::{types::Json, PgPool, Row};
+use std::collections::BTreeMap;
+use std::time::Duration;
 use uuid::Uuid;
 
 impl sqlx::FromRow<'_, sqlx::postgres::PgRow> for Step {
     fn from_row(row: &sqlx::postgres::PgRow) -> sqlx::Result<Self> {
-        let step_type_str: &str = row.try_get("step_type")?;
+        // `step_type` is an enum column, whose value is its label as text
+        let step_type_str: &str = row.try_get_unchecked("step_type")?;
 
         // Try to get llm_model, but don't fail if the column doesn't exist
-        let llm_model: Option<String> = match row.try_get("llm_model") {
-            Ok(model) => model,
-            Err(_) => None, // Column might not exist yet
-        };
+        let llm_model: Option<String> = row.try_get("llm_model").unwrap_or_default();
 
-        let step_type = match step_type_str {
-            "python" => StepType::Python,
-            "prompt" => StepType::Prompt(
-                llm_model.unwrap_or_else(|| crate::JsonModeLLMs::MetaLlama33_70b.to_string()),
-            ),
-            "webscrape" => StepType::WebScrape,
-            _ => return Err(sqlx::Error::ColumnNotFound("Invalid step type".into())),
-        };
+        let step_type = StepType::from_parts(step_type_str, llm_model)
+            .map_err(sqlx::Error::Decode)?;
 
         Ok(Self {
             identifiers: IdFields {
@@ -36,34 +32,66 @@ impl sqlx::FromRow<'_, sqlx::postgres::PgRow> for Step {
             description: row.try_get("description")?,
             step_type,
             step_content: row.try_get("step_content")?,
+            llm_provider: row.try_get("llm_provider").unwrap_or_default(),
+            system_prompt: row.try_get("system_prompt").unwrap_or_default(),
+            input_mapping: row
+                .try_get::<Option<Json<BTreeMap<String, String>>>, _>("input_mapping")
+                .unwrap_or_default()
+                .map(|mapping| mapping.0),
+            cacheable: row.try_get("cacheable").unwrap_or_default(),
+            expected_duration: expected_duration_from_secs(
+                row.try_get("expected_duration").unwrap_or_default(),
+            ),
+            wrap_output: row.try_get("wrap_output").unwrap_or_default(),
+            on_error: row.try_get("on_error").unwrap_or_default(),
+            metrics: StepMetrics::new(
+                row.try_get("run_count").unwrap_or_default(),
+                row.try_get("success_count").unwrap_or_default(),
+            ),
         })
     }
 }
 
+/// A row's aggregated `steps` JSON. A query that didn't select it (or a null) reads
+/// as no steps, with a warning, rather than failing the whole row.
+pub(crate) fn aggregated_steps_json(row: &sqlx::postgres::PgRow) -> sqlx::Result<Value> {
+    match row.try_get::<Option<Value>, _>("steps") {
+        Ok(Some(steps)) => Ok(steps),
+        Ok(None) | Err(sqlx::Error::ColumnNotFound(_)) => {
+            eprintln!("Row has no aggregated `steps` column, loading it without steps");
+            Ok(Value::Array(Vec::new()))
+        }
+        Err(e) => Err(e),
+    }
+}
+
 #[async_trait]
 impl DatabaseItem for Step {
     type IdType = i32;
+    const MODEL: &'static str = "step";
 
     fn id(&self) -> &IdFields<Self::IdType> {
         &self.identifiers
     }
 
-    async fn try_db_create(&self, pool: &PgPool) -> Result<()> {
+    async fn db_create(&self, pool: &PgPool) -> Result<()> {
         let uuid_parsed = Uuid::parse_str(&self.identifiers.global_uuid)?;
 
-        // Extract llm_model from step_type if it's a Prompt step
-        let llm_model = match &self.step_type {
-            StepType::Prompt(model) => Some(model.clone()),
-            _ => None,
-        };
+        // The model (and provider / system prompt) only apply to Prompt steps
+        let llm_model = self.step_type.get_llm_model();
+        let is_prompt = self.is_prompt_step();
+        let llm_provider = self.llm_provider.as_ref().filter(|_| is_prompt);
+        let system_prompt = self.system_prompt.as_ref().filter(|_| is_prompt);
 
         // Insert with the llm_model column
         sqlx::query(
             r#"
             INSERT INTO steps
-                (global_uuid, description, step_type, step_content, llm_model)
+                (global_uuid, description, step_type, step_content, llm_model, llm_provider,
+                 input_mapping, run_count, success_count, system_prompt, cacheable,
+                 expected_duration, wrap_output, on_error)
             VALUES
-                ($1, $2, $3, $4, $5)
+                ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
             "#,
         )
         .bind(uuid_parsed)
@@ -71,20 +99,29 @@ impl DatabaseItem for Step {
         .bind(self.step_type.as_str())
         .bind(&self.step_content)
         .bind(llm_model)
+        .bind(llm_provider)
+        .bind(self.input_mapping.as_ref().map(Json))
+        .bind(self.get_run_count())
+        .bind(self.get_success_count())
+        .bind(system_prompt)
+        .bind(self.cacheable)
+        .bind(self.expected_duration.map(|expected| expected.as_secs_f64()))
+        .bind(self.wrap_output)
+        .bind(&self.on_error)
         .execute(pool)
         .await?;
 
         Ok(())
     }
 
-    async fn try_db_update(&self, pool: &PgPool) -> Result<()> {
+    async fn db_update(&self, pool: &PgPool) -> Result<()> {
         let uuid_parsed = Uuid::parse_str(&self.identifiers.global_uuid)?;
 
-        // Extract llm_model from step_type if it's a Prompt step
-        let llm_model = match &self.step_type {
-            StepType::Prompt(model) => Some(model.clone()),
-            _ => None,
-        };
+        // The model (and provider / system prompt) only apply to Prompt steps
+        let llm_model = self.step_type.get_llm_model();
+        let is_prompt = self.is_prompt_step();
+        let llm_provider = self.llm_provider.as_ref().filter(|_| is_prompt);
+        let system_prompt = self.system_prompt.as_ref().filter(|_| is_prompt);
 
         // Try to update by global UUID first
         let result = sqlx::query(
@@ -95,14 +132,28 @@ impl DatabaseItem for Step {
                 step_type = $2,
                 step_content = $3,
                 llm_model = $4,
+                llm_provider = $5,
+                input_mapping = $6,
+                system_prompt = $7,
+                cacheable = $8,
+                expected_duration = $9,
+                wrap_output = $10,
+                on_error = $11,
                 updated_at = CURRENT_TIMESTAMP
-            WHERE global_uuid = $5
+            WHERE global_uuid = $12
             "#,
         )
         .bind(&self.description)
         .bind(self.step_type.as_str())
         .bind(&self.step_content)
         .bind(&llm_model)
+        .bind(llm_provider)
+        .bind(self.input_mapping.as_ref().map(Json))
+        .bind(system_prompt)
+        .bind(self.cacheable)
+        .bind(self.expected_duration.map(|expected| expected.as_secs_f64()))
+        .bind(self.wrap_output)
+        .bind(&self.on_error)
         .bind(uuid_parsed)
         .execute(pool)
         .await?;
@@ -118,14 +169,28 @@ impl DatabaseItem for Step {
                         step_type = $2,
                         step_content = $3,
                         llm_model = $4,
+                        llm_provider = $5,
+                        input_mapping = $6,
+                        system_prompt = $7,
+                        cacheable = $8,
+                        expected_duration = $9,
+                        wrap_output = $10,
+                        on_error = $11,
                         updated_at = CURRENT_TIMESTAMP
-                    WHERE id = $5
+                    WHERE id = $12
                     "#,
                 )
                 .bind(&self.description)
                 .bind(self.step_type.as_str())
                 .bind(&self.step_content)
                 .bind(&llm_model)
+                .bind(llm_provider)
+                .bind(self.input_mapping.as_ref().map(Json))
+                .bind(system_prompt)
+                .bind(self.cacheable)
+                .bind(self.expected_duration.map(|expected| expected.as_secs_f64()))
+                .bind(self.wrap_output)
+                .bind(&self.on_error)
                 .bind(local_id)
                 .execute(pool)
                 .await?;
@@ -135,7 +200,7 @@ impl DatabaseItem for Step {
         Ok(())
     }
 
-    async fn try_db_delete(&self, pool: &PgPool) -> Result<()> {
+    async fn db_delete(&self, pool: &PgPool) -> Result<()> {
         let uuid_parsed = Uuid::parse_str(&self.identifiers.global_uuid)?;
         let res = sqlx::query("DELETE FROM steps WHERE global_uuid = $1")
             .bind(uuid_parsed)
@@ -149,7 +214,7 @@ impl DatabaseItem for Step {
         }
     }
 
-    async fn try_db_select_all(pool: &PgPool) -> Result<Vec<Self>> {
+    async fn db_select_all(pool: &PgPool) -> Result<Vec<Self>> {
         #[derive(sqlx::FromRow)]
         struct StepRow {
             id: i32,
@@ -158,6 +223,15 @@ impl DatabaseItem for Step {
             step_type: String,
             step_content: String,
             llm_model: Option<String>,
+            llm_provider: Option<String>,
+            system_prompt: Option<String>,
+            input_mapping: Option<Json<BTreeMap<String, String>>>,
+            cacheable: bool,
+            expected_duration: Option<f64>,
+            wrap_output: bool,
+            on_error: Option<String>,
+            run_count: i64,
+            success_count: i64,
             created_at: chrono::DateTime<chrono::Utc>,
             updated_at: chrono::DateTime<chrono::Utc>,
         }
@@ -166,7 +240,10 @@ impl DatabaseItem for Step {
             r#"
             SELECT
                 id, global_uuid, description,
-                step_type, step_content, llm_model,
+                step_type::text AS step_type, step_content,
+                llm_model, llm_provider, system_prompt, input_mapping, cacheable,
+                expected_duration, wrap_output, on_error,
+                run_count, success_count,
                 created_at, updated_at
             FROM steps
             ORDER BY id
@@ -178,17 +255,10 @@ impl DatabaseItem for Step {
         let steps = rows
             .into_iter()
             .map(|row| {
-                let step_type = match row.step_type.as_str() {
-                    "python" => StepType::Python,
-                    "prompt" => StepType::Prompt(
-                        row.llm_model
-                            .unwrap_or_else(|| crate::JsonModeLLMs::MetaLlama33_70b.to_string()),
-                    ),
-                    "webscrape" => StepType::WebScrape,
-                    _ => StepType::Python, // Default fallback
-                };
-
-                Step {
+                let step_type = StepType::from_parts(&row.step_type, row.llm_model)
+                    .map_err(|e| anyhow!("Step {} can't be loaded: {}", row.global_uuid, e))?;
+
+                Ok(Step {
                     identifiers: IdFields {
                         local_id: Some(row.id),
                         global_uuid: row.global_uuid.to_string(),
@@ -200,14 +270,22 @@ impl DatabaseItem for Step {
                     description: row.description,
                     step_type,
                     step_content: row.step_content,
-                }
+                    llm_provider: row.llm_provider,
+                    system_prompt: row.system_prompt,
+                    input_mapping: row.input_mapping.map(|mapping| mapping.0),
+                    cacheable: row.cacheable,
+                    expected_duration: expected_duration_from_secs(row.expected_duration),
+                    wrap_output: row.wrap_output,
+                    on_error: row.on_error,
+                    metrics: StepMetrics::new(row.run_count, row.success_count),
+                })
             })
-            .collect();
+            .collect::<Result<Vec<_>>>()?;
 
         Ok(steps)
     }
 
-    async fn try_db_select_by_id(
+    async fn db_select_by_id(
         pool: &PgPool,
         id: &IdFields<Self::IdType>,
     ) -> Result<Option<Self>> {
@@ -219,6 +297,15 @@ impl DatabaseItem for Step {
             step_type: String,
             step_content: String,
             llm_model: Option<String>,
+            llm_provider: Option<String>,
+            system_prompt: Option<String>,
+            input_mapping: Option<Json<BTreeMap<String, String>>>,
+            cacheable: bool,
+            expected_duration: Option<f64>,
+            wrap_output: bool,
+            on_error: Option<String>,
+            run_count: i64,
+            success_count: i64,
             created_at: chrono::DateTime<chrono::Utc>,
             updated_at: chrono::DateTime<chrono::Utc>,
         }
@@ -228,7 +315,10 @@ impl DatabaseItem for Step {
                 r#"
                 SELECT
                     id, global_uuid, description,
-                    step_type, step_content, llm_model,
+                    step_type::text AS step_type, step_content,
+                    llm_model, llm_provider, system_prompt, input_mapping, cacheable,
+                    expected_duration, wrap_output, on_error,
+                    run_count, success_count,
                     created_at, updated_at
                 FROM steps
                 WHERE id = $1
@@ -243,7 +333,10 @@ impl DatabaseItem for Step {
                 r#"
                 SELECT
                     id, global_uuid, description,
-                    step_type, step_content, llm_model,
+                    step_type::text AS step_type, step_content,
+                    llm_model, llm_provider, system_prompt, input_mapping, cacheable,
+                    expected_duration, wrap_output, on_error,
+                    run_count, success_count,
                     created_at, updated_at
                 FROM steps
                 WHERE global_uuid = $1
@@ -254,30 +347,90 @@ impl DatabaseItem for Step {
             .await?
         };
 
-        Ok(row_opt.map(|row| {
-            let step_type = match row.step_type.as_str() {
-                "python" => StepType::Python,
-                "prompt" => StepType::Prompt(
-                    row.llm_model
-                        .unwrap_or_else(|| crate::JsonModeLLMs::MetaLlama33_70b.to_string()),
-                ),
-                "webscrape" => StepType::WebScrape,
-                _ => StepType::Python, // Default fallback
-            };
-
-            Step {
-                identifiers: IdFields {
-                    local_id: Some(row.id),
-                    global_uuid: row.global_uuid.to_string(),
-                },
-                timestamps: TimestampFields {
-                    created: row.created_at,
-                    updated: row.updated_at,
-                },
-                description: row.description,
-                step_type,
-                step_content: row.step_content,
-            }
+        let Some(row) = row_opt else {
+            return Ok(None);
+        };
+        let step_type = StepType::from_parts(&row.step_type, row.llm_model)
+            .map_err(|e| anyhow!("Step {} can't be loaded: {}", row.global_uuid, e))?;
+
+        Ok(Some(Step {
+            identifiers: IdFields {
+                local_id: Some(row.id),
+                global_uuid: row.global_uuid.to_string(),
+            },
+            timestamps: TimestampFields {
+                created: row.created_at,
+                updated: row.updated_at,
+            },
+            description: row.description,
+            step_type,
+            step_content: row.step_content,
+            llm_provider: row.llm_provider,
+            system_prompt: row.system_prompt,
+            input_mapping: row.input_mapping.map(|mapping| mapping.0),
+            cacheable: row.cacheable,
+            expected_duration: expected_duration_from_secs(row.expected_duration),
+            wrap_output: row.wrap_output,
+            on_error: row.on_error,
+            metrics: StepMetrics::new(row.run_count, row.success_count),
         }))
     }
+
+    async fn db_select_by_ids(pool: &PgPool, ids: &[Self::IdType]) -> Result<Vec<Self>> {
+        let steps = sqlx::query_as::<_, Step>(&select_steps_sql("WHERE id = ANY($1) ORDER BY id"))
+            .bind(ids)
+            .fetch_all(pool)
+            .await?;
+
+        Ok(steps)
+    }
+
+    async fn db_select_by_uuids(pool: &PgPool, uuids: &[String]) -> Result<Vec<Self>> {
+        let steps = sqlx::query_as::<_, Step>(&select_steps_sql(
+            "WHERE global_uuid = ANY($1) ORDER BY id",
+        ))
+        .bind(crate::parse_uuids(uuids)?)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(steps)
+    }
+}
+
+/// Query selecting the columns `Step::from_row` reads
+fn select_steps_sql(where_clause: &str) -> String {
+    format!(
+        r#"
+        SELECT
+            id, global_uuid, description,
+            step_type::text AS step_type, step_content,
+            llm_model, llm_provider, system_prompt, input_mapping, cacheable,
+            expected_duration, wrap_output, on_error,
+            run_count, success_count,
+            created_at, updated_at
+        FROM steps
+        {}
+        "#,
+        where_clause
+    )
+}
+
+/// Reads the `expected_duration` column, in seconds; an unusable value reads as none
+fn expected_duration_from_secs(secs: Option<f64>) -> Option<Duration> {
+    secs.and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+}
+
+impl Step {
+    /// Writes the step's run / success counters. Kept out of `try_db_update`
+    /// so saving an edited step doesn't clobber counts recorded by the engine.
+    pub async fn save_metrics(&self, pool: &PgPool) -> Result<()> {
+        let uuid_parsed = Uuid::parse_str(&self.identifiers.global_uuid)?;
+        sqlx::query("UPDATE steps SET run_count = $1, success_count = $2 WHERE global_uuid = $3")
+            .bind(self.get_run_count())
+            .bind(self.get_success_count())
+            .bind(uuid_parsed)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
 }
@@ -1,22 +1,82 @@
-use super::types::{Step, StepType};
+use super::llm_io::log_llm_io;
+use super::output::StepOutput;
+use super::types::{Step, StepError, StepErrorKind, StepType};
+use crate::models::runtime_sessions::RunScope;
 use crate::PythonRuntime;
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use serde_json::{Value, Map};
+use std::borrow::Cow;
 
 // Define standard output keys for all step types
 pub const STEP_OUTPUT_RESPONSE_KEY: &str = "response";
 pub const STEP_OUTPUT_DATA_KEY: &str = "data";
 pub const STEP_OUTPUT_STATUS_KEY: &str = "status";
 pub const STEP_OUTPUT_ERROR_KEY: &str = "error";
+pub const STEP_OUTPUT_ERROR_KIND_KEY: &str = "error_kind";
 pub const STEP_OUTPUT_TYPE_KEY: &str = "output_type";
 pub const STEP_OUTPUT_SOURCE_KEY: &str = "source_step";
 
+fn legacy_prompt_format() -> bool {
+    RunScope::with_current(|scope| scope.legacy_prompt_format).unwrap_or(false)
+}
+
+/// `content` with the input rendered into it: as a template (see `render_prompt`),
+/// or in the `legacy` format, the raw `content` followed by the input as a JSON
+/// context block
+fn build_prompt(content: &str, source_data: &Value, legacy: bool) -> String {
+    if legacy {
+        crate::prompt_with_context(content, source_data)
+    } else {
+        crate::render_prompt(content, source_data)
+    }
+}
+
+/// What a Prompt step would send the model, see `Step::render_prompt_preview`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptPreview {
+    pub system_prompt: Option<String>,
+    pub prompt: String,
+}
+
+fn random_seed() -> Option<u64> {
+    RunScope::with_current(|scope| scope.seed).flatten()
+}
+
+/// Indents `content` by 4 spaces to sit inside the generated function. The
+/// indentation all its lines share is removed first, so content pasted from an
+/// indented block doesn't end up indented twice; relative indentation is kept.
+fn indent_body(content: &str) -> String {
+    let common = content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| &line[..line.len() - line.trim_start().len()])
+        .reduce(|common, indent| {
+            let shared = common
+                .chars()
+                .zip(indent.chars())
+                .take_while(|(a, b)| a == b)
+                .map(|(c, _)| c.len_utf8())
+                .sum();
+            &common[..shared]
+        })
+        .unwrap_or("");
+
+    content
+        .lines()
+        .map(|line| match line.strip_prefix(common) {
+            Some(rest) if !line.trim().is_empty() => format!("    {}", rest),
+            _ => String::new(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 impl Step {
     /// Generates a Python function with the standardized signature for execution in a PythonRuntime
     pub fn to_python_function(&self) -> String {
         let func_name = self.python_function_name();
         let docstring = format!(
-            "\"\"\"\n    {}\n    \n    Args:\n        source: Input data dictionary from previous step\n        \n    Returns:\n        dict: Output data to pass to next step\n    \"\"\"",
+            "\"\"\"\n    {}\n    \n    Args:\n        source: Input data dictionary from previous step (arrays and scalars arrive as {{\"data\": value}})\n        \n    Returns:\n        Output data to pass to next step\n    \"\"\"",
             self.description.as_deref().unwrap_or("No description provided")
         );
 
@@ -31,93 +91,259 @@ impl Step {
     return result"#,
             func_name,
             docstring,
-            // Indent all lines with 4 spaces for proper Python indentation
-            self.step_content
-                .lines()
-                .map(|line| format!("    {}", line))
-                .collect::<Vec<_>>()
-                .join("\n")
+            indent_body(&self.step_content)
         )
     }
 
+    /// The function `PythonRuntime::add_step` defines for this step, for showing
+    /// authors the code that actually runs. `${secret:NAME}` references are left
+    /// unresolved, so the preview never contains secret values.
+    pub fn preview_generated_code(&self) -> String {
+        self.to_python_function()
+    }
+
+    /// The system prompt and prompt this Prompt step would send for `source` (the
+    /// previous step's output), in the `legacy` format or not (see the agent's
+    /// `legacy_prompt_format`), without calling the model. `${secret:NAME}`
+    /// references are left unresolved, so the preview never contains secret values.
+    pub fn render_prompt_preview(&self, source: &Value, legacy: bool) -> Result<PromptPreview> {
+        if !matches!(self.step_type, StepType::Prompt(_)) {
+            return Err(StepError::new(
+                StepErrorKind::Config,
+                format!(
+                    "Step {} is a {} step, only Prompt steps have a prompt",
+                    self.identifiers.global_uuid,
+                    self.step_type.as_str()
+                ),
+            )
+            .into());
+        }
+        let source_data = self.map_input(&StepOutput::from(source.clone()))?;
+        Ok(PromptPreview {
+            system_prompt: self.system_prompt.clone(),
+            prompt: build_prompt(&self.step_content, &source_data, legacy),
+        })
+    }
+
     /// Returns the standard Python function name for this step
     pub fn python_function_name(&self) -> String {
         format!("step_{}", self.identifiers.global_uuid.replace("-", "_"))
     }
 
-    /// Runs the step with fresh context
+    /// `step_content` with `${secret:NAME}` references resolved from `SecretStore::from_env`.
+    /// The result is only used to run the step and is never stored.
+    pub fn resolved_content(&self) -> Result<Cow<'_, str>> {
+        crate::SecretStore::from_env().resolve(&self.step_content)
+    }
+
+    /// Builds this step's input from the previous step's output using `input_mapping`
+    /// (paths are resolved with `StepOutput::resolve`, so `@name` picks a named output).
+    /// Without a mapping the primary output is passed through unchanged.
+    pub fn map_input(&self, source: &StepOutput) -> Result<Value> {
+        let Some(mapping) = &self.input_mapping else {
+            return Ok(source.primary.clone());
+        };
+
+        let mut input = Map::new();
+        for (param, path) in mapping {
+            let value = source.resolve(path).ok_or_else(|| {
+                StepError::new(
+                    StepErrorKind::Validation,
+                    format!(
+                        "input_mapping for `{}`: `{}` not found in the previous step's output",
+                        param, path
+                    ),
+                )
+            })?;
+            input.insert(param.clone(), value.clone());
+        }
+        Ok(Value::Object(input))
+    }
+
+    /// Runs the step with fresh context, on the input built by `map_input`.
+    /// Failures carry a `StepError` so callers can recover the `StepErrorKind`.
+    /// Every call is counted in `metrics`, successful ones also as a success.
+    ///
+    /// `source` is the previous step's output; a plain `Value` works too. The
+    /// returned `named` outputs are only the ones this step produced.
     pub async fn run(
         &self,
-        source_data: Value,
+        source: impl Into<StepOutput>,
+        step_idx: usize,
+        runtime: Option<&PythonRuntime>,
+    ) -> Result<StepOutput> {
+        self.metrics.record_run();
+        let result = self
+            .execute(source.into(), step_idx, runtime)
+            .await
+            .map(|output| self.shape_output(output));
+        if result.is_ok() {
+            self.metrics.record_success();
+        }
+        result
+    }
+
+    /// Wraps the primary output as `{"data": output}` if the step has `wrap_output`
+    /// set and the output isn't a dict; otherwise it passes through unchanged
+    fn shape_output(&self, mut output: StepOutput) -> StepOutput {
+        if self.wrap_output && !output.primary.is_object() {
+            output.primary = serde_json::json!({ STEP_OUTPUT_DATA_KEY: output.primary });
+        }
+        output
+    }
+
+    async fn execute(
+        &self,
+        source: StepOutput,
         step_idx: usize,
         runtime: Option<&PythonRuntime>,
-    ) -> Result<Value> {
+    ) -> Result<StepOutput> {
+        let source_data = self.map_input(&source).map_err(|err| {
+            classify(err, StepErrorKind::Validation, |err| {
+                format!("Step {} {}", step_idx, err)
+            })
+        })?;
+
         let raw_result = match &self.step_type {
             StepType::Prompt(llm_model) => {
-                match crate::call_llm(&self.step_content, source_data.clone(), Some(llm_model.clone()))
-                    .await
-                {
-                    Ok(res_str) => Ok(Value::String(res_str)),
-                    Err(err) => Err(anyhow!("Step {} failed: {}", step_idx, err))
+                // Secrets are resolved before the input is rendered in, so input
+                // text can't reference them
+                let content = self.resolved_content()?;
+                crate::models::runtime_sessions::charge_llm_call()?;
+                let model = llm_model.clone();
+                let provider = self.llm_provider.as_deref();
+                let system_prompt = self.system_prompt.as_deref();
+                let prompt = build_prompt(&content, &source_data, legacy_prompt_format());
+                let response =
+                    crate::complete_with_provider(&prompt, model, provider, system_prompt).await;
+                match response {
+                    Ok(res_str) => {
+                        log_llm_io(
+                            &format!("step {} (UUID: {})", step_idx, self.identifiers.global_uuid),
+                            system_prompt,
+                            &prompt,
+                            &res_str,
+                        );
+                        Ok(Value::String(res_str).into())
+                    }
+                    Err(err) => Err(classify(
+                        err,
+                        StepErrorKind::Network,
+                        |err| format!("Step {} failed: {}", step_idx, err),
+                    )),
                 }
             }
             StepType::Python => {
                 // For Python steps, require a runtime
                 if let Some(rt) = runtime {
-                    rt.execute_step(&self.identifiers.global_uuid, source_data.clone())
+                    // Anything not already classified is an exception raised by the step's code
+                    rt.execute_step_with_outputs(
+                        &self.identifiers.global_uuid,
+                        source_data.clone(),
+                        random_seed(),
+                    )
+                        .map_err(|err| classify(err, StepErrorKind::UserCode, |err| err.to_string()))
                 } else {
-                    Err(anyhow!(
-                        "Python step {} requires a runtime to execute",
-                        step_idx
-                    ))
+                    Err(StepError::new(
+                        StepErrorKind::Config,
+                        format!("Python step {} requires a runtime to execute", step_idx),
+                    )
+                    .into())
                 }
             }
             StepType::WebScrape => {
                 // For WebScrape steps, the step_content should contain the URL to scrape
-                let url = self.step_content.trim();
+                // (optionally with options, see `WebScrapeTarget`)
+                let content = self.resolved_content()?;
+                let target = crate::WebScrapeTarget::parse(&content)
+                    .map_err(|e| StepError::new(StepErrorKind::Config, e.to_string()))?;
+                let url = target.url.trim();
                 if url.is_empty() {
-                    return Err(anyhow!("WebScrape step {} (UUID: {}) has empty URL",
-                                       step_idx,
-                                       self.identifiers.global_uuid));
+                    return Err(StepError::new(
+                        StepErrorKind::Config,
+                        format!("WebScrape step {} (UUID: {}) has empty URL",
+                                step_idx,
+                                self.identifiers.global_uuid),
+                    )
+                    .into());
                 }
 
                 // Call the web scraping function
-                match crate::scrape_webpage(url).await {
-                    Ok(result) => Ok(result),
-                    Err(err) => Err(anyhow!("WebScrape step {} (UUID: {}) failed: {}",
-                                            step_idx,
-                                            self.identifiers.global_uuid,
-                                            err.to_string()))
+                // The `ScrapeError` stays in the chain for callers deciding whether to retry
+                let config = crate::ScraperConfig {
+                    output: target.output,
+                    ..crate::ScraperConfig::default()
+                };
+                match crate::scrape_webpage_with_config(url, &config).await {
+                    Ok(result) => Ok(result.into()),
+                    Err(err) => Err(StepError::new(
+                        err.kind(),
+                        format!("WebScrape step {} (UUID: {}) failed: {}",
+                                step_idx,
+                                self.identifiers.global_uuid,
+                                err),
+                    )
+                    .with_source(err)
+                    .into()),
                 }
             }
+            StepType::Loop => self
+                .run_loop(StepOutput { primary: source_data.clone(), named: source.named }, runtime)
+                .await
+                .map_err(|err| classify(err, StepErrorKind::Config, |err| err.to_string())),
+            StepType::JsonPatch => self
+                .run_json_patch(source_data.clone())
+                .map(StepOutput::from)
+                .map_err(|err| classify(err, StepErrorKind::Config, |err| {
+                    format!("JsonPatch step {} failed: {}", step_idx, err)
+                })),
+            StepType::FileOp => self
+                .run_file_op(source_data.clone())
+                .await
+                .map(StepOutput::from)
+                .map_err(|err| classify(err, StepErrorKind::Config, |err| {
+                    format!("FileOp step {} failed: {}", step_idx, err)
+                })),
         };
 
-        // Return raw output
-        match raw_result {
-            Ok(output) => Ok(output),
-            Err(err) => {
-                // Create standardized error output
-                let mut error_map = Map::new();
-                error_map.insert(
-                    STEP_OUTPUT_ERROR_KEY.to_string(),
-                    Value::String(err.to_string())
-                );
-                error_map.insert(
-                    STEP_OUTPUT_STATUS_KEY.to_string(),
-                    Value::String("error".to_string()),
-                );
-                error_map.insert(
-                    STEP_OUTPUT_SOURCE_KEY.to_string(),
-                    Value::String(self.identifiers.global_uuid.clone()),
-                );
-                error_map.insert(
-                    STEP_OUTPUT_TYPE_KEY.to_string(),
-                    Value::String(self.step_type.as_str().to_string()),
-                );
-
-                // Still propagate the original error
-                Err(err)
-            }
+        raw_result
+    }
+
+    /// The standardized output recorded for this step when it fails with `err`
+    pub fn error_output(&self, err: &anyhow::Error) -> Value {
+        let mut error_map = Map::new();
+        error_map.insert(
+            STEP_OUTPUT_ERROR_KEY.to_string(),
+            Value::String(err.to_string())
+        );
+        if let Some(kind) = StepErrorKind::of(err) {
+            error_map.insert(
+                STEP_OUTPUT_ERROR_KIND_KEY.to_string(),
+                Value::String(kind.as_str().to_string()),
+            );
         }
+        error_map.insert(
+            STEP_OUTPUT_STATUS_KEY.to_string(),
+            Value::String("error".to_string()),
+        );
+        error_map.insert(
+            STEP_OUTPUT_SOURCE_KEY.to_string(),
+            Value::String(self.identifiers.global_uuid.clone()),
+        );
+        error_map.insert(
+            STEP_OUTPUT_TYPE_KEY.to_string(),
+            Value::String(self.step_type.as_str().to_string()),
+        );
+        Value::Object(error_map)
     }
 }
+
+/// Wraps `err` in a `StepError`, keeping its kind if it already has one and using `fallback` otherwise
+fn classify(
+    err: anyhow::Error,
+    fallback: StepErrorKind,
+    message: impl FnOnce(&anyhow::Error) -> String,
+) -> anyhow::Error {
+    let kind = StepErrorKind::of(&err).unwrap_or(fallback);
+    StepError::new(kind, message(&err)).into()
+}
@@ -0,0 +1,169 @@
+use super::types::{Step, StepError, StepErrorKind};
+use crate::models::runtime_sessions::workspace_path;
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::ErrorKind;
+
+/// Parsed form of a FileOp step's `step_content`, e.g.
+/// ```json
+/// {"op": "write", "path": "notes/summary.txt", "content": "..."}
+/// {"op": "read", "path": "notes/summary.txt"}
+/// {"op": "list", "path": "notes"}
+/// {"op": "delete", "path": "notes/summary.txt"}
+/// ```
+/// Paths are relative to the session's scratch directory, which is removed when
+/// the session ends. A `write` without `content` writes the step's input (strings
+/// as-is, anything else as JSON).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum FileOp {
+    Read {
+        path: String,
+    },
+    Write {
+        path: String,
+        #[serde(default)]
+        content: Option<String>,
+        #[serde(default)]
+        encoding: FileEncoding,
+    },
+    List {
+        #[serde(default)]
+        path: String,
+    },
+    Delete {
+        path: String,
+    },
+}
+
+/// How file contents are carried in step content and output
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileEncoding {
+    #[default]
+    Utf8,
+    Base64,
+}
+
+pub fn parse_file_op(step_content: &str) -> Result<FileOp> {
+    serde_json::from_str(step_content).map_err(|e| {
+        anyhow!(
+            "FileOp step content must be an object with an `op` of read, write, list or delete: {}",
+            e
+        )
+    })
+}
+
+impl Step {
+    /// Runs the step's file operation in the session's scratch directory.
+    /// Reads return text as `utf8` and anything else as `base64`.
+    pub(super) async fn run_file_op(&self, source_data: Value) -> Result<Value> {
+        let op = parse_file_op(&self.resolved_content()?)
+            .map_err(|e| StepError::new(StepErrorKind::Config, e.to_string()))?;
+
+        match op {
+            FileOp::Read { path } => {
+                let bytes = tokio::fs::read(workspace_path(&path)?)
+                    .await
+                    .map_err(|e| file_error(&path, e))?;
+                let (encoding, content) = match String::from_utf8(bytes) {
+                    Ok(text) => (FileEncoding::Utf8, text),
+                    Err(e) => (FileEncoding::Base64, BASE64.encode(e.as_bytes())),
+                };
+                Ok(json!({
+                    "path": path,
+                    "encoding": encoding,
+                    "content": content,
+                }))
+            }
+            FileOp::Write {
+                path,
+                content,
+                encoding,
+            } => {
+                let bytes = match (content, encoding) {
+                    (Some(content), FileEncoding::Utf8) => content.into_bytes(),
+                    (Some(content), FileEncoding::Base64) => {
+                        BASE64.decode(content.trim()).map_err(|e| {
+                            StepError::new(
+                                StepErrorKind::Config,
+                                format!("FileOp content for '{}' isn't valid base64: {}", path, e),
+                            )
+                        })?
+                    }
+                    (None, _) => match source_data {
+                        Value::String(text) => text.into_bytes(),
+                        other => serde_json::to_vec(&other)?,
+                    },
+                };
+
+                let full_path = workspace_path(&path)?;
+                if let Some(parent) = full_path.parent() {
+                    tokio::fs::create_dir_all(parent)
+                        .await
+                        .map_err(|e| file_error(&path, e))?;
+                }
+                tokio::fs::write(&full_path, &bytes)
+                    .await
+                    .map_err(|e| file_error(&path, e))?;
+                Ok(json!({
+                    "path": path,
+                    "size": bytes.len(),
+                }))
+            }
+            FileOp::List { path } => {
+                let mut dir = tokio::fs::read_dir(workspace_path(&path)?)
+                    .await
+                    .map_err(|e| file_error(&path, e))?;
+                let mut entries = Vec::new();
+                while let Some(entry) = dir.next_entry().await? {
+                    let metadata = entry.metadata().await?;
+                    entries.push(json!({
+                        "name": entry.file_name().to_string_lossy(),
+                        "is_dir": metadata.is_dir(),
+                        "size": metadata.len(),
+                    }));
+                }
+                entries.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+                Ok(json!({
+                    "path": path,
+                    "entries": entries,
+                }))
+            }
+            FileOp::Delete { path } => {
+                let full_path = workspace_path(&path)?;
+                if full_path == workspace_path("")? {
+                    return Err(StepError::new(
+                        StepErrorKind::Config,
+                        "FileOp can't delete the session workspace itself",
+                    )
+                    .into());
+                }
+                let metadata = tokio::fs::metadata(&full_path)
+                    .await
+                    .map_err(|e| file_error(&path, e))?;
+                if metadata.is_dir() {
+                    tokio::fs::remove_dir_all(&full_path).await
+                } else {
+                    tokio::fs::remove_file(&full_path).await
+                }
+                .map_err(|e| file_error(&path, e))?;
+                Ok(json!({
+                    "path": path,
+                    "deleted": true,
+                }))
+            }
+        }
+    }
+}
+
+/// Missing files are the step's inputs being wrong; anything else is the environment
+fn file_error(path: &str, err: std::io::Error) -> anyhow::Error {
+    let kind = match err.kind() {
+        ErrorKind::NotFound => StepErrorKind::Validation,
+        _ => StepErrorKind::Config,
+    };
+    StepError::new(kind, format!("FileOp on '{}' failed: {}", path, err)).into()
+}
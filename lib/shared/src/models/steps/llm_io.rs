@@ -0,0 +1,82 @@
+use crate::models::runtime_sessions::RunScope;
+use crate::SecretStore;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Replacement for text matching one of `LlmIoLogConfig::redact_patterns`
+pub const REDACTED_PLACEHOLDER: &str = "[REDACTED]";
+
+/// Debug logging of the exact prompt sent and response received by each Prompt
+/// step, configured in `AgentConfig::log_llm_io`. Resolved secrets are logged as
+/// their `${secret:NAME}` references, and anything matching `redact_patterns`
+/// (regular expressions, e.g. for emails or record numbers) as `[REDACTED]`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct LlmIoLogConfig {
+    #[serde(default)]
+    pub redact_patterns: Vec<String>,
+}
+
+impl LlmIoLogConfig {
+    /// `text` as it may be logged
+    pub fn redact(&self, text: &str, secrets: &SecretStore) -> String {
+        let mut redacted = secrets.redact(text);
+        for pattern in &self.redact_patterns {
+            match Regex::new(pattern) {
+                Ok(re) => redacted = re.replace_all(&redacted, REDACTED_PLACEHOLDER).into_owned(),
+                // Nothing is logged rather than something that should have been redacted
+                Err(e) => {
+                    eprintln!("Invalid redaction pattern '{}': {}", pattern, e);
+                    return REDACTED_PLACEHOLDER.to_string();
+                }
+            }
+        }
+        redacted
+    }
+
+    /// The log entry for one Prompt step call, redacted
+    pub fn format_exchange(
+        &self,
+        step_label: &str,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        response: &str,
+        secrets: &SecretStore,
+    ) -> String {
+        let mut entry = format!("[DEBUG] LLM I/O for {}\n", step_label);
+        if let Some(system_prompt) = system_prompt {
+            entry.push_str(&format!(
+                "--- system ---\n{}\n",
+                self.redact(system_prompt, secrets)
+            ));
+        }
+        entry.push_str(&format!(
+            "--- prompt ---\n{}\n--- response ---\n{}",
+            self.redact(prompt, secrets),
+            self.redact(response, secrets)
+        ));
+        entry
+    }
+}
+
+/// Logs one Prompt step call if the running session has LLM I/O logging on
+pub(crate) fn log_llm_io(
+    step_label: &str,
+    system_prompt: Option<&str>,
+    prompt: &str,
+    response: &str,
+) {
+    RunScope::with_current(|scope| {
+        if let Some(config) = &scope.llm_io_log {
+            eprintln!(
+                "{}",
+                config.format_exchange(
+                    step_label,
+                    system_prompt,
+                    prompt,
+                    response,
+                    &SecretStore::from_env(),
+                )
+            );
+        }
+    });
+}
@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+/// How often a step has run and how often it succeeded, stored in the
+/// `run_count` / `success_count` step columns.
+///
+/// Clones share the same counters, so runs of the copies a `RuntimeSession`
+/// makes of an agent's steps are counted on the agent's steps too.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(from = "StepMetricCounts", into = "StepMetricCounts")]
+pub struct StepMetrics {
+    run_count: Arc<AtomicI64>,
+    success_count: Arc<AtomicI64>,
+}
+
+/// Serialized form of `StepMetrics`
+#[derive(Serialize, Deserialize)]
+struct StepMetricCounts {
+    #[serde(default)]
+    run_count: i64,
+    #[serde(default)]
+    success_count: i64,
+}
+
+impl StepMetrics {
+    pub fn new(run_count: i64, success_count: i64) -> Self {
+        Self {
+            run_count: Arc::new(AtomicI64::new(run_count)),
+            success_count: Arc::new(AtomicI64::new(success_count)),
+        }
+    }
+
+    pub fn run_count(&self) -> i64 {
+        self.run_count.load(Ordering::Relaxed)
+    }
+
+    pub fn success_count(&self) -> i64 {
+        self.success_count.load(Ordering::Relaxed)
+    }
+
+    /// Share of runs that failed, `None` before the first run
+    pub fn failure_rate(&self) -> Option<f64> {
+        let runs = self.run_count();
+        (runs > 0).then(|| (runs - self.success_count()) as f64 / runs as f64)
+    }
+
+    pub(super) fn record_run(&self) {
+        self.run_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn record_success(&self) {
+        self.success_count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl From<StepMetricCounts> for StepMetrics {
+    fn from(counts: StepMetricCounts) -> Self {
+        Self::new(counts.run_count, counts.success_count)
+    }
+}
+
+impl From<StepMetrics> for StepMetricCounts {
+    fn from(metrics: StepMetrics) -> Self {
+        Self {
+            run_count: metrics.run_count(),
+            success_count: metrics.success_count(),
+        }
+    }
+}
@@ -1,14 +1,31 @@
+mod cache;
+mod control;
 mod conversion;
 mod database;
 mod execution;
+mod fileop;
+mod llm_io;
+mod metrics;
+mod output;
+mod patch;
 mod types;
 
-pub use types::{Step, StepType};
+pub(crate) use cache::{cache_step_output, cached_step_output};
+pub use control::{LoopConfig, DEFAULT_MAX_NESTING_DEPTH, LOOP_ITERATIONS_KEY};
+pub use fileop::{parse_file_op, FileEncoding, FileOp};
+pub(crate) use database::aggregated_steps_json;
+pub use execution::PromptPreview;
+pub use llm_io::{LlmIoLogConfig, REDACTED_PLACEHOLDER};
+pub use metrics::StepMetrics;
+pub use output::StepOutput;
+pub use patch::parse_json_patch;
+pub use types::{Step, StepError, StepErrorKind, StepType};
 pub use execution::{
     STEP_OUTPUT_RESPONSE_KEY,
     STEP_OUTPUT_DATA_KEY,
     STEP_OUTPUT_STATUS_KEY,
     STEP_OUTPUT_ERROR_KEY,
+    STEP_OUTPUT_ERROR_KIND_KEY,
     STEP_OUTPUT_TYPE_KEY,
     STEP_OUTPUT_SOURCE_KEY,
 };
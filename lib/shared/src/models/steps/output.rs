@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// What a step hands to the steps after it: the `primary` value, which becomes
+/// the next step's input, plus any `named` outputs (e.g. a scrape's `links`)
+/// that later steps can pick out with `input_mapping` paths like `@links[0]`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct StepOutput {
+    pub primary: Value,
+    #[serde(default)]
+    pub named: HashMap<String, Value>,
+}
+
+impl StepOutput {
+    pub fn new(primary: Value) -> Self {
+        Self {
+            primary,
+            named: HashMap::new(),
+        }
+    }
+
+    /// Adds the named output `name`
+    pub fn with_named(mut self, name: impl Into<String>, value: Value) -> Self {
+        self.named.insert(name.into(), value);
+        self
+    }
+
+    /// Resolves an `input_mapping` path. `@name` addresses the named output
+    /// `name`, optionally followed by a path into it (`@links[0].href`); any
+    /// other path is a JSONPath into `primary` (see `resolve_json_path`).
+    pub fn resolve(&self, path: &str) -> Option<&Value> {
+        let path = path.trim();
+        match path.strip_prefix('@') {
+            Some(named_path) => {
+                let end = named_path.find(['.', '[']).unwrap_or(named_path.len());
+                let (name, rest) = named_path.split_at(end);
+                crate::resolve_json_path(self.named.get(name)?, rest)
+            }
+            None => crate::resolve_json_path(&self.primary, path),
+        }
+    }
+}
+
+impl From<Value> for StepOutput {
+    fn from(primary: Value) -> Self {
+        Self::new(primary)
+    }
+}
@@ -0,0 +1,56 @@
+use super::types::{Step, StepError, StepErrorKind};
+use anyhow::{anyhow, Result};
+use json_patch::{Patch, PatchErrorKind};
+use serde_json::Value;
+
+/// Parses a JsonPatch step's `step_content`, an RFC 6902 patch array, e.g.
+/// ```json
+/// [
+///     {"op": "test", "path": "/status", "value": "draft"},
+///     {"op": "replace", "path": "/status", "value": "published"},
+///     {"op": "add", "path": "/tags/-", "value": "reviewed"}
+/// ]
+/// ```
+pub fn parse_json_patch(step_content: &str) -> Result<Patch> {
+    serde_json::from_str(step_content).map_err(|e| {
+        anyhow!(
+            "JsonPatch step content must be an RFC 6902 patch array: {}",
+            e
+        )
+    })
+}
+
+impl Step {
+    /// Applies the patch to `source_data` and returns the patched document.
+    /// Nothing is applied if any operation fails.
+    pub(super) fn run_json_patch(&self, source_data: Value) -> Result<Value> {
+        let patch = parse_json_patch(&self.resolved_content()?)
+            .map_err(|e| StepError::new(StepErrorKind::Config, e.to_string()))?;
+
+        let mut doc = source_data;
+        json_patch::patch(&mut doc, &patch).map_err(|e| {
+            let op = patch
+                .get(e.operation)
+                .and_then(|op| serde_json::to_value(op).ok())
+                .and_then(|op| op["op"].as_str().map(str::to_string))
+                .unwrap_or_default();
+            let reason = match e.kind {
+                PatchErrorKind::TestFailed => "value did not match".to_string(),
+                PatchErrorKind::InvalidPointer => "path does not exist in the document".to_string(),
+                PatchErrorKind::InvalidFromPointer => {
+                    "`from` path does not exist in the document".to_string()
+                }
+                kind => kind.to_string(),
+            };
+            StepError::new(
+                StepErrorKind::Validation,
+                format!(
+                    "JSON patch operation {} ({} {}) failed: {}",
+                    e.operation, op, e.path, reason
+                ),
+            )
+        })?;
+
+        Ok(doc)
+    }
+}
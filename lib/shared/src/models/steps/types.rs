@@ -1,63 +1,86 @@
+use super::metrics::StepMetrics;
 use crate::{IdFields, TimestampFields};
 use serde::{Deserialize, Serialize};
 use sqlx::{postgres::PgArgumentBuffer, Postgres};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum StepType {
     Python,
-    Prompt(String),
+    /// Calls an LLM with the given model, or with the agent's default model
+    /// (then the global default) when `None`
+    Prompt(Option<String>),
     WebScrape,
+    /// Repeats inner step(s) until a predicate holds; configured by a `LoopConfig` in `step_content`
+    Loop,
+    /// Applies the RFC 6902 patch array in `step_content` to its input
+    JsonPatch,
+    /// Reads, writes, lists or deletes files in the session's scratch directory;
+    /// configured by a `FileOp` in `step_content`
+    FileOp,
 }
 
-impl StepType {
-    pub fn from_str(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+impl FromStr for StepType {
+    type Err = Box<dyn std::error::Error + Send + Sync>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "python" => Ok(StepType::Python),
-            "prompt" => Ok(StepType::Prompt(
-                crate::JsonModeLLMs::MetaLlama33_70b.to_string(),
-            )),
+            "prompt" => Ok(StepType::Prompt(None)),
             "webscrape" => Ok(StepType::WebScrape),
-            _ => Err("Invalid step type".into()),
+            "loop" => Ok(StepType::Loop),
+            "json_patch" => Ok(StepType::JsonPatch),
+            "file_op" => Ok(StepType::FileOp),
+            _ => Err(format!("Invalid step type: {}", s).into()),
         }
     }
+}
 
+impl StepType {
     pub fn as_str(&self) -> &'static str {
         match self {
             StepType::Python => "python",
             StepType::Prompt(_) => "prompt",
             StepType::WebScrape => "webscrape",
+            StepType::Loop => "loop",
+            StepType::JsonPatch => "json_patch",
+            StepType::FileOp => "file_op",
+        }
+    }
+
+    /// Rebuilds a stored step type from its `step_type` name and, for Prompt steps,
+    /// the `llm_model` stored alongside it (unset stays unset, so the step keeps
+    /// following the default model). Every load path goes through here, since the
+    /// name alone can't carry the model.
+    pub fn from_parts(
+        step_type: &str,
+        llm_model: Option<String>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        match step_type {
+            "prompt" => Ok(StepType::Prompt(llm_model)),
+            other => other.parse(),
         }
     }
 
     pub fn get_llm_model(&self) -> Option<String> {
         match self {
-            StepType::Prompt(model) => Some(model.clone()),
+            StepType::Prompt(model) => model.clone(),
             _ => None,
         }
     }
 }
 
+// Encode-only: decoding the `step_type` column alone would lose a Prompt step's
+// model, so rows are mapped with `StepType::from_parts` instead
 impl sqlx::Type<Postgres> for StepType {
     fn type_info() -> sqlx::postgres::PgTypeInfo {
         sqlx::postgres::PgTypeInfo::with_name("step_type")
     }
 }
 
-impl<'r> sqlx::Decode<'r, Postgres> for StepType {
-    fn decode(
-        value: sqlx::postgres::PgValueRef<'r>,
-    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        match value.as_str()? {
-            "python" => Ok(StepType::Python),
-            "prompt" => Ok(StepType::Prompt(
-                crate::JsonModeLLMs::MetaLlama33_70b.to_string(),
-            )),
-            "webscrape" => Ok(StepType::WebScrape),
-            s => Err(format!("Invalid step type: {}", s).into()),
-        }
-    }
-}
-
 impl<'q> sqlx::Encode<'q, Postgres> for StepType {
     fn encode_by_ref(
         &self,
@@ -69,6 +92,142 @@ impl<'q> sqlx::Encode<'q, Postgres> for StepType {
     }
 }
 
+/// Category of a step failure, so failures can be filtered without parsing messages
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "step_error_kind", rename_all = "snake_case")]
+pub enum StepErrorKind {
+    Timeout,
+    Network,
+    /// Error raised by user-provided code (e.g. a Python exception)
+    UserCode,
+    /// Output didn't meet expectations (unparseable response, unsatisfied predicate, ...)
+    Validation,
+    /// Step or environment is misconfigured (missing URL, API key, runtime, ...)
+    Config,
+    RateLimited,
+}
+
+impl StepErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StepErrorKind::Timeout => "timeout",
+            StepErrorKind::Network => "network",
+            StepErrorKind::UserCode => "user_code",
+            StepErrorKind::Validation => "validation",
+            StepErrorKind::Config => "config",
+            StepErrorKind::RateLimited => "rate_limited",
+        }
+    }
+
+    /// Classifies a failed HTTP request
+    pub fn from_reqwest(err: &reqwest::Error) -> Self {
+        if err.is_timeout() {
+            StepErrorKind::Timeout
+        } else if err.status() == Some(reqwest::StatusCode::TOO_MANY_REQUESTS) {
+            StepErrorKind::RateLimited
+        } else {
+            StepErrorKind::Network
+        }
+    }
+
+    /// Classifies an unsuccessful HTTP status
+    pub fn from_status(status: reqwest::StatusCode) -> Self {
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            StepErrorKind::RateLimited
+        } else if status == reqwest::StatusCode::REQUEST_TIMEOUT
+            || status == reqwest::StatusCode::GATEWAY_TIMEOUT
+        {
+            StepErrorKind::Timeout
+        } else if status == reqwest::StatusCode::UNAUTHORIZED
+            || status == reqwest::StatusCode::FORBIDDEN
+        {
+            // Rejected credentials stay rejected until someone fixes the key
+            StepErrorKind::Config
+        } else {
+            StepErrorKind::Network
+        }
+    }
+
+    /// Whether a failure of this kind may go away on its own (network trouble,
+    /// timeouts, rate limits), as opposed to one the user has to fix
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            StepErrorKind::Timeout | StepErrorKind::Network | StepErrorKind::RateLimited
+        )
+    }
+
+    /// Finds the kind attached anywhere in an error chain
+    pub fn of(err: &anyhow::Error) -> Option<Self> {
+        err.chain()
+            .find_map(|cause| cause.downcast_ref::<StepError>())
+            .map(|step_err| step_err.kind)
+    }
+}
+
+impl FromStr for StepErrorKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "timeout" => Ok(StepErrorKind::Timeout),
+            "network" => Ok(StepErrorKind::Network),
+            "user_code" => Ok(StepErrorKind::UserCode),
+            "validation" => Ok(StepErrorKind::Validation),
+            "config" => Ok(StepErrorKind::Config),
+            "rate_limited" => Ok(StepErrorKind::RateLimited),
+            _ => Err(anyhow::anyhow!("Invalid step error kind: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for StepErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A step failure tagged with its `StepErrorKind`. Travels inside `anyhow::Error`,
+/// use `StepErrorKind::of` to recover the kind.
+#[derive(Clone, Debug)]
+pub struct StepError {
+    pub kind: StepErrorKind,
+    pub message: String,
+    /// The error that caused this one (e.g. a `ScrapeError`), kept in the error chain
+    source: Option<Arc<dyn std::error::Error + Send + Sync>>,
+}
+
+impl StepError {
+    pub fn new(kind: StepErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    /// Keeps `source` in the error chain, so callers can downcast to it
+    pub fn with_source(mut self, source: impl std::error::Error + Send + Sync + 'static) -> Self {
+        self.source = Some(Arc::new(source));
+        self
+    }
+}
+
+impl std::fmt::Display for StepError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for StepError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source
+            .as_deref()
+            .map(|source| source as &(dyn std::error::Error + 'static))
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Step {
     pub identifiers: IdFields,
@@ -76,6 +235,39 @@ pub struct Step {
     pub description: Option<String>,
     pub step_type: StepType,
     pub step_content: String,
+    /// Named LLM provider for Prompt steps (`None` uses the default provider)
+    pub llm_provider: Option<String>,
+    /// Instructions sent to the LLM as a system message ahead of the rendered
+    /// prompt, for Prompt steps (`None` sends the prompt alone)
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    /// Builds the step's input from the previous step's output, as
+    /// param name -> JSONPath (see `resolve_json_path`), e.g.
+    /// `{"title": "$.page.meta.title"}` gives `{"title": ...}`.
+    /// `None` passes the whole output through.
+    #[serde(default)]
+    pub input_mapping: Option<BTreeMap<String, String>>,
+    /// Reuse the step's earlier output when it sees the same input again (see
+    /// `StepCache`), instead of running it. Only for deterministic steps.
+    #[serde(default)]
+    pub cacheable: bool,
+    /// How long the step is expected to take; runs that take longer are flagged
+    /// as `RuntimeEvent::SlowStep` but still succeed. `None` never flags.
+    #[serde(default)]
+    pub expected_duration: Option<Duration>,
+    /// Wrap a result that isn't a dict (a list, string, number...) as
+    /// `{"data": result}`. Off by default: results pass through as returned.
+    #[serde(default)]
+    pub wrap_output: bool,
+    /// UUID of the session step that handles this step's failures: it gets the
+    /// step's error output (see `error_output`) and its output is used instead,
+    /// whatever the session's `FailurePolicy`. Handler steps only run this way,
+    /// never in order.
+    #[serde(default)]
+    pub on_error: Option<String>,
+    /// Run / success counters, updated by `run`
+    #[serde(default)]
+    pub metrics: StepMetrics,
 }
 
 impl Step {
@@ -91,6 +283,14 @@ impl Step {
             step_type,
             step_content,
             description,
+            llm_provider: None,
+            system_prompt: None,
+            input_mapping: None,
+            cacheable: false,
+            expected_duration: None,
+            wrap_output: false,
+            on_error: None,
+            metrics: StepMetrics::default(),
         }
     }
 
@@ -103,11 +303,17 @@ impl Step {
         Self {
             identifiers,
             timestamps: TimestampFields::new(),
-            step_type: StepType::Prompt(
-                llm_model.unwrap_or_else(|| crate::JsonModeLLMs::MetaLlama33_70b.to_string()),
-            ),
+            step_type: StepType::Prompt(llm_model),
             step_content,
             description,
+            llm_provider: None,
+            system_prompt: None,
+            input_mapping: None,
+            cacheable: false,
+            expected_duration: None,
+            wrap_output: false,
+            on_error: None,
+            metrics: StepMetrics::default(),
         }
     }
 
@@ -118,9 +324,78 @@ impl Step {
             step_type: StepType::WebScrape,
             step_content: url,
             description,
+            llm_provider: None,
+            system_prompt: None,
+            input_mapping: None,
+            cacheable: false,
+            expected_duration: None,
+            wrap_output: false,
+            on_error: None,
+            metrics: StepMetrics::default(),
         }
     }
 
+    /// Routes a Prompt step to a named provider from the `LlmProviderRegistry`
+    pub fn with_llm_provider(mut self, provider: impl Into<String>) -> Self {
+        self.llm_provider = Some(provider.into());
+        self
+    }
+
+    /// Sets the system prompt sent ahead of a Prompt step's content
+    pub fn with_system_prompt(mut self, system_prompt: impl Into<String>) -> Self {
+        self.system_prompt = Some(system_prompt.into());
+        self
+    }
+
+    /// Sets `input_mapping`, e.g. `[("title", "$.page.meta.title")]`
+    pub fn with_input_mapping<K, V>(mut self, mapping: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.input_mapping = Some(
+            mapping
+                .into_iter()
+                .map(|(param, path)| (param.into(), path.into()))
+                .collect(),
+        );
+        self
+    }
+
+    /// Marks the step `cacheable`
+    pub fn with_caching(mut self) -> Self {
+        self.cacheable = true;
+        self
+    }
+
+    /// Sets `expected_duration`, the step's latency SLA
+    pub fn with_expected_duration(mut self, expected: Duration) -> Self {
+        self.expected_duration = Some(expected);
+        self
+    }
+
+    /// Sets `wrap_output`, so results that aren't dicts arrive as `{"data": result}`
+    pub fn with_wrapped_output(mut self) -> Self {
+        self.wrap_output = true;
+        self
+    }
+
+    /// Sets `on_error`, the step that recovers from this step's failures
+    pub fn with_error_handler(mut self, handler_uuid: impl Into<String>) -> Self {
+        self.on_error = Some(handler_uuid.into());
+        self
+    }
+
+    /// Number of times `run` has been called
+    pub fn get_run_count(&self) -> i64 {
+        self.metrics.run_count()
+    }
+
+    /// Number of runs that returned a result
+    pub fn get_success_count(&self) -> i64 {
+        self.metrics.success_count()
+    }
+
     pub fn is_python_step(&self) -> bool {
         matches!(self.step_type, StepType::Python)
     }
@@ -133,6 +408,18 @@ impl Step {
         matches!(self.step_type, StepType::WebScrape)
     }
 
+    pub fn is_loop_step(&self) -> bool {
+        matches!(self.step_type, StepType::Loop)
+    }
+
+    pub fn is_json_patch_step(&self) -> bool {
+        matches!(self.step_type, StepType::JsonPatch)
+    }
+
+    pub fn is_file_op_step(&self) -> bool {
+        matches!(self.step_type, StepType::FileOp)
+    }
+
     pub fn get_llm_model(&self) -> Option<String> {
         self.step_type.get_llm_model()
     }
@@ -0,0 +1,317 @@
+//! Small predicate language for control-flow steps (e.g. a Loop's `until`),
+//! evaluated in Rust so agents without a PythonRuntime can use it.
+//!
+//! Grammar:
+//! ```plain
+//! expr     := and ("or" and)*
+//! and      := unary ("and" unary)*
+//! unary    := "not" unary | compare
+//! compare  := operand (("==" | "!=" | "<" | "<=" | ">" | ">=") operand)?
+//! operand  := literal | path | "exists" "(" path ")" | "(" expr ")"
+//! literal  := number | 'string' | "string" | true | false | null
+//! path     := JSONPath as accepted by `resolve_json_path`, e.g. `$.items[0].score`
+//! ```
+//! A path that doesn't resolve evaluates to `null`. An expression that isn't a
+//! comparison (e.g. a lone path) is checked for truthiness with `is_truthy`.
+//! `<`, `<=`, `>`, `>=` need two numbers or two strings; anything else is an error.
+//!
+//! Example: `exists($.result) and ($.score >= 0.8 or $.status == 'done')`
+
+use crate::{is_truthy, resolve_json_path};
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::cmp::Ordering;
+
+/// Evaluates `expr` against `data`, see the module docs for the grammar
+pub fn eval_predicate(expr: &str, data: &Value) -> Result<bool> {
+    let tokens = tokenize(expr)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let ast = parser.parse_or()?;
+    if let Some(token) = parser.peek() {
+        return Err(anyhow!("Unexpected {:?} in predicate `{}`", token, expr));
+    }
+    Ok(is_truthy(&ast.eval(data)?))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Literal(Value),
+    Path(String),
+    And,
+    Or,
+    Not,
+    Exists,
+    Compare(CompareOp),
+    LParen,
+    RParen,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug)]
+enum Expr {
+    Literal(Value),
+    Path(String),
+    Exists(String),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(CompareOp, Box<Expr>, Box<Expr>),
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            _ if c.is_whitespace() => i += 1,
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            }
+            '=' | '!' | '<' | '>' => {
+                let two_char = chars.get(i + 1) == Some(&'=');
+                let op = match (c, two_char) {
+                    ('=', true) => CompareOp::Eq,
+                    ('!', true) => CompareOp::Ne,
+                    ('<', true) => CompareOp::Le,
+                    ('>', true) => CompareOp::Ge,
+                    ('<', false) => CompareOp::Lt,
+                    ('>', false) => CompareOp::Gt,
+                    _ => return Err(anyhow!("Unexpected `{}` at position {}", c, i)),
+                };
+                tokens.push(Token::Compare(op));
+                i += if two_char { 2 } else { 1 };
+            }
+            '\'' | '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&ch| ch == c)
+                    .ok_or_else(|| anyhow!("Unterminated string at position {}", i))?;
+                let s: String = chars[i + 1..i + 1 + end].iter().collect();
+                tokens.push(Token::Literal(Value::String(s)));
+                i += end + 2;
+            }
+            _ if c.is_ascii_digit() || (c == '-' && next_is_digit(&chars, i)) => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let raw: String = chars[start..i].iter().collect();
+                let number: Value =
+                    serde_json::from_str(&raw).map_err(|_| anyhow!("Invalid number `{}`", raw))?;
+                tokens.push(Token::Literal(number));
+            }
+            _ if c == '$' || c == '_' || c.is_alphabetic() => {
+                let start = i;
+                i = scan_path(&chars, i)?;
+                let word: String = chars[start..i].iter().collect();
+                tokens.push(match word.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    "exists" => Token::Exists,
+                    "true" => Token::Literal(Value::Bool(true)),
+                    "false" => Token::Literal(Value::Bool(false)),
+                    "null" => Token::Literal(Value::Null),
+                    _ => Token::Path(word),
+                });
+            }
+            _ => return Err(anyhow!("Unexpected `{}` at position {}", c, i)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn next_is_digit(chars: &[char], i: usize) -> bool {
+    chars.get(i + 1).is_some_and(|c| c.is_ascii_digit())
+}
+
+/// Returns the end of a path starting at `start`, keeping bracketed
+/// segments (which may hold quoted keys) together
+fn scan_path(chars: &[char], start: usize) -> Result<usize> {
+    let mut i = start;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '[' {
+            let close = chars[i..]
+                .iter()
+                .position(|&ch| ch == ']')
+                .ok_or_else(|| anyhow!("Unclosed `[` at position {}", i))?;
+            i += close + 1;
+        } else if c.is_alphanumeric() || matches!(c, '_' | '.' | '$' | '-') {
+            i += 1;
+        } else {
+            break;
+        }
+    }
+    Ok(i)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(anyhow!("Expected {:?}, found {:?}", expected, token)),
+            None => Err(anyhow!("Expected {:?}, found end of predicate", expected)),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut left = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            left = Expr::Or(Box::new(left), Box::new(self.parse_and()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut left = self.parse_unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            left = Expr::And(Box::new(left), Box::new(self.parse_unary()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+        self.parse_compare()
+    }
+
+    fn parse_compare(&mut self) -> Result<Expr> {
+        let left = self.parse_operand()?;
+        if let Some(Token::Compare(op)) = self.peek() {
+            let op = *op;
+            self.pos += 1;
+            let right = self.parse_operand()?;
+            return Ok(Expr::Compare(op, Box::new(left), Box::new(right)));
+        }
+        Ok(left)
+    }
+
+    fn parse_operand(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Literal(value)) => Ok(Expr::Literal(value)),
+            Some(Token::Path(path)) => Ok(Expr::Path(path)),
+            Some(Token::Exists) => {
+                self.expect(Token::LParen)?;
+                let path = match self.next() {
+                    Some(Token::Path(path)) => path,
+                    other => return Err(anyhow!("`exists` expects a path, found {:?}", other)),
+                };
+                self.expect(Token::RParen)?;
+                Ok(Expr::Exists(path))
+            }
+            Some(Token::LParen) => {
+                let inner = self.parse_or()?;
+                self.expect(Token::RParen)?;
+                Ok(inner)
+            }
+            Some(token) => Err(anyhow!("Unexpected {:?} in predicate", token)),
+            None => Err(anyhow!("Unexpected end of predicate")),
+        }
+    }
+}
+
+impl Expr {
+    fn eval(&self, data: &Value) -> Result<Value> {
+        Ok(match self {
+            Expr::Literal(value) => value.clone(),
+            Expr::Path(path) => resolve_json_path(data, path)
+                .cloned()
+                .unwrap_or(Value::Null),
+            Expr::Exists(path) => Value::Bool(resolve_json_path(data, path).is_some()),
+            Expr::Not(inner) => Value::Bool(!is_truthy(&inner.eval(data)?)),
+            Expr::And(left, right) => {
+                Value::Bool(is_truthy(&left.eval(data)?) && is_truthy(&right.eval(data)?))
+            }
+            Expr::Or(left, right) => {
+                Value::Bool(is_truthy(&left.eval(data)?) || is_truthy(&right.eval(data)?))
+            }
+            Expr::Compare(op, left, right) => {
+                Value::Bool(compare(*op, &left.eval(data)?, &right.eval(data)?)?)
+            }
+        })
+    }
+}
+
+fn compare(op: CompareOp, left: &Value, right: &Value) -> Result<bool> {
+    // Numbers compare by value so `1 == 1.0`
+    let equal = match (left.as_f64(), right.as_f64()) {
+        (Some(l), Some(r)) => l == r,
+        _ => left == right,
+    };
+
+    let ordering = || -> Result<Ordering> {
+        match (left, right) {
+            (Value::Number(_), Value::Number(_)) => left
+                .as_f64()
+                .zip(right.as_f64())
+                .and_then(|(l, r)| l.partial_cmp(&r))
+                .ok_or_else(|| anyhow!("Cannot compare {} and {}", left, right)),
+            (Value::String(l), Value::String(r)) => Ok(l.cmp(r)),
+            _ => Err(anyhow!(
+                "Type mismatch: cannot order {} and {}",
+                type_name(left),
+                type_name(right)
+            )),
+        }
+    };
+
+    Ok(match op {
+        CompareOp::Eq => equal,
+        CompareOp::Ne => !equal,
+        CompareOp::Lt => ordering()? == Ordering::Less,
+        CompareOp::Le => ordering()? != Ordering::Greater,
+        CompareOp::Gt => ordering()? == Ordering::Greater,
+        CompareOp::Ge => ordering()? != Ordering::Less,
+    })
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
@@ -0,0 +1,86 @@
+use crate::models::steps::{StepError, StepErrorKind};
+use anyhow::Result;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::env;
+
+/// Start of a secret reference in step content, e.g. `${secret:WEATHER_API_KEY}`
+pub const SECRET_REF_PREFIX: &str = "${secret:";
+
+/// Prefix of the environment variables `SecretStore::from_env` reads
+pub const SECRET_ENV_PREFIX: &str = "PORTICO_SECRET_";
+
+/// Secrets that step content can reference as `${secret:NAME}`. References are
+/// resolved when the step runs, so stored step content only holds the placeholder.
+#[derive(Debug, Clone, Default)]
+pub struct SecretStore {
+    secrets: HashMap<String, String>,
+}
+
+impl SecretStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds the store from the environment: `PORTICO_SECRET_<NAME>=value`
+    /// makes `${secret:<NAME>}` resolve to `value`
+    pub fn from_env() -> Self {
+        let mut store = Self::new();
+        for (key, value) in env::vars() {
+            if let Some(name) = key.strip_prefix(SECRET_ENV_PREFIX) {
+                store.insert(name, value);
+            }
+        }
+        store
+    }
+
+    pub fn insert(&mut self, name: &str, value: impl Into<String>) {
+        self.secrets.insert(name.to_string(), value.into());
+    }
+
+    /// Replaces every `${secret:NAME}` in `content` with the secret's value.
+    /// Unknown names and unterminated references are `Config` errors.
+    pub fn resolve<'a>(&self, content: &'a str) -> Result<Cow<'a, str>> {
+        if !content.contains(SECRET_REF_PREFIX) {
+            return Ok(Cow::Borrowed(content));
+        }
+
+        let mut resolved = String::with_capacity(content.len());
+        let mut rest = content;
+        while let Some(start) = rest.find(SECRET_REF_PREFIX) {
+            resolved.push_str(&rest[..start]);
+            let after = &rest[start + SECRET_REF_PREFIX.len()..];
+            let end = after.find('}').ok_or_else(|| {
+                StepError::new(StepErrorKind::Config, "Unterminated secret reference")
+            })?;
+            let name = &after[..end];
+            let value = self.secrets.get(name).ok_or_else(|| {
+                StepError::new(StepErrorKind::Config, format!("Unknown secret: {}", name))
+            })?;
+            resolved.push_str(value);
+            rest = &after[end + 1..];
+        }
+        resolved.push_str(rest);
+
+        Ok(Cow::Owned(resolved))
+    }
+
+    /// The reverse of `resolve`: every secret value in `text` is put back as its
+    /// `${secret:NAME}` reference, so resolved content can be logged
+    pub fn redact(&self, text: &str) -> String {
+        // Longest first, so a secret containing another is replaced whole
+        let mut secrets: Vec<_> = self
+            .secrets
+            .iter()
+            .filter(|(_, value)| !value.is_empty())
+            .collect();
+        secrets.sort_by_key(|(_, value)| std::cmp::Reverse(value.len()));
+
+        let mut redacted = text.to_string();
+        for (name, value) in secrets {
+            redacted =
+                redacted.replace(value.as_str(), &format!("{}{}}}", SECRET_REF_PREFIX, name));
+        }
+        redacted
+    }
+}
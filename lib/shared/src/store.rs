@@ -0,0 +1,456 @@
+//! Persistence backends for the shared models.
+//!
+//! Code that saves or loads models should go through a `&dyn Store` rather than
+//! a `PgPool`, so the same logic runs against Postgres (`PgStore`, the server
+//! deployment) or an embedded backend such as `MemoryStore`.
+
+use crate::{Agent, DatabaseItem, IdFields, RuntimeSession, Signal, Step};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::RwLock;
+
+/// Create/read/update/delete/select for each model
+#[async_trait]
+pub trait Store: Send + Sync {
+    async fn create_agent(&self, agent: &Agent) -> Result<()>;
+    async fn update_agent(&self, agent: &Agent) -> Result<()>;
+    async fn delete_agent(&self, agent: &Agent) -> Result<()>;
+    /// Deletes an agent and its steps by id, without loading the agent first
+    async fn delete_agent_by_id(&self, id: &IdFields) -> Result<()>;
+    async fn select_all_agents(&self) -> Result<Vec<Agent>>;
+    async fn select_agent_by_id(&self, id: &IdFields) -> Result<Option<Agent>>;
+
+    async fn create_step(&self, step: &Step) -> Result<()>;
+    async fn update_step(&self, step: &Step) -> Result<()>;
+    async fn delete_step(&self, step: &Step) -> Result<()>;
+    async fn select_all_steps(&self) -> Result<Vec<Step>>;
+    async fn select_step_by_id(&self, id: &IdFields) -> Result<Option<Step>>;
+
+    async fn create_signal(&self, signal: &Signal) -> Result<()>;
+    async fn update_signal(&self, signal: &Signal) -> Result<()>;
+    async fn delete_signal(&self, signal: &Signal) -> Result<()>;
+    async fn select_all_signals(&self) -> Result<Vec<Signal>>;
+    async fn select_signal_by_id(&self, id: &IdFields<i64>) -> Result<Option<Signal>>;
+
+    async fn create_runtime_session(&self, session: &RuntimeSession) -> Result<()>;
+    async fn update_runtime_session(&self, session: &RuntimeSession) -> Result<()>;
+    async fn delete_runtime_session(&self, session: &RuntimeSession) -> Result<()>;
+    async fn select_all_runtime_sessions(&self) -> Result<Vec<RuntimeSession>>;
+    async fn select_runtime_session_by_id(
+        &self,
+        id: &IdFields<i64>,
+    ) -> Result<Option<RuntimeSession>>;
+}
+
+/// Postgres backend, using each model's `DatabaseItem` queries
+#[derive(Debug, Clone)]
+pub struct PgStore {
+    pool: PgPool,
+}
+
+impl PgStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+}
+
+#[async_trait]
+impl Store for PgStore {
+    async fn create_agent(&self, agent: &Agent) -> Result<()> {
+        agent.try_db_create(&self.pool).await
+    }
+
+    async fn update_agent(&self, agent: &Agent) -> Result<()> {
+        agent.try_db_update(&self.pool).await
+    }
+
+    async fn delete_agent(&self, agent: &Agent) -> Result<()> {
+        agent.try_db_delete(&self.pool).await
+    }
+
+    async fn delete_agent_by_id(&self, id: &IdFields) -> Result<()> {
+        Agent::try_db_delete_by_id(&self.pool, id).await?;
+        Ok(())
+    }
+
+    async fn select_all_agents(&self) -> Result<Vec<Agent>> {
+        Agent::try_db_select_all(&self.pool).await
+    }
+
+    async fn select_agent_by_id(&self, id: &IdFields) -> Result<Option<Agent>> {
+        Agent::try_db_select_by_id(&self.pool, id).await
+    }
+
+    async fn create_step(&self, step: &Step) -> Result<()> {
+        step.try_db_create(&self.pool).await
+    }
+
+    async fn update_step(&self, step: &Step) -> Result<()> {
+        step.try_db_update(&self.pool).await
+    }
+
+    async fn delete_step(&self, step: &Step) -> Result<()> {
+        step.try_db_delete(&self.pool).await
+    }
+
+    async fn select_all_steps(&self) -> Result<Vec<Step>> {
+        Step::try_db_select_all(&self.pool).await
+    }
+
+    async fn select_step_by_id(&self, id: &IdFields) -> Result<Option<Step>> {
+        Step::try_db_select_by_id(&self.pool, id).await
+    }
+
+    async fn create_signal(&self, signal: &Signal) -> Result<()> {
+        signal.try_db_create(&self.pool).await
+    }
+
+    async fn update_signal(&self, signal: &Signal) -> Result<()> {
+        signal.try_db_update(&self.pool).await
+    }
+
+    async fn delete_signal(&self, signal: &Signal) -> Result<()> {
+        signal.try_db_delete(&self.pool).await
+    }
+
+    async fn select_all_signals(&self) -> Result<Vec<Signal>> {
+        Signal::try_db_select_all(&self.pool).await
+    }
+
+    async fn select_signal_by_id(&self, id: &IdFields<i64>) -> Result<Option<Signal>> {
+        Signal::try_db_select_by_id(&self.pool, id).await
+    }
+
+    async fn create_runtime_session(&self, session: &RuntimeSession) -> Result<()> {
+        session.try_db_create(&self.pool).await
+    }
+
+    async fn update_runtime_session(&self, session: &RuntimeSession) -> Result<()> {
+        session.try_db_update(&self.pool).await
+    }
+
+    async fn delete_runtime_session(&self, session: &RuntimeSession) -> Result<()> {
+        session.try_db_delete(&self.pool).await
+    }
+
+    async fn select_all_runtime_sessions(&self) -> Result<Vec<RuntimeSession>> {
+        RuntimeSession::try_db_select_all(&self.pool).await
+    }
+
+    async fn select_runtime_session_by_id(
+        &self,
+        id: &IdFields<i64>,
+    ) -> Result<Option<RuntimeSession>> {
+        RuntimeSession::try_db_select_by_id(&self.pool, id).await
+    }
+}
+
+/// In-process backend for embedded/local use and tests. Nothing is persisted.
+/// Each model is a `RwLock<HashMap>` keyed by `global_uuid`, and behaves like
+/// its `DatabaseItem` queries do against Postgres:
+/// - creating assigns a `local_id` if the record has none. A UUID that already
+///   exists is skipped for agents and runtime sessions, and an error for steps
+///   and signals (which have no existence check before their `INSERT`)
+/// - updating a record that doesn't exist changes nothing
+/// - deleting a step that doesn't exist is an error, other models skip it
+/// - signals can't be updated or deleted without a `local_id`
+/// - an agent's steps are created and deleted along with it
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    agents: Table<Agent>,
+    steps: Table<Step>,
+    signals: Table<Signal>,
+    runtime_sessions: Table<RuntimeSession>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn create_agent(&self, agent: &Agent) -> Result<()> {
+        if self.agents.contains(&agent.identifiers.global_uuid) {
+            return Ok(());
+        }
+
+        let mut row = agent.clone();
+        row.steps = agent
+            .steps
+            .iter()
+            .map(|step| self.steps.create(step))
+            .collect::<Result<_>>()?;
+        self.agents.create(&row)?;
+        Ok(())
+    }
+
+    async fn update_agent(&self, agent: &Agent) -> Result<()> {
+        self.agents.update(agent);
+        Ok(())
+    }
+
+    async fn delete_agent(&self, agent: &Agent) -> Result<()> {
+        if let Some(row) = self.agents.delete(agent)? {
+            for step in &row.steps {
+                self.steps.delete(step)?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete_agent_by_id(&self, id: &IdFields) -> Result<()> {
+        match self.agents.select_by_id(id) {
+            Some(agent) => self.delete_agent(&agent).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn select_all_agents(&self) -> Result<Vec<Agent>> {
+        Ok(self.agents.select_all())
+    }
+
+    async fn select_agent_by_id(&self, id: &IdFields) -> Result<Option<Agent>> {
+        Ok(self.agents.select_by_id(id))
+    }
+
+    async fn create_step(&self, step: &Step) -> Result<()> {
+        self.steps.create(step)?;
+        Ok(())
+    }
+
+    async fn update_step(&self, step: &Step) -> Result<()> {
+        self.steps.update(step);
+        Ok(())
+    }
+
+    async fn delete_step(&self, step: &Step) -> Result<()> {
+        self.steps.delete(step)?;
+        Ok(())
+    }
+
+    async fn select_all_steps(&self) -> Result<Vec<Step>> {
+        Ok(self.steps.select_all())
+    }
+
+    async fn select_step_by_id(&self, id: &IdFields) -> Result<Option<Step>> {
+        Ok(self.steps.select_by_id(id))
+    }
+
+    async fn create_signal(&self, signal: &Signal) -> Result<()> {
+        self.signals.create(signal)?;
+        Ok(())
+    }
+
+    async fn update_signal(&self, signal: &Signal) -> Result<()> {
+        if signal.identifiers.local_id.is_none() {
+            return Err(anyhow!("Cannot update signal without a local ID"));
+        }
+        self.signals.update(signal);
+        Ok(())
+    }
+
+    async fn delete_signal(&self, signal: &Signal) -> Result<()> {
+        if signal.identifiers.local_id.is_none() {
+            return Err(anyhow!("Cannot delete signal without a local ID"));
+        }
+        self.signals.delete(signal)?;
+        Ok(())
+    }
+
+    async fn select_all_signals(&self) -> Result<Vec<Signal>> {
+        Ok(self.signals.select_all())
+    }
+
+    async fn select_signal_by_id(&self, id: &IdFields<i64>) -> Result<Option<Signal>> {
+        Ok(self.signals.select_by_id(id))
+    }
+
+    async fn create_runtime_session(&self, session: &RuntimeSession) -> Result<()> {
+        self.runtime_sessions.create(session)?;
+        Ok(())
+    }
+
+    async fn update_runtime_session(&self, session: &RuntimeSession) -> Result<()> {
+        self.runtime_sessions.update(session);
+        Ok(())
+    }
+
+    async fn delete_runtime_session(&self, session: &RuntimeSession) -> Result<()> {
+        self.runtime_sessions.delete(session)?;
+        Ok(())
+    }
+
+    async fn select_all_runtime_sessions(&self) -> Result<Vec<RuntimeSession>> {
+        Ok(self.runtime_sessions.select_all())
+    }
+
+    async fn select_runtime_session_by_id(
+        &self,
+        id: &IdFields<i64>,
+    ) -> Result<Option<RuntimeSession>> {
+        Ok(self.runtime_sessions.select_by_id(id))
+    }
+}
+
+/// A model `MemoryStore` can hold
+trait Record: DatabaseItem + Clone + Send + Sync {
+    /// Name used in error messages
+    const NAME: &'static str;
+    /// Whether creating an existing UUID is skipped rather than an error
+    const SKIP_EXISTING_ON_CREATE: bool;
+    /// Whether deleting a missing record is an error rather than skipped
+    const DELETE_REQUIRES_EXISTING: bool;
+
+    fn identifiers_mut(&mut self) -> &mut IdFields<Self::IdType>;
+    fn local_id_from(n: i64) -> Self::IdType;
+}
+
+impl Record for Agent {
+    const NAME: &'static str = "Agent";
+    const SKIP_EXISTING_ON_CREATE: bool = true;
+    const DELETE_REQUIRES_EXISTING: bool = false;
+
+    fn identifiers_mut(&mut self) -> &mut IdFields {
+        &mut self.identifiers
+    }
+
+    fn local_id_from(n: i64) -> i32 {
+        n as i32
+    }
+}
+
+impl Record for Step {
+    const NAME: &'static str = "Step";
+    const SKIP_EXISTING_ON_CREATE: bool = false;
+    const DELETE_REQUIRES_EXISTING: bool = true;
+
+    fn identifiers_mut(&mut self) -> &mut IdFields {
+        &mut self.identifiers
+    }
+
+    fn local_id_from(n: i64) -> i32 {
+        n as i32
+    }
+}
+
+impl Record for Signal {
+    const NAME: &'static str = "Signal";
+    const SKIP_EXISTING_ON_CREATE: bool = false;
+    const DELETE_REQUIRES_EXISTING: bool = false;
+
+    fn identifiers_mut(&mut self) -> &mut IdFields<i64> {
+        &mut self.identifiers
+    }
+
+    fn local_id_from(n: i64) -> i64 {
+        n
+    }
+}
+
+impl Record for RuntimeSession {
+    const NAME: &'static str = "RuntimeSession";
+    const SKIP_EXISTING_ON_CREATE: bool = true;
+    const DELETE_REQUIRES_EXISTING: bool = false;
+
+    fn identifiers_mut(&mut self) -> &mut IdFields<i64> {
+        &mut self.identifiers
+    }
+
+    fn local_id_from(n: i64) -> i64 {
+        n
+    }
+}
+
+/// One model's records, keyed by `global_uuid`
+#[derive(Debug)]
+struct Table<T> {
+    rows: RwLock<HashMap<String, T>>,
+    next_id: AtomicI64,
+}
+
+impl<T> Default for Table<T> {
+    fn default() -> Self {
+        Self {
+            rows: RwLock::new(HashMap::new()),
+            next_id: AtomicI64::new(1),
+        }
+    }
+}
+
+impl<T: Record> Table<T>
+where
+    T::IdType: Ord,
+{
+    fn contains(&self, uuid: &str) -> bool {
+        self.rows.read().unwrap().contains_key(uuid)
+    }
+
+    /// Returns the stored copy, with its assigned `local_id`
+    fn create(&self, item: &T) -> Result<T> {
+        let mut rows = self.rows.write().unwrap();
+        let uuid = &item.id().global_uuid;
+        if let Some(existing) = rows.get(uuid) {
+            if T::SKIP_EXISTING_ON_CREATE {
+                return Ok(existing.clone());
+            }
+            return Err(anyhow!("{} with UUID {} already exists", T::NAME, uuid));
+        }
+
+        let mut row = item.clone();
+        if row.id().local_id.is_none() {
+            let next_id = self.next_id.fetch_add(1, Ordering::SeqCst);
+            row.identifiers_mut().local_id = Some(T::local_id_from(next_id));
+        }
+        rows.insert(uuid.clone(), row.clone());
+        Ok(row)
+    }
+
+    fn update(&self, item: &T) {
+        let mut rows = self.rows.write().unwrap();
+        if let Some(row) = rows.get_mut(&item.id().global_uuid) {
+            // Keep the assigned local_id if the caller's copy doesn't have it
+            let local_id = row.id().local_id.clone();
+            *row = item.clone();
+            if row.id().local_id.is_none() {
+                row.identifiers_mut().local_id = local_id;
+            }
+        }
+    }
+
+    /// Returns the removed record, if there was one
+    fn delete(&self, item: &T) -> Result<Option<T>> {
+        let removed = self.rows.write().unwrap().remove(&item.id().global_uuid);
+        if removed.is_none() && T::DELETE_REQUIRES_EXISTING {
+            return Err(anyhow!("Failed to delete {}", T::NAME));
+        }
+        Ok(removed)
+    }
+
+    /// All records, in `local_id` order
+    fn select_all(&self) -> Vec<T> {
+        let mut rows: Vec<T> = self.rows.read().unwrap().values().cloned().collect();
+        rows.sort_by(|a, b| a.id().local_id.cmp(&b.id().local_id));
+        rows
+    }
+
+    /// Matches on `local_id` when given, otherwise on `global_uuid`
+    fn select_by_id(&self, id: &IdFields<T::IdType>) -> Option<T> {
+        let rows = self.rows.read().unwrap();
+        match &id.local_id {
+            Some(local_id) => rows
+                .values()
+                .find(|row| row.id().local_id.as_ref() == Some(local_id))
+                .cloned(),
+            None => rows.get(&id.global_uuid).cloned(),
+        }
+    }
+}
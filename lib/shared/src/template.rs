@@ -0,0 +1,59 @@
+//! Mustache-style templates for Prompt steps, filled from the step's input.
+//!
+//! - `{{path}}` inserts the value at `path`, a JSONPath as accepted by
+//!   `resolve_json_path` (the leading `$` is optional), e.g. `{{article.title}}`
+//!   or `{{items[0].name}}`. `{{.}}` inserts the whole input.
+//! - Strings are inserted as-is; numbers, booleans, arrays and objects as JSON.
+//! - A path that doesn't resolve, or resolves to `null`, inserts nothing.
+//! - `{{{path}}}` is the same as `{{path}}` (nothing is HTML-escaped), and
+//!   `{{! comment }}` is dropped.
+//! - An unclosed `{{` is kept as literal text.
+//!
+//! Example: `Summarize {{article.title}} in {{n}} words`
+
+use crate::resolve_json_path;
+use serde_json::Value;
+
+/// Renders `template` against `data`, see the module docs for the syntax
+pub fn render_prompt(template: &str, data: &Value) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(open) = rest.find("{{") {
+        rendered.push_str(&rest[..open]);
+        let after_open = &rest[open + 2..];
+        let (tag_start, close) = match after_open.strip_prefix('{') {
+            Some(inner) => (inner, "}}}"),
+            None => (after_open, "}}"),
+        };
+
+        let Some(end) = tag_start.find(close) else {
+            // Unclosed tag, so the rest is plain text
+            rendered.push_str(&rest[open..]);
+            return rendered;
+        };
+        let tag = tag_start[..end].trim();
+        if !tag.starts_with('!') {
+            rendered.push_str(&render_value(lookup(data, tag)));
+        }
+        rest = &tag_start[end + close.len()..];
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
+
+fn lookup<'a>(data: &'a Value, path: &str) -> Option<&'a Value> {
+    match path {
+        "." | "$" => Some(data),
+        _ => resolve_json_path(data, path),
+    }
+}
+
+fn render_value(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+    }
+}
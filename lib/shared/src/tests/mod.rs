@@ -1,5 +1,19 @@
 mod test_agents;
 mod test_lib;
+mod test_predicate;
 mod test_runtime_sessions;
 mod test_signals;
 mod test_steps;
+mod test_store;
+mod test_template;
+mod test_webscrape;
+
+/// A pool on the `POSTGRES_DB_URI` database for tests that need one, or `None`
+/// when it isn't set, so those tests skip themselves
+async fn test_pool() -> Option<sqlx::PgPool> {
+    let Ok(db_url) = std::env::var("POSTGRES_DB_URI") else {
+        eprintln!("POSTGRES_DB_URI not set, skipping");
+        return None;
+    };
+    Some(sqlx::PgPool::connect(&db_url).await.unwrap())
+}
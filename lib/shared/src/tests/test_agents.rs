@@ -1,9 +1,18 @@
+use super::test_pool;
+use super::test_steps::{completion_response, spawn_http_handler, LLM_ENV_LOCK};
 use crate::{
-    models::steps::StepType,
-    models::{Agent, Step},
-    IdFields, TimestampFields,
+    check_exists_by_uuid,
+    models::agents::{
+        AgentConfig, AgentState, CascadeCounts, CascadeMode, RateLimitConfig, RateLimiter,
+        RetryConfig, AGENT_BUNDLE_VERSION,
+    },
+    models::runtime_sessions::{FailurePolicy, RunBudget, RunContext},
+    models::steps::{LlmIoLogConfig, StepType},
+    models::{Agent, RuntimeSession, Signal, SignalType, Step},
+    DatabaseItem, IdFields, JsonLike, JsonModeLLMs, RunningStatus,
 };
 use serde_json::json;
+use std::time::{Duration, Instant};
 
 #[test]
 fn test_new_agent() {
@@ -117,20 +126,755 @@ fn test_completion_rate() {
     }
 }
 
-fn create_test_agent() -> Agent {
-    let id_fields = IdFields::new();
-    let timestamps = TimestampFields::new();
-    let description = "Test Agent".to_string();
+#[test]
+fn test_rate_limiter_spaces_out_queued_signals() {
+    tokio_test::block_on(async {
+        // Enqueue a burst of signals, as the agent worker would receive them
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        for signal_id in 0..3 {
+            tx.send(signal_id).await.unwrap();
+        }
+        drop(tx);
+
+        let mut limiter = RateLimiter::new(RateLimitConfig {
+            requests_per_second: 1.0,
+            burst: 1,
+        });
+
+        let mut run_times = Vec::new();
+        while rx.recv().await.is_some() {
+            limiter.acquire().await;
+            run_times.push(Instant::now());
+        }
+
+        assert_eq!(run_times.len(), 3);
+        for pair in run_times.windows(2) {
+            let gap = pair[1].duration_since(pair[0]);
+            assert!(
+                gap >= Duration::from_millis(950),
+                "Runs should be ~1s apart, got {:?}",
+                gap
+            );
+        }
+    });
+}
+
+#[test]
+fn test_agent_config_round_trips_through_json() {
+    let mut agent = create_test_agent();
+    agent.config = AgentConfig {
+        rate_limit: Some(RateLimitConfig {
+            requests_per_second: 2.5,
+            burst: 3,
+        }),
+        budget: Some(RunBudget {
+            max_total_duration_ms: Some(60_000),
+            max_llm_calls: Some(5),
+            ..RunBudget::default()
+        }),
+        legacy_prompt_format: true,
+        failure_policy: FailurePolicy::SkipAndContinue,
+        run_retry: Some(RetryConfig {
+            max_retries: 2,
+            initial_backoff_ms: 250,
+            max_backoff_ms: 1000,
+        }),
+        default_model: Some("agent-model".to_string()),
+        log_llm_io: Some(LlmIoLogConfig {
+            redact_patterns: vec![r"\d{6,}".to_string()],
+        }),
+        min_inter_step_delay_ms: 250,
+    };
+
+    let restored = Agent::from_json(agent.to_json()).unwrap();
+    assert_eq!(restored.config, agent.config);
+    // The steps come back too, timestamps (to the second) included
+    assert_eq!(restored.steps.len(), 1);
+    assert_eq!(
+        restored.steps[0].timestamps.created.timestamp(),
+        agent.steps[0].timestamps.created.timestamp()
+    );
+
+    // Agents without a config fall back to no rate limit
+    let mut legacy_json = agent.to_json();
+    legacy_json.as_object_mut().unwrap().remove("config");
+    let legacy = Agent::from_json(legacy_json).unwrap();
+    assert_eq!(legacy.config, AgentConfig::default());
+}
+
+#[test]
+fn test_agent_env_is_visible_to_python_steps() {
+    let mut agent = create_test_agent();
+    agent
+        .env
+        .insert("API_KEY".to_string(), "secret-123".to_string());
+    agent.steps = vec![Step::new(
+        IdFields::new(),
+        StepType::Python,
+        "source['key'] = AGENT_ENV['API_KEY']\nsource['missing'] = AGENT_ENV.get('OTHER')\nresult = source"
+            .to_string(),
+        None,
+    )];
+    agent.start().unwrap();
+
+    let session = tokio_test::block_on(agent.run(json!({}))).unwrap();
+    assert_eq!(
+        session.last_successful_result.unwrap(),
+        json!({"key": "secret-123", "missing": null})
+    );
+
+    // Env vars may be secrets, so they stay out of serialized forms
+    assert!(!agent.to_json().to_string().contains("secret-123"));
+    assert!(!serde_json::to_string(&agent)
+        .unwrap()
+        .contains("secret-123"));
+    assert!(!agent.export_bundle().to_string().contains("secret-123"));
+
+    // ...but can still be supplied when building an agent from JSON
+    let mut incoming = agent.to_json();
+    incoming["env"] = json!({"API_KEY": "from-json"});
+    let restored = Agent::from_json(incoming).unwrap();
+    assert_eq!(restored.env["API_KEY"], "from-json");
+}
+
+#[test]
+fn test_run_context_is_visible_to_python_steps() {
+    let mut agent = create_test_agent();
+    agent.steps = vec![Step::new(
+        IdFields::new(),
+        StepType::Python,
+        "source['trigger'] = context['trigger']\nsource['by'] = context['requested_by']\nresult = source"
+            .to_string(),
+        None,
+    )];
+    agent.start().unwrap();
+
+    let context = RunContext {
+        trigger: "scheduled".to_string(),
+        requested_by: Some("nightly-digest".to_string()),
+        signal_uuid: None,
+    };
+    let session = tokio_test::block_on(agent.run_with_context(json!({}), context.clone())).unwrap();
+    assert_eq!(
+        session.last_successful_result.unwrap(),
+        json!({"trigger": "scheduled", "by": "nightly-digest"})
+    );
+    assert_eq!(session.context, context);
+
+    // A plain `run` gets the default context
+    let session = tokio_test::block_on(agent.run(json!({}))).unwrap();
+    assert_eq!(
+        session.last_successful_result.unwrap(),
+        json!({"trigger": "manual", "by": null})
+    );
+}
+
+#[test]
+fn test_agent_bundle_round_trip() {
+    let mut agent = create_test_agent();
+    agent.identifiers.local_id = Some(42);
+    agent.steps.push(
+        Step::new_prompt(
+            IdFields::new(),
+            "Summarize the input".to_string(),
+            Some("Summarize".to_string()),
+            Some("deepseek-ai/DeepSeek-V3".to_string()),
+        )
+        .with_llm_provider("premium"),
+    );
+    agent.steps.push(
+        Step::new_webscrape(IdFields::new(), "https://example.com".to_string(), None)
+            .with_error_handler(agent.steps[1].identifiers.global_uuid.clone()),
+    );
+    agent.config = AgentConfig {
+        rate_limit: Some(RateLimitConfig {
+            requests_per_second: 1.0,
+            burst: 2,
+        }),
+        budget: None,
+        legacy_prompt_format: false,
+        failure_policy: FailurePolicy::default(),
+        run_retry: None,
+        default_model: None,
+        log_llm_io: None,
+        min_inter_step_delay_ms: 0,
+    };
 
-    // Create a simple step that adds 10 to the input value
-    let step = Step::new(
+    let bundle = agent.export_bundle();
+    assert_eq!(bundle["bundle_version"], json!(AGENT_BUNDLE_VERSION));
+    // Bundles don't carry any database identity
+    let exported = bundle.to_string();
+    assert!(!exported.contains(&agent.identifiers.global_uuid));
+    assert!(!exported.contains(&agent.steps[1].identifiers.global_uuid));
+
+    let imported = Agent::import_bundle(bundle).unwrap();
+    assert_eq!(imported.identifiers.local_id, None);
+    assert_ne!(
+        imported.identifiers.global_uuid,
+        agent.identifiers.global_uuid
+    );
+    assert_eq!(imported.description, agent.description);
+    assert_eq!(imported.config, agent.config);
+    assert_eq!(imported.steps.len(), 3);
+    for (original, copy) in agent.steps.iter().zip(&imported.steps) {
+        assert_ne!(
+            copy.identifiers.global_uuid,
+            original.identifiers.global_uuid
+        );
+        assert_eq!(copy.identifiers.local_id, None);
+        assert_eq!(copy.step_type.as_str(), original.step_type.as_str());
+        assert_eq!(copy.step_content, original.step_content);
+        assert_eq!(copy.description, original.description);
+        assert_eq!(copy.get_llm_model(), original.get_llm_model());
+        assert_eq!(copy.llm_provider, original.llm_provider);
+    }
+    // The error handler still points at the same step, under its new UUID
+    assert_eq!(
+        imported.steps[2].on_error.as_ref(),
+        Some(&imported.steps[1].identifiers.global_uuid)
+    );
+}
+
+#[test]
+fn test_agent_bundle_import_validation() {
+    let bundle = create_test_agent().export_bundle();
+
+    let mut newer = bundle.clone();
+    newer["bundle_version"] = json!(AGENT_BUNDLE_VERSION + 1);
+    assert!(Agent::import_bundle(newer).is_err());
+
+    let mut bad_type = bundle.clone();
+    bad_type["agent"]["steps"][0]["step_type"] = json!("shell");
+    assert!(Agent::import_bundle(bad_type).is_err());
+
+    // Unknown models are only rejected for the default provider
+    let mut bad_model = bundle.clone();
+    bad_model["agent"]["steps"] = json!([{
+        "step_type": "prompt",
+        "step_content": "Hi",
+        "llm_model": "not-a-model",
+    }]);
+    assert!(Agent::import_bundle(bad_model.clone()).is_err());
+    bad_model["agent"]["steps"][0]["llm_provider"] = json!("premium");
+    assert!(Agent::import_bundle(bad_model).is_ok());
+}
+
+#[test]
+fn test_clone_with_new_identity() {
+    let mut agent = create_test_agent();
+    agent.identifiers.local_id = Some(7);
+    agent.steps[0].identifiers.local_id = Some(11);
+    agent.steps.push(Step::new(
+        IdFields::with_values(Some(12), uuid::Uuid::new_v4().to_string()),
+        StepType::Python,
+        "result = source".to_string(),
+        None,
+    ));
+    let handler = Step::new(
         IdFields::new(),
         StepType::Python,
-        "source['value'] += 10\nresult = source".to_string(),
-        Some("Adds 10 to the input value".to_string()),
+        "result = {'recovered': True}".to_string(),
+        None,
     );
+    agent.steps[1].on_error = Some(handler.identifiers.global_uuid.clone());
+    agent.steps.push(handler);
+    agent.config.legacy_prompt_format = true;
+    agent.start().unwrap();
+    tokio_test::block_on(agent.run(json!({"value": 1}))).unwrap();
+
+    let copy = agent.clone_with_new_identity();
+
+    assert_ne!(copy.identifiers.global_uuid, agent.identifiers.global_uuid);
+    assert_eq!(copy.identifiers.local_id, None);
+    assert_eq!(copy.state(), AgentState::Inactive);
+    assert_eq!(copy.description, agent.description);
+    assert_eq!(copy.config, agent.config);
+    assert_eq!(copy.steps.len(), agent.steps.len());
+    for (copied, original) in copy.steps.iter().zip(&agent.steps) {
+        assert_ne!(
+            copied.identifiers.global_uuid,
+            original.identifiers.global_uuid
+        );
+        assert_eq!(copied.identifiers.local_id, None);
+        assert_eq!(copied.step_content, original.step_content);
+        assert_eq!(copied.description, original.description);
+        assert_eq!(copied.get_run_count(), 0);
+    }
+    // The handler is the copy's own third step, so the copy runs like the original
+    assert_eq!(
+        copy.steps[1].on_error.as_ref(),
+        Some(&copy.steps[2].identifiers.global_uuid)
+    );
+    copy.start().unwrap();
+    let session = tokio_test::block_on(copy.run(json!({"value": 1}))).unwrap();
+    assert_eq!(session.status, RunningStatus::Completed);
+
+    // The original's ids and counters are untouched
+    assert_eq!(agent.steps[0].identifiers.local_id, Some(11));
+    assert_eq!(agent.steps[0].get_run_count(), 1);
+}
+
+#[test]
+fn test_runs_reuse_the_prepared_runtime() {
+    let mut agent = create_test_agent();
+    agent.start().unwrap();
+    agent.warmup().unwrap();
+    assert_eq!(agent.runtime_cache.builds(), 1);
+
+    for value in [1, 2] {
+        let session = tokio_test::block_on(agent.run(json!({"value": value}))).unwrap();
+        assert_eq!(
+            session.last_successful_result,
+            Some(json!({"value": value + 10}))
+        );
+    }
+    assert_eq!(agent.runtime_cache.builds(), 1);
+
+    // Changing a step rebuilds it
+    agent.steps[0].step_content = "source['value'] += 20\nresult = source".to_string();
+    let session = tokio_test::block_on(agent.run(json!({"value": 1}))).unwrap();
+    assert_eq!(session.last_successful_result, Some(json!({"value": 21})));
+    assert_eq!(agent.runtime_cache.builds(), 2);
+}
+
+#[test]
+fn test_replace_steps_swaps_the_pipeline() {
+    let mut agent = create_test_agent();
+    agent.start().unwrap();
+    agent.warmup().unwrap();
+    let updated_before = agent.timestamps.updated;
+
+    let double = Step::new(
+        IdFields::new(),
+        StepType::Python,
+        "source['value'] *= 2\nresult = source".to_string(),
+        None,
+    );
+    agent.replace_steps(vec![double.clone()]).unwrap();
+    assert!(agent.timestamps.updated > updated_before);
+    let session = tokio_test::block_on(agent.run(json!({"value": 3}))).unwrap();
+    assert_eq!(session.last_successful_result, Some(json!({"value": 6})));
+
+    agent
+        .add_step(Step::new(
+            IdFields::new(),
+            StepType::Python,
+            "source['value'] += 1\nresult = source".to_string(),
+            None,
+        ))
+        .unwrap();
+    let session = tokio_test::block_on(agent.run(json!({"value": 3}))).unwrap();
+    assert_eq!(session.last_successful_result, Some(json!({"value": 7})));
 
-    let steps = vec![step];
+    let removed = agent.remove_step(&double.identifiers.global_uuid).unwrap();
+    assert_eq!(removed.step_content, double.step_content);
+    let session = tokio_test::block_on(agent.run(json!({"value": 3}))).unwrap();
+    assert_eq!(session.last_successful_result, Some(json!({"value": 4})));
+    assert!(agent.remove_step(&double.identifiers.global_uuid).is_err());
+
+    // Invalid replacements leave the current steps in place
+    let broken = Step::new(
+        IdFields::new(),
+        StepType::Python,
+        "result = (".to_string(),
+        None,
+    );
+    assert!(agent.replace_steps(vec![broken]).is_err());
+    let step = agent.steps[0].clone();
+    assert!(agent.replace_steps(vec![step.clone(), step]).is_err());
+    assert_eq!(agent.steps.len(), 1);
+}
+
+#[test]
+fn test_run_ephemeral_returns_the_result() {
+    // Previews don't need the agent started
+    let agent = create_test_agent();
+    assert_eq!(agent.state(), AgentState::Inactive);
+    let result = tokio_test::block_on(agent.run_ephemeral(json!({"value": 1}))).unwrap();
+    assert_eq!(result, json!({"value": 11}));
+
+    let failing = Agent::builder()
+        .python_step("raise ValueError('bad sample')")
+        .build();
+    assert!(tokio_test::block_on(failing.run_ephemeral(json!({}))).is_err());
+}
+
+#[test]
+fn test_run_ephemeral_saves_no_session() {
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let agent = create_test_agent();
+        agent.try_db_create(&pool).await.unwrap();
+        let agent = Agent::try_db_select_by_id(
+            &pool,
+            &IdFields::with_values(None, agent.identifiers.global_uuid.clone()),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let agent_id = agent.identifiers.local_id.unwrap();
+
+        let result = agent.run_ephemeral(json!({"value": 1})).await;
+        let sessions = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM runtime_sessions WHERE requested_by_agent_id = $1",
+        )
+        .bind(agent_id)
+        .fetch_one(&pool)
+        .await;
+        agent.try_db_delete(&pool).await.unwrap();
+
+        assert_eq!(result.unwrap(), json!({"value": 11}));
+        assert_eq!(sessions.unwrap(), 0);
+    });
+}
+
+#[test]
+fn test_malformed_agent_is_skipped_by_select_all_and_deletable_by_id() {
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let good = create_test_agent();
+        let bad = create_test_agent();
+        good.try_db_create(&pool).await.unwrap();
+        bad.try_db_create(&pool).await.unwrap();
+        let bad_id = IdFields::with_values(None, bad.identifiers.global_uuid.clone());
+
+        // A step whose input_mapping isn't a map no longer parses
+        sqlx::query("UPDATE steps SET input_mapping = '[1]'::jsonb WHERE global_uuid = $1")
+            .bind(uuid::Uuid::parse_str(&bad.steps[0].identifiers.global_uuid).unwrap())
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let all = Agent::try_db_select_all(&pool).await.unwrap();
+        assert!(all
+            .iter()
+            .any(|agent| agent.identifiers.global_uuid == good.identifiers.global_uuid));
+        assert!(!all
+            .iter()
+            .any(|agent| agent.identifiers.global_uuid == bad.identifiers.global_uuid));
+        // Loading it on its own still reports the bad step
+        assert!(Agent::try_db_select_by_id(&pool, &bad_id).await.is_err());
+
+        assert!(Agent::try_db_delete_by_id(&pool, &bad_id).await.unwrap());
+        assert!(!Agent::try_db_delete_by_id(&pool, &bad_id).await.unwrap());
+        let steps_left =
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM steps WHERE global_uuid = $1")
+                .bind(uuid::Uuid::parse_str(&bad.steps[0].identifiers.global_uuid).unwrap())
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(steps_left, 0);
+
+        let good_id = IdFields::with_values(None, good.identifiers.global_uuid.clone());
+        Agent::try_db_delete_by_id(&pool, &good_id).await.unwrap();
+    });
+}
+
+fn create_test_agent() -> Agent {
+    // A single step that adds 10 to the input value
+    Agent::builder()
+        .description("Test Agent")
+        .step(Step::new(
+            IdFields::new(),
+            StepType::Python,
+            "source['value'] += 10\nresult = source".to_string(),
+            Some("Adds 10 to the input value".to_string()),
+        ))
+        .build()
+}
+
+#[test]
+fn test_agent_builder_runs_three_steps() {
+    let _env = LLM_ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let llm = spawn_http_handler(|_| completion_response("Eleven, up from one"));
+    std::env::set_var("LLM_API_ENDPOINT", &llm);
+    std::env::set_var("LLM_API_KEY", "test-key");
+    std::env::remove_var("LLM_PROVIDERS");
+
+    let agent = Agent::builder()
+        .description("Built agent")
+        .python_step("source['value'] += 10\nresult = source")
+        .prompt_step("Describe {{value}}")
+        .python_step("result = {'summary': source['data']}")
+        .state(AgentState::Stable)
+        .build();
+
+    assert_eq!(agent.description, "Built agent");
+    assert_eq!(agent.state(), AgentState::Stable);
+    assert_eq!(agent.identifiers.local_id, None);
+    let step_types: Vec<_> = agent.steps.iter().map(|s| s.step_type.as_str()).collect();
+    assert_eq!(step_types, vec!["python", "prompt", "python"]);
+    assert_ne!(
+        agent.steps[0].identifiers.global_uuid,
+        agent.steps[2].identifiers.global_uuid
+    );
+
+    let session = tokio_test::block_on(agent.run(json!({"value": 1}))).unwrap();
+    assert_eq!(
+        session.last_successful_result.unwrap(),
+        json!({"summary": "Eleven, up from one"})
+    );
+
+    // Agents start Inactive unless told otherwise
+    let scraper = Agent::builder()
+        .webscrape_step("https://example.com")
+        .build();
+    assert_eq!(scraper.state(), AgentState::Inactive);
+    assert_eq!(scraper.steps[0].step_type.as_str(), "webscrape");
+    assert_eq!(scraper.steps[0].step_content, "https://example.com");
+}
+
+#[test]
+fn test_prompt_steps_inherit_agent_default_model() {
+    let _env = LLM_ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    // Answers with the model each request asked for
+    let llm = spawn_http_handler(|request| {
+        let body = request.split("\r\n\r\n").nth(1).unwrap_or_default();
+        let body: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+        completion_response(body["model"].as_str().unwrap_or("none"))
+    });
+    std::env::set_var("LLM_API_ENDPOINT", &llm);
+    std::env::set_var("LLM_API_KEY", "test-key");
+    std::env::remove_var("LLM_PROVIDERS");
+
+    let agent = Agent::builder()
+        .prompt_step("Which model are you?")
+        .step(Step::new_prompt(
+            IdFields::new(),
+            "And you?".to_string(),
+            None,
+            Some(JsonModeLLMs::Qwen25_72b.to_string()),
+        ))
+        .config(AgentConfig {
+            default_model: Some(JsonModeLLMs::DeepseekV3_671b.to_string()),
+            ..AgentConfig::default()
+        })
+        .state(AgentState::Stable)
+        .build();
+
+    let session = tokio_test::block_on(agent.run(json!({}))).unwrap();
+    assert_eq!(
+        session.step_results,
+        vec![
+            Some(json!(JsonModeLLMs::DeepseekV3_671b.to_string())),
+            Some(json!(JsonModeLLMs::Qwen25_72b.to_string())),
+        ]
+    );
+    // The agent's own steps still follow whatever default it has
+    assert_eq!(agent.steps[0].step_type.get_llm_model(), None);
+
+    // Without an agent default, the global default is used
+    let plain = Agent::builder()
+        .prompt_step("Which model are you?")
+        .state(AgentState::Stable)
+        .build();
+    let session = tokio_test::block_on(plain.run(json!({}))).unwrap();
+    assert_eq!(
+        session.last_successful_result,
+        Some(json!(JsonModeLLMs::MetaLlama33_70b.to_string()))
+    );
+}
+
+#[test]
+fn test_creating_an_agent_twice_upserts_its_steps() {
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let mut agent = Agent::builder()
+            .description("Saved twice")
+            .step(Step::new_prompt(
+                IdFields::new(),
+                "Summarize {{data}}".to_string(),
+                None,
+                Some(JsonModeLLMs::Qwen25_72b.to_string()),
+            ))
+            .build();
+        let first = agent.try_db_create(&pool).await;
+
+        // Saving again with an edited step updates it rather than adding another
+        agent.steps[0].step_content = "Summarize {{data}} briefly".to_string();
+        agent.steps[0].step_type =
+            StepType::Prompt(Some(JsonModeLLMs::DeepseekV3_671b.to_string()));
+        let second = agent.try_db_create(&pool).await;
+        let saved = Agent::try_db_select_by_id(
+            &pool,
+            &IdFields::with_values(None, agent.identifiers.global_uuid.clone()),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        agent.identifiers.local_id = saved.identifiers.local_id;
+        agent.try_db_delete(&pool).await.unwrap();
+
+        first.unwrap();
+        second.unwrap();
+        assert_eq!(saved.steps.len(), 1);
+        assert_eq!(
+            saved.steps[0].identifiers.global_uuid,
+            agent.steps[0].identifiers.global_uuid
+        );
+        assert_eq!(saved.steps[0].step_content, "Summarize {{data}} briefly");
+        assert_eq!(
+            saved.steps[0].step_type.get_llm_model(),
+            Some(JsonModeLLMs::DeepseekV3_671b.to_string())
+        );
+    });
+}
+
+#[test]
+fn test_agent_prompt_steps_keep_their_model() {
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let mut agent = Agent::builder()
+            .step(Step::new_prompt(
+                IdFields::new(),
+                "Classify {{data}}".to_string(),
+                None,
+                Some(JsonModeLLMs::Qwen25_72b.to_string()),
+            ))
+            .prompt_step("Explain {{data}}")
+            .build();
+        agent.try_db_create(&pool).await.unwrap();
+        let saved = Agent::try_db_select_by_id(
+            &pool,
+            &IdFields::with_values(None, agent.identifiers.global_uuid.clone()),
+        )
+        .await;
+        if let Ok(Some(saved)) = &saved {
+            agent.identifiers.local_id = saved.identifiers.local_id;
+        }
+        agent.try_db_delete(&pool).await.unwrap();
+
+        let mut saved = saved.unwrap().unwrap();
+        saved.steps.sort_by_key(|step| step.identifiers.local_id);
+        let models: Vec<_> = saved
+            .steps
+            .iter()
+            .map(|step| step.step_type.get_llm_model())
+            .collect();
+        assert_eq!(
+            models,
+            vec![Some(JsonModeLLMs::Qwen25_72b.to_string()), None]
+        );
+    });
+}
+
+#[test]
+fn test_rows_without_aggregated_steps_load_with_no_steps() {
+    use sqlx::FromRow;
+
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+
+        // An agent row from a query that forgot the steps aggregation
+        let row = sqlx::query(
+            "SELECT 1 AS id, gen_random_uuid() AS global_uuid, now() AS created_at, \
+             now() AS updated_at, 'No steps'::text AS description, \
+             'stable'::agent_state AS agent_state",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let agent = Agent::from_row(&row).unwrap();
+        assert!(agent.steps.is_empty());
+        assert_eq!(agent.description, "No steps");
+
+        // A session row whose aggregation came back null
+        let row = sqlx::query(
+            "SELECT 1::int8 AS id, gen_random_uuid() AS global_uuid, \
+             'completed'::running_status AS rts_status, '{}'::json AS initial_data, \
+             0 AS latest_step_idx, NULL::json AS latest_result, now() AS created_at, \
+             now() AS updated_at, NULL::int4 AS requested_by_agent_id, NULL::json AS steps",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let session = RuntimeSession::from_row(&row).unwrap();
+        assert!(session.steps.is_empty());
+        assert_eq!(session.status, RunningStatus::Completed);
+    });
+}
+
+#[test]
+fn test_delete_cascade_removes_sessions_and_signals() {
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let mut agent = Agent::builder()
+            .description("Deleted with everything it made")
+            .python_step("result = source")
+            .state(AgentState::Stable)
+            .build();
+        agent.identifiers.local_id = Some(agent.try_db_upsert(&pool).await.unwrap());
+
+        // A processed signal, with the session it ran linked to it
+        let mut signal = Signal::new(
+            IdFields::new(),
+            uuid::Uuid::new_v4().to_string(),
+            Some(agent.clone()),
+            SignalType::Run,
+            Some(json!({"value": 1})),
+        );
+        signal.try_db_create(&pool).await.unwrap();
+        signal.process(&pool).await.unwrap();
+        let session_uuid = signal
+            .linked_rts
+            .as_ref()
+            .unwrap()
+            .identifiers
+            .global_uuid
+            .clone();
+
+        let expected = CascadeCounts {
+            signals: 1,
+            runtime_sessions: 1,
+            steps: 1,
+            agents: 1,
+        };
+
+        // A dry run counts the rows but leaves them
+        let counts = agent
+            .try_db_delete_cascade(&pool, CascadeMode::Delete, true)
+            .await
+            .unwrap();
+        assert_eq!(counts, expected);
+        for (table, uuid) in [
+            ("agents", &agent.identifiers.global_uuid),
+            ("signals", &signal.identifiers.global_uuid),
+            ("runtime_sessions", &session_uuid),
+        ] {
+            assert!(check_exists_by_uuid(&pool, table, uuid).await.unwrap());
+        }
+
+        let counts = agent
+            .try_db_delete_cascade(&pool, CascadeMode::Delete, false)
+            .await
+            .unwrap();
+        assert_eq!(counts, expected);
+        for (table, uuid) in [
+            ("agents", &agent.identifiers.global_uuid),
+            ("steps", &agent.steps[0].identifiers.global_uuid),
+            ("signals", &signal.identifiers.global_uuid),
+            ("runtime_sessions", &session_uuid),
+        ] {
+            assert!(!check_exists_by_uuid(&pool, table, uuid).await.unwrap());
+        }
 
-    Agent::new(id_fields, timestamps, description, steps)
+        // Nothing is left to delete
+        let counts = agent
+            .try_db_delete_cascade(&pool, CascadeMode::Delete, false)
+            .await
+            .unwrap();
+        assert_eq!(counts, CascadeCounts::default());
+    });
 }
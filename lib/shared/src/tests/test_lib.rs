@@ -1,5 +1,13 @@
-use crate::{IdFields, TimestampFields};
-use std::time::Duration;
+use super::test_steps::{completion_response, sent_prompt, spawn_http_handler, LLM_ENV_LOCK};
+use crate::{
+    call_llm, call_llm_typed, call_llm_typed_strict, check_exists_by_uuid, duration_from_json,
+    duration_to_json, extract_json_from_text, models::steps::StepErrorKind,
+    session_steps_json_agg_sql, steps_json_agg_sql, IdFields, LlmProviderRegistry, TimestampFields,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 #[test]
 fn test_id_fields_creation() {
@@ -58,3 +66,338 @@ fn test_timestamp_update() {
     // updated should be newer
     assert!(ts.updated > ts.created);
 }
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Triage {
+    priority: u8,
+    summary: String,
+}
+
+fn triage_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "priority": {"type": "integer", "minimum": 1, "maximum": 5},
+            "summary": {"type": "string"}
+        },
+        "required": ["priority", "summary"]
+    })
+}
+
+/// Answers with `responses` in turn (repeating the last one), recording each request body
+fn spawn_llm_sequence(responses: Vec<&'static str>) -> (String, Arc<Mutex<Vec<Value>>>) {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let seen = requests.clone();
+    let url = spawn_http_handler(move |request| {
+        let body = request.split("\r\n\r\n").nth(1).unwrap_or_default();
+        let mut seen = seen.lock().unwrap();
+        seen.push(serde_json::from_str(body).unwrap_or_default());
+        let idx = (seen.len() - 1).min(responses.len() - 1);
+        completion_response(responses[idx])
+    });
+    (url, requests)
+}
+
+#[test]
+fn test_call_llm_typed_decodes_valid_response() {
+    let _env = LLM_ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let (url, requests) = spawn_llm_sequence(vec![r#"{"priority": 2, "summary": "Refill"}"#]);
+    std::env::set_var("LLM_API_ENDPOINT", &url);
+    std::env::set_var("LLM_API_KEY", "test-key");
+    std::env::remove_var("LLM_PROVIDERS");
+
+    let triage: Triage = tokio_test::block_on(call_llm_typed(
+        "Triage this message",
+        json!({"message": "Need a refill"}),
+        None,
+        &triage_schema(),
+    ))
+    .unwrap();
+
+    assert_eq!(
+        triage,
+        Triage {
+            priority: 2,
+            summary: "Refill".to_string()
+        }
+    );
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0]["response_format"]["type"], "json_object");
+    assert_eq!(requests[0]["response_format"]["schema"], triage_schema());
+}
+
+#[test]
+fn test_malformed_llm_endpoint_fails_without_retrying() {
+    let _env = LLM_ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    std::env::set_var("LLM_API_KEY", "test-key");
+    std::env::remove_var("LLM_PROVIDERS");
+
+    for endpoint in ["api.example.com/v1/chat/completions", "localhost:8080/v1"] {
+        std::env::set_var("LLM_API_ENDPOINT", endpoint);
+        let started = Instant::now();
+        let err = tokio_test::block_on(call_llm("Hello", json!({}), None)).unwrap_err();
+        // The first retry alone would have waited 500ms
+        assert!(
+            started.elapsed() < Duration::from_millis(400),
+            "{}",
+            endpoint
+        );
+        assert_eq!(StepErrorKind::of(&err), Some(StepErrorKind::Config));
+        assert!(err.to_string().contains("Invalid LLM endpoint"), "{}", err);
+
+        // Which startup validation reports with the provider's name
+        let err = LlmProviderRegistry::from_env()
+            .unwrap()
+            .validate()
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("'default'"), "{:#}", err);
+    }
+
+    std::env::set_var(
+        "LLM_API_ENDPOINT",
+        "https://api.example.com/v1/chat/completions",
+    );
+    assert!(LlmProviderRegistry::from_env().unwrap().validate().is_ok());
+}
+
+#[test]
+fn test_llm_auth_error_fails_without_retrying() {
+    let _env = LLM_ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let requests = Arc::new(Mutex::new(0));
+    let seen = requests.clone();
+    let url = spawn_http_handler(move |_| {
+        *seen.lock().unwrap() += 1;
+        "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\n\r\n".to_string()
+    });
+    std::env::set_var("LLM_API_ENDPOINT", &url);
+    std::env::set_var("LLM_API_KEY", "revoked-key");
+    std::env::remove_var("LLM_PROVIDERS");
+
+    let err = tokio_test::block_on(call_llm("Hello", json!({}), None)).unwrap_err();
+
+    assert_eq!(*requests.lock().unwrap(), 1);
+    assert_eq!(StepErrorKind::of(&err), Some(StepErrorKind::Config));
+}
+
+#[test]
+fn test_call_llm_typed_reprompts_with_validation_errors() {
+    let _env = LLM_ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let (url, requests) = spawn_llm_sequence(vec![
+        r#"{"priority": 9}"#,
+        r#"{"priority": 1, "summary": "Chest pain"}"#,
+    ]);
+    std::env::set_var("LLM_API_ENDPOINT", &url);
+    std::env::set_var("LLM_API_KEY", "test-key");
+    std::env::remove_var("LLM_PROVIDERS");
+
+    let triage: Triage = tokio_test::block_on(call_llm_typed(
+        "Triage this message",
+        json!({"message": "Chest pain"}),
+        None,
+        &triage_schema(),
+    ))
+    .unwrap();
+    assert_eq!(triage.priority, 1);
+
+    // The reprompt lists what was wrong with the first response
+    {
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        let reprompt = sent_prompt(&requests[1]);
+        assert!(reprompt.starts_with("Triage this message"), "{}", reprompt);
+        assert!(reprompt.contains("/priority"), "{}", reprompt);
+        assert!(reprompt.contains("summary"), "{}", reprompt);
+    }
+
+    // Only one reprompt is made
+    let (url, requests) = spawn_llm_sequence(vec!["not json"]);
+    std::env::set_var("LLM_API_ENDPOINT", &url);
+    let err = tokio_test::block_on(call_llm_typed::<Triage>(
+        "Triage this message",
+        json!({}),
+        None,
+        &triage_schema(),
+    ))
+    .unwrap_err();
+    assert_eq!(StepErrorKind::of(&err), Some(StepErrorKind::Validation));
+    assert!(err.to_string().contains("not valid JSON"), "{}", err);
+    assert_eq!(requests.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn test_extract_json_from_text() {
+    // Bare JSON, fenced JSON and JSON after prose
+    assert_eq!(
+        extract_json_from_text(r#" {"priority": 2} "#),
+        Some(json!({"priority": 2}))
+    );
+    assert_eq!(
+        extract_json_from_text("Here you go:\n```json\n{\"priority\": 2}\n```\nAnything else?"),
+        Some(json!({"priority": 2}))
+    );
+    assert_eq!(
+        extract_json_from_text(
+            r#"Sure! The triage is {"summary": "Uses {braces} and \"quotes\""} as asked."#
+        ),
+        Some(json!({"summary": "Uses {braces} and \"quotes\""}))
+    );
+    assert_eq!(
+        extract_json_from_text("The codes are [1, [2, 3]], in order."),
+        Some(json!([1, [2, 3]]))
+    );
+    // A stray bracket before the JSON is skipped
+    assert_eq!(
+        extract_json_from_text(r#"Result [see below]: {"ok": true}"#),
+        Some(json!({"ok": true}))
+    );
+
+    assert_eq!(
+        extract_json_from_text("I couldn't triage this message."),
+        None
+    );
+    assert_eq!(extract_json_from_text("Unbalanced {\"priority\": 2"), None);
+}
+
+#[test]
+fn test_call_llm_typed_accepts_wrapped_json_unless_strict() {
+    let _env = LLM_ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let wrapped = "Here is the triage:\n```json\n{\"priority\": 3, \"summary\": \"Rash\"}\n```";
+    let (url, _) = spawn_llm_sequence(vec![wrapped]);
+    std::env::set_var("LLM_API_ENDPOINT", &url);
+    std::env::set_var("LLM_API_KEY", "test-key");
+    std::env::remove_var("LLM_PROVIDERS");
+
+    let triage: Triage = tokio_test::block_on(call_llm_typed(
+        "Triage this message",
+        json!({}),
+        None,
+        &triage_schema(),
+    ))
+    .unwrap();
+    assert_eq!(triage.summary, "Rash");
+
+    let (url, requests) = spawn_llm_sequence(vec![wrapped, wrapped]);
+    std::env::set_var("LLM_API_ENDPOINT", &url);
+    let err = tokio_test::block_on(call_llm_typed_strict::<Triage>(
+        "Triage this message",
+        json!({}),
+        None,
+        &triage_schema(),
+    ))
+    .unwrap_err();
+    assert!(err.to_string().contains("not valid JSON"), "{}", err);
+    assert_eq!(requests.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn test_db_create_records_one_timing_sample() {
+    use crate::db_metrics::{DB_DURATION_METRIC, DB_ERRORS_METRIC};
+    use crate::models::steps::StepType;
+    use crate::{DatabaseItem, Step};
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    use metrics_util::MetricKind;
+    use sqlx::postgres::PgPoolOptions;
+
+    // Nothing listens on port 1, so the create fails fast and counts as an error
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let result = metrics::with_local_recorder(&recorder, || {
+        tokio_test::block_on(async {
+            let pool = PgPoolOptions::new()
+                .acquire_timeout(Duration::from_millis(100))
+                .connect_lazy("postgresql://postgres@127.0.0.1:1/postgres")
+                .unwrap();
+            let step = Step::new(
+                IdFields::new(),
+                StepType::Python,
+                "def main(data): return data".to_string(),
+                None,
+            );
+            step.try_db_create(&pool).await
+        })
+    });
+    assert!(result.is_err());
+
+    let metrics: Vec<_> = snapshotter
+        .snapshot()
+        .into_vec()
+        .into_iter()
+        .map(|(key, _, _, value)| (key, value))
+        .collect();
+    assert_eq!(metrics.len(), 2, "{:?}", metrics);
+    for (key, value) in metrics {
+        let labels: Vec<_> = key
+            .key()
+            .labels()
+            .map(|label| (label.key().to_string(), label.value().to_string()))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                ("model".to_string(), "step".to_string()),
+                ("operation".to_string(), "create".to_string()),
+            ]
+        );
+        match (key.kind(), key.key().name(), value) {
+            (MetricKind::Histogram, DB_DURATION_METRIC, DebugValue::Histogram(samples)) => {
+                assert_eq!(samples.len(), 1)
+            }
+            (MetricKind::Counter, DB_ERRORS_METRIC, DebugValue::Counter(errors)) => {
+                assert_eq!(errors, 1)
+            }
+            other => panic!("Unexpected metric {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_durations_round_trip_through_json() {
+    for duration in [
+        Duration::ZERO,
+        Duration::from_millis(250),
+        Duration::new(86_401, 500_000_000),
+    ] {
+        let json = duration_to_json(duration);
+        assert!(json.is_number(), "{}", json);
+        assert_eq!(duration_from_json(&json).unwrap(), duration);
+    }
+    assert_eq!(duration_to_json(Duration::from_millis(1500)), json!(1.5));
+
+    // Nanoseconds survive to within f64 precision
+    let precise = Duration::new(12, 345_678_901);
+    let diff = duration_from_json(&duration_to_json(precise))
+        .unwrap()
+        .abs_diff(precise);
+    assert!(diff < Duration::from_micros(1), "{:?}", diff);
+
+    assert!(duration_from_json(&json!(-1.0)).is_err());
+    assert!(duration_from_json(&json!("1.5")).is_err());
+    assert!(duration_from_json(&Value::Null).is_err());
+}
+
+#[test]
+fn test_sql_builders_reject_unknown_identifiers() {
+    let bogus = "x; DROP TABLE agents";
+
+    assert!(steps_json_agg_sql("a", "agent_id").is_ok());
+    assert!(steps_json_agg_sql(bogus, "agent_id").is_err());
+    assert!(steps_json_agg_sql("a", bogus).is_err());
+    assert!(session_steps_json_agg_sql("rs").is_ok());
+    assert!(session_steps_json_agg_sql(bogus).is_err());
+
+    // Refused before anything is sent (nothing listens on port 1)
+    let err = tokio_test::block_on(async {
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(100))
+            .connect_lazy("postgresql://postgres@127.0.0.1:1/postgres")
+            .unwrap();
+        check_exists_by_uuid(&pool, bogus, &uuid::Uuid::new_v4().to_string()).await
+    })
+    .unwrap_err();
+    assert!(
+        err.to_string().contains("Invalid SQL identifier"),
+        "{}",
+        err
+    );
+}
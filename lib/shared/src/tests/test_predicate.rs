@@ -0,0 +1,81 @@
+use crate::eval_predicate;
+use serde_json::{json, Value};
+
+fn sample() -> Value {
+    json!({
+        "status": "done",
+        "score": 0.85,
+        "count": 3,
+        "flags": {"ready": true, "archived": false},
+        "items": [{"name": "first"}, {"name": "second"}],
+    })
+}
+
+#[test]
+fn test_predicate_comparisons() {
+    let data = sample();
+    assert!(eval_predicate("$.status == 'done'", &data).unwrap());
+    assert!(eval_predicate("$.status != \"pending\"", &data).unwrap());
+    assert!(eval_predicate("$.count == 3.0", &data).unwrap());
+    assert!(eval_predicate("$.count < 4", &data).unwrap());
+    assert!(eval_predicate("$.count <= 3", &data).unwrap());
+    assert!(eval_predicate("$.score > 0.8", &data).unwrap());
+    assert!(eval_predicate("$.score >= -1", &data).unwrap());
+    assert!(!eval_predicate("$.score > 0.9", &data).unwrap());
+    assert!(eval_predicate("$.items[1].name == 'second'", &data).unwrap());
+    assert!(eval_predicate("$.status > 'abc'", &data).unwrap());
+    // Bare keys are paths too
+    assert!(eval_predicate("count == 3", &data).unwrap());
+}
+
+#[test]
+fn test_predicate_exists_and_truthiness() {
+    let data = sample();
+    assert!(eval_predicate("exists($.flags.archived)", &data).unwrap());
+    assert!(!eval_predicate("exists($['flags']['deleted'])", &data).unwrap());
+    assert!(eval_predicate("$.flags.ready", &data).unwrap());
+    assert!(!eval_predicate("$.flags.archived", &data).unwrap());
+    assert!(eval_predicate("true", &data).unwrap());
+}
+
+#[test]
+fn test_predicate_boolean_operators() {
+    let data = sample();
+    assert!(eval_predicate("$.count > 1 and $.status == 'done'", &data).unwrap());
+    assert!(!eval_predicate("$.count > 5 and $.status == 'done'", &data).unwrap());
+    assert!(eval_predicate("$.count > 5 or $.status == 'done'", &data).unwrap());
+    assert!(eval_predicate("not $.flags.archived", &data).unwrap());
+    assert!(!eval_predicate("not not $.flags.archived", &data).unwrap());
+    // `and` binds tighter than `or`; parentheses override it
+    assert!(eval_predicate("true or false and false", &data).unwrap());
+    assert!(!eval_predicate("(true or false) and false", &data).unwrap());
+    assert!(eval_predicate(
+        "exists($.items[0]) and ($.score >= 0.8 or $.status == 'pending')",
+        &data
+    )
+    .unwrap());
+}
+
+#[test]
+fn test_predicate_missing_path_is_null() {
+    let data = sample();
+    assert!(!eval_predicate("$.missing", &data).unwrap());
+    assert!(eval_predicate("$.missing == null", &data).unwrap());
+    assert!(!eval_predicate("$.missing == 'done'", &data).unwrap());
+    assert!(!eval_predicate("$.items[5].name == 'first'", &data).unwrap());
+}
+
+#[test]
+fn test_predicate_errors() {
+    let data = sample();
+
+    let mismatch = eval_predicate("$.status > 3", &data).unwrap_err();
+    assert!(mismatch.to_string().contains("Type mismatch"));
+    assert!(eval_predicate("$.missing < 1", &data).is_err());
+
+    assert!(eval_predicate("$.count ==", &data).is_err());
+    assert!(eval_predicate("($.count > 1", &data).is_err());
+    assert!(eval_predicate("$.status == 'done", &data).is_err());
+    assert!(eval_predicate("exists('x')", &data).is_err());
+    assert!(eval_predicate("$.count = 3", &data).is_err());
+}
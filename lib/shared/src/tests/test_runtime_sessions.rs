@@ -1,9 +1,19 @@
+use super::test_pool;
+use super::test_steps::{completion_response, spawn_http_handler, LLM_ENV_LOCK};
 use crate::{
-    models::steps::StepType,
-    models::{RuntimeSession, Step},
-    IdFields,
+    models::agents::AgentState,
+    models::runtime_sessions::{
+        diff_against, BudgetLimit, FailurePolicy, ResultDiff, RunBudget, RuntimeEvent,
+    },
+    models::steps::{StepErrorKind, StepType, STEP_OUTPUT_ERROR_KIND_KEY, STEP_OUTPUT_STATUS_KEY},
+    models::{Agent, RuntimeSession, Step},
+    DatabaseItem, IdFields, PythonRuntime, RunningStatus, TimestampFields,
 };
-use serde_json::json;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 
 fn create_test_session() -> RuntimeSession {
     let source_data = json!({"value": 5});
@@ -52,3 +62,1061 @@ fn test_session_with_steps() {
     // the session through its public API in a real test
     // For now this is just a placeholder showing how to create a session with steps
 }
+
+#[test]
+fn test_failing_step_marks_session_failed() {
+    let step = Step::new(
+        IdFields::new(),
+        StepType::Python,
+        "raise ValueError('bad input')".to_string(),
+        None,
+    );
+    let mut runtime = PythonRuntime::new("failing_session").unwrap();
+    runtime.add_step(&step).unwrap();
+
+    let mut session = RuntimeSession::new(json!({"value": 5}), vec![step], None);
+    let result = tokio_test::block_on(session.start_with_runtime(&runtime));
+
+    assert!(result.is_err());
+    assert_eq!(session.status, RunningStatus::Failed);
+    assert_eq!(session.error_kind, Some(StepErrorKind::UserCode));
+    let step_result = session.step_results[0].as_ref().unwrap();
+    assert_eq!(step_result[STEP_OUTPUT_ERROR_KIND_KEY], json!("user_code"));
+    assert_eq!(step_result[STEP_OUTPUT_STATUS_KEY], json!("error"));
+}
+
+#[test]
+fn test_seeded_sessions_repeat_random_steps() {
+    let step = Step::new(
+        IdFields::new(),
+        StepType::Python,
+        "import random\nresult = {'draws': [random.random() for _ in range(3)]}".to_string(),
+        None,
+    );
+    let mut runtime = PythonRuntime::new("seeded_session").unwrap();
+    runtime.add_step(&step).unwrap();
+    let run = |seed| {
+        let mut session = RuntimeSession::new(json!({}), vec![step.clone()], None).with_seed(seed);
+        tokio_test::block_on(session.start_with_runtime(&runtime)).unwrap()
+    };
+
+    let first = run(42);
+    assert_eq!(run(42), first);
+    assert_ne!(run(7), first);
+}
+
+#[test]
+fn test_inter_step_delay_counts_toward_total_time_only() {
+    let steps: Vec<Step> = (0..3)
+        .map(|_| {
+            Step::new(
+                IdFields::new(),
+                StepType::Python,
+                "result = source".to_string(),
+                None,
+            )
+        })
+        .collect();
+    let mut runtime = PythonRuntime::new("throttled_session").unwrap();
+    for step in &steps {
+        runtime.add_step(step).unwrap();
+    }
+
+    let delay = Duration::from_millis(50);
+    let mut session =
+        RuntimeSession::new(json!({"value": 1}), steps, None).with_min_inter_step_delay(delay);
+    tokio_test::block_on(session.start_with_runtime(&runtime)).unwrap();
+
+    // Two pauses between three steps, neither charged to a step
+    assert_eq!(session.step_execution_times.len(), 3);
+    let steps_time: Duration = session.step_execution_times.iter().sum();
+    assert!(session
+        .step_execution_times
+        .iter()
+        .all(|time| *time < delay));
+    assert!(session.total_execution_time >= steps_time + 2 * delay);
+}
+
+#[test]
+fn test_cancelled_session_is_not_failed() {
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+
+    // Cancelling before a step runs isn't a failure, so no error kind is recorded
+    let step = Step::new_webscrape(IdFields::new(), "http://127.0.0.1:9".to_string(), None);
+    let mut session = RuntimeSession::new(json!({"value": 5}), vec![step], None);
+    let result = tokio_test::block_on(session.unified_start_cancellable(None, &cancel));
+
+    assert!(result.is_err());
+    assert_eq!(session.status, RunningStatus::Cancelled);
+    assert_eq!(session.error_kind, None);
+}
+
+#[test]
+fn test_running_status_strings_round_trip() {
+    for status in [
+        RunningStatus::Waiting,
+        RunningStatus::Running,
+        RunningStatus::Completed,
+        RunningStatus::Cancelled,
+        RunningStatus::Failed,
+        RunningStatus::BudgetExceeded,
+    ] {
+        assert_eq!(status.as_str().parse::<RunningStatus>().unwrap(), status);
+    }
+    assert_eq!(RunningStatus::Failed.as_str(), "failed");
+    assert!("errored".parse::<RunningStatus>().is_err());
+}
+
+#[test]
+fn test_duration_budget_stops_session() {
+    let steps: Vec<Step> = (0..2)
+        .map(|_| {
+            Step::new(
+                IdFields::new(),
+                StepType::Python,
+                "import time\ntime.sleep(0.3)\nresult = source".to_string(),
+                None,
+            )
+        })
+        .collect();
+    let mut runtime = PythonRuntime::new("duration_budget").unwrap();
+    for step in &steps {
+        runtime.add_step(step).unwrap();
+    }
+
+    let mut session =
+        RuntimeSession::new(json!({"value": 5}), steps, None).with_budget(RunBudget {
+            max_total_duration_ms: Some(100),
+            ..RunBudget::default()
+        });
+    let err = tokio_test::block_on(session.start_with_runtime(&runtime)).unwrap_err();
+
+    // The first step overran the budget, so the second one never started
+    assert!(
+        err.to_string().contains("duration budget exceeded"),
+        "{}",
+        err
+    );
+    assert_eq!(session.status, RunningStatus::BudgetExceeded);
+    assert_eq!(session.budget_exceeded, Some(BudgetLimit::Duration));
+    assert_eq!(session.error_kind, None);
+    assert_eq!(session.last_step_idx, Some(0));
+    assert!(session.step_results[0].is_some());
+    assert!(session.step_results[1].is_none());
+}
+
+#[test]
+fn test_llm_call_budget_stops_before_calling() {
+    // No provider is configured, so reaching the LLM would fail the session instead
+    let step = Step::new_prompt(IdFields::new(), "Summarize".to_string(), None, None);
+    let mut session =
+        RuntimeSession::new(json!({"value": 5}), vec![step], None).with_budget(RunBudget {
+            max_llm_calls: Some(0),
+            ..RunBudget::default()
+        });
+    let result = tokio_test::block_on(session.start());
+
+    assert!(result.is_err());
+    assert_eq!(session.status, RunningStatus::BudgetExceeded);
+    assert_eq!(session.budget_exceeded, Some(BudgetLimit::LlmCalls));
+}
+
+#[test]
+fn test_replay_matches_stored_result() {
+    let python = Step::new(
+        IdFields::new(),
+        StepType::Python,
+        "source['value'] *= 2\nresult = source".to_string(),
+        None,
+    );
+    let patch = Step::new(
+        IdFields::new(),
+        StepType::JsonPatch,
+        r#"[{"op": "add", "path": "/checked", "value": true}]"#.to_string(),
+        None,
+    );
+    let mut runtime = PythonRuntime::new("replay_original").unwrap();
+    runtime.add_step(&python).unwrap();
+
+    let mut session = RuntimeSession::new(json!({"value": 5}), vec![python.clone(), patch], None);
+    let stored = tokio_test::block_on(session.start_with_runtime(&runtime)).unwrap();
+    assert_eq!(stored, json!({"value": 10, "checked": true}));
+
+    // The agent's step has since been edited; the replay still runs the saved version
+    let mut edited = python.clone();
+    edited.step_content = "source['value'] *= 3\nresult = source".to_string();
+    let mut replay_runtime = PythonRuntime::new("replay_edited").unwrap();
+    replay_runtime.add_step(&edited).unwrap();
+
+    let replayed = tokio_test::block_on(session.replay(Some(&mut replay_runtime))).unwrap();
+    assert_eq!(replayed, stored);
+    assert!(diff_against(&replayed, &stored).is_empty());
+
+    // Replaying doesn't touch the stored session
+    assert_eq!(session.status, RunningStatus::Completed);
+    assert_eq!(session.last_successful_result, Some(stored));
+    assert_eq!(session.step_results.len(), 2);
+}
+
+#[test]
+fn test_diff_against_reports_changed_paths() {
+    let stored = json!({"value": 10, "tags": ["a", "b"], "meta": {"a/b": 1, "same": true}});
+    let replayed =
+        json!({"value": 11, "tags": ["a"], "meta": {"a/b": 2, "same": true}, "new": null});
+
+    assert_eq!(
+        diff_against(&replayed, &stored),
+        vec![
+            ResultDiff {
+                path: "/meta/a~1b".to_string(),
+                stored: Some(json!(1)),
+                replayed: Some(json!(2)),
+            },
+            ResultDiff {
+                path: "/new".to_string(),
+                stored: None,
+                replayed: Some(json!(null)),
+            },
+            ResultDiff {
+                path: "/tags/1".to_string(),
+                stored: Some(json!("b")),
+                replayed: None,
+            },
+            ResultDiff {
+                path: "/value".to_string(),
+                stored: Some(json!(10)),
+                replayed: Some(json!(11)),
+            },
+        ]
+    );
+    assert_eq!(
+        diff_against(&json!("done"), &stored)[0].path,
+        "",
+        "Differing types are reported at the top level"
+    );
+}
+
+fn run_with_failing_middle_step(policy: FailurePolicy) -> (RuntimeSession, anyhow::Result<Value>) {
+    let steps: Vec<Step> = [
+        "source['value'] += 1\nresult = source",
+        "raise ValueError('upstream unavailable')",
+        "result = {'input': source}",
+    ]
+    .into_iter()
+    .map(|code| Step::new(IdFields::new(), StepType::Python, code.to_string(), None))
+    .collect();
+    let mut runtime = PythonRuntime::new("failure_policy").unwrap();
+    for step in &steps {
+        runtime.add_step(step).unwrap();
+    }
+
+    let mut session =
+        RuntimeSession::new(json!({"value": 5}), steps, None).with_failure_policy(policy);
+    let result = tokio_test::block_on(session.start_with_runtime(&runtime));
+    (session, result)
+}
+
+#[test]
+fn test_failure_policies() {
+    let (session, result) = run_with_failing_middle_step(FailurePolicy::FailFast);
+    assert!(result.is_err());
+    assert_eq!(session.status, RunningStatus::Failed);
+    assert!(session.step_results[2].is_none());
+
+    // Skipping passes the last good value along
+    let (session, result) = run_with_failing_middle_step(FailurePolicy::SkipAndContinue);
+    assert_eq!(result.unwrap(), json!({"input": {"value": 6}}));
+    assert_eq!(session.status, RunningStatus::Completed);
+    assert_eq!(session.error_kind, None);
+    let failed = session.step_results[1].as_ref().unwrap();
+    assert_eq!(failed[STEP_OUTPUT_STATUS_KEY], "error");
+    assert_eq!(failed[STEP_OUTPUT_ERROR_KIND_KEY], "user_code");
+
+    let (session, result) = run_with_failing_middle_step(FailurePolicy::ContinueWithNull);
+    assert_eq!(result.unwrap(), json!({"input": null}));
+    assert_eq!(session.status, RunningStatus::Completed);
+    assert_eq!(
+        session.step_results[1].as_ref().unwrap()[STEP_OUTPUT_STATUS_KEY],
+        "error"
+    );
+}
+
+#[test]
+fn test_step_summaries_align_steps_with_times_and_statuses() {
+    let (session, _) = run_with_failing_middle_step(FailurePolicy::FailFast);
+    let summaries = session.step_summaries();
+    assert_eq!(summaries.len(), 3);
+    for (summary, step) in summaries.iter().zip(&session.steps) {
+        assert_eq!(summary.step_uuid, step.identifiers.global_uuid);
+        assert_eq!(summary.step_type, "python");
+    }
+    let statuses: Vec<_> = summaries.iter().map(|s| s.status.clone()).collect();
+    assert_eq!(
+        statuses,
+        vec![RunningStatus::Completed, RunningStatus::Failed, RunningStatus::Waiting]
+    );
+    assert_eq!(summaries[0].duration, Some(session.step_execution_times[0]));
+    assert_eq!(summaries[1].duration, Some(session.step_execution_times[1]));
+    assert_eq!(summaries[2].duration, None);
+    assert_eq!(summaries[0].output, Some(json!({"value": 6})));
+    assert_eq!(
+        summaries[1].output.as_ref().unwrap()[STEP_OUTPUT_ERROR_KIND_KEY],
+        "user_code"
+    );
+
+    // A skipped failure keeps its error output, and the run goes on
+    let (session, _) = run_with_failing_middle_step(FailurePolicy::SkipAndContinue);
+    let summaries = session.step_summaries();
+    let statuses: Vec<_> = summaries.iter().map(|s| s.status.clone()).collect();
+    assert_eq!(
+        statuses,
+        vec![RunningStatus::Completed, RunningStatus::Failed, RunningStatus::Completed]
+    );
+    assert_eq!(
+        summaries[1].output.as_ref().unwrap()[STEP_OUTPUT_STATUS_KEY],
+        "error"
+    );
+    assert!(summaries.iter().all(|summary| summary.duration.is_some()));
+
+    // The session's JSON carries the same timeline
+    let json = session.to_json();
+    assert_eq!(json["step_summaries"].as_array().unwrap().len(), 3);
+    assert_eq!(json["step_summaries"][1]["status"], "failed");
+    assert_eq!(
+        json["step_summaries"][2]["output"],
+        json!({"input": {"value": 6}})
+    );
+    assert_eq!(
+        json["step_summaries"][0]["duration"],
+        json["step_execution_times"][0]
+    );
+}
+
+#[test]
+fn test_cacheable_step_reuses_its_output() {
+    // Draws a new number each time it actually runs
+    let step = Step::new(
+        IdFields::new(),
+        StepType::Python,
+        "import random\nresult = {'value': source['value'], 'draw': random.random()}".to_string(),
+        None,
+    )
+    .with_caching();
+    let mut runtime = PythonRuntime::new("step_cache").unwrap();
+    runtime.add_step(&step).unwrap();
+    let run = |source: Value| {
+        let mut session = RuntimeSession::new(source, vec![step.clone()], None);
+        tokio_test::block_on(session.start_with_runtime(&runtime)).unwrap()
+    };
+
+    let first = run(json!({"value": 1}));
+    assert_eq!(run(json!({"value": 1})), first);
+    assert_eq!(step.get_run_count(), 1, "The second run should hit the cache");
+
+    // Another input runs the step again
+    assert_eq!(run(json!({"value": 2}))["value"], 2);
+    assert_eq!(step.get_run_count(), 2);
+
+    // So does an edited step, though its UUID is the same
+    let mut edited = step.clone();
+    edited.step_content = "result = {'edited': True}".to_string();
+    runtime.add_step(&edited).unwrap();
+    let mut session = RuntimeSession::new(json!({"value": 1}), vec![edited], None);
+    assert_eq!(
+        tokio_test::block_on(session.start_with_runtime(&runtime)).unwrap(),
+        json!({"edited": true})
+    );
+}
+
+#[test]
+fn test_cached_output_depends_on_wrap_output() {
+    let step = Step::new(
+        IdFields::new(),
+        StepType::Python,
+        "import random\nresult = random.random()".to_string(),
+        None,
+    )
+    .with_caching();
+    let wrapped = step.clone().with_wrapped_output();
+    let mut runtime = PythonRuntime::new("step_cache_wrap").unwrap();
+    runtime.add_step(&step).unwrap();
+    let run = |step: &Step| {
+        let mut session = RuntimeSession::new(json!({"value": 1}), vec![step.clone()], None);
+        tokio_test::block_on(session.start_with_runtime(&runtime)).unwrap()
+    };
+
+    let bare = run(&step);
+    assert!(bare.is_number(), "{}", bare);
+
+    // Same UUID and input, but wrapping changes the output, so it runs again
+    let wrapped = run(&wrapped);
+    assert!(wrapped["data"].is_number(), "{}", wrapped);
+    assert_eq!(step.get_run_count(), 2);
+}
+
+#[test]
+fn test_cached_output_depends_on_prompt_format_and_seed() {
+    let step = Step::new(
+        IdFields::new(),
+        StepType::Python,
+        "import random\nresult = {'draw': random.random()}".to_string(),
+        None,
+    )
+    .with_caching();
+    let mut runtime = PythonRuntime::new("step_cache_scope").unwrap();
+    runtime.add_step(&step).unwrap();
+    let run = |legacy_prompt_format: bool, seed: Option<u64>| {
+        let mut session = RuntimeSession::new(json!({"value": 1}), vec![step.clone()], None);
+        session.legacy_prompt_format = legacy_prompt_format;
+        session.seed = seed;
+        tokio_test::block_on(session.start_with_runtime(&runtime)).unwrap()
+    };
+
+    run(false, None);
+    run(false, None);
+    assert_eq!(step.get_run_count(), 1);
+
+    // Either setting can change what a step outputs, so each runs it again
+    run(true, None);
+    assert_eq!(step.get_run_count(), 2);
+    run(false, Some(7));
+    assert_eq!(step.get_run_count(), 3);
+    run(false, Some(7));
+    assert_eq!(step.get_run_count(), 3);
+}
+
+#[test]
+fn test_step_over_its_expected_duration_is_flagged() {
+    let slow = Step::new(
+        IdFields::new(),
+        StepType::Python,
+        "import time\ntime.sleep(0.05)\nresult = source".to_string(),
+        None,
+    )
+    .with_expected_duration(Duration::from_millis(10));
+    let fast = Step::new(IdFields::new(), StepType::Python, "result = source".to_string(), None)
+    .with_expected_duration(Duration::from_secs(10));
+    let mut runtime = PythonRuntime::new("slow_step").unwrap();
+    runtime.add_step(&slow).unwrap();
+    runtime.add_step(&fast).unwrap();
+
+    let mut session = RuntimeSession::new(json!({"value": 1}), vec![fast, slow], None);
+    let result = tokio_test::block_on(session.start_with_runtime(&runtime)).unwrap();
+
+    // Only flagged, the step still succeeds
+    assert_eq!(result, json!({"value": 1}));
+    assert_eq!(session.status, RunningStatus::Completed);
+    assert_eq!(
+        session.events,
+        vec![RuntimeEvent::SlowStep {
+            step_idx: 1,
+            actual: session.step_execution_times[1],
+            expected: Duration::from_millis(10),
+        }]
+    );
+}
+
+#[test]
+fn test_error_handler_supplies_a_fallback() {
+    let python = |code: &str| Step::new(IdFields::new(), StepType::Python, code.to_string(), None);
+    let handler = python("result = {'value': 0, 'recovered_from': source['error_kind']}");
+    let failing = python("raise ValueError('page unavailable')")
+        .with_error_handler(handler.identifiers.global_uuid.clone());
+    let next = python("result = {'value': source['value'] + 1}");
+    let mut runtime = PythonRuntime::new("error_handler").unwrap();
+    for step in [&failing, &handler, &next] {
+        runtime.add_step(step).unwrap();
+    }
+
+    // Fail-fast, but the handler catches the failure
+    let mut session = RuntimeSession::new(
+        json!({"value": 5}),
+        vec![failing, handler.clone(), next],
+        None,
+    );
+    let result = tokio_test::block_on(session.start_with_runtime(&runtime)).unwrap();
+    assert_eq!(result, json!({"value": 1}));
+    assert_eq!(session.status, RunningStatus::Completed);
+
+    // The handler only ran for the failure, not again in its place in the pipeline
+    assert_eq!(handler.get_run_count(), 1);
+    let summaries = session.step_summaries();
+    assert_eq!(summaries[0].status, RunningStatus::Failed);
+    assert_eq!(
+        session.step_results[1],
+        Some(json!({"value": 0, "recovered_from": "user_code"}))
+    );
+
+    // A handler that isn't in the session is refused up front
+    let orphan = python("result = source").with_error_handler("no-such-step");
+    let mut session = RuntimeSession::new(json!({}), vec![orphan], None);
+    let err = tokio_test::block_on(session.start_with_runtime(&runtime)).unwrap_err();
+    assert_eq!(StepErrorKind::of(&err), Some(StepErrorKind::Config));
+}
+
+#[test]
+fn test_export_jsonl_pages_through_sessions() {
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let agent = Agent::new(
+            IdFields::new(),
+            TimestampFields::new(),
+            "Export agent".to_string(),
+            vec![],
+        );
+        let agent_id = agent.try_db_upsert(&pool).await.unwrap();
+        let since = chrono::Utc::now() - chrono::Duration::hours(1);
+
+        // One session from before `since`, then five to export
+        let mut old = RuntimeSession::new(json!({"value": 0}), vec![], Some(agent_id));
+        old.timestamps.created = since - chrono::Duration::days(1);
+        old.last_step_idx = Some(0);
+        old.try_db_create(&pool).await.unwrap();
+        for value in 1..=5 {
+            let mut session = RuntimeSession::new(json!({"value": value}), vec![], Some(agent_id));
+            session.status = RunningStatus::Completed;
+            session.last_step_idx = Some(0);
+            session.step_results = vec![Some(json!({"value": value * 10}))];
+            session.step_execution_times = vec![std::time::Duration::from_millis(250)];
+            session.total_execution_time = std::time::Duration::from_millis(250);
+            session.try_db_create(&pool).await.unwrap();
+        }
+
+        let mut out = Vec::new();
+        let written =
+            RuntimeSession::export_jsonl_in_pages(&pool, agent_id, since, &mut out, 2).await;
+
+        sqlx::query("DELETE FROM runtime_sessions WHERE requested_by_agent_id = $1")
+            .bind(agent_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        agent.try_db_delete(&pool).await.unwrap();
+
+        assert_eq!(written.unwrap(), 5);
+        let lines: Vec<Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0]["initial_data"], json!({"value": 1}));
+        assert_eq!(lines[4]["step_results"], json!([{"value": 50}]));
+        assert_eq!(lines[4]["status"], "completed");
+        assert_eq!(lines[4]["total_execution_time"], json!(0.25));
+    });
+}
+
+#[test]
+fn test_resume_from_checkpoint_after_crash() {
+    let _env = LLM_ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+
+        // The first completion request hangs until after the "crash"; later ones answer at once
+        let requests = Arc::new(AtomicUsize::new(0));
+        let seen = requests.clone();
+        let llm = spawn_http_handler(move |_| {
+            if seen.fetch_add(1, Ordering::SeqCst) == 0 {
+                std::thread::sleep(Duration::from_millis(600));
+            }
+            completion_response("Stable overnight")
+        });
+        std::env::set_var("LLM_API_ENDPOINT", &llm);
+        std::env::set_var("LLM_API_KEY", "test-key");
+        std::env::remove_var("LLM_PROVIDERS");
+
+        let steps = vec![
+            Step::new(
+                IdFields::new(),
+                StepType::Python,
+                "source['value'] += 1\nresult = source".to_string(),
+                None,
+            ),
+            Step::new_prompt(
+                IdFields::new(),
+                "Summarize {{value}}".to_string(),
+                None,
+                None,
+            ),
+            Step::new(
+                IdFields::new(),
+                StepType::Python,
+                "result = {'summary': source['data'], 'done': True}".to_string(),
+                None,
+            ),
+        ];
+        let agent = Agent::new(
+            IdFields::new(),
+            TimestampFields::new(),
+            "Checkpoint agent".to_string(),
+            steps,
+        );
+        agent.try_db_create(&pool).await.unwrap();
+        let agent = Agent::try_db_select_by_id(
+            &pool,
+            &IdFields::with_values(None, agent.identifiers.global_uuid.clone()),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let agent_id = agent.identifiers.local_id.unwrap();
+        let runtime = agent.create_python_runtime().unwrap();
+
+        // "Crash" while the prompt step is waiting on the LLM, dropping the session
+        let mut session =
+            RuntimeSession::new(json!({"value": 1}), agent.steps.clone(), Some(agent_id))
+                .with_checkpoints(pool.clone());
+        let session_uuid = session.identifiers.global_uuid.clone();
+        let crashed = tokio::time::timeout(
+            Duration::from_millis(300),
+            session.unified_start(Some(&runtime)),
+        )
+        .await;
+        assert!(crashed.is_err(), "Run should have been cut short");
+        drop(session);
+
+        // Only the first step's progress made it to the database
+        let saved = RuntimeSession::try_db_select_by_id(
+            &pool,
+            &IdFields::with_values(None, session_uuid.clone()),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(saved.status, RunningStatus::Running);
+        assert_eq!(saved.last_step_idx, Some(0));
+        assert_eq!(saved.last_successful_result, Some(json!({"value": 2})));
+
+        let resumed =
+            RuntimeSession::try_resume_from_db(&pool, &session_uuid, Some(&runtime)).await;
+        let reloaded = RuntimeSession::try_db_select_by_id(
+            &pool,
+            &IdFields::with_values(None, session_uuid.clone()),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let finished_again =
+            RuntimeSession::try_resume_from_db(&pool, &session_uuid, Some(&runtime)).await;
+
+        sqlx::query("DELETE FROM runtime_sessions WHERE requested_by_agent_id = $1")
+            .bind(agent_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        agent.try_db_delete(&pool).await.unwrap();
+
+        let resumed = resumed.unwrap();
+        assert_eq!(resumed.status, RunningStatus::Completed);
+        let expected = json!({"summary": "Stable overnight", "done": true});
+        assert_eq!(resumed.last_successful_result, Some(expected.clone()));
+        assert_eq!(
+            resumed.step_results,
+            vec![
+                Some(json!({"value": 2})),
+                Some(json!("Stable overnight")),
+                Some(expected.clone()),
+            ]
+        );
+
+        // The final state was saved, and a finished session can't be resumed again
+        assert_eq!(reloaded.status, RunningStatus::Completed);
+        assert_eq!(reloaded.last_step_idx, Some(2));
+        assert_eq!(reloaded.last_successful_result, Some(expected));
+        assert!(finished_again.is_err());
+    });
+}
+
+#[test]
+fn test_agent_run_attributes_session_to_agent() {
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let mut agent = Agent::builder()
+            .description("Attributed agent")
+            .python_step("result = source")
+            .state(AgentState::Stable)
+            .build();
+        let agent_id = agent.try_db_upsert(&pool).await.unwrap();
+        agent.identifiers.local_id = Some(agent_id);
+
+        let session = agent.run(json!({"value": 1})).await.unwrap();
+        let requested_by = session.requested_by_agent_id;
+        session.try_db_create(&pool).await.unwrap();
+        let saved = RuntimeSession::try_db_select_by_id(
+            &pool,
+            &IdFields::with_values(None, session.identifiers.global_uuid.clone()),
+        )
+        .await;
+
+        sqlx::query("DELETE FROM runtime_sessions WHERE requested_by_agent_id = $1")
+            .bind(agent_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        agent.try_db_delete(&pool).await.unwrap();
+
+        assert_eq!(requested_by, Some(agent_id));
+        let saved = saved.unwrap().unwrap();
+        assert_eq!(saved.requested_by_agent_id, Some(agent_id));
+    });
+}
+
+#[test]
+fn test_execution_times_round_trip_exactly() {
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let agent = Agent::new(
+            IdFields::new(),
+            TimestampFields::new(),
+            "Timing agent".to_string(),
+            vec![],
+        );
+        let agent_id = agent.try_db_upsert(&pool).await.unwrap();
+
+        let mut session = RuntimeSession::new_requested_by(json!({"value": 1}), vec![], agent_id);
+        session.status = RunningStatus::Completed;
+        session.last_step_idx = Some(1);
+        session.step_execution_times = vec![
+            Duration::new(1, 123_456_789),
+            Duration::new(0, 1),
+            Duration::new(86_400, 999_999_999),
+        ];
+        session.total_execution_time = Duration::new(86_401, 123_456_791);
+        session.try_db_create(&pool).await.unwrap();
+        let saved = RuntimeSession::try_db_select_by_id(
+            &pool,
+            &IdFields::with_values(None, session.identifiers.global_uuid.clone()),
+        )
+        .await;
+
+        sqlx::query("DELETE FROM runtime_sessions WHERE requested_by_agent_id = $1")
+            .bind(agent_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        agent.try_db_delete(&pool).await.unwrap();
+
+        let saved = saved.unwrap().unwrap();
+        assert_eq!(saved.step_execution_times, session.step_execution_times);
+        assert_eq!(saved.total_execution_time, session.total_execution_time);
+    });
+}
+
+#[test]
+fn test_step_results_keep_their_indexes_across_a_round_trip() {
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let python =
+            |code: &str| Step::new(IdFields::new(), StepType::Python, code.to_string(), None);
+        // The first step succeeds, so its handler never runs
+        let handler = python("result = {'value': 0}");
+        let mut agent = Agent::builder()
+            .description("Unused handler agent")
+            .step(
+                python("result = {'value': source['value'] + 1}")
+                    .with_error_handler(handler.identifiers.global_uuid.clone()),
+            )
+            .step(handler)
+            .python_step("result = {'value': source['value'] * 10}")
+            .state(AgentState::Stable)
+            .build();
+        let agent_id = agent.try_db_upsert(&pool).await.unwrap();
+        agent.identifiers.local_id = Some(agent_id);
+
+        let session = agent.run(json!({"value": 1})).await.unwrap();
+        let id = IdFields::with_values(None, session.identifiers.global_uuid.clone());
+        session.try_db_create(&pool).await.unwrap();
+        let created = RuntimeSession::try_db_select_by_id(&pool, &id).await;
+        session.try_db_update(&pool).await.unwrap();
+        let updated = RuntimeSession::try_db_select_by_id(&pool, &id).await;
+
+        sqlx::query("DELETE FROM runtime_sessions WHERE requested_by_agent_id = $1")
+            .bind(agent_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        agent.try_db_delete(&pool).await.unwrap();
+
+        let expected = vec![Some(json!({"value": 2})), None, Some(json!({"value": 20}))];
+        assert_eq!(session.step_results, expected);
+        assert_eq!(created.unwrap().unwrap().step_results, expected);
+        assert_eq!(updated.unwrap().unwrap().step_results, expected);
+    });
+}
+
+#[test]
+fn test_reliability_reports_aggregate_session_outcomes() {
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let agent = Agent::builder()
+            .description("Reliability agent")
+            .python_step("result = source")
+            .python_step("result = source")
+            .build();
+        let agent_id = agent.try_db_upsert(&pool).await.unwrap();
+        let agent = Agent::try_db_select_by_id(
+            &pool,
+            &IdFields::with_values(None, agent.identifiers.global_uuid.clone()),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let step_ids: Vec<i32> = agent
+            .steps
+            .iter()
+            .map(|step| step.identifiers.local_id.unwrap())
+            .collect();
+
+        // (status, last step attempted, step times and results, total time)
+        let skipped =
+            json!({STEP_OUTPUT_STATUS_KEY: "error", STEP_OUTPUT_ERROR_KIND_KEY: "user_code"});
+        let ok = Some(json!({"value": 1}));
+        let seeded = [
+            (
+                RunningStatus::Completed,
+                1,
+                vec![(1, ok.clone()), (3, ok.clone())],
+                4,
+            ),
+            (
+                RunningStatus::Failed,
+                1,
+                vec![(1, ok.clone()), (1, None)],
+                2,
+            ),
+            (
+                RunningStatus::Completed,
+                1,
+                vec![(3, Some(skipped)), (2, ok.clone())],
+                5,
+            ),
+            (RunningStatus::Failed, 0, vec![(2, None)], 2),
+            // Still in flight, so not counted yet
+            (RunningStatus::Running, 0, vec![(9, ok.clone())], 9),
+        ];
+        for (status, last_step_idx, ran, total_secs) in seeded {
+            let mut session = RuntimeSession::new_requested_by(
+                json!({"value": 1}),
+                agent.steps.clone(),
+                agent_id,
+            );
+            session.status = status;
+            session.last_step_idx = Some(last_step_idx);
+            session.step_execution_times = ran
+                .iter()
+                .map(|(secs, _)| Duration::from_secs(*secs))
+                .collect();
+            session.step_results = ran.into_iter().map(|(_, result)| result).collect();
+            session.total_execution_time = Duration::from_secs(total_secs);
+            session.try_db_create(&pool).await.unwrap();
+        }
+
+        let agents = Agent::reliability_report(&pool).await;
+        let steps = Step::reliability_report(&pool).await;
+
+        sqlx::query("DELETE FROM runtime_sessions WHERE requested_by_agent_id = $1")
+            .bind(agent_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        agent.try_db_delete(&pool).await.unwrap();
+
+        let agents = agents.unwrap();
+        let report = agents.iter().find(|r| r.id == agent_id).unwrap();
+        assert_eq!((report.runs, report.failures), (4, 2));
+        assert_eq!(report.failure_ratio, 0.5);
+        assert_eq!(report.avg_duration_secs, Some(3.25));
+
+        // The first step failed once outright and once under SkipAndContinue; the
+        // second wasn't reached by the run that failed on the first
+        let steps = steps.unwrap();
+        let first = steps.iter().find(|r| r.id == step_ids[0]).unwrap();
+        assert_eq!((first.runs, first.failures), (4, 2));
+        assert_eq!(first.avg_duration_secs, Some(1.75));
+        let second = steps.iter().find(|r| r.id == step_ids[1]).unwrap();
+        assert_eq!((second.runs, second.failures), (3, 1));
+        assert_eq!(second.failure_ratio, 1.0 / 3.0);
+        assert_eq!(second.avg_duration_secs, Some(2.0));
+    });
+}
+
+#[test]
+fn test_session_loads_the_steps_it_ran() {
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let agent = Agent::builder()
+            .description("Session steps agent")
+            .python_step("result = 1")
+            .python_step("result = 2")
+            .python_step("result = 3")
+            .build();
+        let agent_id = agent.try_db_upsert(&pool).await.unwrap();
+        let agent = Agent::try_db_select_by_id(
+            &pool,
+            &IdFields::with_values(Some(agent_id), String::new()),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let step_id = |content: &str| {
+            agent
+                .steps
+                .iter()
+                .find(|step| step.step_content == content)
+                .and_then(|step| step.identifiers.local_id)
+                .unwrap()
+        };
+
+        // A session that ran some of the agent's steps, not in the order they were created
+        let ran: Vec<Step> = ["result = 3", "result = 1"]
+            .iter()
+            .map(|content| {
+                agent
+                    .steps
+                    .iter()
+                    .find(|step| step.step_content == *content)
+                    .unwrap()
+                    .clone()
+            })
+            .collect();
+        let mut session = RuntimeSession::new_requested_by(json!({}), ran, agent_id);
+        session.last_step_idx = Some(1);
+        session.try_db_create(&pool).await.unwrap();
+        let loaded = RuntimeSession::try_db_select_by_id(
+            &pool,
+            &IdFields::with_values(None, session.identifiers.global_uuid.clone()),
+        )
+        .await;
+        let all_steps = crate::load_agent_steps(&pool, agent_id).await;
+
+        sqlx::query("DELETE FROM runtime_sessions WHERE requested_by_agent_id = $1")
+            .bind(agent_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        agent.try_db_delete(&pool).await.unwrap();
+
+        let loaded = loaded.unwrap().unwrap();
+        let loaded_ids: Vec<i32> = loaded
+            .steps
+            .iter()
+            .map(|step| step.identifiers.local_id.unwrap())
+            .collect();
+        assert_eq!(
+            loaded_ids,
+            vec![step_id("result = 3"), step_id("result = 1")]
+        );
+
+        // The agent's own steps are all of them
+        let all_steps = all_steps.unwrap().unwrap();
+        assert_eq!(all_steps.as_array().unwrap().len(), 3);
+    });
+}
+
+#[test]
+fn test_session_reloads_repeated_steps() {
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let agent = Agent::builder()
+            .description("Repeated steps agent")
+            .python_step("result = 1")
+            .python_step("result = 2")
+            .build();
+        let agent_id = agent.try_db_upsert(&pool).await.unwrap();
+        let agent = Agent::try_db_select_by_id(
+            &pool,
+            &IdFields::with_values(Some(agent_id), String::new()),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        // The same step run before and after another one
+        let (first, second) = (agent.steps[0].clone(), agent.steps[1].clone());
+        let mut session = RuntimeSession::new_requested_by(
+            json!({}),
+            vec![first.clone(), second.clone(), first.clone()],
+            agent_id,
+        );
+        session.last_step_idx = Some(2);
+        session.try_db_create(&pool).await.unwrap();
+        let loaded = RuntimeSession::try_db_select_by_id(
+            &pool,
+            &IdFields::with_values(None, session.identifiers.global_uuid.clone()),
+        )
+        .await;
+
+        sqlx::query("DELETE FROM runtime_sessions WHERE requested_by_agent_id = $1")
+            .bind(agent_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        agent.try_db_delete(&pool).await.unwrap();
+
+        let loaded_ids: Vec<Option<i32>> = loaded
+            .unwrap()
+            .unwrap()
+            .steps
+            .iter()
+            .map(|step| step.identifiers.local_id)
+            .collect();
+        assert_eq!(
+            loaded_ids,
+            vec![
+                first.identifiers.local_id,
+                second.identifiers.local_id,
+                first.identifiers.local_id
+            ]
+        );
+    });
+}
+
+#[test]
+fn test_session_metadata_round_trips() {
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let metadata = json!({
+            "request_id": "req-8841",
+            "tenant_id": "st-marys",
+            "experiments": ["triage-v2"],
+        });
+        let agent = Agent::builder().description("Metadata agent").build();
+        let agent_id = agent.try_db_upsert(&pool).await.unwrap();
+        let mut session = RuntimeSession::new_requested_by(json!({"value": 1}), vec![], agent_id)
+            .with_metadata(metadata.clone());
+        session.last_step_idx = Some(0);
+        session.try_db_create(&pool).await.unwrap();
+        let id = IdFields::with_values(None, session.identifiers.global_uuid.clone());
+        let created = RuntimeSession::try_db_select_by_id(&pool, &id).await;
+
+        session.metadata = None;
+        session.try_db_update(&pool).await.unwrap();
+        let updated = RuntimeSession::try_db_select_by_id(&pool, &id).await;
+        session.try_db_delete(&pool).await.unwrap();
+        agent.try_db_delete(&pool).await.unwrap();
+
+        assert_eq!(created.unwrap().unwrap().metadata, Some(metadata));
+        assert_eq!(updated.unwrap().unwrap().metadata, None);
+    });
+}
@@ -1,8 +1,17 @@
+use super::test_pool;
 use crate::{
-    models::{Agent, Signal, SignalType},
-    IdFields, TimestampFields,
+    check_exists_by_uuid,
+    models::agents::AgentState,
+    models::steps::StepType,
+    models::{
+        Agent, RunDataPayload, RunPayload, RuntimeSession, Signal, SignalSource, SignalType, Step,
+        SyncPayload,
+    },
+    DatabaseItem, IdFields, JsonLike, TimestampFields,
 };
 use serde_json::json;
+use sqlx::postgres::PgPoolOptions;
+use std::time::Duration;
 use uuid::Uuid;
 
 fn create_test_signal() -> Signal {
@@ -51,7 +60,528 @@ fn test_signal_without_data() {
         None, // No data
     );
 
-    // Processing should fail without data
-    let process_result = tokio_test::block_on(signal.process());
+    // Processing should fail without data (before anything is saved, so the
+    // pool is never reached)
+    let process_result = tokio_test::block_on(async {
+        let pool = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(100))
+            .connect_lazy("postgresql://postgres@127.0.0.1:1/postgres")
+            .unwrap();
+        signal.process(&pool).await
+    });
     assert!(process_result.is_err(), "Process should fail without data");
 }
+
+#[test]
+fn test_signal_source_is_stamped_and_round_trips() {
+    let user_uuid = Uuid::new_v4().to_string();
+    let run = |source| {
+        Signal::new_run(
+            IdFields::new(),
+            user_uuid.clone(),
+            None,
+            RunPayload {
+                operation: "run".to_string(),
+                payload: RunDataPayload {
+                    id: "1".to_string(),
+                    properties: json!({}),
+                },
+            },
+            source,
+        )
+    };
+    let sync = |source| {
+        Signal::new_sync(
+            IdFields::new(),
+            user_uuid.clone(),
+            None,
+            SyncPayload {
+                scope: "all".to_string(),
+                mode: "full".to_string(),
+                targets: None,
+            },
+            source,
+        )
+    };
+    let fyi = |source| Signal::new_fyi(IdFields::new(), user_uuid.clone(), None, json!({}), source);
+    let sender = Agent::new(
+        IdFields::new(),
+        TimestampFields::new(),
+        "Sender".to_string(),
+        vec![],
+    );
+    for source in [
+        SignalSource::User,
+        SignalSource::Scheduled,
+        SignalSource::Webhook,
+        SignalSource::Agent(sender.identifiers.global_uuid.clone()),
+    ] {
+        for signal in [
+            run(source.clone()),
+            sync(source.clone()),
+            fyi(source.clone()),
+        ] {
+            assert_eq!(signal.source, source);
+        }
+    }
+    assert_eq!(create_test_signal().source, SignalSource::User);
+    let forwarded = Signal::new_from_agent(
+        &sender,
+        IdFields::new(),
+        user_uuid.clone(),
+        None,
+        SignalType::Fyi,
+        Some(json!({})),
+    );
+    assert_eq!(
+        forwarded.source,
+        SignalSource::Agent(sender.identifiers.global_uuid.clone())
+    );
+
+    let agent_uuid = Uuid::new_v4().to_string();
+    for source in [
+        SignalSource::User,
+        SignalSource::Scheduled,
+        SignalSource::Webhook,
+        SignalSource::Agent(agent_uuid.clone()),
+    ] {
+        let signal = create_test_signal().with_source(source.clone());
+        assert_eq!(signal.source, source);
+        assert_eq!(Signal::from_json(signal.to_json()).unwrap().source, source);
+    }
+    assert_eq!(
+        SignalSource::Agent(agent_uuid.clone()).to_string(),
+        format!("agent:{}", agent_uuid)
+    );
+
+    // Older JSON without a source was always a user request
+    let mut legacy = fyi(SignalSource::User).to_json();
+    legacy.as_object_mut().unwrap().remove("source");
+    assert_eq!(
+        Signal::from_json(legacy).unwrap().source,
+        SignalSource::User
+    );
+
+    let mut invalid = fyi(SignalSource::User).to_json();
+    invalid["source"] = json!("agent:not-a-uuid");
+    assert!(Signal::from_json(invalid).is_err());
+}
+
+#[test]
+fn test_signal_source_survives_db_round_trip() {
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let sender = Uuid::new_v4().to_string();
+
+        let mut created = Vec::new();
+        for source in [
+            SignalSource::User,
+            SignalSource::Scheduled,
+            SignalSource::Webhook,
+            SignalSource::Agent(sender.clone()),
+        ] {
+            let signal = Signal::new(
+                IdFields::new(),
+                Uuid::new_v4().to_string(),
+                None,
+                SignalType::Fyi,
+                Some(json!({"value": 1})),
+            )
+            .with_source(source.clone());
+            signal.try_db_create(&pool).await.unwrap();
+
+            let mut saved = Signal::try_db_select_by_id(
+                &pool,
+                &IdFields::with_values(None, signal.identifiers.global_uuid.clone()),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+            assert_eq!(saved.source, source);
+
+            // Updates write the source back too
+            saved.source = SignalSource::Webhook;
+            saved.try_db_update(&pool).await.unwrap();
+            let updated = Signal::try_db_select_by_id(
+                &pool,
+                &IdFields::with_values(None, signal.identifiers.global_uuid.clone()),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+            assert_eq!(updated.source, SignalSource::Webhook);
+
+            created.push(signal.identifiers.global_uuid);
+        }
+
+        // Clean up
+        for global_uuid in created {
+            sqlx::query("DELETE FROM signals WHERE global_uuid = $1")
+                .bind(Uuid::parse_str(&global_uuid).unwrap())
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+    });
+}
+
+#[test]
+fn test_select_signals_by_user_uuid() {
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let target_user = Uuid::new_v4().to_string();
+        let other_user = Uuid::new_v4().to_string();
+
+        let agent = Agent::new(
+            IdFields::new(),
+            TimestampFields::new(),
+            "Signal lookup agent".to_string(),
+            vec![],
+        );
+        agent.try_db_create(&pool).await.unwrap();
+        let agent = Agent::try_db_select_by_id(
+            &pool,
+            &IdFields::with_values(None, agent.identifiers.global_uuid.clone()),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        let mut created = Vec::new();
+        for (user, value, signal_type) in [
+            (&target_user, 1, SignalType::Fyi),
+            (&other_user, 2, SignalType::Fyi),
+            (&target_user, 3, SignalType::Fyi),
+            (&target_user, 4, SignalType::Run),
+        ] {
+            let signal = Signal::new(
+                IdFields::new(),
+                user.clone(),
+                Some(Agent::from_json(agent.to_json()).unwrap()),
+                signal_type,
+                Some(json!({"value": value})),
+            );
+            signal.try_db_create(&pool).await.unwrap();
+            created.push(signal.identifiers.global_uuid);
+        }
+
+        let signals = Signal::try_db_select_by_user_uuid(&pool, &target_user)
+            .await
+            .unwrap();
+        let values: Vec<_> = signals
+            .iter()
+            .map(|s| s.initial_data.clone().unwrap()["value"].clone())
+            .collect();
+        assert_eq!(values, vec![json!(4), json!(3), json!(1)]);
+        assert!(signals.iter().all(|s| s.user_requested_uuid == target_user));
+        assert_eq!(signals[0].signal_type, SignalType::Run);
+        assert_eq!(
+            signals[0].agent.as_ref().unwrap().identifiers.global_uuid,
+            agent.identifiers.global_uuid
+        );
+
+        // Clean up
+        for global_uuid in created {
+            sqlx::query("DELETE FROM signals WHERE global_uuid = $1")
+                .bind(Uuid::parse_str(&global_uuid).unwrap())
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        agent.try_db_delete(&pool).await.unwrap();
+    });
+}
+
+#[test]
+fn test_create_signal_upserts_unsaved_agent() {
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let step = Step::new(
+            IdFields::new(),
+            StepType::Python,
+            "result = source".to_string(),
+            None,
+        );
+        let agent = Agent::new(
+            IdFields::new(),
+            TimestampFields::new(),
+            "Not yet saved".to_string(),
+            vec![step.clone()],
+        );
+        let agent_uuid = agent.identifiers.global_uuid.clone();
+
+        // The linked session references an agent that doesn't exist, so the
+        // transaction fails after the upsert and the agent isn't kept either
+        let mut failing = Signal::new(
+            IdFields::new(),
+            Uuid::new_v4().to_string(),
+            Some(agent.clone()),
+            SignalType::Run,
+            Some(json!({"value": 1})),
+        );
+        failing.linked_rts = Some(RuntimeSession::new(json!({}), vec![], Some(-1)));
+        assert!(failing.try_db_create_with_agent(&pool).await.is_err());
+        assert!(!check_exists_by_uuid(&pool, "agents", &agent_uuid)
+            .await
+            .unwrap());
+
+        let signal = Signal::new(
+            IdFields::new(),
+            Uuid::new_v4().to_string(),
+            Some(agent),
+            SignalType::Run,
+            Some(json!({"value": 2})),
+        );
+        signal.try_db_create_with_agent(&pool).await.unwrap();
+
+        let saved = Signal::try_db_select_by_id(
+            &pool,
+            &IdFields::with_values(None, signal.identifiers.global_uuid.clone()),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let saved_agent = saved.agent.unwrap();
+        assert_eq!(saved_agent.identifiers.global_uuid, agent_uuid);
+        assert!(
+            check_exists_by_uuid(&pool, "steps", &step.identifiers.global_uuid)
+                .await
+                .unwrap()
+        );
+
+        // Upserting again updates the existing agent rather than duplicating it
+        let mut renamed = Agent::from_json(saved_agent.to_json()).unwrap();
+        renamed.description = "Saved".to_string();
+        assert_eq!(
+            renamed.try_db_upsert(&pool).await.unwrap(),
+            saved_agent.identifiers.local_id.unwrap()
+        );
+
+        // Clean up
+        sqlx::query("DELETE FROM signals WHERE global_uuid = $1")
+            .bind(Uuid::parse_str(&signal.identifiers.global_uuid).unwrap())
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM steps WHERE global_uuid = $1")
+            .bind(Uuid::parse_str(&step.identifiers.global_uuid).unwrap())
+            .execute(&pool)
+            .await
+            .unwrap();
+        renamed.try_db_delete(&pool).await.unwrap();
+    });
+}
+
+#[test]
+fn test_process_signal_persists_result() {
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let step = Step::new(
+            IdFields::new(),
+            StepType::Python,
+            "result = {'doubled': source['value'] * 2}".to_string(),
+            None,
+        );
+        let mut agent = Agent::new(
+            IdFields::new(),
+            TimestampFields::new(),
+            "Signal processing agent".to_string(),
+            vec![step.clone()],
+        );
+        agent.set_state(AgentState::Stable);
+        agent.identifiers.local_id = Some(agent.try_db_upsert(&pool).await.unwrap());
+        let agent_uuid = agent.identifiers.global_uuid.clone();
+
+        let mut signal = Signal::new(
+            IdFields::new(),
+            Uuid::new_v4().to_string(),
+            Some(agent),
+            SignalType::Run,
+            Some(json!({"value": 21})),
+        );
+        signal.try_db_create(&pool).await.unwrap();
+        signal.process(&pool).await.unwrap();
+
+        let saved = Signal::try_db_select_by_id(
+            &pool,
+            &IdFields::with_values(None, signal.identifiers.global_uuid.clone()),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(saved.result_data, Some(json!({"doubled": 42})));
+        assert_eq!(saved.error_message.as_deref().unwrap_or_default(), "");
+
+        // The session was saved and linked to the signal
+        let session_uuid = signal
+            .linked_rts
+            .as_ref()
+            .unwrap()
+            .identifiers
+            .global_uuid
+            .clone();
+        let linked_uuid: Uuid = sqlx::query_scalar(
+            "SELECT rts.global_uuid FROM signals s \
+             JOIN runtime_sessions rts ON s.rts_id = rts.id WHERE s.global_uuid = $1",
+        )
+        .bind(Uuid::parse_str(&signal.identifiers.global_uuid).unwrap())
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(linked_uuid.to_string(), session_uuid);
+
+        // Clean up
+        sqlx::query("DELETE FROM signals WHERE global_uuid = $1")
+            .bind(Uuid::parse_str(&signal.identifiers.global_uuid).unwrap())
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM runtime_sessions WHERE global_uuid = $1")
+            .bind(Uuid::parse_str(&session_uuid).unwrap())
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM steps WHERE global_uuid = $1")
+            .bind(Uuid::parse_str(&step.identifiers.global_uuid).unwrap())
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM agents WHERE global_uuid = $1")
+            .bind(Uuid::parse_str(&agent_uuid).unwrap())
+            .execute(&pool)
+            .await
+            .unwrap();
+    });
+}
+
+#[test]
+fn test_link_session_sets_the_signals_rts_id() {
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let signal = Signal::new(
+            IdFields::new(),
+            Uuid::new_v4().to_string(),
+            None,
+            SignalType::Fyi,
+            Some(json!({"value": 1})),
+        );
+        signal.try_db_create(&pool).await.unwrap();
+        let signal_id = Signal::try_db_select_by_id(
+            &pool,
+            &IdFields::with_values(None, signal.identifiers.global_uuid.clone()),
+        )
+        .await
+        .unwrap()
+        .unwrap()
+        .identifiers
+        .local_id
+        .unwrap();
+        let agent = Agent::new(
+            IdFields::new(),
+            TimestampFields::new(),
+            "Session link agent".to_string(),
+            vec![],
+        );
+        let agent_id = agent.try_db_upsert(&pool).await.unwrap();
+        let mut session = RuntimeSession::new(json!({"value": 1}), vec![], Some(agent_id));
+        session.last_step_idx = Some(0);
+        session.try_db_create(&pool).await.unwrap();
+        let session_uuid = session.identifiers.global_uuid.clone();
+
+        assert!(Signal::try_db_link_session(&pool, signal_id, &session_uuid)
+            .await
+            .unwrap());
+        let linked_uuid: Uuid = sqlx::query_scalar(
+            "SELECT rts.global_uuid FROM signals s \
+             JOIN runtime_sessions rts ON s.rts_id = rts.id WHERE s.id = $1",
+        )
+        .bind(signal_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(linked_uuid.to_string(), session_uuid);
+
+        // A signal that isn't in the database has nothing to link
+        assert!(!Signal::try_db_link_session(&pool, -1, &session_uuid)
+            .await
+            .unwrap());
+
+        // Clean up
+        sqlx::query("DELETE FROM signals WHERE id = $1")
+            .bind(signal_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM runtime_sessions WHERE global_uuid = $1")
+            .bind(Uuid::parse_str(&session_uuid).unwrap())
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM agents WHERE id = $1")
+            .bind(agent_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    });
+}
+
+#[test]
+fn test_agentless_fyi_signal_is_recorded() {
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let data = json!({"note": "Bed 4 discharged"});
+        let mut fyi = Signal::new(
+            IdFields::new(),
+            Uuid::new_v4().to_string(),
+            None,
+            SignalType::Fyi,
+            Some(data.clone()),
+        );
+        fyi.try_db_create(&pool).await.unwrap();
+        let processed = fyi.process(&pool).await;
+        let saved = Signal::try_db_select_by_id(
+            &pool,
+            &IdFields::with_values(None, fyi.identifiers.global_uuid.clone()),
+        )
+        .await;
+
+        // Other signal types still need an agent
+        let mut run = Signal::new(
+            IdFields::new(),
+            Uuid::new_v4().to_string(),
+            None,
+            SignalType::Run,
+            Some(data.clone()),
+        );
+        run.try_db_create(&pool).await.unwrap();
+        let run_processed = run.process(&pool).await;
+
+        for signal in [&fyi, &run] {
+            sqlx::query("DELETE FROM signals WHERE global_uuid = $1")
+                .bind(Uuid::parse_str(&signal.identifiers.global_uuid).unwrap())
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        processed.unwrap();
+        assert!(fyi.linked_rts.is_none());
+        let saved = saved.unwrap().unwrap();
+        assert_eq!(saved.initial_data, Some(data.clone()));
+        assert_eq!(saved.result_data, Some(data));
+        assert_eq!(saved.error_message.as_deref().unwrap_or_default(), "");
+        assert!(run_processed.is_err());
+    });
+}
@@ -1,4 +1,16 @@
-use crate::{models::steps::StepType, models::Step, IdFields};
+use super::test_pool;
+use crate::{
+    models::steps::{
+        LlmIoLogConfig, LoopConfig, PromptPreview, StepErrorKind, StepType,
+        DEFAULT_MAX_NESTING_DEPTH, LOOP_ITERATIONS_KEY, REDACTED_PLACEHOLDER, STEP_OUTPUT_DATA_KEY,
+    },
+    models::{Agent, RuntimeSession, Step},
+    DatabaseItem, IdFields, JsonLike, PythonRuntime, RunningStatus, SecretStore, TimestampFields,
+};
+use serde_json::json;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::Mutex;
 
 fn create_test_step(step_type: StepType) -> Step {
     let id_fields = IdFields::new();
@@ -6,6 +18,16 @@ fn create_test_step(step_type: StepType) -> Step {
         StepType::Python => "source['value'] += 10\nresult = source".to_string(),
         StepType::Prompt(_) => "Add 10 to the value in the data".to_string(),
         StepType::WebScrape => "https://example.com".to_string(),
+        StepType::Loop => json!({
+            "step": {"step_type": "python", "step_content": "source['value'] += 10\nresult = source"},
+            "until": "$.done",
+        })
+        .to_string(),
+        StepType::JsonPatch => json!([
+            {"op": "replace", "path": "/value", "value": 20},
+        ])
+        .to_string(),
+        StepType::FileOp => json!({"op": "read", "path": "value.txt"}).to_string(),
     };
 
     Step::new(
@@ -19,9 +41,9 @@ fn create_test_step(step_type: StepType) -> Step {
 #[test]
 fn test_create_step() {
     let _python_step = create_test_step(StepType::Python);
-    let _prompt_step = create_test_step(StepType::Prompt(
+    let _prompt_step = create_test_step(StepType::Prompt(Some(
         "meta-llama/Llama-3.3-70B-Instruct-Turbo".to_string(),
-    ));
+    )));
     let _webscrape_step = create_test_step(StepType::WebScrape);
 
     // In real tests, we'd test different behavior based on step type
@@ -29,3 +51,1249 @@ fn test_create_step() {
     // and the Prompt step should call the LLM
     // Here we'd mock those dependencies
 }
+
+fn create_counting_loop(max_iterations: u64, fail_on_max: bool) -> Step {
+    let content = json!({
+        "steps": [{
+            "step_type": "python",
+            "step_content": "source['n'] += 1\nsource['done'] = source['n'] >= 3\nresult = source",
+        }],
+        "until": "$.done",
+        "max_iterations": max_iterations,
+        "fail_on_max": fail_on_max,
+    });
+
+    Step::new(
+        IdFields::new(),
+        StepType::Loop,
+        content.to_string(),
+        Some("Counts up to 3".to_string()),
+    )
+}
+
+fn runtime_for(step: &Step) -> PythonRuntime {
+    let mut runtime = PythonRuntime::new(&step.identifiers.global_uuid).unwrap();
+    runtime.add_step(step).unwrap();
+    runtime
+}
+
+#[test]
+fn test_strict_step_array_reports_malformed_index() {
+    let steps_json = json!([
+        {"step_type": "python", "step_content": "result = source"},
+        {"step_type": "shell", "step_content": "ls"},
+    ]);
+
+    // The lenient parser silently drops the bad step
+    assert_eq!(Step::from_json_array(&steps_json).len(), 1);
+
+    let errors = Step::from_json_array_strict(&steps_json).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].0, 1);
+    assert!(errors[0].1.to_string().contains("Invalid step type"));
+    assert!(Step::describe_json_array_errors(&errors).starts_with("step 1:"));
+
+    assert!(Step::from_json_array_strict(&json!(null))
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        Step::from_json_array_strict(&json!([steps_json[0]]))
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn test_loop_config_parsing() {
+    let step = create_test_step(StepType::Loop);
+    let config = LoopConfig::from_step(&step).unwrap();
+
+    assert_eq!(config.steps.len(), 1);
+    assert_eq!(config.max_iterations, 10);
+    assert!(config.fail_on_max);
+    assert_eq!(
+        config.steps[0].identifiers.global_uuid,
+        format!("{}_loop_0", step.identifiers.global_uuid)
+    );
+    assert!(step.requires_runtime());
+
+    let missing_until = Step::new(
+        IdFields::new(),
+        StepType::Loop,
+        json!({"steps": [{"step_type": "python", "step_content": "result = source"}]}).to_string(),
+        None,
+    );
+    assert!(LoopConfig::from_step(&missing_until).is_err());
+}
+
+#[test]
+fn test_loop_runs_until_predicate_holds() {
+    let step = create_counting_loop(10, true);
+    let runtime = runtime_for(&step);
+
+    let output = tokio_test::block_on(step.run(json!({"n": 0}), 0, Some(&runtime)))
+        .unwrap()
+        .primary;
+
+    assert_eq!(output[LOOP_ITERATIONS_KEY], json!(3));
+    assert_eq!(output[STEP_OUTPUT_DATA_KEY], json!({"n": 3, "done": true}));
+}
+
+#[test]
+fn test_loop_hits_iteration_cap() {
+    let failing = create_counting_loop(2, true);
+    let runtime = runtime_for(&failing);
+    let err = tokio_test::block_on(failing.run(json!({"n": 0}), 0, Some(&runtime)))
+        .expect_err("Loop should fail when the cap is hit");
+    assert_eq!(StepErrorKind::of(&err), Some(StepErrorKind::Validation));
+
+    let lenient = create_counting_loop(2, false);
+    let runtime = runtime_for(&lenient);
+    let output = tokio_test::block_on(lenient.run(json!({"n": 0}), 0, Some(&runtime)))
+        .unwrap()
+        .primary;
+    assert_eq!(output[LOOP_ITERATIONS_KEY], json!(2));
+    assert_eq!(output[STEP_OUTPUT_DATA_KEY], json!({"n": 2, "done": false}));
+}
+
+/// `depth` Loop steps nested inside each other around a JsonPatch step, each
+/// running its inner step once
+fn create_nested_loop(depth: usize) -> Step {
+    let mut inner = json!({
+        "step_type": "json_patch",
+        "step_content": json!([{"op": "add", "path": "/seen", "value": true}]).to_string(),
+    });
+    for _ in 0..depth {
+        inner = json!({
+            "step_type": "loop",
+            "step_content": json!({"step": inner, "until": true}).to_string(),
+        });
+    }
+    Step::from_json(inner).unwrap()
+}
+
+#[test]
+fn test_loops_nested_too_deep_fail() {
+    let run = |depth: usize, max_depth: usize| {
+        let mut session = RuntimeSession::new(json!({}), vec![create_nested_loop(depth)], None)
+            .with_max_nesting_depth(max_depth);
+        tokio_test::block_on(session.start())
+    };
+
+    // Each loop wraps its inner step's output once
+    let mut expected = json!({"seen": true});
+    for _ in 0..3 {
+        expected = json!({STEP_OUTPUT_DATA_KEY: expected, LOOP_ITERATIONS_KEY: 1});
+    }
+    assert_eq!(run(3, 3).unwrap(), expected);
+
+    let err = run(3, 2).unwrap_err();
+    assert!(
+        err.to_string().contains("Nesting depth exceeded"),
+        "{}",
+        err
+    );
+    assert!(err.to_string().contains("3 levels deep (max 2)"), "{}", err);
+    assert_eq!(StepErrorKind::of(&err), Some(StepErrorKind::Config));
+
+    // Outside a session the default applies
+    let too_deep = create_nested_loop(DEFAULT_MAX_NESTING_DEPTH + 1);
+    let err = tokio_test::block_on(too_deep.run(json!({}), 0, None)).unwrap_err();
+    assert!(
+        err.to_string().contains("Nesting depth exceeded"),
+        "{}",
+        err
+    );
+}
+
+fn create_json_patch(patch: serde_json::Value) -> Step {
+    Step::new(
+        IdFields::new(),
+        StepType::JsonPatch,
+        patch.to_string(),
+        None,
+    )
+}
+
+#[test]
+fn test_json_patch_applies_operations() {
+    let step = create_json_patch(json!([
+        {"op": "test", "path": "/status", "value": "draft"},
+        {"op": "replace", "path": "/status", "value": "published"},
+        {"op": "add", "path": "/tags/-", "value": "reviewed"},
+        {"op": "add", "path": "/meta", "value": {"rev": 2}},
+        {"op": "remove", "path": "/draft_notes"},
+    ]));
+    let source = json!({"status": "draft", "tags": ["news"], "draft_notes": "tbd"});
+
+    let output = tokio_test::block_on(step.run(source, 0, None))
+        .unwrap()
+        .primary;
+
+    assert_eq!(
+        output,
+        json!({"status": "published", "tags": ["news", "reviewed"], "meta": {"rev": 2}})
+    );
+}
+
+#[test]
+fn test_json_patch_failing_ops_report_operation() {
+    // A failing op leaves nothing applied, so the earlier replace doesn't leak out
+    let failing_test = create_json_patch(json!([
+        {"op": "replace", "path": "/count", "value": 2},
+        {"op": "test", "path": "/status", "value": "draft"},
+    ]));
+    let err =
+        tokio_test::block_on(failing_test.run(json!({"status": "live", "count": 1}), 0, None))
+            .expect_err("Test op should fail");
+    assert_eq!(StepErrorKind::of(&err), Some(StepErrorKind::Validation));
+    assert!(
+        err.to_string()
+            .contains("operation 1 (test /status) failed: value did not match"),
+        "{}",
+        err
+    );
+
+    let missing_remove = create_json_patch(json!([{"op": "remove", "path": "/missing"}]));
+    let err = tokio_test::block_on(missing_remove.run(json!({}), 0, None)).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("(remove /missing) failed: path does not exist"),
+        "{}",
+        err
+    );
+}
+
+fn create_file_op(op: serde_json::Value) -> Step {
+    Step::new(IdFields::new(), StepType::FileOp, op.to_string(), None)
+}
+
+#[test]
+fn test_file_op_write_then_read_in_session() {
+    let steps = vec![
+        create_file_op(json!({"op": "write", "path": "notes/intake.txt"})),
+        create_file_op(
+            json!({"op": "write", "path": "scan.bin", "content": "AP8=", "encoding": "base64"}),
+        ),
+        create_file_op(json!({"op": "list", "path": "/"})),
+        create_file_op(json!({"op": "read", "path": "scan.bin"})),
+        create_file_op(json!({"op": "read", "path": "/notes/intake.txt"})),
+    ];
+    let mut session = RuntimeSession::new(json!("Patient reports\n  mild fever"), steps, None);
+
+    // Absolute paths resolve under the session's workspace, not the real root
+    let output = tokio_test::block_on(session.unified_start(None)).unwrap();
+    assert_eq!(
+        output,
+        json!({"path": "/notes/intake.txt", "encoding": "utf8", "content": "Patient reports\n  mild fever"})
+    );
+
+    let results: Vec<_> = session.step_results.iter().flatten().collect();
+    assert_eq!(results[0]["size"], json!(28));
+    let entries = results[2]["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(
+        (&entries[0]["name"], &entries[0]["is_dir"]),
+        (&json!("notes"), &json!(true))
+    );
+    assert_eq!(
+        entries[1],
+        json!({"name": "scan.bin", "is_dir": false, "size": 2})
+    );
+    assert_eq!(
+        *results[3],
+        json!({"path": "scan.bin", "encoding": "base64", "content": "AP8="})
+    );
+
+    // Each session gets its own workspace, so files don't carry over
+    let mut next = RuntimeSession::new(
+        json!({}),
+        vec![create_file_op(json!({"op": "read", "path": "scan.bin"}))],
+        None,
+    );
+    let err = tokio_test::block_on(next.unified_start(None)).unwrap_err();
+    assert_eq!(StepErrorKind::of(&err), Some(StepErrorKind::Validation));
+}
+
+#[test]
+fn test_file_op_rejects_path_traversal() {
+    let escape =
+        create_file_op(json!({"op": "write", "path": "notes/../../escaped.txt", "content": "x"}));
+    let mut session = RuntimeSession::new(json!({}), vec![escape], None);
+    let err = tokio_test::block_on(session.unified_start(None)).unwrap_err();
+    assert_eq!(StepErrorKind::of(&err), Some(StepErrorKind::Validation));
+    assert!(
+        err.to_string().contains("escapes the session workspace"),
+        "{}",
+        err
+    );
+
+    // Outside a session there's no workspace to use
+    let read = create_test_step(StepType::FileOp);
+    assert_eq!(run_error_kind(&read, None), Some(StepErrorKind::Config));
+
+    // Unknown operations are rejected when the step is loaded
+    let err = Step::from_json(json!({
+        "step_type": "file_op",
+        "step_content": r#"{"op": "chmod", "path": "a.txt"}"#,
+    }))
+    .unwrap_err();
+    assert!(err.to_string().contains("FileOp step content"), "{}", err);
+}
+
+#[test]
+fn test_json_patch_validated_on_load() {
+    let valid =
+        json!({"step_type": "json_patch", "step_content": r#"[{"op": "remove", "path": "/a"}]"#});
+    assert!(Step::from_json(valid).unwrap().is_json_patch_step());
+
+    for content in [
+        r#"{"op": "remove", "path": "/a"}"#,
+        r#"[{"op": "rename", "path": "/a"}]"#,
+        r#"[{"op": "add", "path": "/a"}]"#,
+    ] {
+        let raw = json!({"step_type": "json_patch", "step_content": content});
+        let err = Step::from_json(raw).expect_err(content);
+        assert!(err.to_string().contains("RFC 6902"), "{}", err);
+    }
+}
+
+#[test]
+fn test_run_counts_track_flaky_step() {
+    let step = Step::new(
+        IdFields::new(),
+        StepType::Python,
+        "if source['attempt'] % 3 == 0:\n    raise ValueError('flaky')\nresult = source"
+            .to_string(),
+        None,
+    );
+    let mut runtime = PythonRuntime::new("flaky_step").unwrap();
+    runtime.add_step(&step).unwrap();
+    assert_eq!(step.metrics.failure_rate(), None);
+
+    // Sessions run copies of the step, which share its counters
+    let copy = step.clone();
+    for attempt in 1..=10 {
+        let _ = tokio_test::block_on(copy.run(json!({"attempt": attempt}), 0, Some(&runtime)));
+    }
+
+    assert_eq!(step.get_run_count(), 10);
+    assert_eq!(step.get_success_count(), 7);
+    assert_eq!(step.metrics.failure_rate(), Some(0.3));
+
+    // The counts are carried through JSON, e.g. an agent's `steps` column
+    let json = step.to_json();
+    assert_eq!(
+        (json["run_count"].clone(), json["success_count"].clone()),
+        (json!(10), json!(7))
+    );
+    let loaded = Step::from_json(json!({
+        "step_type": "python",
+        "step_content": step.step_content,
+        "run_count": 10,
+        "success_count": 7,
+    }))
+    .unwrap();
+    assert_eq!(loaded.get_run_count(), 10);
+    assert_eq!(loaded.get_success_count(), 7);
+}
+
+#[test]
+fn test_input_mapping_selects_nested_fields() {
+    let step = create_json_patch(json!([{"op": "add", "path": "/seen", "value": true}]))
+        .with_input_mapping([("title", "$.page.meta.title"), ("first_tag", "$.tags[0]")]);
+    let previous_output = json!({
+        "page": {"meta": {"title": "Hello"}, "body": "<html>...</html>"},
+        "tags": ["news", "health"],
+    });
+
+    let output = tokio_test::block_on(step.run(previous_output, 1, None))
+        .unwrap()
+        .primary;
+    assert_eq!(
+        output,
+        json!({"title": "Hello", "first_tag": "news", "seen": true})
+    );
+
+    // Steps loaded from JSON (e.g. an agent's `steps` column) carry the mapping too
+    let loaded = Step::from_json(json!({
+        "step_type": "json_patch",
+        "step_content": r#"[{"op": "add", "path": "/seen", "value": true}]"#,
+        "input_mapping": {"title": "$.page.meta.title", "first_tag": "$.tags[0]"},
+    }))
+    .unwrap();
+    assert_eq!(loaded.input_mapping, step.input_mapping);
+    assert_eq!(
+        step.to_json()["input_mapping"]["title"],
+        "$.page.meta.title"
+    );
+
+    let err = tokio_test::block_on(step.run(json!({"tags": ["news"]}), 1, None))
+        .expect_err("Missing paths should fail");
+    assert_eq!(StepErrorKind::of(&err), Some(StepErrorKind::Validation));
+    assert!(err.to_string().contains("$.page.meta.title"), "{}", err);
+}
+
+#[test]
+fn test_named_outputs_feed_later_steps() {
+    let scrape = Step::new(
+        IdFields::new(),
+        StepType::Python,
+        "outputs['links'] = ['https://a.example', 'https://b.example']\noutputs['images'] = []\nresult = {'text': 'Flu season is here'}"
+            .to_string(),
+        None,
+    );
+    let summarize = create_json_patch(json!([{"op": "add", "path": "/seen", "value": true}]))
+        .with_input_mapping([("text", "$.text"), ("first_link", "@links[0]")]);
+    let mut runtime = PythonRuntime::new("named_outputs").unwrap();
+    runtime.add_step(&scrape).unwrap();
+
+    let output = tokio_test::block_on(scrape.run(json!({}), 0, Some(&runtime))).unwrap();
+    assert_eq!(output.primary, json!({"text": "Flu season is here"}));
+    assert_eq!(output.named["links"][1], "https://b.example");
+
+    let mut session = RuntimeSession::new(json!({}), vec![scrape, summarize], None);
+    let result = tokio_test::block_on(session.start_with_runtime(&runtime)).unwrap();
+    assert_eq!(
+        result,
+        json!({"text": "Flu season is here", "first_link": "https://a.example", "seen": true})
+    );
+    assert_eq!(session.named_outputs["images"], json!([]));
+
+    // Steps that don't set any have no named outputs
+    let output = tokio_test::block_on(create_json_patch(json!([])).run(json!({}), 0, None));
+    assert!(output.unwrap().named.is_empty());
+}
+
+#[test]
+fn test_no_input_mapping_passes_output_through() {
+    let step = create_json_patch(json!([{"op": "add", "path": "/seen", "value": true}]));
+    assert!(step.input_mapping.is_none());
+
+    let output = tokio_test::block_on(step.run(json!({"page": {"title": "Hello"}}), 1, None))
+        .unwrap()
+        .primary;
+    assert_eq!(output, json!({"page": {"title": "Hello"}, "seen": true}));
+}
+
+/// Serves `response` to every connection; returns the base URL
+fn spawn_http_stub(response: &'static str) -> String {
+    spawn_http_handler(move |_| response.to_string())
+}
+
+/// Answers every request with `handler(raw request)`; returns the base URL
+pub(super) fn spawn_http_handler(handler: impl Fn(&str) -> String + Send + 'static) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            // Read the headers, then as much body as they announce
+            let mut request = Vec::new();
+            let mut buf = [0u8; 8192];
+            while let Ok(n) = stream.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request).to_lowercase();
+                if let Some(header_end) = text.find("\r\n\r\n") {
+                    let content_length = text
+                        .lines()
+                        .find_map(|line| line.strip_prefix("content-length:"))
+                        .and_then(|len| len.trim().parse::<usize>().ok())
+                        .unwrap_or(0);
+                    if request.len() >= header_end + 4 + content_length {
+                        break;
+                    }
+                }
+            }
+            let response = handler(&String::from_utf8_lossy(&request));
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+    format!("http://{}", addr)
+}
+
+pub(super) fn completion_response(content: &str) -> String {
+    json_response(&json!({"choices": [{"message": {"content": content}}]}))
+}
+
+fn json_response(body: &serde_json::Value) -> String {
+    let body = body.to_string();
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
+
+/// The user message of a chat completion request body
+pub(super) fn sent_prompt(request: &serde_json::Value) -> &str {
+    request["messages"]
+        .as_array()
+        .and_then(|messages| messages.iter().find(|m| m["role"] == "user"))
+        .and_then(|message| message["content"].as_str())
+        .unwrap_or_default()
+}
+
+/// Serializes tests that point the LLM env vars at stub servers
+pub(super) static LLM_ENV_LOCK: Mutex<()> = Mutex::new(());
+
+fn run_error_kind(step: &Step, runtime: Option<&PythonRuntime>) -> Option<StepErrorKind> {
+    let err = tokio_test::block_on(step.run(json!({"value": 1}), 0, runtime))
+        .expect_err("Step should fail");
+    StepErrorKind::of(&err)
+}
+
+#[test]
+fn test_config_failures_set_config_kind() {
+    let python_step = create_test_step(StepType::Python);
+    assert_eq!(
+        run_error_kind(&python_step, None),
+        Some(StepErrorKind::Config)
+    );
+
+    let empty_url = Step::new_webscrape(IdFields::new(), "  ".to_string(), None);
+    assert_eq!(
+        run_error_kind(&empty_url, None),
+        Some(StepErrorKind::Config)
+    );
+
+    let bad_scheme = Step::new_webscrape(IdFields::new(), "ftp://example.com".to_string(), None);
+    assert_eq!(
+        run_error_kind(&bad_scheme, None),
+        Some(StepErrorKind::Config)
+    );
+
+    let bad_loop = Step::new(
+        IdFields::new(),
+        StepType::Loop,
+        "not json".to_string(),
+        None,
+    );
+    assert_eq!(run_error_kind(&bad_loop, None), Some(StepErrorKind::Config));
+}
+
+#[test]
+fn test_python_exception_sets_user_code_kind() {
+    let step = Step::new(
+        IdFields::new(),
+        StepType::Python,
+        "raise ValueError('boom')".to_string(),
+        None,
+    );
+    let runtime = runtime_for(&step);
+
+    assert_eq!(
+        run_error_kind(&step, Some(&runtime)),
+        Some(StepErrorKind::UserCode)
+    );
+}
+
+#[test]
+fn test_prompt_failures_set_kind() {
+    let step = create_test_step(StepType::Prompt(Some(
+        "meta-llama/Llama-3.3-70B-Instruct-Turbo".to_string(),
+    )));
+    let _env = LLM_ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    std::env::remove_var("LLM_API_KEY");
+    std::env::remove_var("LLM_PROVIDERS");
+    assert_eq!(run_error_kind(&step, None), Some(StepErrorKind::Config));
+
+    std::env::set_var("LLM_API_KEY", "test-key");
+    std::env::set_var(
+        "LLM_API_ENDPOINT",
+        spawn_http_stub("HTTP/1.1 429 Too Many Requests\r\nContent-Length: 0\r\n\r\n"),
+    );
+    assert_eq!(
+        run_error_kind(&step, None),
+        Some(StepErrorKind::RateLimited)
+    );
+
+    std::env::set_var(
+        "LLM_API_ENDPOINT",
+        spawn_http_stub("HTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\nnot json!"),
+    );
+    assert_eq!(run_error_kind(&step, None), Some(StepErrorKind::Validation));
+
+    // Nothing listens on the discard port
+    std::env::set_var("LLM_API_ENDPOINT", "http://127.0.0.1:9");
+    assert_eq!(run_error_kind(&step, None), Some(StepErrorKind::Network));
+}
+
+#[test]
+fn test_request_timeout_sets_timeout_kind() {
+    // Accepts connections but never answers
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    let err = tokio_test::block_on(async {
+        reqwest::Client::new()
+            .get(&url)
+            .timeout(std::time::Duration::from_millis(100))
+            .send()
+            .await
+            .expect_err("Request should time out")
+    });
+
+    assert_eq!(StepErrorKind::from_reqwest(&err), StepErrorKind::Timeout);
+    drop(listener);
+}
+
+#[test]
+fn test_failed_session_records_error_kind() {
+    let mut session = RuntimeSession::new(
+        json!({"value": 1}),
+        vec![create_test_step(StepType::Python)],
+        None,
+    );
+
+    let err =
+        tokio_test::block_on(session.start_with_runtime(&PythonRuntime::new("empty").unwrap()))
+            .expect_err("Step without a registered function should fail");
+
+    assert_eq!(session.status, RunningStatus::Failed);
+    assert_eq!(session.error_kind, Some(StepErrorKind::Config));
+    assert_eq!(StepErrorKind::of(&err), Some(StepErrorKind::Config));
+    assert_eq!(
+        serde_json::to_value(StepErrorKind::RateLimited).unwrap(),
+        json!("rate_limited")
+    );
+}
+
+#[test]
+fn test_prompt_step_routes_to_named_provider() {
+    let _env = LLM_ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let cheap = spawn_http_handler(|request| {
+        if request
+            .to_lowercase()
+            .contains("authorization: bearer cheap-key")
+        {
+            completion_response("cheap")
+        } else {
+            "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\n\r\n".to_string()
+        }
+    });
+    let premium = spawn_http_handler(|request| {
+        let request = request.to_lowercase();
+        if request.contains("x-api-key: premium-key") && request.contains("\"model\":\"big-model\"")
+        {
+            completion_response("premium")
+        } else {
+            "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\n\r\n".to_string()
+        }
+    });
+
+    std::env::set_var("LLM_API_ENDPOINT", &cheap);
+    std::env::set_var("LLM_API_KEY", "cheap-key");
+    std::env::set_var(
+        "LLM_PROVIDERS",
+        json!({
+            "premium": {"endpoint": premium, "api_key": "premium-key", "auth_header_style": "x_api_key"}
+        })
+        .to_string(),
+    );
+
+    let default_step = create_test_step(StepType::Prompt(Some(
+        "meta-llama/Llama-3.3-70B-Instruct-Turbo".to_string(),
+    )));
+    let premium_step = create_test_step(StepType::Prompt(Some("big-model".to_string())))
+        .with_llm_provider("premium");
+    let unknown_step = create_test_step(StepType::Prompt(Some("big-model".to_string())))
+        .with_llm_provider("missing");
+
+    let default_output = tokio_test::block_on(default_step.run(json!({}), 0, None));
+    let premium_output = tokio_test::block_on(premium_step.run(json!({}), 0, None));
+    let unknown_kind = run_error_kind(&unknown_step, None);
+    std::env::remove_var("LLM_PROVIDERS");
+
+    assert_eq!(default_output.unwrap().primary, json!("cheap"));
+    assert_eq!(premium_output.unwrap().primary, json!("premium"));
+    assert_eq!(unknown_kind, Some(StepErrorKind::Config));
+}
+
+#[test]
+fn test_prompt_step_renders_template() {
+    let _env = LLM_ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    // Answers with the prompt it was sent
+    let echo = spawn_http_handler(|request| {
+        let body = request.split("\r\n\r\n").nth(1).unwrap_or_default();
+        let request: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+        completion_response(sent_prompt(&request))
+    });
+    std::env::set_var("LLM_API_ENDPOINT", &echo);
+    std::env::set_var("LLM_API_KEY", "test-key");
+    std::env::remove_var("LLM_PROVIDERS");
+
+    let step = Step::new_prompt(
+        IdFields::new(),
+        "Summarize {{article.title}} in {{n}} words".to_string(),
+        None,
+        None,
+    );
+    let source = json!({"article": {"title": "Flu season"}, "n": 50});
+
+    let rendered = tokio_test::block_on(step.run(source.clone(), 0, None));
+
+    // The legacy format sends the content untouched with the input appended
+    let mut session = RuntimeSession::new(source, vec![step], None);
+    session.legacy_prompt_format = true;
+    let legacy = tokio_test::block_on(session.start());
+
+    assert_eq!(
+        rendered.unwrap().primary,
+        json!("Summarize Flu season in 50 words")
+    );
+    let legacy = legacy.unwrap();
+    let legacy = legacy.as_str().unwrap();
+    assert!(
+        legacy.starts_with("Summarize {{article.title}} in {{n}} words | Context:"),
+        "{}",
+        legacy
+    );
+    assert!(legacy.contains(r#""title":"Flu season""#), "{}", legacy);
+}
+
+#[test]
+fn test_prompt_preview_matches_the_sent_prompt() {
+    let _env = LLM_ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    // Answers with the prompt it was sent
+    let echo = spawn_http_handler(|request| {
+        let body = request.split("\r\n\r\n").nth(1).unwrap_or_default();
+        let request: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+        completion_response(sent_prompt(&request))
+    });
+    std::env::set_var("LLM_API_ENDPOINT", &echo);
+    std::env::set_var("LLM_API_KEY", "test-key");
+    std::env::remove_var("LLM_PROVIDERS");
+
+    let content = "Summarize {{article.title}} in {{n}} words".to_string();
+    let step = Step::new_prompt(IdFields::new(), content.clone(), None, None)
+        .with_system_prompt("You are a public health editor");
+    let source = json!({"article": {"title": "Flu season"}, "n": 50});
+
+    let preview = step.render_prompt_preview(&source, false).unwrap();
+    let sent = tokio_test::block_on(step.run(source.clone(), 0, None)).unwrap();
+    assert_eq!(
+        preview,
+        PromptPreview {
+            system_prompt: Some("You are a public health editor".to_string()),
+            prompt: "Summarize Flu season in 50 words".to_string(),
+        }
+    );
+    assert_eq!(sent.primary, json!(preview.prompt));
+
+    // The legacy format embeds the input as a context block, like `call_llm`
+    let legacy_preview = step.render_prompt_preview(&source, true).unwrap().prompt;
+    let legacy_sent = tokio_test::block_on(crate::call_llm(&content, source.clone(), None));
+    assert_eq!(legacy_sent.unwrap(), legacy_preview);
+    assert!(
+        legacy_preview.starts_with("Summarize {{article.title}} in {{n}} words | Context:"),
+        "{}",
+        legacy_preview
+    );
+
+    let python_step = create_test_step(StepType::Python);
+    let err = python_step
+        .render_prompt_preview(&source, false)
+        .unwrap_err();
+    assert_eq!(StepErrorKind::of(&err), Some(StepErrorKind::Config));
+}
+
+#[test]
+fn test_prompt_step_resolves_secret_references() {
+    let _env = LLM_ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    // Answers with the prompt it was sent
+    let echo = spawn_http_handler(|request| {
+        let body = request.split("\r\n\r\n").nth(1).unwrap_or_default();
+        let request: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+        completion_response(sent_prompt(&request))
+    });
+    std::env::set_var("LLM_API_ENDPOINT", &echo);
+    std::env::set_var("LLM_API_KEY", "test-key");
+    std::env::remove_var("LLM_PROVIDERS");
+    std::env::set_var("PORTICO_SECRET_TRIAGE_TOKEN", "tok-123");
+
+    let content = "Call with ${secret:TRIAGE_TOKEN} for {{case}}".to_string();
+    let step = Step::new_prompt(IdFields::new(), content.clone(), None, None);
+    let unknown = Step::new_prompt(
+        IdFields::new(),
+        "Call with ${secret:MISSING_TOKEN}".to_string(),
+        None,
+        None,
+    );
+    // Input text is rendered after resolution, so it can't pull secrets in
+    let source = json!({"case": "${secret:TRIAGE_TOKEN}"});
+
+    let output = tokio_test::block_on(step.run(source, 0, None));
+    let unknown_kind = run_error_kind(&unknown, None);
+    std::env::remove_var("PORTICO_SECRET_TRIAGE_TOKEN");
+
+    assert_eq!(
+        output.unwrap().primary,
+        json!("Call with tok-123 for ${secret:TRIAGE_TOKEN}")
+    );
+    assert_eq!(step.step_content, content);
+    assert_eq!(step.to_json()["step_content"], json!(content));
+    assert_eq!(unknown_kind, Some(StepErrorKind::Config));
+}
+
+#[test]
+fn test_llm_io_log_redacts_secrets_and_patterns() {
+    let mut secrets = SecretStore::new();
+    secrets.insert("TRIAGE_TOKEN", "tok-123");
+    let config = LlmIoLogConfig {
+        redact_patterns: vec![r"\b\d{3}-\d{2}-\d{4}\b".to_string()],
+    };
+
+    let entry = config.format_exchange(
+        "step 0",
+        Some("Token: tok-123"),
+        "Call with tok-123 for patient 123-45-6789",
+        "Called for 123-45-6789",
+        &secrets,
+    );
+    assert!(!entry.contains("tok-123"), "{}", entry);
+    assert!(!entry.contains("123-45-6789"), "{}", entry);
+    assert!(entry.contains("--- system ---\nToken: ${secret:TRIAGE_TOKEN}\n"));
+    assert!(entry.contains(&format!(
+        "--- prompt ---\nCall with ${{secret:TRIAGE_TOKEN}} for patient {}\n",
+        REDACTED_PLACEHOLDER
+    )));
+    assert!(entry.ends_with(&format!(
+        "--- response ---\nCalled for {}",
+        REDACTED_PLACEHOLDER
+    )));
+
+    // A pattern that doesn't compile hides the text rather than risking a leak
+    let broken = LlmIoLogConfig {
+        redact_patterns: vec!["(".to_string()],
+    };
+    assert_eq!(
+        broken.redact("patient 123-45-6789", &secrets),
+        REDACTED_PLACEHOLDER
+    );
+}
+
+#[test]
+fn test_prompt_step_sends_system_prompt() {
+    let _env = LLM_ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    // Answers with the request body it was sent
+    let echo = spawn_http_handler(|request| {
+        let body = request.split("\r\n\r\n").nth(1).unwrap_or_default();
+        completion_response(body)
+    });
+    std::env::set_var("LLM_API_ENDPOINT", &echo);
+    std::env::set_var("LLM_API_KEY", "test-key");
+    std::env::remove_var("LLM_PROVIDERS");
+
+    let plain = Step::new_prompt(
+        IdFields::new(),
+        "Summarize {{title}}".to_string(),
+        None,
+        None,
+    );
+    let with_system = plain
+        .clone()
+        .with_system_prompt("You are a terse clinical editor.");
+    let source = json!({"title": "Flu season"});
+
+    let sent = |step: &Step| -> serde_json::Value {
+        let output = tokio_test::block_on(step.run(source.clone(), 0, None)).unwrap();
+        serde_json::from_str(output.primary.as_str().unwrap()).unwrap()
+    };
+    let plain_request = sent(&plain);
+    let system_request = sent(&with_system);
+
+    assert_eq!(
+        plain_request["messages"],
+        json!([{"role": "user", "content": "Summarize Flu season"}])
+    );
+    assert!(plain_request.get("prompt").is_none());
+    assert_eq!(
+        system_request["messages"],
+        json!([
+            {"role": "system", "content": "You are a terse clinical editor."},
+            {"role": "user", "content": "Summarize Flu season"},
+        ])
+    );
+    assert!(system_request.get("prompt").is_none());
+}
+
+#[test]
+fn test_prompt_step_reads_chat_completion_response() {
+    let _env = LLM_ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    // A full response as OpenAI-compatible chat completion APIs send it
+    let llm = spawn_http_handler(|_| {
+        json_response(&json!({
+            "id": "chatcmpl-123",
+            "object": "chat.completion",
+            "created": 1_700_000_000,
+            "model": "meta-llama/Llama-3.3-70B-Instruct-Turbo",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "Flu cases are rising."},
+                "finish_reason": "stop"
+            }],
+            "usage": {"prompt_tokens": 12, "completion_tokens": 5, "total_tokens": 17}
+        }))
+    });
+    std::env::set_var("LLM_API_ENDPOINT", &llm);
+    std::env::set_var("LLM_API_KEY", "test-key");
+    std::env::remove_var("LLM_PROVIDERS");
+
+    let step = Step::new_prompt(IdFields::new(), "Summarize".to_string(), None, None);
+    let output = tokio_test::block_on(step.run(json!({}), 0, None)).unwrap();
+
+    assert_eq!(output.primary, json!("Flu cases are rising."));
+}
+
+#[test]
+fn test_prompt_step_uses_legacy_completions_format() {
+    let _env = LLM_ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    // Answers the way legacy completions endpoints do, with the request body it was sent
+    let echo = spawn_http_handler(|request| {
+        let body = request.split("\r\n\r\n").nth(1).unwrap_or_default();
+        json_response(&json!({"choices": [{"text": body, "index": 0}]}))
+    });
+    std::env::set_var("LLM_API_ENDPOINT", &echo);
+    std::env::set_var("LLM_API_KEY", "test-key");
+    std::env::remove_var("LLM_PROVIDERS");
+    std::env::set_var("LLM_API_REQUEST_FORMAT", "completions");
+
+    let step = Step::new_prompt(
+        IdFields::new(),
+        "Summarize {{title}}".to_string(),
+        None,
+        None,
+    )
+    .with_system_prompt("You are a terse clinical editor.");
+    let output = tokio_test::block_on(step.run(json!({"title": "Flu season"}), 0, None));
+
+    std::env::set_var("LLM_API_REQUEST_FORMAT", "transcripts");
+    let invalid_kind = run_error_kind(&step, None);
+    std::env::remove_var("LLM_API_REQUEST_FORMAT");
+
+    let sent: serde_json::Value =
+        serde_json::from_str(output.unwrap().primary.as_str().unwrap()).unwrap();
+    assert_eq!(
+        sent["prompt"],
+        json!("You are a terse clinical editor.\n\nSummarize Flu season")
+    );
+    assert!(sent.get("messages").is_none());
+    assert_eq!(invalid_kind, Some(StepErrorKind::Config));
+}
+
+#[test]
+fn test_prompt_step_model_survives_loading() {
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let model = "deepseek-ai/DeepSeek-V3";
+        assert_ne!(model, crate::JsonModeLLMs::MetaLlama33_70b.to_string());
+        let step = Step::new_prompt(
+            IdFields::new(),
+            "Summarize {{title}}".to_string(),
+            None,
+            Some(model.to_string()),
+        );
+        // Steps are saved with their agent
+        let agent = Agent::new(
+            IdFields::new(),
+            TimestampFields::new(),
+            "Prompt model agent".to_string(),
+            vec![step.clone()],
+        );
+        agent.try_db_create(&pool).await.unwrap();
+        let id = IdFields::with_values(None, step.identifiers.global_uuid.clone());
+
+        // Typed select, plain `FromRow`, and the full listing all keep the model
+        let selected = Step::try_db_select_by_id(&pool, &id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(selected.get_llm_model().as_deref(), Some(model));
+
+        let from_row: Step = sqlx::query_as("SELECT * FROM steps WHERE global_uuid = $1")
+            .bind(uuid::Uuid::parse_str(&id.global_uuid).unwrap())
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(from_row.get_llm_model().as_deref(), Some(model));
+
+        let listed = Step::try_db_select_all(&pool).await.unwrap();
+        let listed = listed
+            .iter()
+            .find(|s| s.identifiers.global_uuid == id.global_uuid)
+            .unwrap();
+        assert_eq!(listed.get_llm_model().as_deref(), Some(model));
+
+        // As do the agent's steps
+        let loaded_agent = Agent::try_db_select_by_id(
+            &pool,
+            &IdFields::with_values(None, agent.identifiers.global_uuid.clone()),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            loaded_agent.steps[0].get_llm_model().as_deref(),
+            Some(model)
+        );
+
+        selected.try_db_delete(&pool).await.unwrap();
+        loaded_agent.try_db_delete(&pool).await.unwrap();
+    });
+}
+
+#[test]
+fn test_select_steps_by_ids_in_one_query() {
+    tokio_test::block_on(async {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let steps: Vec<Step> = (0..5)
+            .map(|i| {
+                Step::new(
+                    IdFields::new(),
+                    StepType::Python,
+                    format!("result = {{'step': {}}}", i),
+                    None,
+                )
+            })
+            .collect();
+        let agent = Agent::new(
+            IdFields::new(),
+            TimestampFields::new(),
+            "Batch select agent".to_string(),
+            steps.clone(),
+        );
+        agent.try_db_create(&pool).await.unwrap();
+
+        // By UUID, to learn the ids the steps were given
+        let uuids: Vec<String> = steps
+            .iter()
+            .map(|step| step.identifiers.global_uuid.clone())
+            .collect();
+        let saved = Step::try_db_select_by_uuids(&pool, &uuids).await.unwrap();
+        assert_eq!(saved.len(), 5);
+
+        let wanted: Vec<i32> = [0, 2, 4]
+            .iter()
+            .map(|&i| saved[i].identifiers.local_id.unwrap())
+            .collect();
+        // An id with no row is simply absent
+        let mut ids = wanted.clone();
+        ids.push(i32::MAX);
+        let selected = Step::try_db_select_by_ids(&pool, &ids).await.unwrap();
+        let selected_ids: Vec<i32> = selected
+            .iter()
+            .map(|step| step.identifiers.local_id.unwrap())
+            .collect();
+        assert_eq!(selected_ids, wanted);
+        assert_eq!(selected[1].step_content, saved[2].step_content);
+
+        assert!(Step::try_db_select_by_ids(&pool, &[])
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(
+            Step::try_db_select_by_uuids(&pool, &["not-a-uuid".to_string()])
+                .await
+                .is_err()
+        );
+
+        let loaded_agent = Agent::try_db_select_by_uuids(
+            &pool,
+            std::slice::from_ref(&agent.identifiers.global_uuid),
+        )
+        .await
+        .unwrap();
+        assert_eq!(loaded_agent.len(), 1);
+        assert_eq!(loaded_agent[0].steps.len(), 5);
+        loaded_agent[0].try_db_delete(&pool).await.unwrap();
+    });
+}
+
+#[test]
+fn test_python_passthrough_keeps_arrays_and_scalars() {
+    let passthrough = Step::new(
+        IdFields::new(),
+        StepType::Python,
+        "result = source".to_string(),
+        None,
+    );
+    // Arrays and scalars arrive wrapped, so `source["data"]` always works
+    let count = Step::new(
+        IdFields::new(),
+        StepType::Python,
+        "result = {'count': len(source['data'])}".to_string(),
+        None,
+    );
+
+    let mut runtime = PythonRuntime::new("non_object_source").unwrap();
+    runtime.add_step(&passthrough).unwrap();
+    runtime.add_step(&count).unwrap();
+    let passthrough_uuid = &passthrough.identifiers.global_uuid;
+
+    for input in [
+        json!([1, 2, 3]),
+        json!("scraped text"),
+        json!(42),
+        json!(true),
+        json!({"data": [1, 2]}),
+        json!(null),
+    ] {
+        let output = runtime
+            .execute_step(passthrough_uuid, input.clone())
+            .unwrap();
+        assert_eq!(output, input);
+    }
+
+    let output = runtime
+        .execute_step(&count.identifiers.global_uuid, json!(["a", "b"]))
+        .unwrap();
+    assert_eq!(output, json!({"count": 2}));
+
+    // Through a session the list survives each step
+    let mut session = RuntimeSession::new(json!([1, 2, 3]), vec![passthrough.clone()], None);
+    tokio_test::block_on(session.unified_start(Some(&runtime))).unwrap();
+    assert_eq!(session.last_successful_result, Some(json!([1, 2, 3])));
+}
+
+#[test]
+fn test_numpy_scalars_keep_their_json_type() {
+    // Stand-ins for numpy scalars, which `json.dumps` can't serialize by itself
+    // (numpy isn't a dependency, so the test can't import it)
+    let step = Step::new(
+        IdFields::new(),
+        StepType::Python,
+        r#"class bool_:
+    __module__ = 'numpy'
+    def __init__(self, value):
+        self.value = value
+    def tolist(self):
+        return bool(self.value)
+class int64(bool_):
+    __module__ = 'numpy'
+    def tolist(self):
+        return int(self.value)
+outputs['count'] = int64(3)
+result = {'flag': bool_(1), 'total': int64(5), 'plain': True}"#
+            .to_string(),
+        None,
+    );
+    let unserializable = Step::new(
+        IdFields::new(),
+        StepType::Python,
+        "result = {'value': object()}".to_string(),
+        None,
+    );
+
+    let mut runtime = PythonRuntime::new("numpy_scalars").unwrap();
+    runtime.add_step(&step).unwrap();
+    runtime.add_step(&unserializable).unwrap();
+
+    let output = runtime
+        .execute_step_with_outputs(&step.identifiers.global_uuid, json!({}), None)
+        .unwrap();
+    assert_eq!(
+        output.primary,
+        json!({"flag": true, "total": 5, "plain": true})
+    );
+    assert!(output.primary["flag"].is_boolean());
+    assert!(output.primary["total"].is_i64());
+    assert!(output.named["count"].is_i64());
+
+    let err = runtime
+        .execute_step(&unserializable.identifiers.global_uuid, json!({}))
+        .unwrap_err();
+    assert!(err.to_string().contains("not JSON serializable"), "{}", err);
+}
+
+// Runs a Python step with `content` on {"value": 1}
+fn run_python_content(content: &str) -> serde_json::Value {
+    let step = Step::new(IdFields::new(), StepType::Python, content.to_string(), None);
+    runtime_for(&step)
+        .execute_step(&step.identifiers.global_uuid, json!({"value": 1}))
+        .unwrap()
+}
+
+#[test]
+fn test_flat_python_body_is_indented_once() {
+    let step = Step::new(
+        IdFields::new(),
+        StepType::Python,
+        "doubled = source['value'] * 2\nresult = {'doubled': doubled}".to_string(),
+        None,
+    );
+    let code = step.to_python_function();
+    assert!(
+        code.contains("\n    doubled = source['value'] * 2\n    result = {'doubled': doubled}\n")
+    );
+    assert_eq!(
+        run_python_content(&step.step_content),
+        json!({"doubled": 2})
+    );
+}
+
+#[test]
+fn test_python_body_keeps_block_indentation() {
+    let content = "if source['value'] > 0:\n    result = {'sign': 'positive'}\nelse:\n\n    result = {'sign': 'other'}";
+    assert_eq!(run_python_content(content), json!({"sign": "positive"}));
+}
+
+#[test]
+fn test_already_indented_python_body_is_dedented() {
+    // As pasted from inside a function or block
+    let content = "    def double(x):\n        return x * 2\n\n    result = {'doubled': double(source['value'])}\n";
+    let step = Step::new(IdFields::new(), StepType::Python, content.to_string(), None);
+    assert!(step
+        .to_python_function()
+        .contains("\n    def double(x):\n        return x * 2\n\n    result = "));
+    assert_eq!(run_python_content(content), json!({"doubled": 2}));
+
+    // Tab-indented content is dedented the same way
+    let content = "\tif source['value']:\n\t\tresult = {'tabs': True}";
+    assert_eq!(run_python_content(content), json!({"tabs": true}));
+}
+
+#[test]
+fn test_python_list_result_passes_through_unless_wrapped() {
+    let step = Step::new(
+        IdFields::new(),
+        StepType::Python,
+        "result = [source['value'], 2, 3]".to_string(),
+        None,
+    );
+    let runtime = runtime_for(&step);
+    let run = |step: &Step| {
+        tokio_test::block_on(step.run(json!({"value": 1}), 1, Some(&runtime)))
+            .unwrap()
+            .primary
+    };
+    assert!(!step.wrap_output);
+    assert_eq!(run(&step), json!([1, 2, 3]));
+
+    let wrapped = step.clone().with_wrapped_output();
+    assert_eq!(run(&wrapped), json!({STEP_OUTPUT_DATA_KEY: [1, 2, 3]}));
+
+    // Dicts are never wrapped
+    let mut dict_step = wrapped.clone();
+    dict_step.step_content = "result = source".to_string();
+    let runtime = runtime_for(&dict_step);
+    let output = tokio_test::block_on(dict_step.run(json!({"value": 1}), 1, Some(&runtime)))
+        .unwrap()
+        .primary;
+    assert_eq!(output, json!({"value": 1}));
+}
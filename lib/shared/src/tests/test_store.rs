@@ -0,0 +1,239 @@
+use crate::{
+    models::agents::AgentState, models::signals::SignalType, models::steps::StepType, Agent,
+    IdFields, MemoryStore, RunningStatus, RuntimeSession, Signal, Step, Store, TimestampFields,
+};
+use serde_json::json;
+
+fn create_agent(description: &str) -> Agent {
+    let step = Step::new(
+        IdFields::new(),
+        StepType::Python,
+        "result = source".to_string(),
+        None,
+    );
+    Agent::new(
+        IdFields::new(),
+        TimestampFields::new(),
+        description.to_string(),
+        vec![step],
+    )
+}
+
+#[test]
+fn test_memory_store_agent_crud() {
+    // Code written against `&dyn Store` doesn't need Postgres
+    let memory = MemoryStore::new();
+    let store: &dyn Store = &memory;
+
+    let first = create_agent("First");
+    let second = create_agent("Second");
+    tokio_test::block_on(store.create_agent(&first)).unwrap();
+    tokio_test::block_on(store.create_agent(&second)).unwrap();
+
+    // Creating an existing agent is skipped, as `Agent::try_db_create` does
+    tokio_test::block_on(store.create_agent(&first)).unwrap();
+
+    // Local ids are assigned on create
+    let all = tokio_test::block_on(store.select_all_agents()).unwrap();
+    assert_eq!(all.len(), 2);
+    assert_eq!(all[0].identifiers.local_id, Some(1));
+    assert_eq!(all[1].identifiers.local_id, Some(2));
+    assert_eq!(all[0].steps.len(), 1);
+
+    // The agents' steps are stored alongside them
+    assert_eq!(
+        tokio_test::block_on(store.select_all_steps())
+            .unwrap()
+            .len(),
+        2
+    );
+
+    let by_uuid = IdFields {
+        local_id: None,
+        global_uuid: second.identifiers.global_uuid.clone(),
+    };
+    let loaded = tokio_test::block_on(store.select_agent_by_id(&by_uuid))
+        .unwrap()
+        .expect("Agent should be found by UUID");
+    assert_eq!(loaded.description, "Second");
+    assert_eq!(loaded.identifiers.local_id, Some(2));
+
+    // Updates replace the stored copy but keep its local id
+    let mut updated = first.clone();
+    updated.description = "First (renamed)".to_string();
+    updated.set_state(AgentState::Stable);
+    tokio_test::block_on(store.update_agent(&updated)).unwrap();
+
+    let by_local_id = IdFields {
+        local_id: Some(1),
+        global_uuid: String::new(),
+    };
+    let loaded = tokio_test::block_on(store.select_agent_by_id(&by_local_id))
+        .unwrap()
+        .unwrap();
+    assert_eq!(loaded.description, "First (renamed)");
+    assert_eq!(loaded.state(), AgentState::Stable);
+
+    tokio_test::block_on(store.delete_agent(&loaded)).unwrap();
+    assert!(tokio_test::block_on(store.select_agent_by_id(&by_local_id))
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        tokio_test::block_on(store.select_all_agents())
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(
+        tokio_test::block_on(store.select_all_steps())
+            .unwrap()
+            .len(),
+        1
+    );
+
+    // Updating or deleting an agent that doesn't exist changes nothing
+    let missing = create_agent("Missing");
+    tokio_test::block_on(store.update_agent(&missing)).unwrap();
+    tokio_test::block_on(store.delete_agent(&missing)).unwrap();
+    assert_eq!(
+        tokio_test::block_on(store.select_all_agents())
+            .unwrap()
+            .len(),
+        1
+    );
+
+    // Deleting by id takes its steps along too
+    tokio_test::block_on(store.delete_agent_by_id(&by_uuid)).unwrap();
+    tokio_test::block_on(store.delete_agent_by_id(&by_uuid)).unwrap();
+    assert!(tokio_test::block_on(store.select_all_agents())
+        .unwrap()
+        .is_empty());
+    assert!(tokio_test::block_on(store.select_all_steps())
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_memory_store_step_crud() {
+    let store = MemoryStore::new();
+
+    let step = Step::new(
+        IdFields::new(),
+        StepType::Python,
+        "result = source".to_string(),
+        Some("Passthrough".to_string()),
+    );
+    tokio_test::block_on(store.create_step(&step)).unwrap();
+
+    // Steps have no existence check, so a repeated UUID hits the unique constraint
+    assert!(tokio_test::block_on(store.create_step(&step)).is_err());
+
+    let mut updated = step.clone();
+    updated.step_content = "result = {'value': 1}".to_string();
+    tokio_test::block_on(store.update_step(&updated)).unwrap();
+
+    let steps = tokio_test::block_on(store.select_all_steps()).unwrap();
+    assert_eq!(steps.len(), 1);
+    assert_eq!(steps[0].step_content, "result = {'value': 1}");
+    assert_eq!(steps[0].identifiers.local_id, Some(1));
+
+    tokio_test::block_on(store.delete_step(&step)).unwrap();
+    assert!(tokio_test::block_on(store.select_all_steps())
+        .unwrap()
+        .is_empty());
+
+    // Unlike the other models, deleting a missing step is an error
+    assert!(tokio_test::block_on(store.delete_step(&step)).is_err());
+}
+
+#[test]
+fn test_memory_store_signal_crud() {
+    let store = MemoryStore::new();
+
+    let signal = Signal::new(
+        IdFields::new(),
+        "user-1".to_string(),
+        Some(create_agent("Receiver")),
+        SignalType::Fyi,
+        Some(json!({"value": 5})),
+    );
+    tokio_test::block_on(store.create_signal(&signal)).unwrap();
+    assert!(tokio_test::block_on(store.create_signal(&signal)).is_err());
+
+    // Signals are updated and deleted by local id, so the caller's copy needs one
+    assert!(tokio_test::block_on(store.update_signal(&signal)).is_err());
+    assert!(tokio_test::block_on(store.delete_signal(&signal)).is_err());
+
+    let mut loaded = tokio_test::block_on(store.select_signal_by_id(&IdFields {
+        local_id: None,
+        global_uuid: signal.identifiers.global_uuid.clone(),
+    }))
+    .unwrap()
+    .expect("Signal should be found by UUID");
+    assert_eq!(loaded.identifiers.local_id, Some(1));
+
+    loaded.result_data = Some(json!({"value": 6}));
+    tokio_test::block_on(store.update_signal(&loaded)).unwrap();
+    let signals = tokio_test::block_on(store.select_all_signals()).unwrap();
+    assert_eq!(signals.len(), 1);
+    assert_eq!(signals[0].result_data, Some(json!({"value": 6})));
+
+    tokio_test::block_on(store.delete_signal(&loaded)).unwrap();
+    assert!(tokio_test::block_on(store.select_all_signals())
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_memory_store_runtime_session_crud() {
+    let store = MemoryStore::new();
+
+    let first = RuntimeSession::new(json!({"value": 1}), vec![], None);
+    let second = RuntimeSession::new(json!({"value": 2}), vec![], None);
+    tokio_test::block_on(store.create_runtime_session(&first)).unwrap();
+    tokio_test::block_on(store.create_runtime_session(&second)).unwrap();
+    tokio_test::block_on(store.create_runtime_session(&first)).unwrap();
+
+    let sessions = tokio_test::block_on(store.select_all_runtime_sessions()).unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(sessions[0].source_data, json!({"value": 1}));
+    assert_eq!(sessions[1].identifiers.local_id, Some(2));
+
+    let mut updated = first.clone();
+    updated.status = RunningStatus::Completed;
+    tokio_test::block_on(store.update_runtime_session(&updated)).unwrap();
+    let loaded = tokio_test::block_on(store.select_runtime_session_by_id(&IdFields {
+        local_id: Some(1),
+        global_uuid: String::new(),
+    }))
+    .unwrap()
+    .unwrap();
+    assert_eq!(loaded.status, RunningStatus::Completed);
+
+    tokio_test::block_on(store.delete_runtime_session(&first)).unwrap();
+    tokio_test::block_on(store.delete_runtime_session(&first)).unwrap();
+    let sessions = tokio_test::block_on(store.select_all_runtime_sessions()).unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(
+        sessions[0].identifiers.global_uuid,
+        second.identifiers.global_uuid
+    );
+}
+
+#[test]
+fn test_memory_store_keeps_session_results() {
+    let store = MemoryStore::new();
+
+    let mut session = RuntimeSession::new(json!({"value": 5}), vec![], Some(1));
+    tokio_test::block_on(session.start()).unwrap();
+    tokio_test::block_on(store.create_runtime_session(&session)).unwrap();
+
+    let sessions = tokio_test::block_on(store.select_all_runtime_sessions()).unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].status, session.status);
+    assert_eq!(
+        sessions[0].last_successful_result,
+        Some(json!({"value": 5}))
+    );
+    assert_eq!(sessions[0].requested_by_agent_id, Some(1));
+}
@@ -0,0 +1,52 @@
+use crate::render_prompt;
+use serde_json::json;
+
+#[test]
+fn test_render_prompt_substitutes_variables() {
+    let data = json!({"title": "Flu season", "n": 50, "urgent": true, "tags": ["a", "b"]});
+
+    assert_eq!(
+        render_prompt("Summarize {{title}} in {{ n }} words", &data),
+        "Summarize Flu season in 50 words"
+    );
+    // Non-string values are inserted as JSON, `{{.}}` is the whole input
+    assert_eq!(
+        render_prompt("urgent={{urgent}} tags={{{tags}}}", &data),
+        r#"urgent=true tags=["a","b"]"#
+    );
+    assert_eq!(
+        render_prompt("{{! not sent }}Data: {{.}}", &json!({"a": 1})),
+        r#"Data: {"a":1}"#
+    );
+}
+
+#[test]
+fn test_render_prompt_missing_variables_are_empty() {
+    let data = json!({"title": "Flu season", "author": null});
+
+    assert_eq!(
+        render_prompt("[{{missing}}] [{{author}}] [{{title.nested}}]", &data),
+        "[] [] []"
+    );
+    // Unclosed tags aren't variables
+    assert_eq!(
+        render_prompt("Reply with {{title}} and {{ unclosed", &data),
+        "Reply with Flu season and {{ unclosed"
+    );
+}
+
+#[test]
+fn test_render_prompt_nested_fields() {
+    let data = json!({
+        "article": {"title": "Flu season", "authors": [{"name": "Ada"}, {"name": "Grace"}]},
+        "meta key": "spaced",
+    });
+
+    assert_eq!(
+        render_prompt(
+            "{{article.title}} by {{article.authors[1].name}} ({{$['meta key']}})",
+            &data
+        ),
+        "Flu season by Grace (spaced)"
+    );
+}
@@ -0,0 +1,821 @@
+use super::test_steps::spawn_http_handler;
+use crate::{
+    check_url, fetch_sitemap,
+    models::steps::{StepErrorKind, StepType},
+    scrape_webpage, scrape_webpage_with_config, IdFields, ScrapeError, ScrapeOutput, ScraperConfig,
+    Step, UserAgentRotation, WebScrapeTarget,
+};
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
+
+fn check(config: &ScraperConfig, url: &str) -> Result<(), String> {
+    config
+        .check_url(&Url::parse(url).unwrap())
+        .map_err(|e| e.to_string())
+}
+
+#[test]
+fn test_ssrf_default_blocks_internal_addresses() {
+    let config = ScraperConfig::default();
+
+    for url in [
+        "http://169.254.169.254/latest/meta-data/",
+        "http://127.0.0.1:8080/",
+        "http://10.0.0.5/",
+        "http://192.168.1.1/admin",
+        "http://100.64.0.1/",
+        "http://[::1]/",
+        "http://[fe80::1]/",
+        "http://[::ffff:169.254.169.254]/",
+        "http://localhost/",
+    ] {
+        let err = check(&config, url).expect_err(url);
+        assert!(err.contains("SSRF protection"), "{}: {}", url, err);
+    }
+
+    // Public hosts are allowed
+    assert!(check(&config, "https://example.com/page").is_ok());
+    assert!(check(&config, "http://93.184.215.14/").is_ok());
+}
+
+#[test]
+fn test_ssrf_allow_and_deny_lists() {
+    let config = ScraperConfig {
+        blocked_hosts: vec![
+            "*.internal.example.com".to_string(),
+            "203.0.113.0/24".to_string(),
+        ],
+        ..ScraperConfig::default()
+    };
+    assert!(check(&config, "https://api.internal.example.com/").is_err());
+    assert!(check(&config, "http://203.0.113.7/").is_err());
+    assert!(check(&config, "https://example.com/").is_ok());
+
+    // An allow-list restricts fetching to its entries, which may be internal
+    let config = ScraperConfig {
+        allowed_hosts: vec!["docs.example.com".to_string(), "10.1.0.0/16".to_string()],
+        ..ScraperConfig::default()
+    };
+    assert!(check(&config, "https://docs.example.com/").is_ok());
+    assert!(check(&config, "http://10.1.2.3/").is_ok());
+    assert!(check(&config, "http://10.2.0.1/").is_err());
+    assert!(check(&config, "http://93.184.215.14/").is_err());
+}
+
+#[test]
+fn test_ssrf_blocks_redirect_to_private_ip() {
+    let base = spawn_http_handler(|_| {
+        "HTTP/1.1 302 Found\r\nLocation: http://169.254.169.254/latest/meta-data/\r\nContent-Length: 0\r\n\r\n"
+            .to_string()
+    });
+
+    // The stub itself runs on loopback, so it has to be allowed explicitly
+    let config = ScraperConfig {
+        respect_robots_txt: false,
+        request_delay_ms: 0,
+        allowed_hosts: vec!["127.0.0.1".to_string()],
+        ..ScraperConfig::default()
+    };
+    let err = tokio_test::block_on(scrape_webpage_with_config(&base, &config))
+        .expect_err("Redirect to the metadata address should be blocked");
+    assert!(err.to_string().contains("169.254.169.254"), "{}", err);
+    assert!(err.to_string().contains("SSRF protection"), "{}", err);
+    assert_eq!(err.kind(), StepErrorKind::Config);
+
+    // Hostnames are checked against the addresses they resolve to
+    let config = ScraperConfig {
+        allowed_hosts: vec!["localhost".to_string()],
+        blocked_hosts: vec!["127.0.0.0/8".to_string(), "::1/128".to_string()],
+        ..config
+    };
+    let by_name = base.replace("127.0.0.1", "localhost");
+    let err = tokio_test::block_on(scrape_webpage_with_config(&by_name, &config)).unwrap_err();
+    assert!(err.to_string().contains("deny-list"), "{}", err);
+
+    // With the default config the loopback stub is refused before any request
+    let err = tokio_test::block_on(scrape_webpage(&base)).unwrap_err();
+    assert!(err.to_string().contains("SSRF protection"), "{}", err);
+}
+
+#[test]
+fn test_check_url_follows_redirects_without_fetching_the_body() {
+    let base = spawn_http_handler(|request| {
+        let mut request_line = request.split_whitespace();
+        let method = request_line.next().unwrap_or_default();
+        match (method, request_line.next().unwrap_or("/")) {
+            (_, "/old") => {
+                "HTTP/1.1 301 Moved Permanently\r\nLocation: /new\r\nContent-Length: 0\r\n\r\n"
+                    .to_string()
+            }
+            ("HEAD", "/new") => {
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: 1234\r\n\r\n"
+                    .to_string()
+            }
+            // Servers that refuse HEAD get a GET for the first byte
+            ("HEAD", _) => "HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\n\r\n".to_string(),
+            ("GET", "/no-head") if request.to_lowercase().contains("range: bytes=0-0") => {
+                "HTTP/1.1 206 Partial Content\r\nContent-Type: application/pdf\r\nContent-Range: bytes 0-0/5000\r\nContent-Length: 1\r\n\r\n%"
+                    .to_string()
+            }
+            _ => "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n".to_string(),
+        }
+    });
+
+    let config = ScraperConfig {
+        request_delay_ms: 0,
+        allowed_hosts: vec!["127.0.0.1".to_string()],
+        ..ScraperConfig::default()
+    };
+    let checked = tokio_test::block_on(check_url(&format!("{}/old", base), &config)).unwrap();
+    assert_eq!(checked.status, 200);
+    assert_eq!(checked.final_url, format!("{}/new", base));
+    assert_eq!(
+        checked.content_type.as_deref(),
+        Some("text/html; charset=utf-8")
+    );
+    assert_eq!(checked.content_length, Some(1234));
+
+    let checked = tokio_test::block_on(check_url(&format!("{}/no-head", base), &config)).unwrap();
+    assert_eq!(checked.status, 206);
+    assert_eq!(checked.content_type.as_deref(), Some("application/pdf"));
+    assert_eq!(checked.content_length, Some(5000));
+
+    // The SSRF rules apply as when scraping
+    let err = tokio_test::block_on(check_url(&base, &ScraperConfig::default())).unwrap_err();
+    assert!(matches!(err, ScrapeError::Blocked(_)), "{}", err);
+}
+
+fn xml_response(body: &str) -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/xml\r\nContent-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
+
+#[test]
+fn test_fetch_sitemap_follows_robots_and_nested_indexes() {
+    let base = spawn_http_handler(|request| {
+        let path = request.split_whitespace().nth(1).unwrap_or("/");
+        let host = request
+            .lines()
+            .find_map(|line| line.strip_prefix("host: ").or(line.strip_prefix("Host: ")))
+            .unwrap_or_default()
+            .trim();
+        let root = format!("http://{}", host);
+
+        match path {
+            "/robots.txt" => {
+                let body = format!(
+                    "User-agent: *\nDisallow: /private\nSitemap: {}/sitemaps/index.xml\n",
+                    root
+                );
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            }
+            "/sitemaps/index.xml" => xml_response(&format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <sitemap><loc>{root}/sitemaps/pages.xml</loc></sitemap>
+  <sitemap><loc>{root}/sitemaps/nested-index.xml</loc></sitemap>
+</sitemapindex>"#
+            )),
+            "/sitemaps/nested-index.xml" => xml_response(&format!(
+                r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <sitemap><loc>{root}/sitemaps/blog.xml</loc></sitemap>
+  <sitemap><loc>{root}/sitemaps/index.xml</loc></sitemap>
+</sitemapindex>"#
+            )),
+            "/sitemaps/pages.xml" => xml_response(
+                r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <url><loc>https://example.com/</loc><lastmod>2024-01-01</lastmod></url>
+  <url><loc> https://example.com/about </loc></url>
+  <url><loc>https://example.com/search?q=a&amp;page=2</loc></url>
+</urlset>"#,
+            ),
+            "/sitemaps/blog.xml" => xml_response(
+                r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <url><loc><![CDATA[https://example.com/blog/first]]></loc></url>
+  <url><loc>https://example.com/about</loc></url>
+</urlset>"#,
+            ),
+            _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string(),
+        }
+    });
+
+    let config = ScraperConfig {
+        request_delay_ms: 0,
+        allowed_hosts: vec!["127.0.0.1".to_string()],
+        ..ScraperConfig::default()
+    };
+    let urls = tokio_test::block_on(fetch_sitemap(&base, &config)).unwrap();
+    assert_eq!(
+        urls,
+        vec![
+            "https://example.com/",
+            "https://example.com/about",
+            "https://example.com/search?q=a&page=2",
+            "https://example.com/blog/first",
+        ]
+    );
+}
+
+#[test]
+fn test_fetch_sitemap_rejects_non_sitemap_xml() {
+    let base = spawn_http_handler(|request| {
+        if request.starts_with("GET /sitemap.xml") {
+            xml_response("<rss><channel></channel></rss>")
+        } else {
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string()
+        }
+    });
+
+    // Without a robots.txt the default location is used
+    let config = ScraperConfig {
+        request_delay_ms: 0,
+        allowed_hosts: vec!["127.0.0.1".to_string()],
+        ..ScraperConfig::default()
+    };
+    let err = tokio_test::block_on(fetch_sitemap(&base, &config)).unwrap_err();
+    assert!(err.to_string().contains("/sitemap.xml"), "{}", err);
+    assert!(matches!(err, ScrapeError::Parse(_)), "{:?}", err);
+    assert_eq!(err.kind(), StepErrorKind::Validation);
+}
+
+fn typed_response(content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n{}",
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+fn local_config() -> ScraperConfig {
+    ScraperConfig {
+        respect_robots_txt: false,
+        request_delay_ms: 0,
+        allowed_hosts: vec!["127.0.0.1".to_string()],
+        ..ScraperConfig::default()
+    }
+}
+
+#[test]
+fn test_scrape_xhtml_parses_as_html() {
+    let base = spawn_http_handler(|_| {
+        typed_response(
+            "application/xhtml+xml; charset=utf-8",
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml">
+  <head><title>Clinic hours</title></head>
+  <body><main><p>The clinic is open from nine until five on weekdays.</p></main></body>
+</html>"#,
+        )
+    });
+
+    let page = tokio_test::block_on(scrape_webpage_with_config(&base, &local_config())).unwrap();
+    assert_eq!(page["title"], "Clinic hours");
+    assert_eq!(page["content_type"], "application/xhtml+xml");
+    assert_eq!(
+        page["content"][0]["text"],
+        "The clinic is open from nine until five on weekdays."
+    );
+}
+
+#[test]
+fn test_scrape_preserves_code_blocks() {
+    let base = spawn_http_handler(|_| {
+        typed_response(
+            "text/html",
+            r#"<html><body><main>
+<p>Call   <code>triage(patient)</code>   before   the intake step.</p>
+<pre><code class="language-python">
+def triage(patient):
+    if patient.urgent:
+        return "now"
+
+    return "later"
+</code></pre>
+</main></body></html>"#,
+        )
+    });
+
+    let page = tokio_test::block_on(scrape_webpage_with_config(&base, &local_config())).unwrap();
+    assert_eq!(
+        page["content"][0]["text"],
+        "Call `triage(patient)` before the intake step."
+    );
+    assert_eq!(page["content"][1]["type"], "code");
+    assert_eq!(page["content"][1]["language"], "python");
+    assert_eq!(
+        page["content"][1]["text"],
+        "def triage(patient):\n    if patient.urgent:\n        return \"now\"\n\n    return \"later\""
+    );
+
+    // Turned off, code is neither kept as a block nor marked inline
+    let config = ScraperConfig {
+        preserve_code: false,
+        ..local_config()
+    };
+    let page = tokio_test::block_on(scrape_webpage_with_config(&base, &config)).unwrap();
+    let content = page["content"].as_array().unwrap();
+    assert_eq!(content.len(), 1);
+    assert_eq!(
+        content[0]["text"],
+        "Call triage(patient) before the intake step."
+    );
+}
+
+#[test]
+fn test_scrape_text_output_is_one_string() {
+    let base = spawn_http_handler(|_| {
+        typed_response(
+            "text/html",
+            r#"<html><body><main>
+<h1>Discharge   summary</h1>
+<p>The patient was admitted overnight for observation.</p>
+<p>Vitals stayed within normal ranges throughout the stay.</p>
+</main></body></html>"#,
+        )
+    });
+
+    let config = ScraperConfig {
+        output: ScrapeOutput::Text,
+        ..local_config()
+    };
+    let page = tokio_test::block_on(scrape_webpage_with_config(&base, &config)).unwrap();
+    assert_eq!(
+        page["content"],
+        "Discharge summary\n\n\
+         The patient was admitted overnight for observation.\n\n\
+         Vitals stayed within normal ranges throughout the stay."
+    );
+
+    let config = ScraperConfig {
+        output: ScrapeOutput::Markdown,
+        ..local_config()
+    };
+    let page = tokio_test::block_on(scrape_webpage_with_config(&base, &config)).unwrap();
+    assert!(page["content"]
+        .as_str()
+        .unwrap()
+        .starts_with("# Discharge summary\n\nThe patient"));
+
+    // WebScrape steps take the option alongside the URL
+    let target =
+        WebScrapeTarget::parse(r#"{"url": "https://example.com", "output": "text"}"#).unwrap();
+    assert_eq!(target.url, "https://example.com");
+    assert_eq!(target.output, ScrapeOutput::Text);
+    let target = WebScrapeTarget::parse(" https://example.com ").unwrap();
+    assert_eq!(target.url, "https://example.com");
+    assert_eq!(target.output, ScrapeOutput::Structured);
+    assert!(WebScrapeTarget::parse(r#"{"url": "https://example.com", "output": "pdf"}"#).is_err());
+}
+
+#[test]
+fn test_scrape_content_hash_follows_text_only() {
+    let base = spawn_http_handler(|request| {
+        let page = match request.split_whitespace().nth(1).unwrap_or_default() {
+            "/restyled" => {
+                r#"<html><body><main class="v2">
+<p>Visiting   hours are <b>ten</b> until four.</p>
+<p>Masks are required.</p>
+</main></body></html>"#
+            }
+            "/changed" => {
+                r#"<html><body><main>
+<p>Visiting hours are ten until six.</p><p>Masks are required.</p>
+</main></body></html>"#
+            }
+            _ => {
+                r#"<html><body><main>
+<p>Visiting hours are ten until four.</p><p>Masks are required.</p>
+</main></body></html>"#
+            }
+        };
+        typed_response("text/html", page)
+    });
+
+    let hash = |path: &str, output: ScrapeOutput| {
+        let config = ScraperConfig {
+            output,
+            ..local_config()
+        };
+        let url = format!("{}{}", base, path);
+        let page = tokio_test::block_on(scrape_webpage_with_config(&url, &config)).unwrap();
+        page["content_hash"].as_str().unwrap().to_string()
+    };
+    let original = hash("/", ScrapeOutput::Structured);
+
+    assert_eq!(original.len(), 64);
+    assert_eq!(hash("/", ScrapeOutput::Structured), original);
+    assert_eq!(hash("/", ScrapeOutput::Markdown), original);
+    assert_eq!(hash("/restyled", ScrapeOutput::Structured), original);
+    assert_ne!(hash("/changed", ScrapeOutput::Structured), original);
+}
+
+#[test]
+fn test_scrape_feeds_return_items() {
+    let base = spawn_http_handler(|request| {
+        if request.starts_with("GET /atom") {
+            typed_response(
+                "application/atom+xml",
+                r#"<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Updates</title>
+  <link href="https://example.com/"/>
+  <entry>
+    <title type="html">Fish &amp; chips</title>
+    <link rel="edit" href="https://example.com/edit/1"/>
+    <link rel="alternate" href="https://example.com/posts/1"/>
+  </entry>
+</feed>"#,
+            )
+        } else {
+            typed_response(
+                "application/rss+xml; charset=utf-8",
+                r#"<?xml version="1.0"?>
+<rss version="2.0">
+  <channel>
+    <title>News</title>
+    <link>https://example.com/</link>
+    <item><title>First post</title><link>https://example.com/1</link></item>
+    <item><title><![CDATA[Second <b>post</b>]]></title><link> https://example.com/2 </link></item>
+  </channel>
+</rss>"#,
+            )
+        }
+    });
+
+    let config = ScraperConfig {
+        allowed_content_types: vec![
+            "application/rss+xml".to_string(),
+            "application/atom+xml".to_string(),
+        ],
+        ..local_config()
+    };
+
+    let rss = tokio_test::block_on(scrape_webpage_with_config(
+        &format!("{}/rss", base),
+        &config,
+    ))
+    .unwrap();
+    assert_eq!(rss["title"], "News");
+    assert_eq!(
+        rss["items"],
+        serde_json::json!([
+            {"title": "First post", "link": "https://example.com/1"},
+            {"title": "Second <b>post</b>", "link": "https://example.com/2"},
+        ])
+    );
+
+    let atom = tokio_test::block_on(scrape_webpage_with_config(
+        &format!("{}/atom", base),
+        &config,
+    ))
+    .unwrap();
+    assert_eq!(atom["title"], "Updates");
+    assert_eq!(
+        atom["items"],
+        serde_json::json!([{"title": "Fish & chips", "link": "https://example.com/posts/1"}])
+    );
+}
+
+#[test]
+fn test_scrape_rejects_disallowed_content_type() {
+    let base = spawn_http_handler(|_| typed_response("text/plain", "Just some text."));
+
+    let err = tokio_test::block_on(scrape_webpage_with_config(&base, &local_config()))
+        .expect_err("text/plain isn't allowed by default");
+    assert_eq!(err.kind(), StepErrorKind::Validation);
+    assert!(err.to_string().contains("text/plain"), "{}", err);
+
+    // Once allowed, plain text comes back as paragraphs
+    let config = ScraperConfig {
+        allowed_content_types: vec!["text/plain".to_string()],
+        ..local_config()
+    };
+    let page = tokio_test::block_on(scrape_webpage_with_config(&base, &config)).unwrap();
+    assert_eq!(page["content"][0]["text"], "Just some text.");
+}
+
+#[test]
+fn test_robots_txt_is_cached_per_host() {
+    let robots_fetches = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&robots_fetches);
+    let base = spawn_http_handler(move |request| {
+        if request.starts_with("GET /robots.txt") {
+            counter.fetch_add(1, Ordering::SeqCst);
+            typed_response("text/plain", "User-agent: *\nDisallow: /private\n")
+        } else {
+            typed_response(
+                "text/html",
+                "<html><body><p>Opening hours are listed on this page.</p></body></html>",
+            )
+        }
+    });
+
+    let config = ScraperConfig {
+        respect_robots_txt: true,
+        ..local_config()
+    };
+    for path in ["/first", "/second"] {
+        let url = format!("{}{}", base, path);
+        tokio_test::block_on(scrape_webpage_with_config(&url, &config)).unwrap();
+    }
+    let err = tokio_test::block_on(scrape_webpage_with_config(
+        &format!("{}/private", base),
+        &config,
+    ))
+    .unwrap_err();
+    assert!(err.to_string().contains("robots.txt"), "{}", err);
+    assert_eq!(robots_fetches.load(Ordering::SeqCst), 1);
+
+    // Expired rules are fetched again
+    let config = ScraperConfig {
+        robots_cache_ttl: Duration::ZERO,
+        ..config
+    };
+    tokio_test::block_on(scrape_webpage_with_config(
+        &format!("{}/first", base),
+        &config,
+    ))
+    .unwrap();
+    assert_eq!(robots_fetches.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_scrape_errors_name_the_failure() {
+    let base = spawn_http_handler(|request| {
+        if request.starts_with("GET /robots.txt") {
+            typed_response("text/plain", "User-agent: *\nDisallow: /private\n")
+        } else if request.starts_with("GET /unavailable") {
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n".to_string()
+        } else if request.starts_with("GET /pdf") {
+            typed_response("application/pdf", "%PDF-1.7")
+        } else if request.starts_with("GET /feed") {
+            typed_response(
+                "application/rss+xml",
+                "<html><body>Not a feed</body></html>",
+            )
+        } else {
+            typed_response("text/html", &format!("<p>{}</p>", "a".repeat(200)))
+        }
+    });
+    let scrape = |path: &str, config: &ScraperConfig| {
+        tokio_test::block_on(scrape_webpage_with_config(
+            &format!("{}{}", base, path),
+            config,
+        ))
+        .unwrap_err()
+    };
+
+    let err = scrape("/unavailable", &local_config());
+    assert!(matches!(err, ScrapeError::HttpStatus(503)), "{:?}", err);
+    assert!(err.is_retryable());
+
+    let robots = ScraperConfig {
+        respect_robots_txt: true,
+        ..local_config()
+    };
+    let err = scrape("/private", &robots);
+    assert!(matches!(err, ScrapeError::RobotsDisallowed(_)), "{:?}", err);
+    assert!(!err.is_retryable());
+
+    let err = scrape("/pdf", &local_config());
+    assert!(
+        matches!(err, ScrapeError::UnsupportedContentType(_)),
+        "{:?}",
+        err
+    );
+
+    let small = ScraperConfig {
+        max_content_length: 100,
+        ..local_config()
+    };
+    let err = scrape("/page", &small);
+    assert!(
+        matches!(err, ScrapeError::ContentTooLarge { max: 100, .. }),
+        "{:?}",
+        err
+    );
+
+    let feeds = ScraperConfig {
+        allowed_content_types: vec!["application/rss+xml".to_string()],
+        ..local_config()
+    };
+    let err = scrape("/feed", &feeds);
+    assert!(matches!(err, ScrapeError::Parse(_)), "{:?}", err);
+
+    let err = tokio_test::block_on(scrape_webpage("")).unwrap_err();
+    assert!(matches!(err, ScrapeError::InvalidUrl(_)), "{:?}", err);
+
+    let err = tokio_test::block_on(scrape_webpage(&base)).unwrap_err();
+    assert!(matches!(err, ScrapeError::Blocked(_)), "{:?}", err);
+
+    // Nothing listens on a port that was just released
+    let closed = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    };
+    let err =
+        tokio_test::block_on(scrape_webpage_with_config(&closed, &local_config())).unwrap_err();
+    assert!(matches!(err, ScrapeError::Network { .. }), "{:?}", err);
+    assert!(err.is_retryable());
+}
+
+#[test]
+fn test_webscrape_step_keeps_scrape_error() {
+    // The default config refuses the loopback address
+    let step = Step::new(
+        IdFields::new(),
+        StepType::WebScrape,
+        "http://127.0.0.1:9/".to_string(),
+        None,
+    );
+    let err = tokio_test::block_on(step.run(serde_json::json!({}), 0, None)).unwrap_err();
+
+    assert_eq!(StepErrorKind::of(&err), Some(StepErrorKind::Config));
+    assert!(
+        matches!(ScrapeError::of(&err), Some(ScrapeError::Blocked(_))),
+        "{:?}",
+        err
+    );
+}
+
+/// The value of `name` in a raw HTTP request
+fn request_header(request: &str, name: &str) -> Option<String> {
+    request.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.eq_ignore_ascii_case(name)
+            .then(|| value.trim().to_string())
+    })
+}
+
+#[test]
+fn test_user_agents_rotate_between_scrapes() {
+    // (path, user agent, referer) of every request
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let seen = requests.clone();
+    let base = spawn_http_handler(move |request| {
+        let path = request
+            .split_whitespace()
+            .nth(1)
+            .unwrap_or_default()
+            .to_string();
+        seen.lock().unwrap().push((
+            path.clone(),
+            request_header(request, "user-agent"),
+            request_header(request, "referer"),
+        ));
+        if path == "/robots.txt" {
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string()
+        } else {
+            typed_response(
+                "text/html",
+                "<p>Visiting hours run from noon until eight.</p>",
+            )
+        }
+    });
+
+    let config = ScraperConfig {
+        respect_robots_txt: true,
+        user_agents: vec!["PorticoBot/A".to_string(), "PorticoBot/B".to_string()],
+        user_agent_rotation: UserAgentRotation::RoundRobin,
+        referer: Some("https://portico.example/".to_string()),
+        ..local_config()
+    };
+    for _ in 0..2 {
+        tokio_test::block_on(scrape_webpage_with_config(&base, &config)).unwrap();
+    }
+
+    let requests = requests.lock().unwrap();
+    let page_agents: Vec<_> = requests
+        .iter()
+        .filter(|(path, _, _)| path != "/robots.txt")
+        .map(|(_, agent, _)| agent.clone().unwrap())
+        .collect();
+    assert_eq!(page_agents.len(), 2);
+    assert_ne!(page_agents[0], page_agents[1]);
+    assert!(page_agents
+        .iter()
+        .all(|agent| config.user_agents.contains(agent)));
+
+    // robots.txt is fetched as each user agent, and every request carries the referer
+    let mut robots_agents: Vec<_> = requests
+        .iter()
+        .filter(|(path, _, _)| path == "/robots.txt")
+        .map(|(_, agent, _)| agent.clone().unwrap())
+        .collect();
+    robots_agents.sort();
+    assert_eq!(robots_agents, config.user_agents);
+    assert!(requests
+        .iter()
+        .all(|(_, _, referer)| referer.as_deref() == Some("https://portico.example/")));
+}
+
+#[test]
+fn test_concurrent_scrapes_per_host_are_capped() {
+    // Answers each connection on its own thread after a pause, tracking how many
+    // requests are in flight at once
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let most_in_flight = Arc::new(AtomicUsize::new(0));
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let (current, most) = (in_flight.clone(), most_in_flight.clone());
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let (current, most) = (current.clone(), most.clone());
+            std::thread::spawn(move || {
+                let mut buf = [0u8; 8192];
+                let _ = stream.read(&mut buf);
+                let now = current.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(150));
+                current.fetch_sub(1, Ordering::SeqCst);
+                let response = typed_response("text/html", "<p>Ward rounds start at nine.</p>");
+                let _ = stream.write_all(response.as_bytes());
+            });
+        }
+    });
+
+    let config = local_config();
+    assert_eq!(config.max_concurrent_per_host, 2);
+    tokio_test::block_on(async {
+        let mut scrapes = tokio::task::JoinSet::new();
+        for page in 0..6 {
+            let (url, config) = (format!("{}/ward/{}", base, page), config.clone());
+            scrapes.spawn(async move { scrape_webpage_with_config(&url, &config).await });
+        }
+        while let Some(scraped) = scrapes.join_next().await {
+            scraped.unwrap().unwrap();
+        }
+    });
+
+    assert_eq!(most_in_flight.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_request_delay_applies_per_host() {
+    let page = |_: &str| typed_response("text/html", "<p>Clinic hours vary.</p>");
+    let (first_host, second_host) = (spawn_http_handler(page), spawn_http_handler(page));
+
+    let config = ScraperConfig {
+        request_delay_ms: 400,
+        ..local_config()
+    };
+    let started = Instant::now();
+    tokio_test::block_on(scrape_webpage_with_config(&first_host, &config)).unwrap();
+    // The second host wasn't hit before, so it doesn't wait on the first one's delay
+    let second_started = Instant::now();
+    tokio_test::block_on(scrape_webpage_with_config(&second_host, &config)).unwrap();
+    assert!(
+        second_started.elapsed() < Duration::from_millis(400),
+        "{:?}",
+        second_started.elapsed()
+    );
+
+    // The first host was, so the next request to it does
+    tokio_test::block_on(scrape_webpage_with_config(&first_host, &config)).unwrap();
+    assert!(
+        started.elapsed() >= Duration::from_millis(400),
+        "{:?}",
+        started.elapsed()
+    );
+}
+
+#[test]
+fn test_slow_robots_txt_times_out_as_allowed() {
+    let base = spawn_http_handler(|request| {
+        if request.starts_with("GET /robots.txt") {
+            std::thread::sleep(Duration::from_millis(500));
+            typed_response("text/plain", "User-agent: *\nDisallow: /\n")
+        } else {
+            typed_response("text/html", "<p>Visiting hours end at eight.</p>")
+        }
+    });
+    let url = format!("{}/visiting", base);
+
+    // Given up on before it arrives, so scraping proceeds as if there were none
+    let config = ScraperConfig {
+        respect_robots_txt: true,
+        robots_timeout: Duration::from_millis(100),
+        robots_cache_ttl: Duration::ZERO,
+        ..local_config()
+    };
+    let page = tokio_test::block_on(scrape_webpage_with_config(&url, &config)).unwrap();
+    assert_eq!(page["content"][0]["text"], "Visiting hours end at eight.");
+
+    // Waited for, it disallows the page
+    let config = ScraperConfig {
+        robots_timeout: ScraperConfig::default().robots_timeout,
+        ..config
+    };
+    let err = tokio_test::block_on(scrape_webpage_with_config(&url, &config)).unwrap_err();
+    assert!(matches!(err, ScrapeError::RobotsDisallowed(_)), "{}", err);
+}
@@ -1,26 +1,79 @@
+use crate::models::steps::StepErrorKind;
 use anyhow::{anyhow, Result};
+use ipnet::IpNet;
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{Reader, XmlVersion};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::header::{
+    HeaderMap, HeaderValue, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE, REFERER, USER_AGENT,
+};
 use scraper::{Html, Selector};
+use serde::Deserialize;
 use serde_json::{json, Value};
-use std::collections::HashSet;
-use std::time::Duration;
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{BuildHasher, Hasher};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tokio::time::sleep;
-use url::Url;
+use url::{Host, Url};
 
 /// Configuration for web scraping behavior
 #[derive(Debug, Clone)]
 pub struct ScraperConfig {
     /// Whether to respect robots.txt (default: true)
     pub respect_robots_txt: bool,
-    /// Delay between requests in milliseconds (default: 1000)
+    /// Least time between requests to the same host in milliseconds; requests to
+    /// other hosts aren't held up by it (default: 1000)
     pub request_delay_ms: u64,
+    /// Most scrapes that may run at once against one host, the rest wait their
+    /// turn; 0 means no limit (default: 2)
+    pub max_concurrent_per_host: usize,
     /// User agent to use for requests (default: "Portico WebScraper/1.0")
     pub user_agent: String,
+    /// If non-empty, user agents to rotate through instead of `user_agent`. One is
+    /// picked per scrape and sent with all of its requests, robots.txt included
+    /// (default: empty)
+    pub user_agents: Vec<String>,
+    /// How the next entry of `user_agents` is picked (default: round-robin)
+    pub user_agent_rotation: UserAgentRotation,
+    /// `Referer` header sent with every request (default: none)
+    pub referer: Option<String>,
     /// Maximum content length to process in bytes (default: 5MB)
     pub max_content_length: usize,
     /// Whether to follow redirects (default: true)
     pub follow_redirects: bool,
     /// Maximum number of redirects to follow (default: 5)
     pub max_redirects: usize,
+    /// Block loopback, private, link-local and other internal addresses (default: true)
+    pub block_private_networks: bool,
+    /// If non-empty, only these hosts may be fetched (and they bypass `block_private_networks`).
+    /// Entries are hostnames, `*.example.com` wildcards, IPs or CIDR ranges (default: empty)
+    pub allowed_hosts: Vec<String>,
+    /// Hosts that are never fetched, in the same formats as `allowed_hosts` (default: empty)
+    pub blocked_hosts: Vec<String>,
+    /// MIME types that may be scraped (default: `text/html`, `application/xhtml+xml`).
+    /// RSS/Atom feeds and other XML are parsed as feeds, `text/plain` as paragraphs
+    /// of text, and anything else as HTML.
+    pub allowed_content_types: Vec<String>,
+    /// How long a host's parsed robots.txt rules are reused before it's fetched
+    /// again (default: 1 hour)
+    pub robots_cache_ttl: Duration,
+    /// How long fetching robots.txt may take before it's given up on, and scraping
+    /// treated as allowed (default: 10 seconds)
+    pub robots_timeout: Duration,
+    /// How long each page request may take (default: 30 seconds)
+    pub request_timeout: Duration,
+    /// Keep `<pre>` blocks as `code` content with their newlines and indentation,
+    /// and inline `<code>` in prose as `` `backticked` `` text (default: true)
+    pub preserve_code: bool,
+    /// Shape of the page's `content` (default: structured)
+    pub output: ScrapeOutput,
 }
 
 impl Default for ScraperConfig {
@@ -28,14 +81,385 @@ impl Default for ScraperConfig {
         Self {
             respect_robots_txt: true,
             request_delay_ms: 1000,
+            max_concurrent_per_host: 2,
             user_agent: "Portico WebScraper/1.0".to_string(),
+            user_agents: Vec::new(),
+            user_agent_rotation: UserAgentRotation::default(),
+            referer: None,
             max_content_length: 5 * 1024 * 1024, // 5MB
             follow_redirects: true,
             max_redirects: 5,
+            block_private_networks: true,
+            allowed_hosts: Vec::new(),
+            blocked_hosts: Vec::new(),
+            allowed_content_types: vec![
+                "text/html".to_string(),
+                "application/xhtml+xml".to_string(),
+            ],
+            robots_cache_ttl: Duration::from_secs(60 * 60),
+            robots_timeout: Duration::from_secs(10),
+            request_timeout: Duration::from_secs(30),
+            preserve_code: true,
+            output: ScrapeOutput::default(),
         }
     }
 }
 
+/// What a scraped page's `content` holds. Feeds always list their items.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScrapeOutput {
+    /// An array of heading / paragraph / code / list / table / link nodes
+    #[default]
+    Structured,
+    /// The nodes' text as one string, blocks separated by blank lines
+    Text,
+    /// The nodes rendered as one Markdown string
+    Markdown,
+}
+
+/// A WebScrape step's `step_content`: either a bare URL, or an object with the
+/// URL and options, e.g. `{"url": "https://example.com", "output": "text"}`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WebScrapeTarget {
+    pub url: String,
+    #[serde(default)]
+    pub output: ScrapeOutput,
+}
+
+impl WebScrapeTarget {
+    pub fn parse(step_content: &str) -> Result<Self> {
+        let content = step_content.trim();
+        if !content.starts_with('{') {
+            return Ok(Self {
+                url: content.to_string(),
+                output: ScrapeOutput::default(),
+            });
+        }
+        serde_json::from_str(content).map_err(|e| {
+            anyhow!(
+                "WebScrape step content must be a URL or an object with a `url`: {}",
+                e
+            )
+        })
+    }
+}
+
+/// How `ScraperConfig::user_agents` are rotated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UserAgentRotation {
+    /// Each in turn
+    #[default]
+    RoundRobin,
+    Random,
+}
+
+/// Turn counter for round-robin user agent rotation, shared by all configs
+static USER_AGENT_TURN: AtomicUsize = AtomicUsize::new(0);
+
+impl ScraperConfig {
+    /// The user agent for the next scrape: the static `user_agent`, or the next of
+    /// `user_agents` when that's set
+    pub fn next_user_agent(&self) -> &str {
+        if self.user_agents.is_empty() {
+            return &self.user_agent;
+        }
+        let turn = match self.user_agent_rotation {
+            UserAgentRotation::RoundRobin => USER_AGENT_TURN.fetch_add(1, Ordering::Relaxed),
+            UserAgentRotation::Random => RandomState::new().build_hasher().finish() as usize,
+        };
+        &self.user_agents[turn % self.user_agents.len()]
+    }
+}
+
+/// A request refused by the SSRF rules in `ScraperConfig`
+#[derive(Debug, Clone)]
+pub struct SsrfBlocked {
+    pub target: String,
+    pub reason: String,
+}
+
+impl std::fmt::Display for SsrfBlocked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "SSRF protection blocked request to {}: {}",
+            self.target, self.reason
+        )
+    }
+}
+
+impl std::error::Error for SsrfBlocked {}
+
+/// Why a scrape failed, so callers can tell failures worth retrying (a 503, a
+/// dropped connection) from ones that won't change (robots.txt, a bad URL)
+#[derive(Debug, Clone)]
+pub enum ScrapeError {
+    /// No response was received (connection refused, DNS failure, timeout, ...)
+    Network { message: String, timed_out: bool },
+    /// The server answered with a non-success status
+    HttpStatus(u16),
+    /// robots.txt disallows the URL for our user agent
+    RobotsDisallowed(String),
+    /// The body is larger than `max_content_length`
+    ContentTooLarge { size: usize, max: usize },
+    /// The content type isn't in `allowed_content_types`
+    UnsupportedContentType(String),
+    /// The body couldn't be parsed (malformed feed or sitemap)
+    Parse(String),
+    InvalidUrl(String),
+    /// Refused by the SSRF rules in `ScraperConfig`
+    Blocked(SsrfBlocked),
+    /// The run's download budget ran out
+    BudgetExceeded(String),
+}
+
+impl ScrapeError {
+    fn from_reqwest(err: &reqwest::Error, context: String) -> Self {
+        ScrapeError::Network {
+            message: format!("{}: {}", context, err),
+            timed_out: err.is_timeout(),
+        }
+    }
+
+    /// The `StepErrorKind` a WebScrape step reports for this failure
+    pub fn kind(&self) -> StepErrorKind {
+        match self {
+            ScrapeError::Network { timed_out: true, .. } => StepErrorKind::Timeout,
+            ScrapeError::Network { .. } | ScrapeError::BudgetExceeded(_) => StepErrorKind::Network,
+            ScrapeError::HttpStatus(status) => StepErrorKind::from_status(
+                reqwest::StatusCode::from_u16(*status)
+                    .unwrap_or(reqwest::StatusCode::INTERNAL_SERVER_ERROR),
+            ),
+            ScrapeError::ContentTooLarge { .. }
+            | ScrapeError::UnsupportedContentType(_)
+            | ScrapeError::Parse(_) => StepErrorKind::Validation,
+            ScrapeError::RobotsDisallowed(_)
+            | ScrapeError::InvalidUrl(_)
+            | ScrapeError::Blocked(_) => StepErrorKind::Config,
+        }
+    }
+
+    /// Whether the same request might succeed later
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ScrapeError::Network { .. } => true,
+            ScrapeError::HttpStatus(status) => matches!(status, 408 | 429 | 500..=599),
+            _ => false,
+        }
+    }
+
+    /// Finds a `ScrapeError` anywhere in an error chain, e.g. one returned by a WebScrape step
+    pub fn of(err: &anyhow::Error) -> Option<&ScrapeError> {
+        err.chain().find_map(|cause| cause.downcast_ref::<ScrapeError>())
+    }
+}
+
+impl std::fmt::Display for ScrapeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScrapeError::Network { message, .. } => write!(f, "{}", message),
+            ScrapeError::HttpStatus(status) => write!(f, "HTTP status {}", status),
+            ScrapeError::RobotsDisallowed(url) => {
+                write!(f, "Scraping not allowed by robots.txt for {}", url)
+            }
+            ScrapeError::ContentTooLarge { size, max } => {
+                write!(f, "Content too large: {} bytes (max: {} bytes)", size, max)
+            }
+            ScrapeError::UnsupportedContentType(content_type) => {
+                write!(f, "Unsupported content type: {}", content_type)
+            }
+            ScrapeError::Parse(message)
+            | ScrapeError::InvalidUrl(message)
+            | ScrapeError::BudgetExceeded(message) => write!(f, "{}", message),
+            ScrapeError::Blocked(blocked) => write!(f, "{}", blocked),
+        }
+    }
+}
+
+impl std::error::Error for ScrapeError {}
+
+impl ScraperConfig {
+    /// Whether `mime_type` (without parameters such as `charset`) is in `allowed_content_types`
+    pub fn allows_content_type(&self, mime_type: &str) -> bool {
+        self.allowed_content_types
+            .iter()
+            .any(|allowed| allowed.trim().eq_ignore_ascii_case(mime_type))
+    }
+
+    /// Checks a URL (the initial one or a redirect hop) against the SSRF rules.
+    /// Hostnames are checked again against their resolved addresses when connecting.
+    pub fn check_url(&self, url: &Url) -> std::result::Result<(), SsrfBlocked> {
+        match url.host() {
+            Some(Host::Domain(domain)) => self.check_target(domain, None),
+            Some(Host::Ipv4(ip)) => self.check_target(&ip.to_string(), Some(IpAddr::V4(ip))),
+            Some(Host::Ipv6(ip)) => self.check_target(&ip.to_string(), Some(IpAddr::V6(ip))),
+            None => Err(SsrfBlocked {
+                target: url.to_string(),
+                reason: "URL has no host".to_string(),
+            }),
+        }
+    }
+
+    /// `ip` is `None` for a hostname that hasn't been resolved yet; rules that
+    /// need an address are then left to the resolver
+    fn check_target(&self, host: &str, ip: Option<IpAddr>) -> std::result::Result<(), SsrfBlocked> {
+        let host = host.trim_end_matches('.').to_lowercase();
+        let blocked = |reason: &str| {
+            Err(SsrfBlocked {
+                target: match ip {
+                    Some(ip) if ip.to_string() != host => format!("{} ({})", host, ip),
+                    _ => host.clone(),
+                },
+                reason: reason.to_string(),
+            })
+        };
+
+        if self
+            .blocked_hosts
+            .iter()
+            .any(|rule| host_rule_matches(rule, &host, ip))
+        {
+            return blocked("host is on the deny-list");
+        }
+
+        if !self.allowed_hosts.is_empty() {
+            if self
+                .allowed_hosts
+                .iter()
+                .any(|rule| host_rule_matches(rule, &host, ip))
+            {
+                return Ok(());
+            }
+            // IP / CIDR entries can only be matched once the hostname is resolved
+            if ip.is_some() || !self.allowed_hosts.iter().any(|rule| is_ip_rule(rule)) {
+                return blocked("host is not on the allow-list");
+            }
+        }
+
+        if self.block_private_networks {
+            if host == "localhost" || host.ends_with(".localhost") {
+                return blocked("localhost is not allowed");
+            }
+            if ip.is_some_and(is_internal_ip) {
+                return blocked("private, loopback and link-local addresses are not allowed");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// HTTP client that applies the SSRF rules to every redirect hop and every
+    /// address it connects to (so DNS names pointing at internal IPs are caught too)
+    pub fn build_client(&self) -> Result<reqwest::Client> {
+        let redirect_config = self.clone();
+        let redirect_policy = reqwest::redirect::Policy::custom(move |attempt| {
+            if !redirect_config.follow_redirects {
+                attempt.stop()
+            } else if attempt.previous().len() > redirect_config.max_redirects {
+                attempt.error(anyhow!(
+                    "Too many redirects (max: {})",
+                    redirect_config.max_redirects
+                ))
+            } else if let Err(e) = redirect_config.check_url(attempt.url()) {
+                attempt.error(e)
+            } else {
+                attempt.follow()
+            }
+        });
+
+        let mut headers = HeaderMap::new();
+        if let Some(referer) = &self.referer {
+            headers.insert(REFERER, HeaderValue::from_str(referer)?);
+        }
+
+        let client = reqwest::Client::builder()
+            .user_agent(&self.user_agent)
+            .default_headers(headers)
+            .redirect(redirect_policy)
+            .dns_resolver(Arc::new(SsrfResolver {
+                config: self.clone(),
+            }))
+            // A proxy would resolve hosts itself, bypassing the resolver checks
+            .no_proxy()
+            .timeout(self.request_timeout)
+            .build()?;
+
+        Ok(client)
+    }
+}
+
+/// Resolver that refuses hostnames resolving to addresses blocked by the config
+struct SsrfResolver {
+    config: ScraperConfig,
+}
+
+impl Resolve for SsrfResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let config = self.config.clone();
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let addrs: Vec<_> = tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            for addr in &addrs {
+                config.check_target(&host, Some(addr.ip()))?;
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Whether an allow/deny entry is an IP or CIDR range (rather than a hostname)
+fn is_ip_rule(rule: &str) -> bool {
+    rule.parse::<IpNet>().is_ok() || rule.parse::<IpAddr>().is_ok()
+}
+
+/// Matches an allow/deny entry: a CIDR range or IP against `ip`, otherwise a
+/// hostname (optionally `*.`-prefixed to include subdomains) against `host`
+fn host_rule_matches(rule: &str, host: &str, ip: Option<IpAddr>) -> bool {
+    let rule = rule.trim().to_lowercase();
+    if let Ok(net) = rule.parse::<IpNet>() {
+        return ip.is_some_and(|ip| net.contains(&ip));
+    }
+    if let Ok(rule_ip) = rule.parse::<IpAddr>() {
+        return ip == Some(rule_ip);
+    }
+    match rule.strip_prefix("*.") {
+        Some(suffix) => host == suffix || host.ends_with(&format!(".{}", suffix)),
+        None => host == rule,
+    }
+}
+
+/// Addresses that aren't publicly routable (loopback, RFC 1918, link-local incl.
+/// cloud metadata at 169.254.169.254, CGNAT, unique-local IPv6, ...)
+fn is_internal_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => is_internal_ipv4(v4),
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_internal_ipv4(v4);
+            }
+            v6.is_loopback()
+                || v6.is_unspecified()
+                || is_in(v6, "fc00::/7") // unique local
+                || is_in(v6, "fe80::/10") // link-local
+        }
+    }
+}
+
+fn is_internal_ipv4(ip: Ipv4Addr) -> bool {
+    ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.octets()[0] == 0
+        || "100.64.0.0/10".parse::<IpNet>().unwrap().contains(&IpAddr::V4(ip)) // CGNAT
+}
+
+fn is_in(ip: Ipv6Addr, cidr: &str) -> bool {
+    cidr.parse::<IpNet>().unwrap().contains(&IpAddr::V6(ip))
+}
+
 /// Validate and normalize a URL string
 pub fn validate_url(url_str: &str) -> Result<Url> {
     let trimmed_url = url_str.trim();
@@ -85,112 +509,229 @@ pub fn validate_url(url_str: &str) -> Result<Url> {
     Ok(url)
 }
 
-/// Check if scraping is allowed by robots.txt
-async fn is_scraping_allowed(url: &Url, user_agent: &str) -> bool {
-    // Try to get robots.txt
+/// Fetch the site's robots.txt, `None` if it doesn't exist or can't be read
+async fn fetch_robots_txt(
+    client: &reqwest::Client,
+    url: &Url,
+    user_agent: &str,
+    timeout: Duration,
+) -> Option<String> {
     let robots_url = url.join("/robots.txt").unwrap_or_else(|_| url.clone());
 
-    let client = reqwest::Client::new();
-    let response = match client
+    let response = client
         .get(robots_url.as_str())
-        .header("User-Agent", user_agent)
-        .timeout(Duration::from_secs(10))
+        .header(USER_AGENT, user_agent)
+        .timeout(timeout)
         .send()
         .await
-    {
-        Ok(resp) => resp,
-        Err(_) => return true, // If we can't get robots.txt, assume scraping is allowed
-    };
+        .ok()?;
 
     if !response.status().is_success() {
-        return true; // If robots.txt doesn't exist or can't be accessed, assume scraping is allowed
+        return None;
     }
 
-    let robots_txt = match response.text().await {
-        Ok(text) => text,
-        Err(_) => return true, // If we can't parse robots.txt, assume scraping is allowed
-    };
+    response.text().await.ok()
+}
 
-    // Very simple robots.txt parsing
-    // This is a simplified implementation and doesn't handle all robots.txt rules
-    let path = url.path();
-    let mut current_agent = "";
-    let mut disallowed_paths = HashSet::new();
+/// The `Disallow` rules in a robots.txt that apply to one user agent
+#[derive(Debug, Clone, Default)]
+struct RobotsRules {
+    disallowed_paths: HashSet<String>,
+}
 
-    for line in robots_txt.lines() {
-        let line = line.trim();
-        if line.is_empty() || line.starts_with('#') {
-            continue;
-        }
+impl RobotsRules {
+    fn parse(robots_txt: &str, user_agent: &str) -> Self {
+        // Very simple robots.txt parsing
+        // This is a simplified implementation and doesn't handle all robots.txt rules
+        let mut current_agent = "";
+        let mut disallowed_paths = HashSet::new();
 
-        if let Some(agent) = line.strip_prefix("User-agent:") {
-            current_agent = agent.trim();
-            if current_agent == "*" || current_agent == user_agent {
-                disallowed_paths.clear(); // Reset for new agent
+        for line in robots_txt.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
             }
-        } else if (current_agent == "*" || current_agent == user_agent)
-            && line.starts_with("Disallow:")
-        {
-            if let Some(disallowed) = line.strip_prefix("Disallow:") {
-                let disallowed = disallowed.trim();
-                if !disallowed.is_empty() {
-                    disallowed_paths.insert(disallowed.to_string());
+
+            if let Some(agent) = line.strip_prefix("User-agent:") {
+                current_agent = agent.trim();
+                if current_agent == "*" || current_agent == user_agent {
+                    disallowed_paths.clear(); // Reset for new agent
+                }
+            } else if (current_agent == "*" || current_agent == user_agent)
+                && line.starts_with("Disallow:")
+            {
+                if let Some(disallowed) = line.strip_prefix("Disallow:") {
+                    let disallowed = disallowed.trim();
+                    if !disallowed.is_empty() {
+                        disallowed_paths.insert(disallowed.to_string());
+                    }
                 }
             }
         }
+
+        Self { disallowed_paths }
     }
 
-    // Check if the path is allowed
-    !disallowed_paths
-        .iter()
-        .any(|disallowed| path.starts_with(disallowed))
+    fn allows(&self, path: &str) -> bool {
+        !self
+            .disallowed_paths
+            .iter()
+            .any(|disallowed| path.starts_with(disallowed))
+    }
+}
+
+/// Parsed robots.txt rules by (origin, user agent), with the time they were fetched
+type RobotsCache = HashMap<(String, String), (Instant, RobotsRules)>;
+
+static ROBOTS_CACHE: LazyLock<Mutex<RobotsCache>> = LazyLock::new(Default::default);
+
+/// Check if scraping is allowed by robots.txt, fetched within `timeout`. Rules are
+/// cached per host for `ttl`.
+async fn is_scraping_allowed(
+    client: &reqwest::Client,
+    url: &Url,
+    user_agent: &str,
+    ttl: Duration,
+    timeout: Duration,
+) -> bool {
+    let key = (url.origin().ascii_serialization(), user_agent.to_string());
+    let cached = ROBOTS_CACHE
+        .lock()
+        .unwrap()
+        .get(&key)
+        .filter(|(fetched_at, _)| fetched_at.elapsed() < ttl)
+        .map(|(_, rules)| rules.clone());
+
+    let rules = match cached {
+        Some(rules) => rules,
+        None => {
+            // If robots.txt doesn't exist or can't be accessed, assume scraping is allowed
+            let rules = fetch_robots_txt(client, url, user_agent, timeout)
+                .await
+                .map(|robots_txt| RobotsRules::parse(&robots_txt, user_agent))
+                .unwrap_or_default();
+            ROBOTS_CACHE
+                .lock()
+                .unwrap()
+                .insert(key, (Instant::now(), rules.clone()));
+            rules
+        }
+    };
+
+    rules.allows(url.path())
+}
+
+/// Slots for scraping each origin, by (origin, `max_concurrent_per_host`)
+type HostPermits = HashMap<(String, usize), Arc<Semaphore>>;
+
+static HOST_PERMITS: LazyLock<Mutex<HostPermits>> = LazyLock::new(Default::default);
+
+/// Waits for one of `limit` slots for scraping `url`'s origin (scheme, host and
+/// port), held until the permit is dropped. No limit if `limit` is 0.
+async fn acquire_host_permit(url: &Url, limit: usize) -> Option<OwnedSemaphorePermit> {
+    if limit == 0 {
+        return None;
+    }
+    let semaphore = HOST_PERMITS
+        .lock()
+        .unwrap()
+        .entry((url.origin().ascii_serialization(), limit))
+        .or_insert_with(|| Arc::new(Semaphore::new(limit)))
+        .clone();
+    semaphore.acquire_owned().await.ok()
+}
+
+/// When the next request to each origin may be sent, for `request_delay_ms`
+static HOST_NEXT_REQUEST: LazyLock<Mutex<HashMap<String, Instant>>> =
+    LazyLock::new(Default::default);
+
+/// Waits until `delay` has passed since the last request to `url`'s origin (not
+/// at all for an origin that hasn't been requested lately), and books the next
+/// slot, so concurrent requests to one host are spaced out too
+async fn polite_delay(url: &Url, delay: Duration) {
+    if delay.is_zero() {
+        return;
+    }
+    let now = Instant::now();
+    let send_at = {
+        let mut next_request = HOST_NEXT_REQUEST.lock().unwrap();
+        next_request.retain(|_, next| *next > now);
+        let send_at = next_request
+            .get(&url.origin().ascii_serialization())
+            .copied()
+            .unwrap_or(now);
+        next_request.insert(url.origin().ascii_serialization(), send_at + delay);
+        send_at
+    };
+    sleep(send_at.saturating_duration_since(now)).await;
 }
 
 /// Scrape a webpage and convert it to a structured JSON representation
-/// focusing on the core textual content.
-pub async fn scrape_webpage(url_str: &str) -> Result<Value> {
+/// focusing on the core textual content. Its `content_hash` only changes when
+/// that text does, so monitoring steps can skip pages that haven't changed.
+pub async fn scrape_webpage(url_str: &str) -> Result<Value, ScrapeError> {
+    scrape_webpage_with_config(url_str, &ScraperConfig::default()).await
+}
+
+/// `scrape_webpage` with custom settings
+pub async fn scrape_webpage_with_config(
+    url_str: &str,
+    config: &ScraperConfig,
+) -> Result<Value, ScrapeError> {
     // Validate the URL
-    let url = validate_url(url_str)?;
+    let url = validate_url(url_str).map_err(|e| ScrapeError::InvalidUrl(e.to_string()))?;
 
-    // Use default scraper configuration
-    let config = ScraperConfig::default();
+    // Refuse internal targets before sending anything (robots.txt included)
+    config.check_url(&url).map_err(ScrapeError::Blocked)?;
+
+    // Wait for a free slot on this host, kept until the page is read (the
+    // robots.txt check and polite delay included)
+    let _host_permit = acquire_host_permit(&url, config.max_concurrent_per_host).await;
+
+    // Build a client with custom settings
+    let client = build_client(config)?;
+    let user_agent = config.next_user_agent();
 
     // Respect robots.txt if configured
     if config.respect_robots_txt {
-        let allowed = is_scraping_allowed(&url, &config.user_agent).await;
+        let allowed = is_scraping_allowed(
+            &client,
+            &url,
+            user_agent,
+            config.robots_cache_ttl,
+            config.robots_timeout,
+        )
+        .await;
         if !allowed {
-            return Err(anyhow!("Scraping not allowed by robots.txt for {}", url));
+            return Err(ScrapeError::RobotsDisallowed(url.to_string()));
         }
     }
 
-    // Add a delay to be polite
-    if config.request_delay_ms > 0 {
-        sleep(Duration::from_millis(config.request_delay_ms)).await;
-    }
-
-    // Build a client with custom settings
-    let client = reqwest::Client::builder()
-        .user_agent(&config.user_agent)
-        .redirect(if config.follow_redirects {
-            reqwest::redirect::Policy::limited(config.max_redirects)
-        } else {
-            reqwest::redirect::Policy::none()
-        })
-        .timeout(Duration::from_secs(30))
-        .build()?;
+    // Don't hit the same host again too soon
+    polite_delay(&url, Duration::from_millis(config.request_delay_ms)).await;
 
     // Fetch the webpage content
-    let response = match client.get(url.as_str()).send().await {
+    let response = match client
+        .get(url.as_str())
+        .header(USER_AGENT, user_agent)
+        .send()
+        .await
+    {
         Ok(resp) => resp,
-        Err(e) => return Err(anyhow!("Failed to fetch URL '{}': {}", url_str, e)),
+        Err(e) => {
+            // A redirect hop or resolved address was refused by the SSRF rules
+            if let Some(blocked) = ssrf_cause(&e) {
+                return Err(ScrapeError::Blocked(blocked.clone()));
+            }
+            return Err(ScrapeError::from_reqwest(
+                &e,
+                format!("Failed to fetch URL '{}'", url_str),
+            ));
+        }
     };
 
     if !response.status().is_success() {
-        return Err(anyhow!(
-            "Failed to fetch webpage '{}': HTTP status {}",
-            url_str,
-            response.status()
-        ));
+        return Err(ScrapeError::HttpStatus(response.status().as_u16()));
     }
 
     // Check content type
@@ -199,13 +740,15 @@ pub async fn scrape_webpage(url_str: &str) -> Result<Value> {
         .get("content-type")
         .and_then(|v| v.to_str().ok())
         .unwrap_or("text/html");
+    let mime_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
 
-    if !content_type.contains("text/html") {
-        return Err(anyhow!(
-            "Unsupported content type for '{}': {}. Only HTML is supported",
-            url_str,
-            content_type
-        ));
+    if !config.allows_content_type(&mime_type) {
+        return Err(ScrapeError::UnsupportedContentType(content_type.to_string()));
     }
 
     // Check content length
@@ -217,51 +760,568 @@ pub async fn scrape_webpage(url_str: &str) -> Result<Value> {
         .unwrap_or(0);
 
     if content_length > config.max_content_length && content_length > 0 {
-        return Err(anyhow!(
-            "Content too large for '{}': {} bytes (max: {} bytes)",
-            url_str,
-            content_length,
-            config.max_content_length
-        ));
+        return Err(ScrapeError::ContentTooLarge {
+            size: content_length,
+            max: config.max_content_length,
+        });
     }
 
-    let html_content = match response.text().await {
+    let body = match response.text().await {
         Ok(text) => text,
-        Err(e) => return Err(anyhow!("Failed to get HTML text from '{}': {}", url_str, e)),
+        Err(e) => {
+            return Err(ScrapeError::from_reqwest(
+                &e,
+                format!("Failed to get text from '{}'", url_str),
+            ))
+        }
     };
 
+    charge_bytes(body.len())?;
+
     // Check actual content length
-    if html_content.len() > config.max_content_length {
-        return Err(anyhow!(
-            "Content too large for '{}': {} bytes (max: {} bytes)",
-            url_str,
-            html_content.len(),
-            config.max_content_length
-        ));
+    if body.len() > config.max_content_length {
+        return Err(ScrapeError::ContentTooLarge {
+            size: body.len(),
+            max: config.max_content_length,
+        });
     }
 
-    // Parse the HTML
-    let document = Html::parse_document(&html_content);
-
-    // Extract metadata
-    let title = extract_title(&document).unwrap_or_default();
-    let metadata = extract_metadata(&document);
+    // Feeds are listed item by item rather than as page content
+    if is_feed_type(&mime_type) {
+        let feed = parse_feed(&body)
+            .map_err(|e| ScrapeError::Parse(format!("Invalid feed at '{}': {}", url_str, e)))?;
+        return Ok(json!({
+            "url": url.as_str(),
+            "title": feed.title.unwrap_or_default(),
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "content_type": mime_type,
+            "items": feed.items
+        }));
+    }
 
-    // Extract main content with filtering
-    let content = extract_filtered_content(&document);
+    let (title, metadata, content) = if mime_type == "text/plain" {
+        (String::new(), json!({}), extract_plain_text_content(&body))
+    } else {
+        // Parse the HTML
+        let document = Html::parse_document(&body);
+
+        // Extract metadata and main content with filtering
+        (
+            extract_title(&document).unwrap_or_default(),
+            extract_metadata(&document),
+            extract_filtered_content(&document, config.preserve_code),
+        )
+    };
 
     // Create the JSON structure
     let result = json!({
         "url": url.as_str(),
         "title": title,
         "timestamp": chrono::Utc::now().to_rfc3339(),
+        "content_type": mime_type,
         "metadata": metadata,
-        "content": content
+        "content_hash": content_hash(&content),
+        "content": render_content(content, config.output)
     });
 
     Ok(result)
 }
 
+/// What `check_url` found at a URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UrlCheck {
+    /// Status of the last response, after redirects (not necessarily a success)
+    pub status: u16,
+    /// The `Content-Type` header as sent, parameters included
+    pub content_type: Option<String>,
+    /// Size of the full body in bytes, if the server said
+    pub content_length: Option<u64>,
+    /// Where the request ended up after redirects
+    pub final_url: String,
+}
+
+/// Checks whether a URL is reachable without downloading or parsing it, for agents
+/// that only need to know a link works. Sends a HEAD request, or a GET for the
+/// first byte when the server doesn't allow HEAD. Redirects, the SSRF rules and
+/// the per-host limits apply as when scraping; robots.txt isn't consulted. Error
+/// statuses are returned in the `UrlCheck`, not as an `Err`.
+pub async fn check_url(url_str: &str, config: &ScraperConfig) -> Result<UrlCheck, ScrapeError> {
+    let url = validate_url(url_str).map_err(|e| ScrapeError::InvalidUrl(e.to_string()))?;
+    config.check_url(&url).map_err(ScrapeError::Blocked)?;
+
+    let _host_permit = acquire_host_permit(&url, config.max_concurrent_per_host).await;
+
+    let client = build_client(config)?;
+    let user_agent = config.next_user_agent();
+
+    polite_delay(&url, Duration::from_millis(config.request_delay_ms)).await;
+
+    let send = |request: reqwest::RequestBuilder| async move {
+        request
+            .header(USER_AGENT, user_agent)
+            .send()
+            .await
+            .map_err(|e| match ssrf_cause(&e) {
+                Some(blocked) => ScrapeError::Blocked(blocked.clone()),
+                None => ScrapeError::from_reqwest(&e, format!("Failed to check URL '{}'", url_str)),
+            })
+    };
+
+    let mut response = send(client.head(url.as_str())).await?;
+    let mut ranged = false;
+    if matches!(
+        response.status(),
+        reqwest::StatusCode::METHOD_NOT_ALLOWED | reqwest::StatusCode::NOT_IMPLEMENTED
+    ) {
+        response = send(client.get(url.as_str()).header(RANGE, "bytes=0-0")).await?;
+        ranged = response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+    }
+
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    // A ranged response's own length is the range's; the full size follows the `/`
+    let content_length = if ranged {
+        header(CONTENT_RANGE).and_then(|range| range.rsplit_once('/')?.1.parse().ok())
+    } else {
+        header(CONTENT_LENGTH).and_then(|len| len.parse().ok())
+    };
+
+    Ok(UrlCheck {
+        status: response.status().as_u16(),
+        content_type: header(CONTENT_TYPE),
+        content_length,
+        final_url: response.url().to_string(),
+    })
+}
+
+/// RSS/Atom feeds, plus generic XML which is most often a feed served with a generic type
+fn is_feed_type(mime_type: &str) -> bool {
+    matches!(
+        mime_type,
+        "application/rss+xml" | "application/atom+xml" | "application/xml" | "text/xml"
+    )
+}
+
+/// A feed's title and its items' titles and links
+#[derive(Debug)]
+struct Feed {
+    title: Option<String>,
+    items: Vec<Value>,
+}
+
+/// Parses RSS 2.0, RSS 1.0 (RDF) and Atom feeds
+fn parse_feed(xml: &str) -> Result<Feed> {
+    let mut reader = Reader::from_str(xml);
+    let mut is_feed = false;
+    let mut title = None;
+    let mut items = Vec::new();
+    // Title and link of the `<item>` / `<entry>` being read
+    let mut item: Option<(Option<String>, Option<String>)> = None;
+    // Text of the `<title>` or `<link>` being read, which may arrive in several events
+    let mut field: Option<(&str, String)> = None;
+
+    loop {
+        let event = reader.read_event()?;
+        match &event {
+            Event::Start(start) | Event::Empty(start) => {
+                let is_empty = matches!(event, Event::Empty(_));
+                match start.local_name().as_ref() {
+                    "rss" | "feed" | "RDF" => is_feed = true,
+                    "item" | "entry" if !is_empty => item = Some((None, None)),
+                    "title" if !is_empty => field = Some(("title", String::new())),
+                    "link" => match attr_value(start, "href")? {
+                        // Atom links are `<link href="..."/>`; `rel="alternate"` (or no rel) is the page
+                        Some(href) => {
+                            let is_alternate =
+                                attr_value(start, "rel")?.is_none_or(|rel| rel == "alternate");
+                            if let Some((_, link)) = item.as_mut().filter(|_| is_alternate) {
+                                link.get_or_insert(href.trim().to_string());
+                            }
+                        }
+                        None if !is_empty => field = Some(("link", String::new())),
+                        None => {}
+                    },
+                    _ => {}
+                }
+            }
+            Event::Text(_) | Event::CData(_) | Event::GeneralRef(_) => {
+                if let Some((_, text)) = field.as_mut() {
+                    push_xml_text(text, &event)?;
+                }
+            }
+            Event::End(end) => match end.local_name().as_ref() {
+                name @ ("title" | "link") => {
+                    if let Some((_, text)) = field.take().filter(|(field, _)| *field == name) {
+                        let text = text.trim().to_string();
+                        match (item.as_mut(), name) {
+                            (Some((item_title, _)), "title") => {
+                                item_title.get_or_insert(text);
+                            }
+                            (Some((_, link)), _) => {
+                                link.get_or_insert(text);
+                            }
+                            (None, "title") => {
+                                title.get_or_insert(text);
+                            }
+                            (None, _) => {}
+                        }
+                    }
+                }
+                "item" | "entry" => {
+                    if let Some((item_title, link)) = item.take() {
+                        items.push(json!({"title": item_title, "link": link}));
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if !is_feed {
+        return Err(anyhow!(
+            "Expected an <rss>, <feed> or <rdf:RDF> root element"
+        ));
+    }
+    Ok(Feed { title, items })
+}
+
+/// Appends the character data of a text, CDATA or entity reference event to `out`
+fn push_xml_text(out: &mut String, event: &Event) -> Result<()> {
+    match event {
+        Event::Text(text) => out.push_str(&text.xml10_content()),
+        Event::CData(cdata) => out.push_str(&cdata.xml10_content()),
+        Event::GeneralRef(reference) => match reference.resolve_char_ref()? {
+            Some(ch) => out.push(ch),
+            None => out.push_str(
+                resolve_predefined_entity(reference)
+                    .ok_or_else(|| anyhow!("Unknown entity &{};", reference.as_ref() as &str))?,
+            ),
+        },
+        _ => {}
+    }
+    Ok(())
+}
+
+fn attr_value(element: &BytesStart, name: &str) -> Result<Option<String>> {
+    Ok(match element.try_get_attribute(name)? {
+        Some(attr) => Some(attr.normalized_value(XmlVersion::default())?.into_owned()),
+        None => None,
+    })
+}
+
+/// Shapes extracted content nodes as `output` asks
+fn render_content(content: Vec<Value>, output: ScrapeOutput) -> Value {
+    let blocks = |render: fn(&Value) -> String| {
+        let blocks: Vec<String> = content
+            .iter()
+            .map(render)
+            .filter(|block| !block.is_empty())
+            .collect();
+        Value::String(blocks.join("\n\n"))
+    };
+    match output {
+        ScrapeOutput::Structured => Value::Array(content),
+        ScrapeOutput::Text => blocks(node_text),
+        ScrapeOutput::Markdown => blocks(node_markdown),
+    }
+}
+
+/// Hex SHA-256 of the extracted text with whitespace collapsed, so it only
+/// changes when the page's text does (not its markup or the `output` shape)
+fn content_hash(content: &[Value]) -> String {
+    let text: Vec<String> = content
+        .iter()
+        .map(|node| node_text(node).split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|text| !text.is_empty())
+        .collect();
+    format!("{:x}", Sha256::digest(text.join("\n")))
+}
+
+/// The strings in a JSON array (e.g. a list's items or a table row)
+fn strings(values: &Value) -> Vec<&str> {
+    values
+        .as_array()
+        .map(|values| values.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default()
+}
+
+/// A content node's text, without any markup
+fn node_text(node: &Value) -> String {
+    match node["type"].as_str() {
+        Some("list") => strings(&node["items"]).join("\n"),
+        Some("table") => {
+            let mut lines = vec![strings(&node["headers"]).join(" ")];
+            if let Some(rows) = node["rows"].as_array() {
+                lines.extend(rows.iter().map(|row| strings(row).join(" ")));
+            }
+            lines.retain(|line| !line.is_empty());
+            lines.join("\n")
+        }
+        _ => node["text"].as_str().unwrap_or_default().to_string(),
+    }
+}
+
+/// A content node as Markdown
+fn node_markdown(node: &Value) -> String {
+    let text = node["text"].as_str().unwrap_or_default();
+    match node["type"].as_str() {
+        Some("heading") => {
+            let level = node["level"].as_u64().unwrap_or(1).clamp(1, 6) as usize;
+            format!("{} {}", "#".repeat(level), text)
+        }
+        Some("code") => format!(
+            "```{}\n{}\n```",
+            node["language"].as_str().unwrap_or_default(),
+            text
+        ),
+        Some("list") => strings(&node["items"])
+            .iter()
+            .map(|item| format!("- {}", item))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Some("table") => {
+            let rows: Vec<Vec<&str>> = node["rows"]
+                .as_array()
+                .map(|rows| rows.iter().map(strings).collect())
+                .unwrap_or_default();
+            let mut headers = strings(&node["headers"]);
+            let width = rows.iter().map(Vec::len).chain([headers.len()]).max().unwrap_or(0);
+            headers.resize(width, "");
+            let mut lines = vec![
+                format!("| {} |", headers.join(" | ")),
+                format!("|{}", " --- |".repeat(width)),
+            ];
+            for mut row in rows {
+                row.resize(width, "");
+                lines.push(format!("| {} |", row.join(" | ")));
+            }
+            lines.join("\n")
+        }
+        Some("link") => format!("[{}]({})", text, node["href"].as_str().unwrap_or_default()),
+        _ => text.to_string(),
+    }
+}
+
+/// Splits plain text into paragraphs on blank lines
+fn extract_plain_text_content(text: &str) -> Vec<Value> {
+    text.split("\n\n")
+        .map(clean_text)
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| json!({"type": "paragraph", "text": paragraph}))
+        .collect()
+}
+
+/// Upper bound on the sitemap files (indexes included) `fetch_sitemap` will fetch for one site
+const MAX_SITEMAP_FILES: usize = 50;
+
+/// List the page URLs in a site's sitemap. The sitemaps named by `Sitemap:` lines
+/// in robots.txt are used if there are any, otherwise `/sitemap.xml`. Sitemap
+/// index files are followed; each page URL is returned once, in document order.
+pub async fn fetch_sitemap(
+    base_url: &str,
+    config: &ScraperConfig,
+) -> Result<Vec<String>, ScrapeError> {
+    let base = validate_url(base_url).map_err(|e| ScrapeError::InvalidUrl(e.to_string()))?;
+    config.check_url(&base).map_err(ScrapeError::Blocked)?;
+
+    let client = build_client(config)?;
+    let user_agent = config.next_user_agent();
+
+    let robots_txt = fetch_robots_txt(&client, &base, user_agent, config.robots_timeout).await;
+    let mut queue: VecDeque<Url> = match robots_txt {
+        Some(robots_txt) => sitemap_directives(&robots_txt, &base).into(),
+        None => VecDeque::new(),
+    };
+    if queue.is_empty() {
+        let default_sitemap = base
+            .join("/sitemap.xml")
+            .map_err(|e| ScrapeError::InvalidUrl(e.to_string()))?;
+        queue.push_back(default_sitemap);
+    }
+
+    let mut fetched = HashSet::new();
+    let mut seen_pages = HashSet::new();
+    let mut pages = Vec::new();
+
+    while let Some(sitemap_url) = queue.pop_front() {
+        if fetched.contains(&sitemap_url) {
+            continue;
+        }
+        if fetched.len() >= MAX_SITEMAP_FILES {
+            eprintln!(
+                "Stopped reading sitemaps for {} after {} files",
+                base, MAX_SITEMAP_FILES
+            );
+            break;
+        }
+
+        // Be polite between sitemap requests too
+        polite_delay(&sitemap_url, Duration::from_millis(config.request_delay_ms)).await;
+
+        let xml = fetch_sitemap_file(&client, &sitemap_url, user_agent, config).await?;
+        fetched.insert(sitemap_url.clone());
+
+        let sitemap = parse_sitemap(&xml)
+            .map_err(|e| ScrapeError::Parse(format!("Invalid sitemap '{}': {}", sitemap_url, e)))?;
+
+        if sitemap.is_index {
+            for loc in sitemap.locs {
+                match Url::parse(&loc) {
+                    Ok(nested) => queue.push_back(nested),
+                    Err(e) => eprintln!("Skipping sitemap '{}' from {}: {}", loc, sitemap_url, e),
+                }
+            }
+        } else {
+            for loc in sitemap.locs {
+                if seen_pages.insert(loc.clone()) {
+                    pages.push(loc);
+                }
+            }
+        }
+    }
+
+    Ok(pages)
+}
+
+/// Sitemap URLs listed in robots.txt (`Sitemap:` lines apply to every user agent)
+fn sitemap_directives(robots_txt: &str, base: &Url) -> Vec<Url> {
+    robots_txt
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.trim().split_once(':')?;
+            if !key.trim().eq_ignore_ascii_case("sitemap") {
+                return None;
+            }
+            base.join(value.trim()).ok()
+        })
+        .collect()
+}
+
+async fn fetch_sitemap_file(
+    client: &reqwest::Client,
+    url: &Url,
+    user_agent: &str,
+    config: &ScraperConfig,
+) -> Result<String, ScrapeError> {
+    // Sitemaps may point at other hosts, so each one is checked like a redirect hop
+    config.check_url(url).map_err(ScrapeError::Blocked)?;
+
+    let response = match client
+        .get(url.as_str())
+        .header(USER_AGENT, user_agent)
+        .send()
+        .await
+    {
+        Ok(resp) => resp,
+        Err(e) => {
+            if let Some(blocked) = ssrf_cause(&e) {
+                return Err(ScrapeError::Blocked(blocked.clone()));
+            }
+            return Err(ScrapeError::from_reqwest(
+                &e,
+                format!("Failed to fetch sitemap '{}'", url),
+            ));
+        }
+    };
+
+    if !response.status().is_success() {
+        return Err(ScrapeError::HttpStatus(response.status().as_u16()));
+    }
+
+    let xml = response
+        .text()
+        .await
+        .map_err(|e| ScrapeError::from_reqwest(&e, format!("Failed to read sitemap '{}'", url)))?;
+    charge_bytes(xml.len())?;
+
+    if xml.len() > config.max_content_length {
+        return Err(ScrapeError::ContentTooLarge {
+            size: xml.len(),
+            max: config.max_content_length,
+        });
+    }
+
+    Ok(xml)
+}
+
+/// The `<loc>` entries of a sitemap, which are pages for a `<urlset>`
+/// and further sitemaps for a `<sitemapindex>`
+#[derive(Debug)]
+struct Sitemap {
+    is_index: bool,
+    locs: Vec<String>,
+}
+
+fn parse_sitemap(xml: &str) -> Result<Sitemap> {
+    let mut reader = Reader::from_str(xml);
+    let mut is_index = None;
+    let mut locs = Vec::new();
+    // Text of the `<loc>` being read, which may arrive in several events
+    let mut current_loc: Option<String> = None;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(start) => match start.local_name().as_ref() {
+                "urlset" if is_index.is_none() => is_index = Some(false),
+                "sitemapindex" if is_index.is_none() => is_index = Some(true),
+                "loc" => current_loc = Some(String::new()),
+                _ => {}
+            },
+            event @ (Event::Text(_) | Event::CData(_) | Event::GeneralRef(_)) => {
+                if let Some(loc) = current_loc.as_mut() {
+                    push_xml_text(loc, &event)?;
+                }
+            }
+            Event::End(end) if end.local_name().as_ref() == "loc" => {
+                if let Some(loc) = current_loc.take() {
+                    let loc = loc.trim();
+                    if !loc.is_empty() {
+                        locs.push(loc.to_string());
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    let is_index =
+        is_index.ok_or_else(|| anyhow!("Expected a <urlset> or <sitemapindex> root element"))?;
+    Ok(Sitemap { is_index, locs })
+}
+
+fn build_client(config: &ScraperConfig) -> Result<reqwest::Client, ScrapeError> {
+    config.build_client().map_err(|e| ScrapeError::Network {
+        message: format!("Failed to build HTTP client: {}", e),
+        timed_out: false,
+    })
+}
+
+/// Counts downloaded bytes against the run's budget
+fn charge_bytes(len: usize) -> Result<(), ScrapeError> {
+    crate::models::runtime_sessions::charge_bytes(len)
+        .map_err(|e| ScrapeError::BudgetExceeded(e.to_string()))
+}
+
+/// Finds an `SsrfBlocked` raised by the redirect policy or resolver inside a reqwest error
+fn ssrf_cause(err: &reqwest::Error) -> Option<&SsrfBlocked> {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(err);
+    while let Some(cause) = source {
+        if let Some(blocked) = cause.downcast_ref::<SsrfBlocked>() {
+            return Some(blocked);
+        }
+        source = cause.source();
+    }
+    None
+}
+
 /// Extract the title from the HTML document
 fn extract_title(document: &Html) -> Option<String> {
     let title_selector = Selector::parse("title").ok()?;
@@ -380,7 +1440,7 @@ fn is_main_content(element: &scraper::ElementRef) -> bool {
 }
 
 /// Extract the main content from the HTML document with filtering
-fn extract_filtered_content(document: &Html) -> Vec<Value> {
+fn extract_filtered_content(document: &Html, preserve_code: bool) -> Vec<Value> {
     let mut content = Vec::new();
 
     // Try to find the main content container
@@ -414,19 +1474,17 @@ fn extract_filtered_content(document: &Html) -> Vec<Value> {
 
     // If we found a main container, extract content from it
     // Otherwise, fall back to the whole document
-    let target_document = main_container
-        .map(|container| container)
-        .unwrap_or_else(|| {
-            // If no main container found, use the body
-            if let Ok(body_selector) = Selector::parse("body") {
-                document.select(&body_selector).next().unwrap_or_else(|| {
-                    // If no body found, use the document root
-                    document.root_element()
-                })
-            } else {
+    let target_document = main_container.unwrap_or_else(|| {
+        // If no main container found, use the body
+        if let Ok(body_selector) = Selector::parse("body") {
+            document.select(&body_selector).next().unwrap_or_else(|| {
+                // If no body found, use the document root
                 document.root_element()
-            }
-        });
+            })
+        } else {
+            document.root_element()
+        }
+    });
 
     // Extract headings (h1-h6)
     for level in 1..=6 {
@@ -452,7 +1510,7 @@ fn extract_filtered_content(document: &Html) -> Vec<Value> {
                 continue;
             }
 
-            let text = clean_text(&element.text().collect::<Vec<_>>().join(""));
+            let text = clean_text(&element_text(&element, preserve_code));
             if !text.is_empty() && text.split_whitespace().count() > 3 {
                 content.push(json!({
                     "type": "paragraph",
@@ -462,6 +1520,26 @@ fn extract_filtered_content(document: &Html) -> Vec<Value> {
         }
     }
 
+    // Extract code blocks, whitespace intact
+    if preserve_code {
+        if let Ok(pre_selector) = Selector::parse("pre") {
+            for element in target_document.select(&pre_selector) {
+                if is_in_non_content_area(&element) {
+                    continue;
+                }
+
+                let text = clean_code(&element.text().collect::<Vec<_>>().join(""));
+                if !text.is_empty() {
+                    content.push(json!({
+                        "type": "code",
+                        "language": code_language(&element),
+                        "text": text
+                    }));
+                }
+            }
+        }
+    }
+
     // Extract lists
     if let (Ok(ul_selector), Ok(li_selector)) = (Selector::parse("ul"), Selector::parse("li")) {
         for ul in target_document.select(&ul_selector) {
@@ -472,7 +1550,7 @@ fn extract_filtered_content(document: &Html) -> Vec<Value> {
 
             let mut items = Vec::new();
             for li in ul.select(&li_selector) {
-                let text = clean_text(&li.text().collect::<Vec<_>>().join(""));
+                let text = clean_text(&element_text(&li, preserve_code));
                 if !text.is_empty() {
                     items.push(Value::String(text));
                 }
@@ -629,14 +1707,70 @@ fn is_in_non_content_area(element: &scraper::ElementRef) -> bool {
         }
 
         // Move to parent element
-        current = el
-            .parent()
-            .and_then(|parent| scraper::ElementRef::wrap(parent));
+        current = el.parent().and_then(scraper::ElementRef::wrap);
     }
 
     false
 }
 
+/// The element's text, with inline `<code>` wrapped in backticks when `preserve_code` is set
+fn element_text(element: &scraper::ElementRef, preserve_code: bool) -> String {
+    if !preserve_code {
+        return element.text().collect();
+    }
+
+    let mut text = String::new();
+    for child in element.children() {
+        if let Some(text_node) = child.value().as_text() {
+            text.push_str(text_node);
+        } else if let Some(child_element) = scraper::ElementRef::wrap(child) {
+            if child_element.value().name() == "code" {
+                let code = clean_text(&child_element.text().collect::<String>());
+                if !code.is_empty() {
+                    text.push('`');
+                    text.push_str(&code);
+                    text.push('`');
+                }
+            } else {
+                text.push_str(&element_text(&child_element, preserve_code));
+            }
+        }
+    }
+    text
+}
+
+/// Language of a code block, from a `language-*` or `lang-*` class on the `<pre>`
+/// or the `<code>` inside it
+fn code_language(pre: &scraper::ElementRef) -> Option<String> {
+    let inner_code = Selector::parse("code")
+        .ok()
+        .and_then(|selector| pre.select(&selector).next());
+
+    std::iter::once(*pre)
+        .chain(inner_code)
+        .flat_map(|element| element.value().classes())
+        .find_map(|class| {
+            class
+                .strip_prefix("language-")
+                .or_else(|| class.strip_prefix("lang-"))
+        })
+        .filter(|language| !language.is_empty())
+        .map(str::to_string)
+}
+
+/// Helper function to tidy a code block: only blank lines at either end and
+/// trailing whitespace are dropped, so newlines and indentation survive
+fn clean_code(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+    let start = lines.iter().position(|line| !line.is_empty());
+    let end = lines.iter().rposition(|line| !line.is_empty());
+
+    match (start, end) {
+        (Some(start), Some(end)) => lines[start..=end].join("\n"),
+        _ => String::new(),
+    }
+}
+
 /// Helper function to clean up text by removing extra whitespace and normalizing
 fn clean_text(text: &str) -> String {
     // Remove extra whitespace
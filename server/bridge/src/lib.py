@@ -1,3 +1,4 @@
+import asyncio
 import logging
 import json
 import uuid
@@ -37,6 +38,8 @@ class BridgeClient:
         self.port = port
         self.channel: grpc.aio.Channel | None = None
         self.stub: pb2_grpc.BridgeServiceStub | None = None
+        # Run signals still waiting on their ProcessSignal call (see `start_run`)
+        self.pending_runs: set[asyncio.Task] = set()
 
     async def connect(self) -> bool:
         """Connect to the gRPC server"""
@@ -103,20 +106,13 @@ class BridgeClient:
             # Process the actual signal based on the data
             signal_request = await create_signal_request(data)
             if signal_request:
-                response = await self.process_signal(signal_request)
-                if response and getattr(response, "success", False):
-                    logger.info(
-                        f"Successfully sent {meta} message: {sanitize_data(getattr(response, 'message', ''))}"
-                    )
+                if signal_request.signal_type == pb2.SignalType.RUN:
+                    # ProcessSignal only returns once the run finishes, so don't hold up
+                    # the next signal on it
+                    self.start_run(signal_request, meta)
                     return True
-                else:
-                    error_msg = (
-                        getattr(response, "message", "No response received")
-                        if response
-                        else "No response received"
-                    )
-                    logger.error(f"Failed to send {meta} message: {error_msg}")
-                    return False
+                response = await self.process_signal(signal_request)
+                return self.check_response(response, meta)
             else:
                 logger.error(
                     f"Failed to create signal request from data: {sanitize_data(data)}"
@@ -126,6 +122,35 @@ class BridgeClient:
             logger.error(f"Error sending message to engine: {sanitize_data(str(e))}")
             return False
 
+    def start_run(self, signal_request: Any, meta: str = "signal") -> asyncio.Task:
+        """Send a Run signal in the background, logging its outcome once it finishes"""
+        task = asyncio.create_task(self.process_signal(signal_request))
+        # Keep a reference until it's done, otherwise the call can be garbage collected
+        self.pending_runs.add(task)
+
+        def on_done(done: asyncio.Task) -> None:
+            self.pending_runs.discard(done)
+            if not done.cancelled():
+                self.check_response(done.result(), meta)
+
+        task.add_done_callback(on_done)
+        return task
+
+    def check_response(self, response: Any, meta: str = "signal") -> bool:
+        """Log whether the engine processed a signal successfully"""
+        if response and getattr(response, "success", False):
+            logger.info(
+                f"Successfully sent {meta} message: {sanitize_data(getattr(response, 'message', ''))}"
+            )
+            return True
+        error_msg = (
+            getattr(response, "message", "No response received")
+            if response
+            else "No response received"
+        )
+        logger.error(f"Failed to send {meta} message: {error_msg}")
+        return False
+
     async def close(self):
         """Close the gRPC channel"""
         if self.channel:
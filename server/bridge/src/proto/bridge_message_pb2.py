@@ -22,7 +22,7 @@ from google.protobuf import struct_pb2 as google_dot_protobuf_dot_struct__pb2
 
 
 DESCRIPTOR = _descriptor_pool.Default().AddSerializedFile(
-    b'\n\x14\x62ridge_message.proto\x12\x07portico\x1a\x1cgoogle/protobuf/struct.proto"(\n\x11ServerInitRequest\x12\x13\n\x0bserver_init\x18\x01 \x01(\x08"3\n\x0fGeneralResponse\x12\x0f\n\x07success\x18\x01 \x01(\x08\x12\x0f\n\x07message\x18\x02 \x01(\t"\xe9\x01\n\rSignalRequest\x12\x11\n\tsignal_id\x18\x01 \x01(\x05\x12\x10\n\x08\x61gent_id\x18\x02 \x01(\x05\x12(\n\x0bsignal_type\x18\x03 \x01(\x0e\x32\x13.portico.SignalType\x12+\n\x08run_data\x18\x04 \x01(\x0b\x32\x17.google.protobuf.StructH\x00\x12$\n\x04sync\x18\x05 \x01(\x0b\x32\x14.portico.SyncPayloadH\x00\x12+\n\x08\x66yi_data\x18\x06 \x01(\x0b\x32\x17.google.protobuf.StructH\x00\x42\t\n\x07payload"~\n\x0eSignalResponse\x12\x0f\n\x07success\x18\x01 \x01(\x08\x12\x0f\n\x07message\x18\x02 \x01(\t\x12\x1c\n\x14runtime_session_uuid\x18\x03 \x01(\t\x12,\n\x0bresult_data\x18\x04 \x01(\x0b\x32\x17.google.protobuf.Struct"A\n\x12\x43reateAgentRequest\x12+\n\nagent_json\x18\x01 \x01(\x0b\x32\x17.google.protobuf.Struct"&\n\x12\x44\x65leteAgentRequest\x12\x10\n\x08\x61gent_id\x18\x01 \x01(\x05"E\n\x0bSyncPayload\x12!\n\x05scope\x18\x01 \x01(\x0e\x32\x12.portico.SyncScope\x12\x13\n\x0b\x61gent_uuids\x18\x02 \x03(\t*(\n\nSignalType\x12\x07\n\x03RUN\x10\x00\x12\x08\n\x04SYNC\x10\x01\x12\x07\n\x03\x46YI\x10\x02*"\n\tSyncScope\x12\x07\n\x03\x41LL\x10\x00\x12\x0c\n\x08SPECIFIC\x10\x01\x32\xa1\x02\n\rBridgeService\x12\x42\n\nInitServer\x12\x1a.portico.ServerInitRequest\x1a\x18.portico.GeneralResponse\x12@\n\rProcessSignal\x12\x16.portico.SignalRequest\x1a\x17.portico.SignalResponse\x12\x44\n\x0b\x43reateAgent\x12\x1b.portico.CreateAgentRequest\x1a\x18.portico.GeneralResponse\x12\x44\n\x0b\x44\x65leteAgent\x12\x1b.portico.DeleteAgentRequest\x1a\x18.portico.GeneralResponseb\x06proto3'
+    b'\n\x14\x62ridge_message.proto\x12\x07portico\x1a\x1cgoogle/protobuf/struct.proto"(\n\x11ServerInitRequest\x12\x13\n\x0bserver_init\x18\x01 \x01(\x08"3\n\x0fGeneralResponse\x12\x0f\n\x07success\x18\x01 \x01(\x08\x12\x0f\n\x07message\x18\x02 \x01(\t"\xe9\x01\n\rSignalRequest\x12\x11\n\tsignal_id\x18\x01 \x01(\x05\x12\x10\n\x08\x61gent_id\x18\x02 \x01(\x05\x12(\n\x0bsignal_type\x18\x03 \x01(\x0e\x32\x13.portico.SignalType\x12+\n\x08run_data\x18\x04 \x01(\x0b\x32\x17.google.protobuf.StructH\x00\x12$\n\x04sync\x18\x05 \x01(\x0b\x32\x14.portico.SyncPayloadH\x00\x12+\n\x08\x66yi_data\x18\x06 \x01(\x0b\x32\x17.google.protobuf.StructH\x00\x42\t\n\x07payload"\xaf\x01\n\x0eSignalResponse\x12\x0f\n\x07success\x18\x01 \x01(\x08\x12\x0f\n\x07message\x18\x02 \x01(\t\x12\x1c\n\x14runtime_session_uuid\x18\x03 \x01(\t\x12,\n\x0bresult_data\x18\x04 \x01(\x0b\x32\x17.google.protobuf.Struct\x12\x0e\n\x06status\x18\x05 \x01(\t\x12\x1f\n\x17total_execution_time_ms\x18\x06 \x01(\x04"?\n\x14SubmitSignalsRequest\x12\'\n\x07signals\x18\x01 \x03(\x0b\x32\x16.portico.SignalRequest"]\n\x15SubmitSignalsResponse\x12,\n\x07results\x18\x01 \x03(\x0b\x32\x1b.portico.SignalAcceptResult\x12\x16\n\x0e\x65nqueued_count\x18\x02 \x01(\r"q\n\x12SignalAcceptResult\x12\x11\n\tsignal_id\x18\x01 \x01(\x05\x12\x10\n\x08\x61gent_id\x18\x02 \x01(\x05\x12%\n\x06status\x18\x03 \x01(\x0e\x32\x15.portico.AcceptStatus\x12\x0f\n\x07message\x18\x04 \x01(\t"A\n\x12\x43reateAgentRequest\x12+\n\nagent_json\x18\x01 \x01(\x0b\x32\x17.google.protobuf.Struct"&\n\x12\x44\x65leteAgentRequest\x12\x10\n\x08\x61gent_id\x18\x01 \x01(\x05"U\n\x17UpdateAgentStepsRequest\x12\x12\n\nagent_uuid\x18\x01 \x01(\t\x12&\n\x05steps\x18\x02 \x03(\x0b\x32\x17.google.protobuf.Struct"S\n\x17ReprocessSignalsRequest\x12\x12\n\nagent_uuid\x18\x01 \x01(\t\x12\x15\n\rstatus_filter\x18\x02 \x01(\t\x12\r\n\x05since\x18\x03 \x01(\t"`\n\x18ReprocessSignalsResponse\x12,\n\x07results\x18\x01 \x03(\x0b\x32\x1b.portico.SignalAcceptResult\x12\x16\n\x0e\x65nqueued_count\x18\x02 \x01(\r"n\n\x11PreviewRunRequest\x12+\n\nagent_json\x18\x01 \x01(\x0b\x32\x17.google.protobuf.Struct\x12,\n\x0bsource_data\x18\x02 \x01(\x0b\x32\x17.google.protobuf.Struct"d\n\x12PreviewRunResponse\x12\x0f\n\x07success\x18\x01 \x01(\x08\x12\x0f\n\x07message\x18\x02 \x01(\t\x12,\n\x0bresult_data\x18\x03 \x01(\x0b\x32\x17.google.protobuf.Struct"D\n\x16PreviewStepCodeRequest\x12*\n\tstep_json\x18\x01 \x01(\x0b\x32\x17.google.protobuf.Struct">\n\x17PreviewStepCodeResponse\x12\x15\n\rfunction_name\x18\x01 \x01(\t\x12\x0c\n\x04\x63ode\x18\x02 \x01(\t"\x92\x01\n\x18PreviewStepPromptRequest\x12*\n\tstep_json\x18\x01 \x01(\x0b\x32\x17.google.protobuf.Struct\x12,\n\x0bsource_data\x18\x02 \x01(\x0b\x32\x17.google.protobuf.Struct\x12\x1c\n\x14legacy_prompt_format\x18\x03 \x01(\x08"B\n\x19PreviewStepPromptResponse\x12\x0e\n\x06prompt\x18\x01 \x01(\t\x12\x15\n\rsystem_prompt\x18\x02 \x01(\t"E\n\x0bSyncPayload\x12!\n\x05scope\x18\x01 \x01(\x0e\x32\x12.portico.SyncScope\x12\x13\n\x0b\x61gent_uuids\x18\x02 \x03(\t*(\n\nSignalType\x12\x07\n\x03RUN\x10\x00\x12\x08\n\x04SYNC\x10\x01\x12\x07\n\x03\x46YI\x10\x02*p\n\x0c\x41\x63\x63\x65ptStatus\x12\x0c\n\x08\x45NQUEUED\x10\x00\x12\x17\n\x13REJECTED_QUEUE_FULL\x10\x01\x12\x11\n\rUNKNOWN_AGENT\x10\x02\x12\x12\n\x0eINVALID_SIGNAL\x10\x03\x12\x12\n\x0e\x41LREADY_QUEUED\x10\x04*"\n\tSyncScope\x12\x07\n\x03\x41LL\x10\x00\x12\x0c\n\x08SPECIFIC\x10\x01\x32\x93\x06\n\rBridgeService\x12\x42\n\nInitServer\x12\x1a.portico.ServerInitRequest\x1a\x18.portico.GeneralResponse\x12@\n\rProcessSignal\x12\x16.portico.SignalRequest\x1a\x17.portico.SignalResponse\x12N\n\rSubmitSignals\x12\x1d.portico.SubmitSignalsRequest\x1a\x1e.portico.SubmitSignalsResponse\x12\x44\n\x0b\x43reateAgent\x12\x1b.portico.CreateAgentRequest\x1a\x18.portico.GeneralResponse\x12\x44\n\x0b\x44\x65leteAgent\x12\x1b.portico.DeleteAgentRequest\x1a\x18.portico.GeneralResponse\x12N\n\x10UpdateAgentSteps\x12 .portico.UpdateAgentStepsRequest\x1a\x18.portico.GeneralResponse\x12W\n\x10ReprocessSignals\x12 .portico.ReprocessSignalsRequest\x1a!.portico.ReprocessSignalsResponse\x12\x45\n\nPreviewRun\x12\x1a.portico.PreviewRunRequest\x1a\x1b.portico.PreviewRunResponse\x12T\n\x0fPreviewStepCode\x12\x1f.portico.PreviewStepCodeRequest\x1a .portico.PreviewStepCodeResponse\x12Z\n\x11PreviewStepPrompt\x12!.portico.PreviewStepPromptRequest\x1a".portico.PreviewStepPromptResponseb\x06proto3'
 )
 
 _globals = globals()
@@ -30,24 +30,50 @@ _builder.BuildMessageAndEnumDescriptors(DESCRIPTOR, _globals)
 _builder.BuildTopDescriptorsAndMessages(DESCRIPTOR, "bridge_message_pb2", _globals)
 if not _descriptor._USE_C_DESCRIPTORS:
     DESCRIPTOR._loaded_options = None
-    _globals["_SIGNALTYPE"]._serialized_start = 700
-    _globals["_SIGNALTYPE"]._serialized_end = 740
-    _globals["_SYNCSCOPE"]._serialized_start = 742
-    _globals["_SYNCSCOPE"]._serialized_end = 776
+    _globals["_SIGNALTYPE"]._serialized_start = 1860
+    _globals["_SIGNALTYPE"]._serialized_end = 1900
+    _globals["_ACCEPTSTATUS"]._serialized_start = 1902
+    _globals["_ACCEPTSTATUS"]._serialized_end = 2014
+    _globals["_SYNCSCOPE"]._serialized_start = 2016
+    _globals["_SYNCSCOPE"]._serialized_end = 2050
     _globals["_SERVERINITREQUEST"]._serialized_start = 63
     _globals["_SERVERINITREQUEST"]._serialized_end = 103
     _globals["_GENERALRESPONSE"]._serialized_start = 105
     _globals["_GENERALRESPONSE"]._serialized_end = 156
     _globals["_SIGNALREQUEST"]._serialized_start = 159
     _globals["_SIGNALREQUEST"]._serialized_end = 392
-    _globals["_SIGNALRESPONSE"]._serialized_start = 394
-    _globals["_SIGNALRESPONSE"]._serialized_end = 520
-    _globals["_CREATEAGENTREQUEST"]._serialized_start = 522
-    _globals["_CREATEAGENTREQUEST"]._serialized_end = 587
-    _globals["_DELETEAGENTREQUEST"]._serialized_start = 589
-    _globals["_DELETEAGENTREQUEST"]._serialized_end = 627
-    _globals["_SYNCPAYLOAD"]._serialized_start = 629
-    _globals["_SYNCPAYLOAD"]._serialized_end = 698
-    _globals["_BRIDGESERVICE"]._serialized_start = 779
-    _globals["_BRIDGESERVICE"]._serialized_end = 1068
+    _globals["_SIGNALRESPONSE"]._serialized_start = 395
+    _globals["_SIGNALRESPONSE"]._serialized_end = 570
+    _globals["_SUBMITSIGNALSREQUEST"]._serialized_start = 572
+    _globals["_SUBMITSIGNALSREQUEST"]._serialized_end = 635
+    _globals["_SUBMITSIGNALSRESPONSE"]._serialized_start = 637
+    _globals["_SUBMITSIGNALSRESPONSE"]._serialized_end = 730
+    _globals["_SIGNALACCEPTRESULT"]._serialized_start = 732
+    _globals["_SIGNALACCEPTRESULT"]._serialized_end = 845
+    _globals["_CREATEAGENTREQUEST"]._serialized_start = 847
+    _globals["_CREATEAGENTREQUEST"]._serialized_end = 912
+    _globals["_DELETEAGENTREQUEST"]._serialized_start = 914
+    _globals["_DELETEAGENTREQUEST"]._serialized_end = 952
+    _globals["_UPDATEAGENTSTEPSREQUEST"]._serialized_start = 954
+    _globals["_UPDATEAGENTSTEPSREQUEST"]._serialized_end = 1039
+    _globals["_REPROCESSSIGNALSREQUEST"]._serialized_start = 1041
+    _globals["_REPROCESSSIGNALSREQUEST"]._serialized_end = 1124
+    _globals["_REPROCESSSIGNALSRESPONSE"]._serialized_start = 1126
+    _globals["_REPROCESSSIGNALSRESPONSE"]._serialized_end = 1222
+    _globals["_PREVIEWRUNREQUEST"]._serialized_start = 1224
+    _globals["_PREVIEWRUNREQUEST"]._serialized_end = 1334
+    _globals["_PREVIEWRUNRESPONSE"]._serialized_start = 1336
+    _globals["_PREVIEWRUNRESPONSE"]._serialized_end = 1436
+    _globals["_PREVIEWSTEPCODEREQUEST"]._serialized_start = 1438
+    _globals["_PREVIEWSTEPCODEREQUEST"]._serialized_end = 1506
+    _globals["_PREVIEWSTEPCODERESPONSE"]._serialized_start = 1508
+    _globals["_PREVIEWSTEPCODERESPONSE"]._serialized_end = 1570
+    _globals["_PREVIEWSTEPPROMPTREQUEST"]._serialized_start = 1573
+    _globals["_PREVIEWSTEPPROMPTREQUEST"]._serialized_end = 1719
+    _globals["_PREVIEWSTEPPROMPTRESPONSE"]._serialized_start = 1721
+    _globals["_PREVIEWSTEPPROMPTRESPONSE"]._serialized_end = 1787
+    _globals["_SYNCPAYLOAD"]._serialized_start = 1789
+    _globals["_SYNCPAYLOAD"]._serialized_end = 1858
+    _globals["_BRIDGESERVICE"]._serialized_start = 2053
+    _globals["_BRIDGESERVICE"]._serialized_end = 2840
 # @@protoc_insertion_point(module_scope)
@@ -49,6 +49,12 @@ class BridgeServiceStub(object):
             response_deserializer=bridge__message__pb2.SignalResponse.FromString,
             _registered_method=True,
         )
+        self.SubmitSignals = channel.unary_unary(
+            "/portico.BridgeService/SubmitSignals",
+            request_serializer=bridge__message__pb2.SubmitSignalsRequest.SerializeToString,
+            response_deserializer=bridge__message__pb2.SubmitSignalsResponse.FromString,
+            _registered_method=True,
+        )
         self.CreateAgent = channel.unary_unary(
             "/portico.BridgeService/CreateAgent",
             request_serializer=bridge__message__pb2.CreateAgentRequest.SerializeToString,
@@ -61,6 +67,36 @@ class BridgeServiceStub(object):
             response_deserializer=bridge__message__pb2.GeneralResponse.FromString,
             _registered_method=True,
         )
+        self.UpdateAgentSteps = channel.unary_unary(
+            "/portico.BridgeService/UpdateAgentSteps",
+            request_serializer=bridge__message__pb2.UpdateAgentStepsRequest.SerializeToString,
+            response_deserializer=bridge__message__pb2.GeneralResponse.FromString,
+            _registered_method=True,
+        )
+        self.ReprocessSignals = channel.unary_unary(
+            "/portico.BridgeService/ReprocessSignals",
+            request_serializer=bridge__message__pb2.ReprocessSignalsRequest.SerializeToString,
+            response_deserializer=bridge__message__pb2.ReprocessSignalsResponse.FromString,
+            _registered_method=True,
+        )
+        self.PreviewRun = channel.unary_unary(
+            "/portico.BridgeService/PreviewRun",
+            request_serializer=bridge__message__pb2.PreviewRunRequest.SerializeToString,
+            response_deserializer=bridge__message__pb2.PreviewRunResponse.FromString,
+            _registered_method=True,
+        )
+        self.PreviewStepCode = channel.unary_unary(
+            "/portico.BridgeService/PreviewStepCode",
+            request_serializer=bridge__message__pb2.PreviewStepCodeRequest.SerializeToString,
+            response_deserializer=bridge__message__pb2.PreviewStepCodeResponse.FromString,
+            _registered_method=True,
+        )
+        self.PreviewStepPrompt = channel.unary_unary(
+            "/portico.BridgeService/PreviewStepPrompt",
+            request_serializer=bridge__message__pb2.PreviewStepPromptRequest.SerializeToString,
+            response_deserializer=bridge__message__pb2.PreviewStepPromptResponse.FromString,
+            _registered_method=True,
+        )
 
 
 class BridgeServiceServicer(object):
@@ -78,6 +114,12 @@ class BridgeServiceServicer(object):
         context.set_details("Method not implemented!")
         raise NotImplementedError("Method not implemented!")
 
+    def SubmitSignals(self, request, context):
+        """Queue many Run signals in one call, without waiting for them to run"""
+        context.set_code(grpc.StatusCode.UNIMPLEMENTED)
+        context.set_details("Method not implemented!")
+        raise NotImplementedError("Method not implemented!")
+
     def CreateAgent(self, request, context):
         """Process changes"""
         context.set_code(grpc.StatusCode.UNIMPLEMENTED)
@@ -90,6 +132,36 @@ class BridgeServiceServicer(object):
         context.set_details("Method not implemented!")
         raise NotImplementedError("Method not implemented!")
 
+    def UpdateAgentSteps(self, request, context):
+        """Replace a loaded agent's steps, e.g. after they were edited in the UI"""
+        context.set_code(grpc.StatusCode.UNIMPLEMENTED)
+        context.set_details("Method not implemented!")
+        raise NotImplementedError("Method not implemented!")
+
+    def ReprocessSignals(self, request, context):
+        """Queue an agent's past Run signals again, e.g. the failed ones after its steps were fixed"""
+        context.set_code(grpc.StatusCode.UNIMPLEMENTED)
+        context.set_details("Method not implemented!")
+        raise NotImplementedError("Method not implemented!")
+
+    def PreviewRun(self, request, context):
+        """Run an agent (e.g. one not created yet) on sample data and return its result, saving nothing"""
+        context.set_code(grpc.StatusCode.UNIMPLEMENTED)
+        context.set_details("Method not implemented!")
+        raise NotImplementedError("Method not implemented!")
+
+    def PreviewStepCode(self, request, context):
+        """The Python function generated for a step, as it runs in the runtime, for debugging"""
+        context.set_code(grpc.StatusCode.UNIMPLEMENTED)
+        context.set_details("Method not implemented!")
+        raise NotImplementedError("Method not implemented!")
+
+    def PreviewStepPrompt(self, request, context):
+        """The prompt a Prompt step would send for some input, without calling the model"""
+        context.set_code(grpc.StatusCode.UNIMPLEMENTED)
+        context.set_details("Method not implemented!")
+        raise NotImplementedError("Method not implemented!")
+
 
 def add_BridgeServiceServicer_to_server(servicer, server):
     rpc_method_handlers = {
@@ -103,6 +175,11 @@ def add_BridgeServiceServicer_to_server(servicer, server):
             request_deserializer=bridge__message__pb2.SignalRequest.FromString,
             response_serializer=bridge__message__pb2.SignalResponse.SerializeToString,
         ),
+        "SubmitSignals": grpc.unary_unary_rpc_method_handler(
+            servicer.SubmitSignals,
+            request_deserializer=bridge__message__pb2.SubmitSignalsRequest.FromString,
+            response_serializer=bridge__message__pb2.SubmitSignalsResponse.SerializeToString,
+        ),
         "CreateAgent": grpc.unary_unary_rpc_method_handler(
             servicer.CreateAgent,
             request_deserializer=bridge__message__pb2.CreateAgentRequest.FromString,
@@ -113,6 +190,31 @@ def add_BridgeServiceServicer_to_server(servicer, server):
             request_deserializer=bridge__message__pb2.DeleteAgentRequest.FromString,
             response_serializer=bridge__message__pb2.GeneralResponse.SerializeToString,
         ),
+        "UpdateAgentSteps": grpc.unary_unary_rpc_method_handler(
+            servicer.UpdateAgentSteps,
+            request_deserializer=bridge__message__pb2.UpdateAgentStepsRequest.FromString,
+            response_serializer=bridge__message__pb2.GeneralResponse.SerializeToString,
+        ),
+        "ReprocessSignals": grpc.unary_unary_rpc_method_handler(
+            servicer.ReprocessSignals,
+            request_deserializer=bridge__message__pb2.ReprocessSignalsRequest.FromString,
+            response_serializer=bridge__message__pb2.ReprocessSignalsResponse.SerializeToString,
+        ),
+        "PreviewRun": grpc.unary_unary_rpc_method_handler(
+            servicer.PreviewRun,
+            request_deserializer=bridge__message__pb2.PreviewRunRequest.FromString,
+            response_serializer=bridge__message__pb2.PreviewRunResponse.SerializeToString,
+        ),
+        "PreviewStepCode": grpc.unary_unary_rpc_method_handler(
+            servicer.PreviewStepCode,
+            request_deserializer=bridge__message__pb2.PreviewStepCodeRequest.FromString,
+            response_serializer=bridge__message__pb2.PreviewStepCodeResponse.SerializeToString,
+        ),
+        "PreviewStepPrompt": grpc.unary_unary_rpc_method_handler(
+            servicer.PreviewStepPrompt,
+            request_deserializer=bridge__message__pb2.PreviewStepPromptRequest.FromString,
+            response_serializer=bridge__message__pb2.PreviewStepPromptResponse.SerializeToString,
+        ),
     }
     generic_handler = grpc.method_handlers_generic_handler(
         "portico.BridgeService", rpc_method_handlers
@@ -185,6 +287,36 @@ class BridgeService(object):
             _registered_method=True,
         )
 
+    @staticmethod
+    def SubmitSignals(
+        request,
+        target,
+        options=(),
+        channel_credentials=None,
+        call_credentials=None,
+        insecure=False,
+        compression=None,
+        wait_for_ready=None,
+        timeout=None,
+        metadata=None,
+    ):
+        return grpc.experimental.unary_unary(
+            request,
+            target,
+            "/portico.BridgeService/SubmitSignals",
+            bridge__message__pb2.SubmitSignalsRequest.SerializeToString,
+            bridge__message__pb2.SubmitSignalsResponse.FromString,
+            options,
+            channel_credentials,
+            insecure,
+            call_credentials,
+            compression,
+            wait_for_ready,
+            timeout,
+            metadata,
+            _registered_method=True,
+        )
+
     @staticmethod
     def CreateAgent(
         request,
@@ -244,3 +376,153 @@ class BridgeService(object):
             metadata,
             _registered_method=True,
         )
+
+    @staticmethod
+    def UpdateAgentSteps(
+        request,
+        target,
+        options=(),
+        channel_credentials=None,
+        call_credentials=None,
+        insecure=False,
+        compression=None,
+        wait_for_ready=None,
+        timeout=None,
+        metadata=None,
+    ):
+        return grpc.experimental.unary_unary(
+            request,
+            target,
+            "/portico.BridgeService/UpdateAgentSteps",
+            bridge__message__pb2.UpdateAgentStepsRequest.SerializeToString,
+            bridge__message__pb2.GeneralResponse.FromString,
+            options,
+            channel_credentials,
+            insecure,
+            call_credentials,
+            compression,
+            wait_for_ready,
+            timeout,
+            metadata,
+            _registered_method=True,
+        )
+
+    @staticmethod
+    def ReprocessSignals(
+        request,
+        target,
+        options=(),
+        channel_credentials=None,
+        call_credentials=None,
+        insecure=False,
+        compression=None,
+        wait_for_ready=None,
+        timeout=None,
+        metadata=None,
+    ):
+        return grpc.experimental.unary_unary(
+            request,
+            target,
+            "/portico.BridgeService/ReprocessSignals",
+            bridge__message__pb2.ReprocessSignalsRequest.SerializeToString,
+            bridge__message__pb2.ReprocessSignalsResponse.FromString,
+            options,
+            channel_credentials,
+            insecure,
+            call_credentials,
+            compression,
+            wait_for_ready,
+            timeout,
+            metadata,
+            _registered_method=True,
+        )
+
+    @staticmethod
+    def PreviewRun(
+        request,
+        target,
+        options=(),
+        channel_credentials=None,
+        call_credentials=None,
+        insecure=False,
+        compression=None,
+        wait_for_ready=None,
+        timeout=None,
+        metadata=None,
+    ):
+        return grpc.experimental.unary_unary(
+            request,
+            target,
+            "/portico.BridgeService/PreviewRun",
+            bridge__message__pb2.PreviewRunRequest.SerializeToString,
+            bridge__message__pb2.PreviewRunResponse.FromString,
+            options,
+            channel_credentials,
+            insecure,
+            call_credentials,
+            compression,
+            wait_for_ready,
+            timeout,
+            metadata,
+            _registered_method=True,
+        )
+
+    @staticmethod
+    def PreviewStepCode(
+        request,
+        target,
+        options=(),
+        channel_credentials=None,
+        call_credentials=None,
+        insecure=False,
+        compression=None,
+        wait_for_ready=None,
+        timeout=None,
+        metadata=None,
+    ):
+        return grpc.experimental.unary_unary(
+            request,
+            target,
+            "/portico.BridgeService/PreviewStepCode",
+            bridge__message__pb2.PreviewStepCodeRequest.SerializeToString,
+            bridge__message__pb2.PreviewStepCodeResponse.FromString,
+            options,
+            channel_credentials,
+            insecure,
+            call_credentials,
+            compression,
+            wait_for_ready,
+            timeout,
+            metadata,
+            _registered_method=True,
+        )
+
+    @staticmethod
+    def PreviewStepPrompt(
+        request,
+        target,
+        options=(),
+        channel_credentials=None,
+        call_credentials=None,
+        insecure=False,
+        compression=None,
+        wait_for_ready=None,
+        timeout=None,
+        metadata=None,
+    ):
+        return grpc.experimental.unary_unary(
+            request,
+            target,
+            "/portico.BridgeService/PreviewStepPrompt",
+            bridge__message__pb2.PreviewStepPromptRequest.SerializeToString,
+            bridge__message__pb2.PreviewStepPromptResponse.FromString,
+            options,
+            channel_credentials,
+            insecure,
+            call_credentials,
+            compression,
+            wait_for_ready,
+            timeout,
+            metadata,
+            _registered_method=True,
+        )
@@ -1,3 +1,4 @@
+import asyncio
 import uuid
 import pytest
 from unittest.mock import AsyncMock, MagicMock, patch
@@ -175,3 +176,33 @@ async def test_bridge_client():
     # Verify the stub was called correctly
     mock_stub.InitServer.assert_called_once()
     mock_stub.ProcessSignal.assert_called_once_with(request)
+
+
+@pytest.mark.asyncio
+async def test_send_signal_does_not_wait_for_runs(signal_data):
+    """Run signals are sent in the background, so one long run doesn't block the next"""
+    run_finished = asyncio.Event()
+    mock_signal_response = MagicMock()
+    mock_signal_response.success = True
+    mock_signal_response.message = "Processed"
+
+    async def slow_process_signal(request):
+        await run_finished.wait()
+        return mock_signal_response
+
+    mock_stub = AsyncMock()
+    mock_stub.ProcessSignal.side_effect = slow_process_signal
+    client = BridgeClient("localhost", 50051)
+    client.stub = mock_stub
+
+    # Both return while their runs are still going
+    assert await client.send_signal(signal_data) is True
+    assert await client.send_signal(signal_data) is True
+    await asyncio.sleep(0)
+    assert mock_stub.ProcessSignal.call_count == 2
+    assert len(client.pending_runs) == 2
+
+    run_finished.set()
+    await asyncio.gather(*client.pending_runs)
+    await asyncio.sleep(0)
+    assert not client.pending_runs
@@ -1,4 +1,9 @@
+use std::path::{Path, PathBuf};
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // The descriptor set backs the gRPC reflection service
+    let descriptor_path = PathBuf::from(std::env::var("OUT_DIR")?).join("portico_descriptor.bin");
+
     // Try different paths for the proto file to handle both local and Docker environments
     let proto_paths = [
         "../proto/bridge_message.proto",           // Local development path
@@ -11,7 +16,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut last_error = None;
 
     for path in proto_paths {
-        match tonic_build::compile_protos(path) {
+        let include_dir = Path::new(path).parent().unwrap_or(Path::new("."));
+        match tonic_build::configure()
+            .file_descriptor_set_path(&descriptor_path)
+            .compile(&[path], &[include_dir])
+        {
             Ok(_) => {
                 success = true;
                 println!("Successfully compiled proto from: {}", path);
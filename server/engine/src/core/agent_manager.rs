@@ -1,23 +1,96 @@
+use crate::core::agent_queue::{AgentQueue, IdleReceiver};
+use crate::core::dead_letter::DeadLetter;
 use crate::handlers::{run, fyi, sync};
 use crate::proto::{SignalRequest, SignalResponse, SignalType};
 use crate::proto_struct_to_json;
 use crate::SharedAgentMap;
-use portico_shared::{DatabaseItem, RunningStatus, RuntimeSession};
-use serde_json::json;
+use portico_shared::models::agents::RateLimiter;
+use portico_shared::models::steps::StepErrorKind;
+use portico_shared::{PgStore, RunningStatus, RuntimeSession, Signal, Store};
+use serde_json::{json, Value};
 use sqlx::PgPool;
-use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, Mutex as AsyncMutex, Semaphore};
+use tokio_util::sync::CancellationToken;
 use tonic::Status;
 use uuid;
 
+// What a worker reports back for a finished run
+#[derive(Debug, Clone)]
+pub struct RunOutcome {
+    pub runtime_session_uuid: String,
+    pub status: RunningStatus,
+    pub result: Option<Value>,
+    pub total_execution_time: Duration,
+}
+
+// A signal waiting in an agent's queue
+pub struct QueuedSignal {
+    pub signal: SignalRequest,
+    // Cancelled when the client that sent the signal goes away
+    pub cancel: CancellationToken,
+    // Receives the run's outcome (or error message) if someone is waiting on it
+    pub reply: Option<oneshot::Sender<Result<RunOutcome, String>>>,
+    // Held until the signal has run, if it was queued by a reprocess
+    pub reprocess: Option<ReprocessGuard>,
+}
+
+impl From<SignalRequest> for QueuedSignal {
+    fn from(signal: SignalRequest) -> Self {
+        Self {
+            signal,
+            cancel: CancellationToken::new(),
+            reply: None,
+            reprocess: None,
+        }
+    }
+}
+
+// Marks a saved signal as being reprocessed until dropped, so reprocessing it again
+// before it has run doesn't queue it twice
+pub struct ReprocessGuard {
+    signal_id: i32,
+    reprocessing: Arc<Mutex<HashSet<i32>>>,
+}
+
+impl ReprocessGuard {
+    // `None` if the signal is being reprocessed already
+    pub fn try_new(manager: &AgentManager, signal_id: i32) -> Option<Self> {
+        let reprocessing = manager.reprocessing.clone();
+        if !reprocessing.lock().unwrap().insert(signal_id) {
+            return None;
+        }
+        Some(Self {
+            signal_id,
+            reprocessing,
+        })
+    }
+}
+
+impl Drop for ReprocessGuard {
+    fn drop(&mut self) {
+        self.reprocessing.lock().unwrap().remove(&self.signal_id);
+    }
+}
+
 // Agent manager handles message queuing and processing
 pub struct AgentManager {
     pub agents: SharedAgentMap,
     // Map from local ID (as string) to global UUID for quick lookups
     pub local_id_map: HashMap<String, String>,
-    pub message_queues: HashMap<String, mpsc::Sender<SignalRequest>>,
+    pub message_queues: HashMap<String, AgentQueue>,
+    // Saves agents and sessions (Postgres-backed here)
+    pub store: Arc<dyn Store>,
+    // Engine-only tables (e.g. dead letters) that aren't part of `Store`
     pub db_pool: PgPool,
+    // One permit per run in flight across all agents (unbounded unless capped)
+    pub run_permits: Arc<Semaphore>,
+    // Set when workers start on an agent's first signal and stop once idle this long
+    pub worker_idle_timeout: Option<Duration>,
+    // IDs of the saved signals queued by a reprocess that haven't run yet
+    pub reprocessing: Arc<Mutex<HashSet<i32>>>,
 }
 
 impl AgentManager {
@@ -26,10 +99,30 @@ impl AgentManager {
             agents,
             local_id_map: HashMap::new(),
             message_queues: HashMap::new(),
+            store: Arc::new(PgStore::new(db_pool.clone())),
             db_pool,
+            run_permits: Arc::new(Semaphore::new(Semaphore::MAX_PERMITS)),
+            worker_idle_timeout: None,
+            reprocessing: Arc::new(Mutex::new(HashSet::new())),
         }
     }
 
+    // Caps how many agent runs may be in flight at once across all agents, so busy
+    // agents can't together exhaust the GIL, DB pool or memory. Set it before any
+    // queues are set up; workers keep the permits they started with.
+    pub fn with_max_global_concurrency(mut self, max_runs: usize) -> Self {
+        self.run_permits = Arc::new(Semaphore::new(max_runs.max(1)));
+        self
+    }
+
+    // Starts each agent's worker on its first signal instead of when its queue is
+    // set up, and stops it again after `idle_timeout` without signals, so idle
+    // agents don't each hold a task. Set it before any queues are set up.
+    pub fn with_lazy_workers(mut self, idle_timeout: Duration) -> Self {
+        self.worker_idle_timeout = Some(idle_timeout);
+        self
+    }
+
     // Set up message queues for all existing agents
     pub async fn init_agent_queues(&mut self) -> Result<(), Status> {
         // Collect all agent UUIDs and their local IDs first to avoid borrowing conflicts
@@ -83,14 +176,51 @@ impl AgentManager {
         match signal.signal_type() {
             SignalType::Run => {
                 // Direct handling of RUN signals
-                run::handle_run(self, signal.clone(), runtime_session_uuid).await
+                run::handle_run(self, signal, CancellationToken::new())
+                    .await?
+                    .wait()
+                    .await
             }
             SignalType::Sync => sync::handle_sync(self, &signal, runtime_session_uuid).await,
             SignalType::Fyi => fyi::handle_fyi(self, &signal, runtime_session_uuid).await,
         }
     }
 
-    // Set up processing for a specific agent
+    // Send a dead-lettered signal back to its agent's queue
+    pub async fn requeue_dead_letter(&self, dead_letter_id: i64) -> Result<(), Status> {
+        let mut dead_letter = DeadLetter::get(&self.db_pool, dead_letter_id)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to load dead letter: {}", e)))?
+            .ok_or_else(|| {
+                Status::not_found(format!("Dead letter {} not found", dead_letter_id))
+            })?;
+
+        let queue = self.message_queues.get(&dead_letter.agent_uuid).ok_or_else(|| {
+            Status::not_found(format!(
+                "Agent with UUID {} not found",
+                dead_letter.agent_uuid
+            ))
+        })?;
+
+        if let Err(e) = queue.send(dead_letter.signal.clone().into()).await {
+            eprintln!("[ERROR] Failed to requeue dead letter {}: {}", dead_letter_id, e);
+            return Err(Status::internal("Failed to forward signal to agent queue"));
+        }
+
+        dead_letter
+            .mark_requeued(&self.db_pool)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to mark dead letter requeued: {}", e)))?;
+
+        println!(
+            "[INFO] Requeued dead letter {} (signal {}) to agent {}",
+            dead_letter_id, dead_letter.signal.signal_id, dead_letter.agent_uuid
+        );
+        Ok(())
+    }
+
+    // Set up processing for a specific agent. Its worker starts now, or with lazy
+    // workers (see `with_lazy_workers`) once the agent's first signal arrives.
     pub async fn setup_agent_queue(&mut self, agent_uuid: String) -> Result<(), Status> {
         // Check if queue already exists
         if self.message_queues.contains_key(&agent_uuid) {
@@ -100,115 +230,317 @@ impl AgentManager {
         println!("[INFO] Setting up message queue for agent {}", agent_uuid);
 
         // Create a channel for this agent
-        let (tx, mut rx) = mpsc::channel::<SignalRequest>(32);
-        self.message_queues.insert(agent_uuid.clone(), tx);
-
-        // Clone shared resources for the worker task
-        let agents = Arc::clone(&self.agents);
-        let db_pool = self.db_pool.clone();
-
-        // Spawn a dedicated worker for this agent
-        tokio::spawn(async move {
-            println!("[INFO] Started worker for agent {}", agent_uuid);
-
-            while let Some(signal) = rx.recv().await {
-                println!(
-                    "[INFO] Agent {} worker processing signal: signal_id={}, type={:?}",
-                    agent_uuid,
-                    signal.signal_id,
-                    signal.signal_type()
-                );
+        let (tx, rx) = mpsc::channel::<QueuedSignal>(32);
+
+        // The worker gets clones of the shared resources
+        let worker = AgentWorker {
+            agent_uuid: agent_uuid.clone(),
+            agents: Arc::clone(&self.agents),
+            store: Arc::clone(&self.store),
+            db_pool: self.db_pool.clone(),
+            run_permits: Arc::clone(&self.run_permits),
+            rate_limiter: Arc::new(AsyncMutex::new(None)),
+        };
+        let queue = AgentQueue::new(tx, rx, worker, self.worker_idle_timeout);
+        if self.worker_idle_timeout.is_none() {
+            queue.ensure_worker();
+        }
+        self.message_queues.insert(agent_uuid, queue);
+
+        Ok(())
+    }
+}
+
+// Runs one agent's queued signals, one at a time
+#[derive(Clone)]
+pub(crate) struct AgentWorker {
+    agent_uuid: String,
+    agents: SharedAgentMap,
+    store: Arc<dyn Store>,
+    db_pool: PgPool,
+    run_permits: Arc<Semaphore>,
+    // Built lazily from the agent's config, and rebuilt if that config changes.
+    // Shared by the queue's workers, so one that stopped while idle doesn't take
+    // the agent's spent tokens with it.
+    rate_limiter: Arc<AsyncMutex<Option<RateLimiter>>>,
+}
+
+impl AgentWorker {
+    // Processes signals until the queue is dropped. With an `idle_timeout`, the worker
+    // also stops once no signal has come for that long, handing `rx` back to `idle_rx`
+    // for the next signal to start a new worker with.
+    pub(crate) async fn run(
+        self,
+        mut rx: mpsc::Receiver<QueuedSignal>,
+        idle_rx: IdleReceiver,
+        idle_timeout: Option<Duration>,
+    ) {
+        println!("[INFO] Started worker for agent {}", self.agent_uuid);
+
+        loop {
+            let next = match idle_timeout {
+                None => rx.recv().await,
+                Some(idle_timeout) => match tokio::time::timeout(idle_timeout, rx.recv()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        // Senders check `idle_rx` after sending, so a signal queued
+                        // meanwhile is either seen here or starts a new worker
+                        let mut idle = idle_rx.lock().unwrap();
+                        if !rx.is_empty() {
+                            continue;
+                        }
+                        *idle = Some(rx);
+                        println!(
+                            "[INFO] Worker for agent {} idle for {:?}, stopping",
+                            self.agent_uuid, idle_timeout
+                        );
+                        return;
+                    }
+                },
+            };
+            let Some(queued) = next else { break };
+            self.process(queued).await;
+        }
+
+        println!("[INFO] Worker for agent {} shutting down", self.agent_uuid);
+    }
+
+    async fn process(&self, queued: QueuedSignal) {
+        // The reprocess guard is kept until the run is over
+        let QueuedSignal {
+            signal,
+            cancel,
+            reply,
+            reprocess: _reprocess,
+        } = queued;
+        let AgentWorker {
+            agent_uuid,
+            agents,
+            store,
+            db_pool,
+            run_permits,
+            rate_limiter,
+        } = self;
+
+        println!(
+            "[INFO] Agent {} worker processing signal: signal_id={}, type={:?}",
+            agent_uuid,
+            signal.signal_id,
+            signal.signal_type()
+        );
 
-                if let SignalType::Run = signal.signal_type() {
-                    if let Some(crate::proto::signal_request::Payload::RunData(run_data)) =
-                        &signal.payload
+        if let SignalType::Run = signal.signal_type() {
+            if let Some(crate::proto::signal_request::Payload::RunData(run_data)) =
+                &signal.payload
+            {
+                // Process the run data - expecting a "data" field in the wrapper
+                if let Some(data_field) = run_data.fields.get("data") {
+                    if let Some(prost_types::value::Kind::StructValue(data_struct)) =
+                        &data_field.kind
                     {
-                        // Process the run data - expecting a "data" field in the wrapper
-                        if let Some(data_field) = run_data.fields.get("data") {
-                            if let Some(value) = &data_field.kind {
-                                if let prost_types::value::Kind::StructValue(data_struct) = value {
-                                    let run_data_json = proto_struct_to_json(data_struct);
-                                    let agents_guard = agents.read().await;
-
-                                    if let Some(agent) = agents_guard.get(&agent_uuid) {
+                        let run_data_json = proto_struct_to_json(data_struct);
+
+                        // Respect the agent's rate limit before running. The read lock
+                        // is released before waiting so other tasks aren't blocked.
+                        let rate_limit = agents
+                            .read()
+                            .await
+                            .get(agent_uuid)
+                            .and_then(|agent| agent.config.rate_limit.clone());
+                        let mut rate_limiter = rate_limiter.lock().await;
+                        match rate_limit {
+                            Some(limit) => {
+                                if rate_limiter.as_ref().map(|l| l.config()) != Some(&limit) {
+                                    *rate_limiter = Some(RateLimiter::new(limit));
+                                }
+                                if let Some(limiter) = rate_limiter.as_mut() {
+                                    limiter.acquire().await;
+                                }
+                            }
+                            None => *rate_limiter = None,
+                        }
+                        drop(rate_limiter);
+
+                        // Nobody is waiting on this run anymore
+                        if cancel.is_cancelled() {
+                            println!(
+                                "[INFO] Skipping signal {}: cancelled before it ran",
+                                signal.signal_id
+                            );
+                            return;
+                        }
+
+                        // Held for the whole run, including any retries, so don't keep the map locked
+                        let agent = agents.read().await.get(agent_uuid).cloned();
+
+                        if let Some(agent) = agent {
+                            println!(
+                                "[INFO] Running agent {} with data from signal {}",
+                                agent_uuid,
+                                signal.signal_id
+                            );
+
+                            // Retry runs that failed transiently, per the agent's config
+                            let mut retries: u32 = 0;
+                            let run_result = loop {
+                                // Wait for a slot under the engine-wide cap, held for the run only
+                                let run_permit = run_permits.acquire().await;
+
+                                // Call agent.run() which creates a RuntimeSession internally
+                                let run_result =
+                                    agent.run_cancellable(run_data_json.clone(), &cancel).await;
+                                drop(run_permit);
+
+                                let Err(e) = &run_result else { break run_result };
+                                let retryable = StepErrorKind::of(e).is_some_and(|kind| kind.is_retryable());
+                                let backoff = match &agent.config.run_retry {
+                                    Some(retry) if retryable && retries < retry.max_retries => retry.backoff(retries),
+                                    _ => break run_result,
+                                };
+                                retries += 1;
+                                eprintln!(
+                                    "[WARN] Run for signal {} failed ({}), retry {} in {:?}",
+                                    signal.signal_id, e, retries, backoff
+                                );
+
+                                // Stop waiting if nobody wants the result anymore
+                                tokio::select! {
+                                    _ = tokio::time::sleep(backoff) => {}
+                                    _ = cancel.cancelled() => break run_result,
+                                }
+                            };
+
+                            match run_result {
+                                Ok(session) => {
+                                    if session.status == RunningStatus::Cancelled {
                                         println!(
-                                            "[INFO] Running agent {} with data from signal {}",
-                                            agent_uuid,
+                                            "[INFO] Run for signal {} was cancelled, saving session",
                                             signal.signal_id
                                         );
+                                    } else if session.status == RunningStatus::BudgetExceeded {
+                                        println!(
+                                            "[INFO] Run for signal {} exceeded its {} budget, saving session",
+                                            signal.signal_id,
+                                            session
+                                                .budget_exceeded
+                                                .map(|limit| limit.as_str())
+                                                .unwrap_or("run")
+                                        );
+                                    } else {
+                                        println!(
+                                            "[INFO] Agent execution successful, saving session"
+                                        );
+                                    }
 
-                                        // Call agent.run() which creates a RuntimeSession internally
-                                        match agent.run(run_data_json.clone()).await {
-                                            Ok(session) => {
-                                                println!(
-                                                    "[INFO] Agent execution successful, saving session"
-                                                );
-
-                                                // Save the session to the database using the DatabaseItem trait
-                                                if let Err(e) = session.try_db_create(&db_pool).await {
-                                                    eprintln!("[ERROR] Failed to save session: {}", e);
-                                                }
-                                            }
-                                            Err(e) => {
-                                                eprintln!(
-                                                    "[ERROR] Agent execution failed: {}",
-                                                    e
-                                                );
-
-                                                // Create a failed session
-                                                println!("[INFO] Creating and saving failed RuntimeSession");
-
-                                                // Extract steps from the agent
-                                                let steps = agent.steps.clone();
-
-                                                // Create a new RuntimeSession with failed status
-                                                // Pass the agent's local_id as the requested_by_agent_id
-                                                let mut failed_session = RuntimeSession::new(
-                                                    run_data_json,
-                                                    steps,
-                                                    Some(agent.identifiers.local_id.unwrap_or(0)),
-                                                );
-
-                                                // Set the status to Cancelled
-                                                failed_session.status = RunningStatus::Cancelled;
-
-                                                // Set the last_step_idx to 0 to avoid database constraint violation
-                                                failed_session.last_step_idx = Some(0);
-
-                                                // Set the last result to include the error message
-                                                failed_session.last_successful_result = Some(json!({
-                                                    "error": e.to_string(),
-                                                    "signal_uuid": signal.signal_id,
-                                                    "agent_uuid": agent_uuid
-                                                }));
-
-                                                // Try to save the failed session
-                                                if let Err(db_err) = failed_session
-                                                    .try_db_create(&db_pool)
-                                                    .await
-                                                {
-                                                    eprintln!("[ERROR] Failed to save error session: {}", db_err);
-                                                } else {
-                                                    println!(
-                                                        "[INFO] Failed session saved successfully with UUID: {}",
-                                                        failed_session.identifiers.global_uuid
-                                                    );
-                                                }
-                                            }
-                                        }
+                                    // Save the session through the store, and link the signal
+                                    // to it (it may have taken retries to get one)
+                                    if let Err(e) = store.create_runtime_session(&session).await {
+                                        eprintln!("[ERROR] Failed to save session: {}", e);
+                                    } else if let Err(e) = Signal::try_db_link_session(
+                                        db_pool,
+                                        signal.signal_id.into(),
+                                        &session.identifiers.global_uuid,
+                                    )
+                                    .await
+                                    {
+                                        eprintln!(
+                                            "[ERROR] Failed to link signal {} to its session: {}",
+                                            signal.signal_id, e
+                                        );
+                                    }
+
+                                    if let Some(reply) = reply {
+                                        let _ = reply.send(Ok(RunOutcome {
+                                            runtime_session_uuid: session.identifiers.global_uuid.clone(),
+                                            status: session.status.clone(),
+                                            result: session.last_successful_result.clone(),
+                                            total_execution_time: session.total_execution_time,
+                                        }));
+                                    }
+                                }
+                                Err(e) => {
+                                    eprintln!(
+                                        "[ERROR] Agent execution failed: {}",
+                                        e
+                                    );
+
+                                    // Create a failed session
+                                    println!("[INFO] Creating and saving failed RuntimeSession");
+
+                                    // Extract steps from the agent
+                                    let steps = agent.steps.clone();
+
+                                    // Create a new RuntimeSession with failed status
+                                    // Pass the agent's local_id as the requested_by_agent_id
+                                    let mut failed_session = RuntimeSession::new(
+                                        run_data_json,
+                                        steps,
+                                        Some(agent.identifiers.local_id.unwrap_or(0)),
+                                    );
+
+                                    // Set the status to Failed
+                                    failed_session.status = RunningStatus::Failed;
+
+                                    // Set the last_step_idx to 0 to avoid database constraint violation
+                                    failed_session.last_step_idx = Some(0);
+
+                                    // Keep the failure category so it can be filtered on
+                                    failed_session.error_kind = StepErrorKind::of(&e);
+
+                                    // Set the last result to include the error message
+                                    failed_session.last_successful_result = Some(json!({
+                                        "error": e.to_string(),
+                                        "error_kind": failed_session.error_kind,
+                                        "signal_uuid": signal.signal_id,
+                                        "agent_uuid": agent_uuid
+                                    }));
+
+                                    // Try to save the failed session
+                                    if let Err(db_err) = store
+                                        .create_runtime_session(&failed_session)
+                                        .await
+                                    {
+                                        eprintln!("[ERROR] Failed to save error session: {}", db_err);
                                     } else {
-                                        eprintln!("[ERROR] Agent {} not found in map", agent_uuid);
+                                        println!(
+                                            "[INFO] Failed session saved successfully with UUID: {}",
+                                            failed_session.identifiers.global_uuid
+                                        );
+                                    }
+
+                                    let retries = i32::try_from(retries).unwrap_or(i32::MAX);
+                                    match DeadLetter::record(db_pool, agent_uuid, &signal, &e, retries).await {
+                                        Ok(id) => println!(
+                                            "[INFO] Signal {} dead-lettered with id {}",
+                                            signal.signal_id, id
+                                        ),
+                                        Err(dl_err) => eprintln!(
+                                            "[ERROR] Failed to dead-letter signal {}: {}",
+                                            signal.signal_id, dl_err
+                                        ),
                                     }
+
+                                    if let Some(reply) = reply {
+                                        let _ = reply.send(Err(e.to_string()));
+                                    }
+                                }
+                            }
+
+                            // Session steps share their counters with the agent's steps
+                            for step in &agent.steps {
+                                if let Err(e) = step.save_metrics(db_pool).await {
+                                    eprintln!(
+                                        "[ERROR] Failed to save run counts for step {}: {}",
+                                        step.identifiers.global_uuid, e
+                                    );
                                 }
                             }
+                        } else {
+                            eprintln!("[ERROR] Agent {} not found in map", agent_uuid);
                         }
                     }
                 }
             }
-
-            println!("[INFO] Worker for agent {} shutting down", agent_uuid);
-        });
-
-        Ok(())
+        }
     }
 }
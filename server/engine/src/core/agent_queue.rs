@@ -0,0 +1,75 @@
+use crate::core::agent_manager::{AgentWorker, QueuedSignal};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::{
+    self,
+    error::{SendError, TrySendError},
+};
+
+// Holds a queue's receiving end while no worker is draining it
+pub(crate) type IdleReceiver = Arc<Mutex<Option<mpsc::Receiver<QueuedSignal>>>>;
+
+// The sending end of an agent's queue. Sending makes sure a worker is running to
+// drain it, so workers that stopped while idle come back with the next signal.
+pub struct AgentQueue {
+    tx: mpsc::Sender<QueuedSignal>,
+    idle_rx: IdleReceiver,
+    worker: Option<AgentWorker>,
+    idle_timeout: Option<Duration>,
+}
+
+impl AgentQueue {
+    pub(crate) fn new(
+        tx: mpsc::Sender<QueuedSignal>,
+        rx: mpsc::Receiver<QueuedSignal>,
+        worker: AgentWorker,
+        idle_timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            tx,
+            idle_rx: Arc::new(Mutex::new(Some(rx))),
+            worker: Some(worker),
+            idle_timeout,
+        }
+    }
+
+    pub async fn send(&self, queued: QueuedSignal) -> Result<(), SendError<QueuedSignal>> {
+        self.tx.send(queued).await?;
+        self.ensure_worker();
+        Ok(())
+    }
+
+    pub fn try_send(&self, queued: QueuedSignal) -> Result<(), TrySendError<QueuedSignal>> {
+        self.tx.try_send(queued)?;
+        self.ensure_worker();
+        Ok(())
+    }
+
+    // Whether a worker is currently draining the queue
+    pub fn worker_running(&self) -> bool {
+        self.worker.is_some() && self.idle_rx.lock().unwrap().is_none()
+    }
+
+    // Starts a worker unless one is already running. Called after sending, so a
+    // worker stopping for idleness at the same time still sees the new signal.
+    pub(crate) fn ensure_worker(&self) {
+        let Some(worker) = &self.worker else { return };
+        let Some(rx) = self.idle_rx.lock().unwrap().take() else {
+            return;
+        };
+        let idle_rx = self.idle_rx.clone();
+        tokio::spawn(worker.clone().run(rx, idle_rx, self.idle_timeout));
+    }
+}
+
+// A queue drained by whoever holds the receiver instead of a worker
+impl From<mpsc::Sender<QueuedSignal>> for AgentQueue {
+    fn from(tx: mpsc::Sender<QueuedSignal>) -> Self {
+        Self {
+            tx,
+            idle_rx: Arc::new(Mutex::new(None)),
+            worker: None,
+            idle_timeout: None,
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use anyhow::{anyhow, Result};
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Postgres pool settings, shared by the RPC handlers and every agent worker
+#[derive(Debug, Clone, PartialEq)]
+pub struct DbPoolConfig {
+    /// Maximum open connections (env: `DB_MAX_CONNECTIONS`, default: 10)
+    pub max_connections: u32,
+    /// How long to wait for a free connection before erroring (env: `DB_ACQUIRE_TIMEOUT_SECS`, default: 30)
+    pub acquire_timeout: Duration,
+    /// Idle connections are closed after this long; `None` keeps them (env: `DB_IDLE_TIMEOUT_SECS`, 0 disables, default: 600)
+    pub idle_timeout: Option<Duration>,
+    /// Ping connections before handing them out (env: `DB_TEST_BEFORE_ACQUIRE`, default: true)
+    pub test_before_acquire: bool,
+}
+
+impl Default for DbPoolConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 10,
+            acquire_timeout: Duration::from_secs(30),
+            idle_timeout: Some(Duration::from_secs(600)),
+            test_before_acquire: true,
+        }
+    }
+}
+
+impl DbPoolConfig {
+    /// Reads the config from environment variables, keeping defaults for unset ones
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+
+        let idle_timeout = match parse_env::<u64>("DB_IDLE_TIMEOUT_SECS")? {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => defaults.idle_timeout,
+        };
+
+        Ok(Self {
+            max_connections: parse_env("DB_MAX_CONNECTIONS")?.unwrap_or(defaults.max_connections),
+            acquire_timeout: parse_env("DB_ACQUIRE_TIMEOUT_SECS")?
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.acquire_timeout),
+            idle_timeout,
+            test_before_acquire: parse_env("DB_TEST_BEFORE_ACQUIRE")?
+                .unwrap_or(defaults.test_before_acquire),
+        })
+    }
+
+    pub fn pool_options(&self) -> PgPoolOptions {
+        PgPoolOptions::new()
+            .max_connections(self.max_connections)
+            .acquire_timeout(self.acquire_timeout)
+            .idle_timeout(self.idle_timeout)
+            .test_before_acquire(self.test_before_acquire)
+    }
+
+    /// Connects and verifies the database answers queries before returning the pool
+    pub async fn connect(&self, db_url: &str) -> Result<PgPool> {
+        let pool = self.pool_options().connect(db_url).await?;
+        check_ready(&pool).await?;
+        Ok(pool)
+    }
+}
+
+/// Readiness check: runs `SELECT 1` through the pool
+pub async fn check_ready(pool: &PgPool) -> Result<()> {
+    let one: i32 = sqlx::query_scalar("SELECT 1")
+        .fetch_one(pool)
+        .await
+        .map_err(|e| anyhow!("Database readiness check failed: {}", e))?;
+
+    if one == 1 {
+        Ok(())
+    } else {
+        Err(anyhow!("Database readiness check returned {}", one))
+    }
+}
+
+fn parse_env<T: FromStr>(key: &str) -> Result<Option<T>>
+where
+    T::Err: std::fmt::Display,
+{
+    match env::var(key) {
+        Ok(raw) => raw
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|e| anyhow!("Invalid value for {}: {}", key, e)),
+        Err(_) => Ok(None),
+    }
+}
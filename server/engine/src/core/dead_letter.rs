@@ -0,0 +1,127 @@
+use crate::proto::{signal_request::Payload, SignalRequest};
+use crate::proto_struct_to_json;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use portico_shared::models::steps::StepErrorKind;
+use prost::Message;
+use serde_json::Value;
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+
+/// A signal whose run failed permanently. Kept in `dead_letter_signals`
+/// (separate from cancelled sessions) until someone requeues it.
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    pub id: i64,
+    pub agent_uuid: String,
+    pub signal: SignalRequest,
+    pub payload: Option<Value>,
+    pub final_error: String,
+    pub error_kind: Option<StepErrorKind>,
+    pub retry_count: i32,
+    pub created_at: DateTime<Utc>,
+    pub requeued_at: Option<DateTime<Utc>>,
+}
+
+impl sqlx::FromRow<'_, sqlx::postgres::PgRow> for DeadLetter {
+    fn from_row(row: &sqlx::postgres::PgRow) -> sqlx::Result<Self> {
+        let encoded: Vec<u8> = row.try_get("signal_request")?;
+        let signal = SignalRequest::decode(encoded.as_slice())
+            .map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+
+        Ok(Self {
+            id: row.try_get("id")?,
+            agent_uuid: row.try_get::<Uuid, _>("agent_uuid")?.to_string(),
+            signal,
+            payload: row.try_get("payload")?,
+            final_error: row.try_get("final_error")?,
+            error_kind: row.try_get("error_kind")?,
+            retry_count: row.try_get("retry_count")?,
+            created_at: row.try_get("created_at")?,
+            requeued_at: row.try_get("requeued_at")?,
+        })
+    }
+}
+
+impl DeadLetter {
+    /// Stores a signal that exhausted its retries, returning the new row's id
+    pub async fn record(
+        pool: &PgPool,
+        agent_uuid: &str,
+        signal: &SignalRequest,
+        error: &anyhow::Error,
+        retry_count: i32,
+    ) -> Result<i64> {
+        let payload = match &signal.payload {
+            Some(Payload::RunData(data)) | Some(Payload::FyiData(data)) => {
+                Some(proto_struct_to_json(data))
+            }
+            _ => None,
+        };
+
+        let id: i64 = sqlx::query_scalar(
+            r#"
+            INSERT INTO dead_letter_signals
+                (agent_uuid, signal_id, signal_request, payload, final_error, error_kind, retry_count)
+            VALUES
+                ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id
+            "#,
+        )
+        .bind(Uuid::parse_str(agent_uuid)?)
+        .bind(signal.signal_id)
+        .bind(signal.encode_to_vec())
+        .bind(payload)
+        .bind(error.to_string())
+        .bind(StepErrorKind::of(error))
+        .bind(retry_count)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// Dead letters that haven't been requeued yet, oldest first
+    pub async fn list(pool: &PgPool) -> Result<Vec<Self>> {
+        let dead_letters = sqlx::query_as::<_, Self>(
+            r#"
+            SELECT * FROM dead_letter_signals
+            WHERE requeued_at IS NULL
+            ORDER BY created_at, id
+            "#,
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(dead_letters)
+    }
+
+    pub async fn get(pool: &PgPool, id: i64) -> Result<Option<Self>> {
+        let dead_letter =
+            sqlx::query_as::<_, Self>("SELECT * FROM dead_letter_signals WHERE id = $1")
+                .bind(id)
+                .fetch_optional(pool)
+                .await?;
+
+        Ok(dead_letter)
+    }
+
+    /// Marks the dead letter as handled so it drops out of `list`
+    pub async fn mark_requeued(&mut self, pool: &PgPool) -> Result<()> {
+        let requeued_at: DateTime<Utc> = sqlx::query_scalar(
+            r#"
+            UPDATE dead_letter_signals
+            SET requeued_at = CURRENT_TIMESTAMP
+            WHERE id = $1
+            RETURNING requeued_at
+            "#,
+        )
+        .bind(self.id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| anyhow!("Dead letter {} not found", self.id))?;
+
+        self.requeued_at = Some(requeued_at);
+        Ok(())
+    }
+}
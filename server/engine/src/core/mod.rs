@@ -1,2 +1,5 @@
 pub mod agent_manager;
+pub mod agent_queue;
+pub mod db_pool;
+pub mod dead_letter;
 pub mod rpc_server;
@@ -1,12 +1,16 @@
 use crate::core::agent_manager::AgentManager;
 use crate::proto::bridge_service_server::{BridgeService, BridgeServiceServer};
 use crate::proto::{
-    CreateAgentRequest, DeleteAgentRequest, GeneralResponse, ServerInitRequest, SignalRequest,
-    SignalResponse,
+    CreateAgentRequest, DeleteAgentRequest, GeneralResponse, PreviewRunRequest,
+    PreviewRunResponse, PreviewStepCodeRequest, PreviewStepCodeResponse, PreviewStepPromptRequest,
+    PreviewStepPromptResponse, ReprocessSignalsRequest, ReprocessSignalsResponse, ServerInitRequest, SignalRequest,
+    SignalResponse, SignalType, SubmitSignalsRequest, SubmitSignalsResponse,
+    UpdateAgentStepsRequest,
 };
 use crate::SharedAgentMap;
 use sqlx::PgPool;
 use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
 use tonic::{Request, Response, Status};
 
 // Bridge service implementation
@@ -16,9 +20,12 @@ pub struct RpcServer {
 
 impl RpcServer {
     pub fn new(agent_map: SharedAgentMap, db_pool: PgPool) -> Self {
-        let agent_manager = Arc::new(tokio::sync::Mutex::new(AgentManager::new(
-            agent_map, db_pool,
-        )));
+        Self::from_manager(AgentManager::new(agent_map, db_pool))
+    }
+
+    // Serves an already configured manager (e.g. with a global concurrency cap)
+    pub fn from_manager(agent_manager: AgentManager) -> Self {
+        let agent_manager = Arc::new(tokio::sync::Mutex::new(agent_manager));
 
         let instance = Self { agent_manager };
 
@@ -71,14 +78,50 @@ impl BridgeService for RpcServer {
             signal.signal_id
         );
 
-        // Process the signal using the agent manager
-        let mut manager = self.agent_manager.lock().await;
-        match manager.process_signal(signal).await {
+        // tonic drops this future if the client disconnects, which cancels the run
+        let cancel = CancellationToken::new();
+        let cancel_on_drop = cancel.clone().drop_guard();
+
+        let result = if signal.signal_type() == SignalType::Run {
+            // Only hold the manager lock while queueing, not for the whole run
+            let pending = {
+                let manager = self.agent_manager.lock().await;
+                crate::handlers::run::handle_run(&manager, signal, cancel).await
+            };
+            match pending {
+                Ok(pending) => pending.wait().await,
+                Err(status) => Err(status),
+            }
+        } else {
+            // Process the signal using the agent manager
+            let mut manager = self.agent_manager.lock().await;
+            manager.process_signal(signal).await
+        };
+
+        cancel_on_drop.disarm();
+        match result {
             Ok(response) => Ok(Response::new(response)),
             Err(status) => Err(status),
         }
     }
 
+    async fn submit_signals(
+        &self,
+        request: Request<SubmitSignalsRequest>,
+    ) -> Result<Response<SubmitSignalsResponse>, Status> {
+        let signals = request.into_inner().signals;
+
+        println!(
+            "[INFO] Received submit_signals request: {} signals",
+            signals.len()
+        );
+
+        let manager = self.agent_manager.lock().await;
+        Ok(Response::new(
+            crate::handlers::submit::handle_submit_signals(&manager, signals),
+        ))
+    }
+
     async fn create_agent(
         &self,
         request: Request<CreateAgentRequest>,
@@ -90,7 +133,7 @@ impl BridgeService for RpcServer {
         if let Some(agent_json) = &agent_request.agent_json {
             // Use the create_agent handler directly
             let mut manager = self.agent_manager.lock().await;
-            match crate::handlers::create::handle_create_agent(&mut *manager, agent_json).await {
+            match crate::handlers::create::handle_create_agent(&mut manager, agent_json).await {
                 Ok(response) => {
                     println!("[INFO] Agent created successfully");
                     Ok(Response::new(response))
@@ -127,7 +170,7 @@ impl BridgeService for RpcServer {
 
         // Use the delete_agent handler directly
         let mut manager = self.agent_manager.lock().await;
-        match crate::handlers::delete::handle_delete_agent(&mut *manager, agent_id).await {
+        match crate::handlers::delete::handle_delete_agent(&mut manager, agent_id).await {
             Ok(response) => {
                 println!("[INFO] Agent deleted successfully");
                 Ok(Response::new(response))
@@ -138,4 +181,140 @@ impl BridgeService for RpcServer {
             }
         }
     }
+
+    async fn update_agent_steps(
+        &self,
+        request: Request<UpdateAgentStepsRequest>,
+    ) -> Result<Response<GeneralResponse>, Status> {
+        let update_request = request.into_inner();
+
+        println!(
+            "[INFO] Received update_agent_steps request for UUID: {}",
+            update_request.agent_uuid
+        );
+
+        if update_request.agent_uuid.is_empty() {
+            return Err(Status::invalid_argument(
+                "Missing agent_uuid in UpdateAgentStepsRequest",
+            ));
+        }
+
+        // Building the new steps' runtime takes a while, so don't hold the manager meanwhile
+        let agents = self.agent_manager.lock().await.agents.clone();
+        match crate::handlers::update_steps::handle_update_steps(
+            &agents,
+            &update_request.agent_uuid,
+            &update_request.steps,
+        )
+        .await
+        {
+            Ok(response) => Ok(Response::new(response)),
+            Err(status) => {
+                eprintln!("[ERROR] Failed to update agent steps: {}", status);
+                Err(status)
+            }
+        }
+    }
+
+    async fn reprocess_signals(
+        &self,
+        request: Request<ReprocessSignalsRequest>,
+    ) -> Result<Response<ReprocessSignalsResponse>, Status> {
+        let reprocess_request = request.into_inner();
+
+        println!(
+            "[INFO] Received reprocess_signals request for UUID: {}",
+            reprocess_request.agent_uuid
+        );
+
+        if reprocess_request.agent_uuid.is_empty() {
+            return Err(Status::invalid_argument(
+                "Missing agent_uuid in ReprocessSignalsRequest",
+            ));
+        }
+
+        let manager = self.agent_manager.lock().await;
+        match crate::handlers::reprocess::handle_reprocess_signals(
+            &manager,
+            &reprocess_request.agent_uuid,
+            &reprocess_request.status_filter,
+            &reprocess_request.since,
+        )
+        .await
+        {
+            Ok(response) => Ok(Response::new(response)),
+            Err(status) => {
+                eprintln!("[ERROR] Failed to reprocess signals: {}", status);
+                Err(status)
+            }
+        }
+    }
+    async fn preview_run(
+        &self,
+        request: Request<PreviewRunRequest>,
+    ) -> Result<Response<PreviewRunResponse>, Status> {
+        let preview_request = request.into_inner();
+
+        println!("[INFO] Received preview_run request");
+
+        let Some(agent_json) = &preview_request.agent_json else {
+            return Err(Status::invalid_argument(
+                "Missing agent_json in PreviewRunRequest",
+            ));
+        };
+        let source_data = preview_request.source_data.unwrap_or_default();
+
+        // Only hold the manager lock to get the run permits, not for the whole run
+        let run_permits = Arc::clone(&self.agent_manager.lock().await.run_permits);
+        match crate::handlers::preview::handle_preview_run(&run_permits, agent_json, &source_data)
+            .await
+        {
+            Ok(response) => Ok(Response::new(response)),
+            Err(status) => {
+                eprintln!("[ERROR] Failed to preview run: {}", status);
+                Err(status)
+            }
+        }
+    }
+
+    async fn preview_step_code(
+        &self,
+        request: Request<PreviewStepCodeRequest>,
+    ) -> Result<Response<PreviewStepCodeResponse>, Status> {
+        println!("[INFO] Received preview_step_code request");
+
+        let Some(step_json) = &request.get_ref().step_json else {
+            return Err(Status::invalid_argument(
+                "Missing step_json in PreviewStepCodeRequest",
+            ));
+        };
+
+        crate::handlers::preview_code::handle_preview_step_code(step_json)
+            .await
+            .map(Response::new)
+    }
+
+    async fn preview_step_prompt(
+        &self,
+        request: Request<PreviewStepPromptRequest>,
+    ) -> Result<Response<PreviewStepPromptResponse>, Status> {
+        let preview_request = request.into_inner();
+
+        println!("[INFO] Received preview_step_prompt request");
+
+        let Some(step_json) = &preview_request.step_json else {
+            return Err(Status::invalid_argument(
+                "Missing step_json in PreviewStepPromptRequest",
+            ));
+        };
+        let source_data = preview_request.source_data.unwrap_or_default();
+
+        crate::handlers::preview_code::handle_preview_step_prompt(
+            step_json,
+            &source_data,
+            preview_request.legacy_prompt_format,
+        )
+        .await
+        .map(Response::new)
+    }
 }
@@ -3,8 +3,8 @@ use crate::proto::GeneralResponse;
 use crate::proto_struct_to_json;
 use portico_shared::models::Agent;
 use portico_shared::JsonLike;
-use portico_shared::DatabaseItem;
 use prost_types::Struct;
+use std::sync::Arc;
 use tonic::Status;
 
 // Create agent operation handler
@@ -21,6 +21,11 @@ pub async fn handle_create_agent(
             let agent_uuid = agent.identifiers.global_uuid.clone();
             println!("[INFO] Adding agent with UUID: {}", agent_uuid);
 
+            // Compile its Python steps now rather than on its first signal
+            if let Err(e) = agent.warmup() {
+                eprintln!("[WARN] Failed to warm up agent {}: {}", agent_uuid, e);
+            }
+
             // Setup a queue for this agent
             if let Err(e) = manager.setup_agent_queue(agent_uuid.clone()).await {
                 eprintln!("[ERROR] Failed to setup agent queue: {}", e);
@@ -29,11 +34,11 @@ pub async fn handle_create_agent(
 
             // Store the agent
             let mut agents_guard = manager.agents.write().await;
-            agents_guard.insert(agent_uuid.clone(), agent);
+            agents_guard.insert(agent_uuid.clone(), Arc::new(agent));
 
             // Save to database if not already there
             let agent = agents_guard.get(&agent_uuid).unwrap();
-            if let Err(e) = agent.try_db_create(&manager.db_pool).await {
+            if let Err(e) = manager.store.create_agent(agent).await {
                 if !e.to_string().contains("duplicate key") {
                     eprintln!("[ERROR] Failed to save agent to database: {}", e);
                     return Err(Status::internal("Failed to save agent to database"));
@@ -1,6 +1,6 @@
 use crate::core::agent_manager::AgentManager;
 use crate::proto::GeneralResponse;
-use sqlx;
+use portico_shared::IdFields;
 use tonic::Status;
 
 // Delete agent operation handler
@@ -18,21 +18,14 @@ pub async fn handle_delete_agent(
     // In a production system, you'd want to look up the agent by ID and then remove it
     println!("[INFO] Removing agent with ID {} from database", agent_id);
 
-    // First delete associated steps for the agent
-    if let Err(e) = sqlx::query("DELETE FROM steps WHERE agent_id = $1")
-        .bind(agent_id)
-        .execute(&manager.db_pool)
-        .await {
-        eprintln!("[ERROR] Failed to delete agent's steps from database: {}", e);
-        return Err(Status::internal("Failed to delete agent's steps from database"));
-    }
+    let id = IdFields {
+        local_id: Some(agent_id),
+        global_uuid: String::new(),
+    };
 
-    // Then delete the agent itself
-    if let Err(e) = sqlx::query("DELETE FROM agents WHERE id = $1")
-        .bind(agent_id)
-        .execute(&manager.db_pool)
-        .await
-    {
+    // Deleting the agent also deletes its steps. It isn't loaded first, so an agent
+    // whose steps no longer parse can still be deleted.
+    if let Err(e) = manager.store.delete_agent_by_id(&id).await {
         eprintln!("[ERROR] Failed to delete agent from database: {}", e);
         return Err(Status::internal("Failed to delete agent from database"));
     }
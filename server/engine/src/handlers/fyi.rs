@@ -29,6 +29,7 @@ pub async fn handle_fyi(
             message: "FYI data received".to_string(),
             runtime_session_uuid,
             result_data: None,
+            ..Default::default()
         })
     } else {
         Err(Status::invalid_argument(
@@ -1,5 +1,10 @@
 pub mod fyi;
 pub mod run;
+pub mod submit;
 pub mod sync;
 pub mod create;
 pub mod delete;
+pub mod update_steps;
+pub mod reprocess;
+pub mod preview;
+pub mod preview_code;
@@ -0,0 +1,57 @@
+use crate::handlers::run::result_to_proto_struct;
+use crate::proto::PreviewRunResponse;
+use crate::proto_struct_to_json;
+use portico_shared::models::{Agent, Step};
+use portico_shared::JsonLike;
+use prost_types::Struct;
+use tokio::sync::Semaphore;
+use tonic::Status;
+
+// Preview run handler: runs the agent on the sample data without queueing a signal
+// or saving a session. It waits for a slot under the engine-wide cap like any run.
+pub async fn handle_preview_run(
+    run_permits: &Semaphore,
+    agent_json: &Struct,
+    source_data: &Struct,
+) -> Result<PreviewRunResponse, Status> {
+    let agent_json = proto_struct_to_json(agent_json);
+    // `Agent::from_json` skips steps that don't parse, which would preview a
+    // different pipeline than the one sent
+    Step::from_json_array_strict(&agent_json["steps"]).map_err(|errors| {
+        Status::invalid_argument(format!(
+            "Invalid agent steps: {}",
+            Step::describe_json_array_errors(&errors)
+        ))
+    })?;
+    let agent = Agent::from_json(agent_json).map_err(|e| {
+        eprintln!("[ERROR] Failed to parse agent JSON: {}", e);
+        Status::invalid_argument(format!("Invalid agent data: {}", e))
+    })?;
+    let agent_uuid = agent.identifiers.global_uuid.clone();
+
+    println!(
+        "[INFO] Processing preview run for agent {} ({} steps)",
+        agent_uuid,
+        agent.steps.len()
+    );
+
+    let run_permit = run_permits.acquire().await;
+    let result = agent.run_ephemeral(proto_struct_to_json(source_data)).await;
+    drop(run_permit);
+
+    match result {
+        Ok(result) => Ok(PreviewRunResponse {
+            success: true,
+            message: format!("Preview of agent {} completed", agent_uuid),
+            result_data: Some(result_to_proto_struct(&result)),
+        }),
+        Err(e) => {
+            println!("[INFO] Preview of agent {} failed: {}", agent_uuid, e);
+            Ok(PreviewRunResponse {
+                success: false,
+                message: format!("Preview of agent {} failed: {}", agent_uuid, e),
+                result_data: None,
+            })
+        }
+    }
+}
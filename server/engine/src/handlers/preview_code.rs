@@ -0,0 +1,53 @@
+use crate::proto::{PreviewStepCodeResponse, PreviewStepPromptResponse};
+use crate::proto_struct_to_json;
+use portico_shared::models::Step;
+use portico_shared::JsonLike;
+use prost_types::Struct;
+use tonic::Status;
+
+// Preview step code handler: returns the Python function the runtime would define
+// for the step, without building or running it
+pub async fn handle_preview_step_code(
+    step_json: &Struct,
+) -> Result<PreviewStepCodeResponse, Status> {
+    let step = Step::from_json(proto_struct_to_json(step_json)).map_err(|e| {
+        eprintln!("[ERROR] Failed to parse step JSON: {}", e);
+        Status::invalid_argument(format!("Invalid step data: {}", e))
+    })?;
+
+    if !step.is_python_step() {
+        return Err(Status::invalid_argument(format!(
+            "{} steps don't run generated Python code",
+            step.step_type.as_str()
+        )));
+    }
+
+    Ok(PreviewStepCodeResponse {
+        function_name: step.python_function_name(),
+        code: step.preview_generated_code(),
+    })
+}
+
+// Preview step prompt handler: returns the system prompt and prompt a Prompt step
+// would send for `source_data`, without calling the model
+pub async fn handle_preview_step_prompt(
+    step_json: &Struct,
+    source_data: &Struct,
+    legacy_prompt_format: bool,
+) -> Result<PreviewStepPromptResponse, Status> {
+    let step = Step::from_json(proto_struct_to_json(step_json)).map_err(|e| {
+        eprintln!("[ERROR] Failed to parse step JSON: {}", e);
+        Status::invalid_argument(format!("Invalid step data: {}", e))
+    })?;
+
+    // Steps other than Prompt steps fail here too
+    let source = proto_struct_to_json(source_data);
+    let preview = step
+        .render_prompt_preview(&source, legacy_prompt_format)
+        .map_err(|e| Status::invalid_argument(format!("Failed to render prompt: {}", e)))?;
+
+    Ok(PreviewStepPromptResponse {
+        prompt: preview.prompt,
+        system_prompt: preview.system_prompt.unwrap_or_default(),
+    })
+}
@@ -0,0 +1,110 @@
+use crate::core::agent_manager::{AgentManager, QueuedSignal, ReprocessGuard};
+use crate::handlers::submit::submit_signal;
+use crate::json_to_proto_struct;
+use crate::proto::signal_request::Payload;
+use crate::proto::{
+    AcceptStatus, ReprocessSignalsResponse, SignalAcceptResult, SignalRequest, SignalType,
+};
+use chrono::{DateTime, Utc};
+use portico_shared::{RunningStatus, Signal};
+use serde_json::json;
+use std::str::FromStr;
+use tonic::Status;
+
+// Reprocess operation handler: queues the agent's past Run signals that match the
+// filters again, like a SubmitSignals batch of them (so a full queue rejects the rest).
+// A signal still waiting from an earlier reprocess isn't queued again.
+pub async fn handle_reprocess_signals(
+    manager: &AgentManager,
+    agent_uuid: &str,
+    status_filter: &str,
+    since: &str,
+) -> Result<ReprocessSignalsResponse, Status> {
+    println!(
+        "[INFO] Reprocessing signals for agent {} (status filter: '{}', since: '{}')",
+        agent_uuid, status_filter, since
+    );
+
+    let status = Some(status_filter)
+        .filter(|status| !status.is_empty())
+        .map(RunningStatus::from_str)
+        .transpose()
+        .map_err(|_| {
+            Status::invalid_argument(format!("Unknown status filter: {}", status_filter))
+        })?;
+    let since = Some(since)
+        .filter(|since| !since.is_empty())
+        .map(DateTime::parse_from_rfc3339)
+        .transpose()
+        .map_err(|e| Status::invalid_argument(format!("Invalid since '{}': {}", since, e)))?
+        .map(|since| since.with_timezone(&Utc));
+
+    let agent_id = manager
+        .agents
+        .read()
+        .await
+        .get(agent_uuid)
+        .ok_or_else(|| Status::not_found(format!("Agent {} not found", agent_uuid)))?
+        .identifiers
+        .local_id
+        .ok_or_else(|| {
+            Status::failed_precondition(format!("Agent {} has no saved signals", agent_uuid))
+        })?;
+
+    let signals = Signal::try_db_select_by_agent(&manager.db_pool, agent_id, status, since)
+        .await
+        .map_err(|e| Status::internal(format!("Failed to load signals: {}", e)))?;
+
+    // Every ID is checked before any signal is queued
+    let mut requests = Vec::with_capacity(signals.len());
+    for signal in signals {
+        let local_id = signal.identifiers.local_id.unwrap_or_default();
+        let signal_id = i32::try_from(local_id).map_err(|_| {
+            Status::invalid_argument(format!("Signal ID {} is out of range", local_id))
+        })?;
+        // Wrapped the way the bridge sends Run signals
+        let run_data = json!({"data": signal.initial_data.unwrap_or_else(|| json!({}))});
+        requests.push(SignalRequest {
+            signal_id,
+            agent_id,
+            signal_type: SignalType::Run as i32,
+            payload: Some(Payload::RunData(json_to_proto_struct(&run_data))),
+        });
+    }
+
+    let results: Vec<_> = requests
+        .into_iter()
+        .map(
+            |signal| match ReprocessGuard::try_new(manager, signal.signal_id) {
+                Some(guard) => submit_signal(
+                    manager,
+                    QueuedSignal {
+                        reprocess: Some(guard),
+                        ..signal.into()
+                    },
+                ),
+                None => SignalAcceptResult {
+                    signal_id: signal.signal_id,
+                    agent_id,
+                    status: AcceptStatus::AlreadyQueued as i32,
+                    message: format!("Signal {} is already being reprocessed", signal.signal_id),
+                },
+            },
+        )
+        .collect();
+    let enqueued_count = results
+        .iter()
+        .filter(|result| result.status() == AcceptStatus::Enqueued)
+        .count() as u32;
+
+    println!(
+        "[INFO] Requeued {} of {} matching signals for agent {}",
+        enqueued_count,
+        results.len(),
+        agent_uuid
+    );
+    Ok(ReprocessSignalsResponse {
+        results,
+        enqueued_count,
+    })
+}
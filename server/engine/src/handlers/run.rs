@@ -1,13 +1,58 @@
-use crate::core::agent_manager::AgentManager;
+use crate::core::agent_manager::{AgentManager, QueuedSignal, RunOutcome};
+use crate::json_to_proto_struct;
 use crate::proto::{SignalRequest, SignalResponse};
+use portico_shared::RunningStatus;
+use prost_types::Struct;
+use serde_json::{json, Value};
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
 use tonic::Status;
 
-// Run operation handler
+// A run that has been queued; `wait` resolves once the agent's worker finishes it
+pub struct PendingRun {
+    agent_uuid: String,
+    reply: oneshot::Receiver<Result<RunOutcome, String>>,
+}
+
+impl PendingRun {
+    pub async fn wait(self) -> Result<SignalResponse, Status> {
+        let outcome = self
+            .reply
+            .await
+            .map_err(|_| Status::internal("Agent worker dropped the run without a result"))?
+            .map_err(|e| Status::internal(format!("Agent execution failed: {}", e)))?;
+
+        let result_data = outcome.result.as_ref().map(result_to_proto_struct);
+
+        Ok(SignalResponse {
+            success: outcome.status == RunningStatus::Completed,
+            message: format!(
+                "Agent {} run finished with status {:?}",
+                self.agent_uuid, outcome.status
+            ),
+            runtime_session_uuid: outcome.runtime_session_uuid,
+            result_data,
+            status: outcome.status.as_str().to_string(),
+            total_execution_time_ms: outcome.total_execution_time.as_millis() as u64,
+        })
+    }
+}
+
+// Responses carry a Struct, so non-object results are wrapped as {"result": ...}
+pub fn result_to_proto_struct(result: &Value) -> Struct {
+    match result {
+        Value::Object(_) => json_to_proto_struct(result),
+        other => json_to_proto_struct(&json!({ "result": other })),
+    }
+}
+
+// Run operation handler: queues the signal for its agent. Cancelling `cancel`
+// (e.g. when the client disconnects) stops the run and ends its session Cancelled.
 pub async fn handle_run(
     manager: &AgentManager,
     signal: SignalRequest,
-    runtime_session_uuid: String,
-) -> Result<SignalResponse, Status> {
+    cancel: CancellationToken,
+) -> Result<PendingRun, Status> {
     // Process run signal
     println!(
         "[INFO] Processing run operation for signal: {}",
@@ -47,16 +92,23 @@ pub async fn handle_run(
         let mut modified_signal = signal.clone();
         modified_signal.agent_id = agent_uuid.parse::<i32>().unwrap_or(0);
 
-        if let Err(e) = queue.send(modified_signal).await {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let queued = QueuedSignal {
+            signal: modified_signal,
+            cancel,
+            reply: Some(reply_tx),
+            reprocess: None,
+        };
+
+        if let Err(e) = queue.send(queued).await {
             eprintln!("[ERROR] Failed to send signal to agent queue: {}", e);
             return Err(Status::internal("Failed to forward signal to agent queue"));
         }
 
-        Ok(SignalResponse {
-            success: true,
-            message: format!("Signal forwarded to agent {}", agent_uuid),
-            runtime_session_uuid,
-            result_data: None,
+        println!("[INFO] Signal forwarded to agent {}", agent_uuid);
+        Ok(PendingRun {
+            agent_uuid,
+            reply: reply_rx,
         })
     } else {
         eprintln!("[ERROR] No queue found for agent: {}", agent_uuid);
@@ -0,0 +1,92 @@
+use crate::core::agent_manager::{AgentManager, QueuedSignal};
+use crate::proto::signal_request::Payload;
+use crate::proto::{
+    AcceptStatus, SignalAcceptResult, SignalRequest, SignalType, SubmitSignalsResponse,
+};
+use tokio::sync::mpsc::error::TrySendError;
+
+// Bulk submission handler: queues each Run signal on its agent's queue without
+// waiting for it to run. A full queue rejects the signal rather than blocking the batch.
+pub fn handle_submit_signals(
+    manager: &AgentManager,
+    signals: Vec<SignalRequest>,
+) -> SubmitSignalsResponse {
+    println!("[INFO] Submitting batch of {} signals", signals.len());
+
+    let results: Vec<SignalAcceptResult> = signals
+        .into_iter()
+        .map(|signal| submit_signal(manager, signal.into()))
+        .collect();
+    let enqueued_count = results
+        .iter()
+        .filter(|result| result.status() == AcceptStatus::Enqueued)
+        .count() as u32;
+
+    println!(
+        "[INFO] Enqueued {} of {} submitted signals",
+        enqueued_count,
+        results.len()
+    );
+    SubmitSignalsResponse {
+        results,
+        enqueued_count,
+    }
+}
+
+pub(crate) fn submit_signal(manager: &AgentManager, queued: QueuedSignal) -> SignalAcceptResult {
+    let signal = &queued.signal;
+    let signal_id = signal.signal_id;
+    let agent_id = signal.agent_id;
+    let result = |status: AcceptStatus, message: String| SignalAcceptResult {
+        signal_id,
+        agent_id,
+        status: status as i32,
+        message,
+    };
+
+    // The worker only runs signals that carry a "data" struct
+    let has_run_data = matches!(
+        &signal.payload,
+        Some(Payload::RunData(run_data)) if matches!(
+            run_data.fields.get("data").and_then(|data| data.kind.as_ref()),
+            Some(prost_types::value::Kind::StructValue(_))
+        )
+    );
+    if signal.signal_type() != SignalType::Run || !has_run_data {
+        return result(
+            AcceptStatus::InvalidSignal,
+            "Only Run signals with a \"data\" struct can be submitted".to_string(),
+        );
+    }
+
+    let Some(agent_uuid) = manager.local_id_map.get(&agent_id.to_string()) else {
+        return result(
+            AcceptStatus::UnknownAgent,
+            format!("Agent with local ID {} not found in UUID map", agent_id),
+        );
+    };
+    let Some(queue) = manager.message_queues.get(agent_uuid) else {
+        return result(
+            AcceptStatus::UnknownAgent,
+            format!("Agent with UUID {} not found", agent_uuid),
+        );
+    };
+
+    match queue.try_send(queued) {
+        Ok(()) => result(
+            AcceptStatus::Enqueued,
+            format!("Signal queued for agent {}", agent_uuid),
+        ),
+        Err(TrySendError::Full(_)) => result(
+            AcceptStatus::RejectedQueueFull,
+            format!("Queue for agent {} is full", agent_uuid),
+        ),
+        Err(TrySendError::Closed(_)) => {
+            eprintln!("[ERROR] Queue for agent {} is closed", agent_uuid);
+            result(
+                AcceptStatus::UnknownAgent,
+                format!("Agent {} is no longer accepting signals", agent_uuid),
+            )
+        }
+    }
+}
@@ -37,6 +37,7 @@ pub async fn handle_sync(
                     message: "All entities synced".to_string(),
                     runtime_session_uuid,
                     result_data: Some(json_to_proto_struct(&result_value)),
+                    ..Default::default()
                 })
             }
             SyncScope::Specific => {
@@ -69,6 +70,7 @@ pub async fn handle_sync(
                     message: "Specific entities synced".to_string(),
                     runtime_session_uuid,
                     result_data: Some(json_to_proto_struct(&result_value)),
+                    ..Default::default()
                 })
             }
         }
@@ -0,0 +1,60 @@
+use crate::proto::GeneralResponse;
+use crate::{proto_struct_to_json, SharedAgentMap};
+use portico_shared::models::Step;
+use prost_types::Struct;
+use serde_json::Value;
+use std::sync::Arc;
+use tonic::Status;
+
+// Update agent steps operation handler
+pub async fn handle_update_steps(
+    agents: &SharedAgentMap,
+    agent_uuid: &str,
+    steps_json: &[Struct],
+) -> Result<GeneralResponse, Status> {
+    println!(
+        "[INFO] Processing step update for agent {} ({} steps)",
+        agent_uuid,
+        steps_json.len()
+    );
+
+    let steps_json = Value::Array(steps_json.iter().map(proto_struct_to_json).collect());
+    let steps = Step::from_json_array_strict(&steps_json).map_err(|errors| {
+        Status::invalid_argument(format!(
+            "Invalid steps: {}",
+            Step::describe_json_array_errors(&errors)
+        ))
+    })?;
+
+    // Dry run: build the new steps' runtime without holding up the agent's runs
+    let prepared = {
+        let agents_guard = agents.read().await;
+        let agent = agents_guard
+            .get(agent_uuid)
+            .ok_or_else(|| Status::not_found(format!("Agent {} not found", agent_uuid)))?;
+        agent.prepare_steps(steps).map_err(|e| {
+            eprintln!(
+                "[ERROR] New steps for agent {} don't build: {}",
+                agent_uuid, e
+            );
+            Status::invalid_argument(format!("Steps failed to build: {}", e))
+        })?
+    };
+
+    // Swap them in; in-flight runs keep the agent they started with, later runs use the new steps
+    let mut agents_guard = agents.write().await;
+    let agent = agents_guard
+        .get_mut(agent_uuid)
+        .ok_or_else(|| Status::not_found(format!("Agent {} not found", agent_uuid)))?;
+    Arc::make_mut(agent).set_steps(prepared);
+
+    println!(
+        "[INFO] Agent {} now has {} steps",
+        agent_uuid,
+        agent.steps.len()
+    );
+    Ok(GeneralResponse {
+        success: true,
+        message: format!("Steps of agent {} updated", agent_uuid),
+    })
+}
@@ -4,10 +4,15 @@ use serde_json::Value;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tonic_reflection::server::{ServerReflection, ServerReflectionServer};
 
 // Include the generated proto code
 pub mod proto {
     tonic::include_proto!("portico");
+
+    // Encoded descriptors of the proto, served by the reflection service
+    pub const FILE_DESCRIPTOR_SET: &[u8] =
+        tonic::include_file_descriptor_set!("portico_descriptor");
 }
 
 // Export our modules
@@ -17,8 +22,18 @@ pub mod handlers;
 // Re-export important types
 pub use crate::core::rpc_server::RpcServer;
 
-// Thread-safe Agent map type
-pub type SharedAgentMap = Arc<RwLock<HashMap<String, Agent>>>;
+// Thread-safe Agent map type. Agents are behind an `Arc` so a run can hold on to
+// its agent without keeping the map locked.
+pub type SharedAgentMap = Arc<RwLock<HashMap<String, Arc<Agent>>>>;
+
+// gRPC reflection service describing the `portico` package, so tools like grpcurl
+// and client generators can discover the Bridge service without a copy of the .proto
+pub fn reflection_service(
+) -> Result<ServerReflectionServer<impl ServerReflection>, tonic_reflection::server::Error> {
+    tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(proto::FILE_DESCRIPTOR_SET)
+        .build()
+}
 
 // Convert a protobuf Struct to a serde_json::Value
 pub fn proto_struct_to_json(proto_struct: &Struct) -> Value {
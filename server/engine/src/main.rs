@@ -1,16 +1,20 @@
 use anyhow::Result;
 use dotenvy::dotenv;
-use sqlx::postgres::PgPoolOptions;
-use std::collections::HashMap;
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder};
 use std::env;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 use tonic::transport::Server;
 
-use portico_engine::RpcServer;
+use portico_engine::core::agent_manager::AgentManager;
+use portico_engine::core::db_pool::DbPoolConfig;
+use portico_engine::{RpcServer, SharedAgentMap};
 use portico_shared::models::Agent;
-use portico_shared::DatabaseItem;
+use portico_shared::{
+    LlmProviderRegistry, PgStore, Store, DB_DURATION_BUCKETS, DB_DURATION_METRIC,
+};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -20,6 +24,33 @@ async fn main() -> Result<()> {
         .unwrap_or_else(|_| "50051".to_string())
         .parse()
         .expect("GRPC_PORT should be a number");
+    // Reflection lets tools like grpcurl discover the service; off unless asked for
+    let grpc_reflection: bool = env::var("GRPC_REFLECTION")
+        .unwrap_or_else(|_| "false".to_string())
+        .parse()
+        .expect("GRPC_REFLECTION should be true or false");
+    // Caps agent runs in flight across all agents; unbounded when unset
+    let max_global_concurrency: Option<usize> =
+        env::var("MAX_GLOBAL_CONCURRENCY").ok().map(|max| {
+            max.parse()
+                .expect("MAX_GLOBAL_CONCURRENCY should be a positive number")
+        });
+    // Workers start on an agent's first signal and stop after this long idle;
+    // every agent keeps a worker when unset
+    let worker_idle_timeout: Option<Duration> =
+        env::var("WORKER_IDLE_TIMEOUT_SECS").ok().map(|secs| {
+            Duration::from_secs(
+                secs.parse()
+                    .expect("WORKER_IDLE_TIMEOUT_SECS should be a number of seconds"),
+            )
+        });
+    // Serves Prometheus metrics (e.g. database operation latencies) on this port;
+    // none are exported when unset
+    let metrics_port: Option<u16> = env::var("METRICS_PORT")
+        .ok()
+        .map(|port| port.parse().expect("METRICS_PORT should be a number"));
+    // Refuse a malformed LLM endpoint now rather than on the first Prompt step
+    LlmProviderRegistry::from_env()?.validate()?;
     let db_url: String = env::var("POSTGRES_DB_URI")
         .expect("POSTGRES_DB_URI needs to be specified")
         .parse()
@@ -29,33 +60,78 @@ async fn main() -> Result<()> {
     let addr = format!("0.0.0.0:{}", grpc_port).parse::<SocketAddr>()?;
     println!("Will try to start the gRPC server on {}", addr);
 
+    if let Some(metrics_port) = metrics_port {
+        let metrics_addr = format!("0.0.0.0:{}", metrics_port).parse::<SocketAddr>()?;
+        PrometheusBuilder::new()
+            .with_http_listener(metrics_addr)
+            .set_buckets_for_metric(
+                Matcher::Full(DB_DURATION_METRIC.to_string()),
+                &DB_DURATION_BUCKETS,
+            )?
+            .install()?;
+        println!("Serving Prometheus metrics on {}", metrics_addr);
+    }
+
+    let pool_config = DbPoolConfig::from_env()?;
+
     println!("Trying to connect to the database...");
-    // Connect to database (share pooled connection)
-    let db_conn_pool = PgPoolOptions::new().connect(&db_url).await?;
-    println!("Connected to the database successfully");
+    // Connect to database (share pooled connection) and make sure it answers queries
+    let db_conn_pool = pool_config.connect(&db_url).await?;
+    println!(
+        "Connected to the database successfully (max connections: {})",
+        pool_config.max_connections
+    );
 
     // Pull corresponding `Agents` and corresponding `Steps`
-    let agents: Vec<Agent> = Agent::try_db_select_all(&db_conn_pool)
+    let agents: Vec<Agent> = PgStore::new(db_conn_pool.clone())
+        .select_all_agents()
         .await
         .expect("Failed to fetch agents from database");
 
     println!("Fetched agents successfully, count: {}", agents.len());
 
+    // Compile each agent's Python steps now rather than on its first signal
+    for agent in &agents {
+        if let Err(e) = agent.warmup() {
+            eprintln!(
+                "[WARN] Failed to warm up agent {}: {}",
+                agent.identifiers.global_uuid, e
+            );
+        }
+    }
+
     // Create a thread-safe agent map
-    let agent_map: Arc<RwLock<HashMap<String, Agent>>> = Arc::new(RwLock::new(
+    let agent_map: SharedAgentMap = Arc::new(RwLock::new(
         agents
             .into_iter()
-            .map(|agent| (agent.identifiers.global_uuid.clone(), agent))
+            .map(|agent| (agent.identifiers.global_uuid.clone(), Arc::new(agent)))
             .collect(),
     ));
 
     // Create an instance of our gRPC service
-    let bridge_service = RpcServer::new(agent_map, db_conn_pool);
+    let mut agent_manager = AgentManager::new(agent_map, db_conn_pool);
+    if let Some(max_runs) = max_global_concurrency {
+        println!("Capping concurrent agent runs at {}", max_runs);
+        agent_manager = agent_manager.with_max_global_concurrency(max_runs);
+    }
+    if let Some(idle_timeout) = worker_idle_timeout {
+        println!("Stopping agent workers after {:?} idle", idle_timeout);
+        agent_manager = agent_manager.with_lazy_workers(idle_timeout);
+    }
+    let bridge_service = RpcServer::from_manager(agent_manager);
+
+    let reflection = if grpc_reflection {
+        println!("gRPC reflection is enabled");
+        Some(portico_engine::reflection_service()?)
+    } else {
+        None
+    };
 
     // Start the gRPC server
     println!("Starting gRPC server with agent queuing support...");
     Server::builder()
         .add_service(bridge_service.with_server())
+        .add_optional_service(reflection)
         .serve(addr)
         .await?;
 
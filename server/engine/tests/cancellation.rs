@@ -0,0 +1,124 @@
+use portico_engine::proto::bridge_service_client::BridgeServiceClient;
+use portico_engine::proto::{signal_request::Payload, SignalRequest, SignalType};
+use portico_engine::{json_to_proto_struct, RpcServer, SharedAgentMap};
+use portico_shared::models::agents::AgentState;
+use portico_shared::models::steps::StepType;
+use portico_shared::{Agent, DatabaseItem, IdFields, RunningStatus, Step, TimestampFields};
+use serde_json::json;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tonic::transport::Server;
+
+// Needs a running database; skipped when POSTGRES_DB_URI isn't set
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_client_disconnect_cancels_session() {
+    dotenvy::dotenv().ok();
+    let Ok(db_url) = std::env::var("POSTGRES_DB_URI") else {
+        eprintln!("POSTGRES_DB_URI not set, skipping");
+        return;
+    };
+    let pool = PgPool::connect(&db_url).await.unwrap();
+
+    // The first step is slow enough to disconnect during it; the second should never run
+    let steps = vec![
+        Step::new(
+            IdFields::new(),
+            StepType::Python,
+            "import time\ntime.sleep(1)\nresult = source".to_string(),
+            None,
+        ),
+        Step::new(
+            IdFields::new(),
+            StepType::Python,
+            "source['second_step_ran'] = True\nresult = source".to_string(),
+            None,
+        ),
+    ];
+    let agent = Agent::new(
+        IdFields::new(),
+        TimestampFields::new(),
+        "Cancellation test agent".to_string(),
+        vec![],
+    );
+    agent.try_db_create(&pool).await.unwrap();
+    let mut agent = Agent::try_db_select_by_id(
+        &pool,
+        &IdFields::with_values(None, agent.identifiers.global_uuid.clone()),
+    )
+    .await
+    .unwrap()
+    .expect("Agent should have been saved");
+    agent.steps = steps;
+    agent.set_state(AgentState::Stable);
+    let agent_uuid = agent.identifiers.global_uuid.clone();
+    let agent_id = agent.identifiers.local_id.unwrap();
+
+    // Serve the engine on a free port
+    let agent_map: SharedAgentMap = Arc::new(RwLock::new(HashMap::from([(
+        agent_uuid.clone(),
+        Arc::new(agent),
+    )])));
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let service = RpcServer::new(agent_map, pool.clone());
+    tokio::spawn(
+        Server::builder()
+            .add_service(service.with_server())
+            .serve(addr),
+    );
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut client = BridgeServiceClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+    let signal = SignalRequest {
+        signal_id: 4343,
+        agent_id,
+        signal_type: SignalType::Run as i32,
+        payload: Some(Payload::RunData(json_to_proto_struct(
+            &json!({"data": {"value": 1}}),
+        ))),
+    };
+
+    // Disconnect while the first step is still running
+    let request = tokio::spawn(async move { client.process_signal(signal).await });
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    request.abort();
+
+    let mut saved = None;
+    for _ in 0..50 {
+        saved = sqlx::query_as::<_, (RunningStatus, i32)>(
+            "SELECT rts_status, latest_step_idx FROM runtime_sessions WHERE requested_by_agent_id = $1",
+        )
+        .bind(agent_id)
+        .fetch_optional(&pool)
+        .await
+        .unwrap();
+        if saved.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    let (status, latest_step_idx) = saved.expect("Cancelled session should have been saved");
+    assert_eq!(status, RunningStatus::Cancelled);
+    // Stopped during the first step, so the second never ran
+    assert_eq!(latest_step_idx, 0);
+
+    // Clean up
+    sqlx::query("DELETE FROM runtime_sessions WHERE requested_by_agent_id = $1")
+        .bind(agent_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM agents WHERE id = $1")
+        .bind(agent_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+}
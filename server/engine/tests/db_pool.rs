@@ -0,0 +1,31 @@
+use portico_engine::core::db_pool::{check_ready, DbPoolConfig};
+use std::time::Duration;
+
+// Needs a running database; skipped when POSTGRES_DB_URI isn't set
+#[tokio::test]
+async fn test_pool_from_config_round_trips_query() {
+    dotenvy::dotenv().ok();
+    let Ok(db_url) = std::env::var("POSTGRES_DB_URI") else {
+        eprintln!("POSTGRES_DB_URI not set, skipping");
+        return;
+    };
+
+    let config = DbPoolConfig {
+        max_connections: 2,
+        acquire_timeout: Duration::from_secs(5),
+        idle_timeout: None,
+        test_before_acquire: true,
+    };
+    let pool = config.connect(&db_url).await.expect("Failed to connect");
+
+    assert_eq!(pool.options().get_max_connections(), 2);
+    assert_eq!(pool.options().get_acquire_timeout(), Duration::from_secs(5));
+    assert_eq!(pool.options().get_idle_timeout(), None);
+
+    let value: i32 = sqlx::query_scalar("SELECT 41 + 1")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(value, 42);
+    check_ready(&pool).await.unwrap();
+}
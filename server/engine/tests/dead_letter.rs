@@ -0,0 +1,112 @@
+use portico_engine::core::agent_manager::AgentManager;
+use portico_engine::core::dead_letter::DeadLetter;
+use portico_engine::proto::{signal_request::Payload, SignalRequest, SignalType};
+use portico_engine::{json_to_proto_struct, SharedAgentMap};
+use portico_shared::{Agent, DatabaseItem, IdFields, TimestampFields};
+use serde_json::json;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+async fn wait_for_dead_letters(pool: &PgPool, agent_uuid: &str, count: usize) -> Vec<DeadLetter> {
+    for _ in 0..50 {
+        let dead_letters: Vec<DeadLetter> = DeadLetter::list(pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|dl| dl.agent_uuid == agent_uuid)
+            .collect();
+        if dead_letters.len() >= count {
+            return dead_letters;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Timed out waiting for {} dead letter(s)", count);
+}
+
+// Needs a running database; skipped when POSTGRES_DB_URI isn't set
+#[tokio::test]
+async fn test_permanent_failure_is_dead_lettered_and_requeueable() {
+    dotenvy::dotenv().ok();
+    let Ok(db_url) = std::env::var("POSTGRES_DB_URI") else {
+        eprintln!("POSTGRES_DB_URI not set, skipping");
+        return;
+    };
+    let pool = PgPool::connect(&db_url).await.unwrap();
+
+    // Agents start Inactive, so every run of this one fails
+    let agent = Agent::new(
+        IdFields::new(),
+        TimestampFields::new(),
+        "Dead letter test agent".to_string(),
+        vec![],
+    );
+    agent.try_db_create(&pool).await.unwrap();
+    let agent = Agent::try_db_select_by_id(
+        &pool,
+        &IdFields::with_values(None, agent.identifiers.global_uuid.clone()),
+    )
+    .await
+    .unwrap()
+    .expect("Agent should have been saved");
+    let agent_uuid = agent.identifiers.global_uuid.clone();
+    let agent_id = agent.identifiers.local_id.unwrap();
+
+    let agent_map: SharedAgentMap = Arc::new(RwLock::new(HashMap::from([(
+        agent_uuid.clone(),
+        Arc::new(agent),
+    )])));
+    let mut manager = AgentManager::new(agent_map, pool.clone());
+    manager.setup_agent_queue(agent_uuid.clone()).await.unwrap();
+
+    let signal = SignalRequest {
+        signal_id: 4242,
+        agent_id: 0,
+        signal_type: SignalType::Run as i32,
+        payload: Some(Payload::RunData(json_to_proto_struct(
+            &json!({"data": {"value": 1}}),
+        ))),
+    };
+    manager.message_queues[&agent_uuid]
+        .send(signal.clone().into())
+        .await
+        .unwrap();
+
+    let dead_letters = wait_for_dead_letters(&pool, &agent_uuid, 1).await;
+    let dead_letter = &dead_letters[0];
+    assert_eq!(dead_letter.signal, signal);
+    assert_eq!(dead_letter.payload, Some(json!({"data": {"value": 1.0}})));
+    assert_eq!(dead_letter.retry_count, 0);
+    assert!(dead_letter.final_error.contains("Inactive"));
+
+    // Requeueing hands the signal back to the worker, which fails (and dead-letters) it again
+    manager.requeue_dead_letter(dead_letter.id).await.unwrap();
+    let requeued = DeadLetter::get(&pool, dead_letter.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(requeued.requeued_at.is_some());
+
+    let dead_letters = wait_for_dead_letters(&pool, &agent_uuid, 1).await;
+    assert_ne!(dead_letters[0].id, dead_letter.id);
+    assert_eq!(dead_letters[0].signal, signal);
+
+    // Clean up
+    sqlx::query("DELETE FROM dead_letter_signals WHERE agent_uuid = $1::uuid")
+        .bind(&agent_uuid)
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM runtime_sessions WHERE requested_by_agent_id = $1")
+        .bind(agent_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM agents WHERE global_uuid = $1::uuid")
+        .bind(&agent_uuid)
+        .execute(&pool)
+        .await
+        .unwrap();
+}
@@ -0,0 +1,100 @@
+use portico_engine::core::agent_manager::{AgentManager, QueuedSignal};
+use portico_engine::proto::{signal_request::Payload, SignalRequest, SignalType};
+use portico_engine::{json_to_proto_struct, SharedAgentMap};
+use portico_shared::models::agents::AgentState;
+use portico_shared::{Agent, RunningStatus};
+use serde_json::json;
+use sqlx::postgres::PgPoolOptions;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{oneshot, RwLock};
+use tokio_util::sync::CancellationToken;
+
+// Records when its run started and ended (time.sleep releases the GIL, so
+// uncapped runs of two agents would overlap)
+fn busy_agent(local_id: i32) -> Agent {
+    let mut agent = Agent::builder()
+        .description("Busy agent")
+        .python_step(
+            "import time\nstarted = time.time()\ntime.sleep(0.3)\n\
+             result = {'started': started, 'ended': time.time()}",
+        )
+        .state(AgentState::Stable)
+        .build();
+    agent.identifiers.local_id = Some(local_id);
+    agent
+}
+
+fn run_signal(signal_id: i32, agent_id: i32) -> SignalRequest {
+    SignalRequest {
+        signal_id,
+        agent_id,
+        signal_type: SignalType::Run as i32,
+        payload: Some(Payload::RunData(json_to_proto_struct(
+            &json!({"data": {"value": signal_id}}),
+        ))),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_global_cap_serializes_runs_across_agents() {
+    let agents = [busy_agent(913_001), busy_agent(913_002)];
+    let agent_uuids: Vec<String> = agents
+        .iter()
+        .map(|agent| agent.identifiers.global_uuid.clone())
+        .collect();
+    let agent_map: SharedAgentMap = Arc::new(RwLock::new(
+        agents
+            .into_iter()
+            .map(|agent| (agent.identifiers.global_uuid.clone(), Arc::new(agent)))
+            .collect::<HashMap<_, _>>(),
+    ));
+
+    // Only run timing is under test; the workers' session saves just log errors
+    let pool = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_millis(100))
+        .connect_lazy("postgresql://postgres@127.0.0.1:1/postgres")
+        .unwrap();
+    let mut manager = AgentManager::new(agent_map, pool).with_max_global_concurrency(1);
+
+    // Both agents get a signal at the same time
+    let mut replies = Vec::new();
+    for (idx, agent_uuid) in agent_uuids.iter().enumerate() {
+        manager.setup_agent_queue(agent_uuid.clone()).await.unwrap();
+        let (reply, outcome) = oneshot::channel();
+        manager.message_queues[agent_uuid]
+            .send(QueuedSignal {
+                signal: run_signal(idx as i32, 0),
+                cancel: CancellationToken::new(),
+                reply: Some(reply),
+                reprocess: None,
+            })
+            .await
+            .unwrap();
+        replies.push(outcome);
+    }
+
+    let mut windows = Vec::new();
+    for outcome in replies {
+        let outcome = tokio::time::timeout(Duration::from_secs(10), outcome)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(outcome.status, RunningStatus::Completed);
+        let result = outcome.result.unwrap();
+        windows.push((
+            result["started"].as_f64().unwrap(),
+            result["ended"].as_f64().unwrap(),
+        ));
+    }
+
+    // One run finished before the other started
+    windows.sort_by(|a, b| a.0.total_cmp(&b.0));
+    assert!(
+        windows[0].1 <= windows[1].0,
+        "Runs overlapped: {:?}",
+        windows
+    );
+}
@@ -0,0 +1,134 @@
+use portico_engine::core::agent_manager::{AgentManager, QueuedSignal, RunOutcome};
+use portico_engine::proto::{signal_request::Payload, SignalRequest, SignalType};
+use portico_engine::{json_to_proto_struct, SharedAgentMap};
+use portico_shared::models::agents::{AgentState, RateLimitConfig};
+use portico_shared::{Agent, RunningStatus};
+use serde_json::json;
+use sqlx::postgres::PgPoolOptions;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, RwLock};
+use tokio_util::sync::CancellationToken;
+
+// Queues one Run signal and returns the receiver for its outcome
+async fn send_run(
+    manager: &AgentManager,
+    agent_uuid: &str,
+) -> oneshot::Receiver<Result<RunOutcome, String>> {
+    let (reply, outcome) = oneshot::channel();
+    manager.message_queues[agent_uuid]
+        .send(QueuedSignal {
+            signal: SignalRequest {
+                signal_id: 1,
+                agent_id: 0,
+                signal_type: SignalType::Run as i32,
+                payload: Some(Payload::RunData(json_to_proto_struct(
+                    &json!({"data": {"value": 1}}),
+                ))),
+            },
+            cancel: CancellationToken::new(),
+            reply: Some(reply),
+            reprocess: None,
+        })
+        .await
+        .unwrap();
+    outcome
+}
+
+#[tokio::test]
+async fn test_worker_starts_on_first_signal_and_stops_when_idle() {
+    let agent = Agent::builder()
+        .python_step("result = {'ran': True}")
+        .state(AgentState::Stable)
+        .build();
+    let agent_uuid = agent.identifiers.global_uuid.clone();
+    let agent_map: SharedAgentMap = Arc::new(RwLock::new(HashMap::from([(
+        agent_uuid.clone(),
+        Arc::new(agent),
+    )])));
+
+    // Only the worker's lifetime is under test; session saves just log errors
+    let pool = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_millis(100))
+        .connect_lazy("postgresql://postgres@127.0.0.1:1/postgres")
+        .unwrap();
+    let mut manager =
+        AgentManager::new(agent_map, pool).with_lazy_workers(Duration::from_millis(200));
+    manager.setup_agent_queue(agent_uuid.clone()).await.unwrap();
+    assert!(!manager.message_queues[&agent_uuid].worker_running());
+
+    for _ in 0..2 {
+        let outcome = send_run(&manager, &agent_uuid).await;
+        assert!(manager.message_queues[&agent_uuid].worker_running());
+        let outcome = tokio::time::timeout(Duration::from_secs(10), outcome)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(outcome.status, RunningStatus::Completed);
+        assert_eq!(outcome.result, Some(json!({"ran": true})));
+
+        // Stops once no signal has come for the idle timeout, and the next
+        // signal starts a new one
+        let stopped = async {
+            while manager.message_queues[&agent_uuid].worker_running() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), stopped)
+            .await
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_rate_limit_carries_over_to_the_next_worker() {
+    let mut agent = Agent::builder()
+        .python_step("result = {'ran': True}")
+        .state(AgentState::Stable)
+        .build();
+    // One run every 4 seconds
+    agent.config.rate_limit = Some(RateLimitConfig {
+        requests_per_second: 0.25,
+        burst: 1,
+    });
+    let agent_uuid = agent.identifiers.global_uuid.clone();
+    let agent_map: SharedAgentMap = Arc::new(RwLock::new(HashMap::from([(
+        agent_uuid.clone(),
+        Arc::new(agent),
+    )])));
+
+    let pool = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_millis(100))
+        .connect_lazy("postgresql://postgres@127.0.0.1:1/postgres")
+        .unwrap();
+    let mut manager =
+        AgentManager::new(agent_map, pool).with_lazy_workers(Duration::from_millis(100));
+    manager.setup_agent_queue(agent_uuid.clone()).await.unwrap();
+
+    let mut waited = Vec::new();
+    for _ in 0..2 {
+        let started = Instant::now();
+        let outcome = send_run(&manager, &agent_uuid).await;
+        tokio::time::timeout(Duration::from_secs(10), outcome)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        waited.push(started.elapsed());
+
+        let stopped = async {
+            while manager.message_queues[&agent_uuid].worker_running() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), stopped)
+            .await
+            .unwrap();
+    }
+
+    // The second worker waits out the first run's token instead of starting
+    // with a full bucket
+    assert!(waited[1] >= Duration::from_secs(2), "{:?}", waited);
+}
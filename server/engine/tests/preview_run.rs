@@ -0,0 +1,71 @@
+use portico_engine::proto::bridge_service_client::BridgeServiceClient;
+use portico_engine::proto::PreviewRunRequest;
+use portico_engine::{json_to_proto_struct, proto_struct_to_json, RpcServer, SharedAgentMap};
+use portico_shared::models::agents::AgentState;
+use portico_shared::{Agent, JsonLike};
+use serde_json::json;
+use sqlx::postgres::PgPoolOptions;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tonic::transport::Server;
+use tonic::Code;
+
+#[tokio::test]
+async fn test_preview_run_runs_the_agent_it_is_sent() {
+    // Previews don't touch the database
+    let pool = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_millis(100))
+        .connect_lazy("postgresql://postgres@127.0.0.1:1/postgres")
+        .unwrap();
+    let agent_map: SharedAgentMap = Arc::new(RwLock::new(HashMap::new()));
+
+    // Serve the engine on a free port
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let service = RpcServer::new(agent_map, pool);
+    tokio::spawn(
+        Server::builder()
+            .add_service(service.with_server())
+            .serve(addr),
+    );
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let mut client = BridgeServiceClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+
+    // Sent back as the engine serializes it
+    let agent = Agent::builder()
+        .python_step("result = {'value': source['value'] * 2}")
+        .state(AgentState::Stable)
+        .build();
+    let mut agent_json = agent.to_json();
+    let preview = client
+        .preview_run(PreviewRunRequest {
+            agent_json: Some(json_to_proto_struct(&agent_json)),
+            source_data: Some(json_to_proto_struct(&json!({"value": 21}))),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(preview.success, "{}", preview.message);
+    assert_eq!(
+        proto_struct_to_json(&preview.result_data.unwrap()),
+        json!({"value": 42.0})
+    );
+
+    // A step that doesn't parse fails the preview instead of being left out
+    agent_json["steps"][0]["step_type"] = json!("teleport");
+    let err = client
+        .preview_run(PreviewRunRequest {
+            agent_json: Some(json_to_proto_struct(&agent_json)),
+            source_data: Some(json_to_proto_struct(&json!({"value": 21}))),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::InvalidArgument);
+    assert!(err.message().contains("step 0"), "{}", err.message());
+}
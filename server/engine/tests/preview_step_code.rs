@@ -0,0 +1,63 @@
+use portico_engine::handlers::preview_code::{
+    handle_preview_step_code, handle_preview_step_prompt,
+};
+use portico_engine::json_to_proto_struct;
+use serde_json::json;
+use tonic::Code;
+
+#[tokio::test]
+async fn test_preview_step_code_returns_the_generated_function() {
+    let step = json_to_proto_struct(&json!({
+        "global_uuid": "0b6c4a52-9a43-4bd5-8a3e-2f1c7d5e9a10",
+        "step_type": "python",
+        "step_content": "if source['value'] > 1:\n    result = {'big': True}",
+    }));
+    let preview = handle_preview_step_code(&step).await.unwrap();
+    assert_eq!(
+        preview.function_name,
+        "step_0b6c4a52_9a43_4bd5_8a3e_2f1c7d5e9a10"
+    );
+    assert!(preview
+        .code
+        .starts_with("def step_0b6c4a52_9a43_4bd5_8a3e_2f1c7d5e9a10(source):\n"));
+    assert!(preview
+        .code
+        .contains("\n    if source['value'] > 1:\n        result = {'big': True}\n"));
+    assert!(preview.code.ends_with("\n    return result"));
+
+    // Other step types don't run generated code
+    let prompt = json_to_proto_struct(&json!({"step_type": "prompt", "step_content": "Hi"}));
+    let err = handle_preview_step_code(&prompt).await.unwrap_err();
+    assert_eq!(err.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn test_preview_step_prompt_renders_the_input() {
+    let step = json_to_proto_struct(&json!({
+        "step_type": "prompt",
+        "step_content": "Triage ${secret:TOKEN} case {{case.id}}",
+        "system_prompt": "You are a triage nurse",
+    }));
+    let source = json_to_proto_struct(&json!({"case": {"id": "C-7"}}));
+
+    let preview = handle_preview_step_prompt(&step, &source, false)
+        .await
+        .unwrap();
+    assert_eq!(preview.prompt, "Triage ${secret:TOKEN} case C-7");
+    assert_eq!(preview.system_prompt, "You are a triage nurse");
+
+    let legacy = handle_preview_step_prompt(&step, &source, true)
+        .await
+        .unwrap();
+    assert!(legacy
+        .prompt
+        .starts_with("Triage ${secret:TOKEN} case {{case.id}} | Context: ```json\n"));
+
+    // Python steps don't send a prompt
+    let python =
+        json_to_proto_struct(&json!({"step_type": "python", "step_content": "result = 1"}));
+    let err = handle_preview_step_prompt(&python, &source, false)
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Code::InvalidArgument);
+}
@@ -0,0 +1,25 @@
+use portico_engine::{json_to_proto_struct, proto_struct_to_json};
+use prost_types::value::Kind;
+use serde_json::json;
+
+#[test]
+fn test_bools_round_trip_as_bools() {
+    let value = json!({
+        "flag": true,
+        "off": false,
+        "one": 1,
+        "nested": {"flags": [true, 0, false]}
+    });
+    let proto = json_to_proto_struct(&value);
+    assert_eq!(proto.fields["flag"].kind, Some(Kind::BoolValue(true)));
+    assert_eq!(proto.fields["off"].kind, Some(Kind::BoolValue(false)));
+    assert_eq!(proto.fields["one"].kind, Some(Kind::NumberValue(1.0)));
+
+    let back = proto_struct_to_json(&proto);
+    assert_eq!(back["flag"], json!(true));
+    assert_eq!(back["off"], json!(false));
+    assert!(back["one"].is_number());
+    assert_eq!(back["nested"]["flags"][0], json!(true));
+    assert!(back["nested"]["flags"][1].is_number());
+    assert_eq!(back["nested"]["flags"][2], json!(false));
+}
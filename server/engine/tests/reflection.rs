@@ -0,0 +1,101 @@
+use portico_engine::{reflection_service, RpcServer, SharedAgentMap};
+use prost::Message;
+use prost_types::FileDescriptorProto;
+use sqlx::postgres::PgPoolOptions;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tonic::transport::{Channel, Server};
+use tonic_reflection::pb::server_reflection_client::ServerReflectionClient;
+use tonic_reflection::pb::server_reflection_request::MessageRequest;
+use tonic_reflection::pb::server_reflection_response::MessageResponse;
+use tonic_reflection::pb::ServerReflectionRequest;
+
+fn reflection_request(message_request: MessageRequest) -> ServerReflectionRequest {
+    ServerReflectionRequest {
+        host: String::new(),
+        message_request: Some(message_request),
+    }
+}
+
+#[tokio::test]
+async fn test_reflection_lists_bridge_service_methods() {
+    // Reflection never touches the database
+    let pool = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_millis(100))
+        .connect_lazy("postgresql://postgres@127.0.0.1:1/postgres")
+        .unwrap();
+    let agent_map: SharedAgentMap = Arc::new(RwLock::new(HashMap::new()));
+
+    // Serve the engine with reflection on a free port
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let service = RpcServer::new(agent_map, pool);
+    tokio::spawn(
+        Server::builder()
+            .add_service(service.with_server())
+            .add_service(reflection_service().unwrap())
+            .serve(addr),
+    );
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    let mut client = ServerReflectionClient::new(channel);
+    let requests = futures::stream::iter(vec![
+        reflection_request(MessageRequest::ListServices(String::new())),
+        reflection_request(MessageRequest::FileContainingSymbol(
+            "portico.BridgeService".to_string(),
+        )),
+    ]);
+    let mut responses = client
+        .server_reflection_info(requests)
+        .await
+        .unwrap()
+        .into_inner();
+
+    let Some(MessageResponse::ListServicesResponse(listed)) =
+        responses.message().await.unwrap().unwrap().message_response
+    else {
+        panic!("Expected a list of services");
+    };
+    let services: Vec<_> = listed.service.iter().map(|s| s.name.as_str()).collect();
+    assert!(
+        services.contains(&"portico.BridgeService"),
+        "{:?}",
+        services
+    );
+
+    let Some(MessageResponse::FileDescriptorResponse(files)) =
+        responses.message().await.unwrap().unwrap().message_response
+    else {
+        panic!("Expected the proto file declaring the Bridge service");
+    };
+    let file = FileDescriptorProto::decode(files.file_descriptor_proto[0].as_slice()).unwrap();
+    let bridge = file
+        .service
+        .iter()
+        .find(|service| service.name() == "BridgeService")
+        .unwrap();
+    let methods: Vec<_> = bridge.method.iter().map(|method| method.name()).collect();
+    for expected in [
+        "InitServer",
+        "ProcessSignal",
+        "SubmitSignals",
+        "CreateAgent",
+        "DeleteAgent",
+        "UpdateAgentSteps",
+        "ReprocessSignals",
+        "PreviewRun",
+        "PreviewStepCode",
+        "PreviewStepPrompt",
+    ] {
+        assert!(methods.contains(&expected), "{:?}", methods);
+    }
+}
@@ -0,0 +1,175 @@
+use portico_engine::core::agent_manager::{AgentManager, QueuedSignal};
+use portico_engine::handlers::reprocess::handle_reprocess_signals;
+use portico_engine::proto::signal_request::Payload;
+use portico_engine::proto::{AcceptStatus, SignalRequest};
+use portico_engine::{proto_struct_to_json, SharedAgentMap};
+use portico_shared::{Agent, DatabaseItem, IdFields, TimestampFields};
+use serde_json::json;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{mpsc, RwLock};
+use tonic::Code;
+
+// Inserts a Run signal for `agent_id`, created `age_days` ago, linked to a new
+// session with `session_status` (if any); returns its ID
+async fn seed_signal(
+    pool: &PgPool,
+    agent_id: i32,
+    session_status: Option<&str>,
+    error_message: Option<&str>,
+    age_days: i32,
+) -> i64 {
+    let rts_id: Option<i64> = match session_status {
+        Some(status) => Some(
+            sqlx::query_scalar(
+                "INSERT INTO runtime_sessions (requested_by_agent_id, rts_status, initial_data, latest_step_idx)
+                 VALUES ($1, $2::running_status, '{}', 0) RETURNING id",
+            )
+            .bind(agent_id)
+            .bind(status)
+            .fetch_one(pool)
+            .await
+            .unwrap(),
+        ),
+        None => None,
+    };
+
+    sqlx::query_scalar(
+        "INSERT INTO signals (agent_id, rts_id, user_requested_uuid, signal_type, initial_data, error_message, created_at)
+         VALUES ($1, $2, gen_random_uuid(), 'run', $3, $4, now() - make_interval(days => $5))
+         RETURNING id",
+    )
+    .bind(agent_id)
+    .bind(rts_id)
+    .bind(json!({"ward": format!("{:?}-{}", session_status, age_days)}))
+    .bind(error_message)
+    .bind(age_days)
+    .fetch_one(pool)
+    .await
+    .unwrap()
+}
+
+// Needs a running database; skipped when POSTGRES_DB_URI isn't set
+#[tokio::test]
+async fn test_reprocess_queues_exactly_the_matching_signals() {
+    dotenvy::dotenv().ok();
+    let Ok(db_url) = std::env::var("POSTGRES_DB_URI") else {
+        eprintln!("POSTGRES_DB_URI not set, skipping");
+        return;
+    };
+    let pool = PgPool::connect(&db_url).await.unwrap();
+
+    let agent = Agent::new(
+        IdFields::new(),
+        TimestampFields::new(),
+        "Reprocess test agent".to_string(),
+        vec![],
+    );
+    agent.try_db_create(&pool).await.unwrap();
+    let agent = Agent::try_db_select_by_id(
+        &pool,
+        &IdFields::with_values(None, agent.identifiers.global_uuid.clone()),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    let agent_uuid = agent.identifiers.global_uuid.clone();
+    let agent_id = agent.identifiers.local_id.unwrap();
+
+    let failed = seed_signal(&pool, agent_id, Some("failed"), Some("Step 1 failed"), 1).await;
+    let completed = seed_signal(&pool, agent_id, Some("completed"), None, 1).await;
+    let errored = seed_signal(&pool, agent_id, None, Some("Agent is Inactive"), 1).await;
+    // Saved signals that haven't run yet have an empty error message
+    let unprocessed = seed_signal(&pool, agent_id, None, Some(""), 1).await;
+    let failed_long_ago = seed_signal(&pool, agent_id, Some("failed"), Some("Timeout"), 30).await;
+
+    // The signals land on a queue the test reads instead of a worker
+    let agent_map: SharedAgentMap = Arc::new(RwLock::new(HashMap::from([(
+        agent_uuid.clone(),
+        Arc::new(agent),
+    )])));
+    let mut manager = AgentManager::new(agent_map, pool.clone());
+    let (tx, mut rx) = mpsc::channel::<QueuedSignal>(32);
+    manager.message_queues.insert(agent_uuid.clone(), tx.into());
+    manager
+        .local_id_map
+        .insert(agent_id.to_string(), agent_uuid.clone());
+    let mut queued = || {
+        let mut signals = Vec::new();
+        while let Ok(queued) = rx.try_recv() {
+            signals.push(queued);
+        }
+        signals
+    };
+    let signals = |queued: Vec<QueuedSignal>| -> Vec<SignalRequest> {
+        queued.into_iter().map(|queued| queued.signal).collect()
+    };
+
+    let since = (chrono::Utc::now() - chrono::Duration::days(7)).to_rfc3339();
+    let recent_failures = handle_reprocess_signals(&manager, &agent_uuid, "failed", &since).await;
+    let recent_failures_queued = queued();
+    // They haven't run yet, so asking again doesn't queue them twice
+    let repeated = handle_reprocess_signals(&manager, &agent_uuid, "failed", &since).await;
+    let repeated_queued = queued();
+    // Once they're done (here: dropped) they can be reprocessed again
+    let recent_failures_queued = signals(recent_failures_queued);
+    let any_status = handle_reprocess_signals(&manager, &agent_uuid, "", "").await;
+    let any_status_queued = signals(queued());
+    let bad_filter = handle_reprocess_signals(&manager, &agent_uuid, "broken", "").await;
+
+    for table in ["signals", "runtime_sessions"] {
+        let column = if table == "signals" {
+            "agent_id"
+        } else {
+            "requested_by_agent_id"
+        };
+        sqlx::query(&format!("DELETE FROM {} WHERE {} = $1", table, column))
+            .bind(agent_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+    manager.agents.read().await[&agent_uuid]
+        .try_db_delete(&pool)
+        .await
+        .unwrap();
+
+    // Failed recently, with or without a session
+    let recent_failures = recent_failures.unwrap();
+    assert_eq!(recent_failures.enqueued_count, 2);
+    let ids: Vec<i64> = recent_failures_queued
+        .iter()
+        .map(|signal| signal.signal_id as i64)
+        .collect();
+    assert_eq!(ids, vec![failed, errored]);
+    let Some(Payload::RunData(run_data)) = &recent_failures_queued[0].payload else {
+        panic!("Reprocessed signals carry run data");
+    };
+    assert_eq!(
+        proto_struct_to_json(run_data),
+        json!({"data": {"ward": "Some(\"failed\")-1"}})
+    );
+
+    let repeated = repeated.unwrap();
+    assert_eq!(repeated.enqueued_count, 0);
+    assert!(repeated
+        .results
+        .iter()
+        .all(|result| result.status() == AcceptStatus::AlreadyQueued));
+    assert_eq!(repeated.results.len(), 2);
+    assert!(repeated_queued.is_empty());
+
+    // Without filters every Run signal is queued again, oldest first
+    assert_eq!(any_status.unwrap().enqueued_count, 5);
+    let ids: Vec<i64> = any_status_queued
+        .iter()
+        .map(|signal| signal.signal_id as i64)
+        .collect();
+    assert_eq!(
+        ids,
+        vec![failed_long_ago, failed, completed, errored, unprocessed]
+    );
+
+    assert_eq!(bad_filter.unwrap_err().code(), Code::InvalidArgument);
+}
@@ -0,0 +1,103 @@
+use portico_engine::proto::bridge_service_client::BridgeServiceClient;
+use portico_engine::proto::{signal_request::Payload, SignalRequest, SignalType};
+use portico_engine::{json_to_proto_struct, proto_struct_to_json, RpcServer, SharedAgentMap};
+use portico_shared::models::agents::AgentState;
+use portico_shared::models::steps::StepType;
+use portico_shared::{Agent, DatabaseItem, IdFields, Step, TimestampFields};
+use serde_json::json;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tonic::transport::Server;
+
+// Needs a running database; skipped when POSTGRES_DB_URI isn't set
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_run_response_carries_result_and_status() {
+    dotenvy::dotenv().ok();
+    let Ok(db_url) = std::env::var("POSTGRES_DB_URI") else {
+        eprintln!("POSTGRES_DB_URI not set, skipping");
+        return;
+    };
+    let pool = PgPool::connect(&db_url).await.unwrap();
+
+    let agent = Agent::new(
+        IdFields::new(),
+        TimestampFields::new(),
+        "Run response test agent".to_string(),
+        vec![],
+    );
+    agent.try_db_create(&pool).await.unwrap();
+    let mut agent = Agent::try_db_select_by_id(
+        &pool,
+        &IdFields::with_values(None, agent.identifiers.global_uuid.clone()),
+    )
+    .await
+    .unwrap()
+    .expect("Agent should have been saved");
+    agent.steps = vec![Step::new(
+        IdFields::new(),
+        StepType::Python,
+        "source['value'] += 1\nresult = source".to_string(),
+        None,
+    )];
+    agent.set_state(AgentState::Stable);
+    let agent_uuid = agent.identifiers.global_uuid.clone();
+    let agent_id = agent.identifiers.local_id.unwrap();
+
+    // Serve the engine on a free port
+    let agent_map: SharedAgentMap = Arc::new(RwLock::new(HashMap::from([(
+        agent_uuid.clone(),
+        Arc::new(agent),
+    )])));
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let service = RpcServer::new(agent_map, pool.clone());
+    tokio::spawn(
+        Server::builder()
+            .add_service(service.with_server())
+            .serve(addr),
+    );
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut client = BridgeServiceClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+    let response = client
+        .process_signal(SignalRequest {
+            signal_id: 4344,
+            agent_id,
+            signal_type: SignalType::Run as i32,
+            payload: Some(Payload::RunData(json_to_proto_struct(
+                &json!({"data": {"value": 1}}),
+            ))),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+
+    // Everything a client needs is in the response, no follow-up query required
+    assert!(response.success);
+    assert_eq!(response.status, "completed");
+    assert_eq!(
+        proto_struct_to_json(&response.result_data.unwrap()),
+        json!({"value": 2.0})
+    );
+    assert!(!response.runtime_session_uuid.is_empty());
+    assert!(response.total_execution_time_ms < 60_000);
+
+    // Clean up
+    sqlx::query("DELETE FROM runtime_sessions WHERE requested_by_agent_id = $1")
+        .bind(agent_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM agents WHERE id = $1")
+        .bind(agent_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+}
@@ -0,0 +1,143 @@
+use portico_engine::core::agent_manager::{AgentManager, QueuedSignal};
+use portico_engine::proto::{signal_request::Payload, SignalRequest, SignalType};
+use portico_engine::{json_to_proto_struct, SharedAgentMap};
+use portico_shared::models::agents::{AgentConfig, AgentState, RetryConfig};
+use portico_shared::{Agent, RunningStatus};
+use serde_json::json;
+use sqlx::postgres::PgPoolOptions;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, RwLock};
+use tokio_util::sync::CancellationToken;
+
+// LLM stub answering 503 to the first `failures` requests, then a completion;
+// returns its base URL
+fn spawn_flaky_llm(failures: usize, hits: Arc<AtomicUsize>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            // Read the headers, then as much body as they announce
+            let mut request = Vec::new();
+            let mut buf = [0u8; 8192];
+            while let Ok(n) = stream.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request).to_lowercase();
+                if let Some(header_end) = text.find("\r\n\r\n") {
+                    let content_length = text
+                        .lines()
+                        .find_map(|line| line.strip_prefix("content-length:"))
+                        .and_then(|len| len.trim().parse::<usize>().ok())
+                        .unwrap_or(0);
+                    if request.len() >= header_end + 4 + content_length {
+                        break;
+                    }
+                }
+            }
+
+            let response = if hits.fetch_add(1, Ordering::SeqCst) < failures {
+                "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n".to_string()
+            } else {
+                let body = json!({"choices": [{"message": {"content": "Recovered"}}]}).to_string();
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            };
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+    format!("http://{}", addr)
+}
+
+// Retries twice, waiting `backoff_ms` before each retry
+fn retry_config(backoff_ms: u64) -> AgentConfig {
+    AgentConfig {
+        run_retry: Some(RetryConfig {
+            max_retries: 2,
+            initial_backoff_ms: backoff_ms,
+            max_backoff_ms: backoff_ms,
+        }),
+        ..AgentConfig::default()
+    }
+}
+
+// Sends one Run signal to a fresh worker for `agent` and waits for its outcome
+async fn run_once(agent: Agent) -> Result<RunningStatus, String> {
+    let agent_uuid = agent.identifiers.global_uuid.clone();
+    let agent_map: SharedAgentMap = Arc::new(RwLock::new(HashMap::from([(
+        agent_uuid.clone(),
+        Arc::new(agent),
+    )])));
+
+    // Only the runs are under test; the workers' session saves just log errors
+    let pool = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_millis(100))
+        .connect_lazy("postgresql://postgres@127.0.0.1:1/postgres")
+        .unwrap();
+    let mut manager = AgentManager::new(agent_map, pool);
+    manager.setup_agent_queue(agent_uuid.clone()).await.unwrap();
+
+    let (reply, outcome) = oneshot::channel();
+    manager.message_queues[&agent_uuid]
+        .send(QueuedSignal {
+            signal: SignalRequest {
+                signal_id: 1,
+                agent_id: 0,
+                signal_type: SignalType::Run as i32,
+                payload: Some(Payload::RunData(json_to_proto_struct(
+                    &json!({"data": {"value": 1}}),
+                ))),
+            },
+            cancel: CancellationToken::new(),
+            reply: Some(reply),
+            reprocess: None,
+        })
+        .await
+        .unwrap();
+
+    tokio::time::timeout(Duration::from_secs(20), outcome)
+        .await
+        .unwrap()
+        .unwrap()
+        .map(|outcome| outcome.status)
+}
+
+#[tokio::test]
+async fn test_transient_failure_is_retried() {
+    // The LLM client makes 3 attempts per call, so the first run fails with a
+    // network error and the retried run gets the completion
+    let hits = Arc::new(AtomicUsize::new(0));
+    let llm = spawn_flaky_llm(3, Arc::clone(&hits));
+    std::env::set_var("LLM_API_ENDPOINT", &llm);
+    std::env::set_var("LLM_API_KEY", "test-key");
+    std::env::remove_var("LLM_PROVIDERS");
+
+    let agent = Agent::builder()
+        .prompt_step("Say something")
+        .config(retry_config(100))
+        .state(AgentState::Stable)
+        .build();
+    let status = run_once(agent).await;
+    assert_eq!(status, Ok(RunningStatus::Completed));
+    assert_eq!(hits.load(Ordering::SeqCst), 4);
+
+    // A Python exception is the user's to fix, so it isn't retried
+    let agent = Agent::builder()
+        .python_step("raise ValueError('bad input')")
+        .config(retry_config(5_000))
+        .state(AgentState::Stable)
+        .build();
+    let started = Instant::now();
+    let status = run_once(agent).await;
+    assert!(status.unwrap_err().contains("bad input"));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
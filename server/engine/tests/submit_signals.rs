@@ -0,0 +1,128 @@
+use portico_engine::proto::bridge_service_client::BridgeServiceClient;
+use portico_engine::proto::{
+    signal_request::Payload, AcceptStatus, SignalRequest, SignalType, SubmitSignalsRequest,
+};
+use portico_engine::{json_to_proto_struct, RpcServer, SharedAgentMap};
+use portico_shared::models::agents::{AgentState, RateLimitConfig};
+use portico_shared::{Agent, IdFields, TimestampFields};
+use serde_json::json;
+use sqlx::postgres::PgPoolOptions;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tonic::transport::Server;
+
+fn test_agent(local_id: i32, name: &str) -> Agent {
+    let agent = Agent::new(
+        IdFields::with_values(Some(local_id), uuid::Uuid::new_v4().to_string()),
+        TimestampFields::new(),
+        name.to_string(),
+        vec![],
+    );
+    agent.set_state(AgentState::Stable);
+    agent
+}
+
+fn run_signal(signal_id: i32, agent_id: i32) -> SignalRequest {
+    SignalRequest {
+        signal_id,
+        agent_id,
+        signal_type: SignalType::Run as i32,
+        payload: Some(Payload::RunData(json_to_proto_struct(
+            &json!({"data": {"value": signal_id}}),
+        ))),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_submit_signals_reports_per_signal_status() {
+    // The second agent's worker stalls on its rate limit after one run, so its
+    // queue fills up
+    let open_agent = test_agent(912_001, "Bulk submit test agent");
+    let mut throttled_agent = test_agent(912_002, "Throttled bulk submit test agent");
+    throttled_agent.config.rate_limit = Some(RateLimitConfig {
+        requests_per_second: 0.001,
+        burst: 1,
+    });
+
+    let agent_map: SharedAgentMap = Arc::new(RwLock::new(HashMap::from([
+        (
+            open_agent.identifiers.global_uuid.clone(),
+            Arc::new(open_agent),
+        ),
+        (
+            throttled_agent.identifiers.global_uuid.clone(),
+            Arc::new(throttled_agent),
+        ),
+    ])));
+
+    // Only queueing is under test; the workers' session saves just log errors
+    let pool = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_millis(100))
+        .connect_lazy("postgresql://postgres@127.0.0.1:1/postgres")
+        .unwrap();
+
+    // Serve the engine on a free port
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let service = RpcServer::new(agent_map, pool);
+    tokio::spawn(
+        Server::builder()
+            .add_service(service.with_server())
+            .serve(addr),
+    );
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut signals = vec![
+        run_signal(1, 912_001),
+        run_signal(2, 912_001),
+        run_signal(3, 999_999),
+        SignalRequest {
+            signal_id: 4,
+            agent_id: 912_001,
+            signal_type: SignalType::Fyi as i32,
+            payload: None,
+        },
+    ];
+    signals.extend((100..140).map(|signal_id| run_signal(signal_id, 912_002)));
+
+    let mut client = BridgeServiceClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+    let response = client
+        .submit_signals(SubmitSignalsRequest { signals })
+        .await
+        .unwrap()
+        .into_inner();
+
+    let statuses: Vec<AcceptStatus> = response.results.iter().map(|r| r.status()).collect();
+    assert_eq!(statuses.len(), 44);
+    assert_eq!(
+        statuses[..4],
+        [
+            AcceptStatus::Enqueued,
+            AcceptStatus::Enqueued,
+            AcceptStatus::UnknownAgent,
+            AcceptStatus::InvalidSignal,
+        ]
+    );
+    assert_eq!(response.results[2].signal_id, 3);
+
+    // The queue holds 32, and the worker takes at most two off it while the
+    // batch is queued (one runs, the next waits on the rate limit)
+    let throttled = &statuses[4..];
+    assert!(throttled[..32].iter().all(|s| *s == AcceptStatus::Enqueued));
+    assert!(throttled[34..]
+        .iter()
+        .all(|s| *s == AcceptStatus::RejectedQueueFull));
+    assert_eq!(
+        response.enqueued_count as usize,
+        statuses
+            .iter()
+            .filter(|s| **s == AcceptStatus::Enqueued)
+            .count()
+    );
+}
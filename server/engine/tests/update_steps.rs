@@ -0,0 +1,100 @@
+use portico_engine::core::agent_manager::{AgentManager, QueuedSignal};
+use portico_engine::handlers::update_steps::handle_update_steps;
+use portico_engine::proto::{signal_request::Payload, SignalRequest, SignalType};
+use portico_engine::{json_to_proto_struct, SharedAgentMap};
+use portico_shared::models::agents::AgentState;
+use portico_shared::{Agent, RunningStatus};
+use serde_json::{json, Value};
+use sqlx::postgres::PgPoolOptions;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{oneshot, RwLock};
+use tokio_util::sync::CancellationToken;
+use tonic::Code;
+
+// Sends one Run signal to the agent's worker and returns the run's result
+async fn run_once(manager: &AgentManager, agent_uuid: &str) -> Value {
+    let (reply, outcome) = oneshot::channel();
+    manager.message_queues[agent_uuid]
+        .send(QueuedSignal {
+            signal: SignalRequest {
+                signal_id: 1,
+                agent_id: 0,
+                signal_type: SignalType::Run as i32,
+                payload: Some(Payload::RunData(json_to_proto_struct(
+                    &json!({"data": {"value": 1}}),
+                ))),
+            },
+            cancel: CancellationToken::new(),
+            reply: Some(reply),
+            reprocess: None,
+        })
+        .await
+        .unwrap();
+
+    let outcome = tokio::time::timeout(Duration::from_secs(10), outcome)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(outcome.status, RunningStatus::Completed);
+    outcome.result.unwrap()
+}
+
+fn python_step(code: &str) -> prost_types::Struct {
+    json_to_proto_struct(&json!({"step_type": "python", "step_content": code}))
+}
+
+#[tokio::test]
+async fn test_update_steps_swaps_the_pipeline() {
+    let agent = Agent::builder()
+        .python_step("result = {'version': 'one'}")
+        .state(AgentState::Stable)
+        .build();
+    let agent_uuid = agent.identifiers.global_uuid.clone();
+    let agent_map: SharedAgentMap = Arc::new(RwLock::new(HashMap::from([(
+        agent_uuid.clone(),
+        Arc::new(agent),
+    )])));
+
+    // Only the runs are under test; the workers' session saves just log errors
+    let pool = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_millis(100))
+        .connect_lazy("postgresql://postgres@127.0.0.1:1/postgres")
+        .unwrap();
+    let mut manager = AgentManager::new(agent_map.clone(), pool);
+    manager.setup_agent_queue(agent_uuid.clone()).await.unwrap();
+    assert_eq!(
+        run_once(&manager, &agent_uuid).await,
+        json!({"version": "one"})
+    );
+
+    let steps = [
+        python_step("result = {'version': 'two'}"),
+        python_step("source['checked'] = True\nresult = source"),
+    ];
+    handle_update_steps(&agent_map, &agent_uuid, &steps)
+        .await
+        .unwrap();
+    assert_eq!(
+        run_once(&manager, &agent_uuid).await,
+        json!({"version": "two", "checked": true})
+    );
+
+    // Steps that don't compile are refused and the current ones kept
+    let status = handle_update_steps(&agent_map, &agent_uuid, &[python_step("result = (")])
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(agent_map.read().await[&agent_uuid].steps.len(), 2);
+    assert_eq!(
+        run_once(&manager, &agent_uuid).await,
+        json!({"version": "two", "checked": true})
+    );
+
+    let status = handle_update_steps(&agent_map, "no-such-agent", &steps)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
@@ -142,6 +142,21 @@ table "signals" {
         type = sql("text")
         null = true
     }
+
+    column "source" {
+        type = sql("text")
+        null = false
+        default = "user"
+        comment = "Where the signal came from: user, scheduled, webhook or agent:<uuid>"
+    }
+
+    # Lookups of a user's signals, newest first
+    index "signals_user_requested_uuid_idx" {
+        columns = [
+            column.user_requested_uuid,
+            column.created_at
+        ]
+    }
 }
 
 table "agents" {
@@ -215,6 +230,24 @@ table "agents" {
         type = sql("text")
         null = true
     }
+    column "config" {
+        type = sql("json")
+        null = true
+        comment = "Per-agent execution settings (e.g. rate limit)"
+    }
+    column "env" {
+        type = sql("json")
+        null = true
+        comment = "Variables exposed to the agent's Python steps as AGENT_ENV"
+    }
+
+    # Conflict target for agent upserts
+    index "agents_global_uuid_key" {
+        unique = true
+        columns = [
+            column.global_uuid
+        ]
+    }
 }
 
 table "steps" {
@@ -293,6 +326,63 @@ table "steps" {
         null = true
         comment = "The LLM model to use for this step"
     }
+    column "llm_provider" {
+        type = sql("varchar(255)")
+        null = true
+        comment = "Named LLM provider to route this step to (NULL uses the default provider)"
+    }
+    column "system_prompt" {
+        type = sql("text")
+        null = true
+        comment = "System message sent ahead of a Prompt step's content (NULL sends the prompt alone)"
+    }
+    column "input_mapping" {
+        type = sql("jsonb")
+        null = true
+        comment = "Param name -> JSONPath into the previous step's output (NULL passes the whole output)"
+    }
+    column "cacheable" {
+        type = sql("boolean")
+        null = false
+        default = false
+        comment = "Reuse the step's output for an input it has seen before instead of running it"
+    }
+    column "expected_duration" {
+        type = sql("double precision")
+        null = true
+        comment = "Seconds the step is expected to take; slower runs are flagged (NULL never flags)"
+    }
+    column "wrap_output" {
+        type = sql("boolean")
+        null = false
+        default = false
+        comment = "Wrap results that aren't objects under a `data` key instead of passing them through"
+    }
+    column "on_error" {
+        type = sql("text")
+        null = true
+        comment = "UUID of the step that handles this step's failures (NULL lets them fail as usual)"
+    }
+    column "run_count" {
+        type = sql("bigint")
+        null = false
+        default = 0
+        comment = "Number of times the step has run"
+    }
+    column "success_count" {
+        type = sql("bigint")
+        null = false
+        default = 0
+        comment = "Number of runs that succeeded"
+    }
+
+    # Conflict target for step upserts
+    index "steps_global_uuid_key" {
+        unique = true
+        columns = [
+            column.global_uuid
+        ]
+    }
 }
 
 table "runtime_sessions" {
@@ -365,9 +455,9 @@ table "runtime_sessions" {
         null = true
     }
     column "step_execution_times" {
-        type = sql("numeric(20,6)[]")
+        type = sql("numeric(20,9)[]")
         null = true
-        comment = "Array of execution times in seconds with microsecond precision"
+        comment = "Array of execution times in seconds with nanosecond precision"
     }
     column "step_ids" {
         type = sql("int[]")
@@ -375,15 +465,104 @@ table "runtime_sessions" {
         comment = "Array of step IDs that were executed in this session"
     }
     column "total_execution_time" {
-        type = sql("numeric(20,6)")
+        type = sql("numeric(20,9)")
         null = true
-        comment = "Total execution time in seconds with microsecond precision"
+        comment = "Total execution time in seconds with nanosecond precision"
     }
     column "step_results" {
         type = sql("json[]")
         null = true
         comment = "Array of JSON results for each step, in execution order"
     }
+    column "error_kind" {
+        type = enum.step_error_kind
+        null = true
+        comment = "Category of the step failure that cancelled the session"
+    }
+    column "budget_exceeded" {
+        type = enum.run_budget_limit
+        null = true
+        comment = "The agent budget limit that stopped the session, if any"
+    }
+    column "metadata" {
+        type = jsonb
+        null = true
+        comment = "Caller-supplied tags for tracing, e.g. a request, tenant or experiment id"
+    }
+}
+
+
+table "dead_letter_signals" {
+    # === General ===
+    schema = schema.public
+    comment = "Signals whose run failed permanently and need human attention"
+
+    # === Ids ===
+    column "id" {
+        type = sql("bigint")
+        null = false
+        identity {
+            generated = "ALWAYS"
+        }
+    }
+
+    column "global_uuid" {
+        type = sql("uuid")
+        null = false
+        default = sql("gen_random_uuid()")
+    }
+
+    primary_key {
+        columns = [
+            column.id
+        ]
+    }
+
+    # === Timestamps ===
+    column "created_at" {
+        type = sql("timestamptz")
+        null = false
+        default = sql("CURRENT_TIMESTAMP")
+    }
+
+    column "requeued_at" {
+        type = sql("timestamptz")
+        null = true
+        comment = "Set when the signal is sent back to its agent's queue"
+    }
+
+    # === Custom (table-specific) ===
+    column "agent_uuid" {
+        type = sql("uuid")
+        null = false
+    }
+    column "signal_id" {
+        type = int
+        null = false
+    }
+    column "signal_request" {
+        type = sql("bytea")
+        null = false
+        comment = "Protobuf-encoded SignalRequest, used to requeue it as-is"
+    }
+    column "payload" {
+        type = sql("json")
+        null = true
+        comment = "Readable copy of the signal's run data"
+    }
+    column "final_error" {
+        type = sql("text")
+        null = false
+    }
+    column "error_kind" {
+        type = enum.step_error_kind
+        null = true
+    }
+    column "retry_count" {
+        type = int
+        null = false
+        default = 0
+    }
 }
 
 
@@ -412,7 +591,22 @@ enum "step_type" {
     values = [
         "python",
         "prompt",
-        "webscrape"
+        "webscrape",
+        "loop",
+        "json_patch",
+        "file_op"
+    ]
+}
+
+enum "step_error_kind" {
+    schema = schema.public
+    values = [
+        "timeout",
+        "network",
+        "user_code",
+        "validation",
+        "config",
+        "rate_limited"
     ]
 }
 
@@ -423,6 +617,17 @@ enum "running_status" {
         "waiting",  # This means it is on the queue (not started)
         "running",  # This means it is actively being worked on (in the thread)
         "completed",  # This means it was seen-through to completion (even if resulting data is error, workflow completed)
-        "cancelled"   # This means it was intentionally cancelled (e.g. workflow error)
+        "cancelled",  # This means it was intentionally cancelled (e.g. client disconnected)
+        "failed",     # This means a step errored (see `error_kind`)
+        "budget_exceeded"  # This means the agent's run budget ran out (see `budget_exceeded`)
+    ]
+}
+
+enum "run_budget_limit" {
+    schema = schema.public
+    values = [
+        "duration",
+        "bytes",
+        "llm_calls"
     ]
 }